@@ -0,0 +1,222 @@
+//! Provides the [`IpNetwork`] and [`IpFilterKind`] types, used for restricting which source IP
+//! addresses may connect to each of the server's listening sockets, as well as the
+//! [`parse_ip_filter`] function for parsing human-readable filter rules.
+//!
+//! Filters are assigned to a listening socket by its address. A connection to a listener is
+//! rejected if its source address matches any of said listener's deny filters, or if the listener
+//! has any allow filters and the source address matches none of them.
+
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    serialize::{ByteRead, ByteWrite},
+    u8_repr_enum::U8ReprEnum,
+};
+
+/// The character separating a listener's socket address from the network in a filter rule.
+pub const LISTENER_SEPARATOR_CHAR: char = '=';
+
+/// The character separating an IP address from its prefix length in an [`IpNetwork`].
+pub const PREFIX_SEPARATOR_CHAR: char = '/';
+
+/// Whether a filter allows or denies the addresses it matches.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpFilterKind {
+    Allow = 0x00,
+    Deny = 0x01,
+}
+
+impl U8ReprEnum for IpFilterKind {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(Self::Allow),
+            0x01 => Some(Self::Deny),
+            _ => None,
+        }
+    }
+
+    fn into_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+impl IpFilterKind {
+    /// Gets this `IpFilterKind` represented by a `&'static str`.
+    pub const fn to_str(&self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+        }
+    }
+}
+
+impl fmt::Display for IpFilterKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+impl ByteWrite for IpFilterKind {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        self.into_u8().write(writer).await
+    }
+}
+
+impl ByteRead for IpFilterKind {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        match Self::from_u8(u8::read(reader).await?) {
+            Some(value) => Ok(value),
+            None => Err(Error::new(ErrorKind::InvalidData, "Invalid IpFilterKind type byte")),
+        }
+    }
+}
+
+/// An IP network, represented by an address and a prefix length, such as `10.0.0.0/8` or
+/// `fd00::/8`. Any host bits in the address are cleared on construction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNetwork {
+    address: IpAddr,
+    prefix_len: u8,
+}
+
+/// An error from constructing or parsing an invalid [`IpNetwork`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum IpNetworkParseError {
+    /// The address part is not a valid IP address.
+    InvalidAddress,
+
+    /// The prefix length is not a number or is larger than the address' bit count.
+    InvalidPrefixLength,
+}
+
+impl fmt::Display for IpNetworkParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAddress => write!(f, "invalid IP address"),
+            Self::InvalidPrefixLength => write!(f, "invalid prefix length"),
+        }
+    }
+}
+
+impl IpNetwork {
+    /// Creates a new `IpNetwork`, or returns `None` if the prefix length is larger than the
+    /// address' bit count.
+    pub fn new(address: IpAddr, prefix_len: u8) -> Option<Self> {
+        let address = match address {
+            IpAddr::V4(v4) if prefix_len <= 32 => {
+                IpAddr::V4((u32::from(v4) & u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0)).into())
+            }
+            IpAddr::V6(v6) if prefix_len <= 128 => {
+                IpAddr::V6((u128::from(v6) & u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0)).into())
+            }
+            _ => return None,
+        };
+
+        Some(Self { address, prefix_len })
+    }
+
+    /// Gets this network's address.
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    /// Gets this network's prefix length.
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Returns whether the given address belongs to this network. IPv4-mapped IPv6 addresses are
+    /// treated as their IPv4 counterparts.
+    pub fn contains(&self, address: IpAddr) -> bool {
+        let address = match address {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(address),
+            v4 => v4,
+        };
+
+        Self::new(address, self.prefix_len).is_some_and(|n| n.address == self.address)
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = IpNetworkParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = match s.split_once(PREFIX_SEPARATOR_CHAR) {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (s, None),
+        };
+
+        let address = address.parse::<IpAddr>().map_err(|_| IpNetworkParseError::InvalidAddress)?;
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse::<u8>().map_err(|_| IpNetworkParseError::InvalidPrefixLength)?,
+            None if address.is_ipv4() => 32,
+            None => 128,
+        };
+
+        Self::new(address, prefix_len).ok_or(IpNetworkParseError::InvalidPrefixLength)
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{PREFIX_SEPARATOR_CHAR}{}", self.address, self.prefix_len)
+    }
+}
+
+impl ByteWrite for IpNetwork {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (self.address, self.prefix_len).write(writer).await
+    }
+}
+
+impl ByteRead for IpNetwork {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let address = IpAddr::read(reader).await?;
+        let prefix_len = u8::read(reader).await?;
+        Self::new(address, prefix_len).ok_or_else(|| Error::new(ErrorKind::InvalidData, "Invalid IpNetwork prefix length"))
+    }
+}
+
+/// An error from parsing an invalid filter rule with [`parse_ip_filter`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum IpFilterParseError {
+    /// The rule has no '=' separating the listener address from the network.
+    MissingSeparator,
+
+    /// The listener part is not a valid socket address.
+    InvalidListenerAddress,
+
+    /// The network part is not valid.
+    InvalidNetwork(IpNetworkParseError),
+}
+
+impl fmt::Display for IpFilterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSeparator => write!(f, "expected listener{LISTENER_SEPARATOR_CHAR}network"),
+            Self::InvalidListenerAddress => write!(f, "invalid listener socket address"),
+            Self::InvalidNetwork(error) => error.fmt(f),
+        }
+    }
+}
+
+/// Parses a filter rule in the `listener=network` format, where the listener is a socket address
+/// (including the port) and the network is an IP address optionally followed by a prefix length.
+/// For example, `[::]:2222=10.5.0.0/16`, or `0.0.0.0:1080=192.168.1.10`.
+pub fn parse_ip_filter(s: &str) -> Result<(SocketAddr, IpNetwork), IpFilterParseError> {
+    let (listener, network) = s.rsplit_once(LISTENER_SEPARATOR_CHAR).ok_or(IpFilterParseError::MissingSeparator)?;
+    let listener = listener
+        .trim()
+        .parse::<SocketAddr>()
+        .map_err(|_| IpFilterParseError::InvalidListenerAddress)?;
+    let network = network.trim().parse::<IpNetwork>().map_err(IpFilterParseError::InvalidNetwork)?;
+    Ok((listener, network))
+}
@@ -28,6 +28,9 @@
 //! define async `read` and `write` functions.
 
 pub mod buffer_size;
+pub mod ip_filter;
+pub mod listener_profile;
+pub mod log_filter;
 pub mod logging;
 pub mod sandstorm;
 pub mod serialize;
@@ -0,0 +1,219 @@
+//! Provides the [`ListenerProfile`] type, used for attaching settings to each of the server's
+//! SOCKS5 listening sockets, as well as its human-readable text format.
+//!
+//! A profile is written as a list of options separated by commas. The available options are:
+//! - `noauth` or `-noauth`, to enable or disable the "noauth" authentication method.
+//! - `userpass` or `-userpass`, to enable or disable the "userpass" authentication method.
+//! - `buffer:<size>`, to set the buffer size used for clients, as parsed by
+//!   [`parse_pretty_buffer_size`].
+//! - `role:<role>`, to only allow users with the given role (`admin` or `regular`) to log in.
+//! - `user:<username>`, to only allow the given user to log in.
+//!
+//! For example, `noauth,-userpass` or `-noauth,buffer:16K,role:admin,user:pedro`. Any setting not
+//! specified by a profile takes the server-wide value. The `role` and `user` options may be
+//! specified multiple times, and only restrict logins with username and password. If both are
+//! specified, a user must have one of the given roles and also be one of the given users.
+
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+    str::FromStr,
+};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    buffer_size::parse_pretty_buffer_size,
+    serialize::{ByteRead, ByteWrite},
+    users::UserRole,
+};
+
+/// The character separating a listener's socket address from its profile.
+pub const PROFILE_START_CHAR: char = '=';
+
+/// The character separating the options of a profile.
+pub const OPTION_SEPARATOR_CHAR: char = ',';
+
+/// The character separating an option's name from its value.
+pub const OPTION_VALUE_CHAR: char = ':';
+
+/// The character that, prepended to an auth method option, disables said auth method.
+pub const OPTION_DISABLE_CHAR: char = '-';
+
+/// Settings attached to a SOCKS5 listening socket, which override the server-wide settings for the
+/// clients that connect through said socket.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListenerProfile {
+    /// Whether "noauth" authentication is enabled, or `None` to use the server-wide setting.
+    pub no_auth_enabled: Option<bool>,
+
+    /// Whether "userpass" authentication is enabled, or `None` to use the server-wide setting.
+    pub userpass_auth_enabled: Option<bool>,
+
+    /// The buffer size for clients, or `None` to use the server-wide setting.
+    pub buffer_size: Option<u32>,
+
+    /// The roles allowed to log in, or empty to allow any role.
+    pub allowed_roles: Vec<UserRole>,
+
+    /// The usernames allowed to log in, or empty to allow any user.
+    pub allowed_users: Vec<String>,
+}
+
+impl ListenerProfile {
+    /// Returns whether this profile doesn't override any of the server-wide settings.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns whether a user with the given username and role may log in through this profile,
+    /// which requires both the role and the username to be allowed.
+    pub fn allows_user(&self, username: &str, role: UserRole) -> bool {
+        (self.allowed_roles.is_empty() || self.allowed_roles.contains(&role))
+            && (self.allowed_users.is_empty() || self.allowed_users.iter().any(|u| u == username))
+    }
+}
+
+/// An error from parsing an invalid [`ListenerProfile`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum ListenerProfileParseError {
+    /// An option's name is not recognized.
+    UnknownOption(String),
+
+    /// The buffer size option's value is not valid.
+    InvalidBufferSize(String),
+
+    /// The role option's value is not a valid role.
+    InvalidRole(String),
+
+    /// The user option's value is empty or longer than 255 bytes.
+    InvalidUsername(String),
+}
+
+impl fmt::Display for ListenerProfileParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOption(option) => write!(f, "unknown profile option {option}"),
+            Self::InvalidBufferSize(value) => write!(f, "invalid buffer size {value}"),
+            Self::InvalidRole(value) => write!(f, "invalid role {value}"),
+            Self::InvalidUsername(value) => write!(f, "invalid username {value}"),
+        }
+    }
+}
+
+impl FromStr for ListenerProfile {
+    type Err = ListenerProfileParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut profile = ListenerProfile::default();
+
+        for option in s.split(OPTION_SEPARATOR_CHAR).map(|o| o.trim()).filter(|o| !o.is_empty()) {
+            let (name, value) = match option.split_once(OPTION_VALUE_CHAR) {
+                Some((name, value)) => (name.trim(), Some(value.trim())),
+                None => (option, None),
+            };
+
+            let (enable, auth_name) = match name.strip_prefix(OPTION_DISABLE_CHAR) {
+                Some(stripped) => (false, stripped),
+                None => (true, name),
+            };
+
+            match value {
+                None if auth_name.eq_ignore_ascii_case("noauth") => profile.no_auth_enabled = Some(enable),
+                None if auth_name.eq_ignore_ascii_case("userpass") => profile.userpass_auth_enabled = Some(enable),
+                Some(value) if name.eq_ignore_ascii_case("buffer") => match parse_pretty_buffer_size(value) {
+                    Ok(size) => profile.buffer_size = Some(size),
+                    Err(_) => return Err(ListenerProfileParseError::InvalidBufferSize(value.to_string())),
+                },
+                Some(value) if name.eq_ignore_ascii_case("role") => {
+                    let role = match value {
+                        v if v.eq_ignore_ascii_case(UserRole::Admin.to_str()) => UserRole::Admin,
+                        v if v.eq_ignore_ascii_case(UserRole::Regular.to_str()) => UserRole::Regular,
+                        _ => return Err(ListenerProfileParseError::InvalidRole(value.to_string())),
+                    };
+
+                    if !profile.allowed_roles.contains(&role) {
+                        profile.allowed_roles.push(role);
+                    }
+                }
+                Some(value) if name.eq_ignore_ascii_case("user") => {
+                    if value.is_empty() || value.len() > 255 {
+                        return Err(ListenerProfileParseError::InvalidUsername(value.to_string()));
+                    }
+
+                    if !profile.allowed_users.iter().any(|u| u == value) {
+                        profile.allowed_users.push(value.to_string());
+                    }
+                }
+                _ => return Err(ListenerProfileParseError::UnknownOption(option.to_string())),
+            }
+        }
+
+        Ok(profile)
+    }
+}
+
+impl fmt::Display for ListenerProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut options = Vec::new();
+
+        let auth_options = [("noauth", self.no_auth_enabled), ("userpass", self.userpass_auth_enabled)];
+        for (name, enabled) in auth_options {
+            match enabled {
+                Some(true) => options.push(name.to_string()),
+                Some(false) => options.push(format!("{OPTION_DISABLE_CHAR}{name}")),
+                None => {}
+            }
+        }
+
+        if let Some(buffer_size) = self.buffer_size {
+            options.push(format!("buffer{OPTION_VALUE_CHAR}{buffer_size}"));
+        }
+
+        for role in &self.allowed_roles {
+            options.push(format!("role{OPTION_VALUE_CHAR}{role}"));
+        }
+
+        for username in &self.allowed_users {
+            options.push(format!("user{OPTION_VALUE_CHAR}{username}"));
+        }
+
+        write!(f, "{}", options.join(&OPTION_SEPARATOR_CHAR.to_string()))
+    }
+}
+
+impl ByteWrite for ListenerProfile {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (
+            self.no_auth_enabled,
+            self.userpass_auth_enabled,
+            self.buffer_size,
+            self.allowed_roles.as_slice(),
+            self.allowed_users.as_slice(),
+        )
+            .write(writer)
+            .await
+    }
+}
+
+impl ByteRead for ListenerProfile {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let no_auth_enabled = <Option<bool> as ByteRead>::read(reader).await?;
+        let userpass_auth_enabled = <Option<bool> as ByteRead>::read(reader).await?;
+        let buffer_size = <Option<u32> as ByteRead>::read(reader).await?;
+        let allowed_roles = <Vec<UserRole> as ByteRead>::read(reader).await?;
+        let allowed_users = <Vec<String> as ByteRead>::read(reader).await?;
+
+        if buffer_size == Some(0) {
+            return Err(Error::new(ErrorKind::InvalidData, "ListenerProfile buffer size may not be zero"));
+        }
+
+        Ok(ListenerProfile {
+            no_auth_enabled,
+            userpass_auth_enabled,
+            buffer_size,
+            allowed_roles,
+            allowed_users,
+        })
+    }
+}
@@ -0,0 +1,529 @@
+//! Provides the [`LogFilter`] and [`LogSink`] types, used for choosing which events each of the
+//! server's log sinks writes, as well as their human-readable format.
+//!
+//! A filter is written as a comma-separated list of `key=value` settings, where list values are
+//! separated by `+`. For example, `severity=info,exclude=client_bytes_sent+client_bytes_received`
+//! or `include=client_*,users=pedro+juan`. The possible settings are:
+//! * `severity`: The minimum severity of the events to write, one of `error`, `warning`,
+//!   `notice`, `info` or `debug`.
+//! * `include`: Only write events whose type is in this list.
+//! * `exclude`: Don't write events whose type is in this list.
+//! * `clients`: Only write events of the clients with these IDs.
+//! * `users`: Only write events of the clients authenticated as these users.
+//!
+//! Event types are written as returned by [`EventData::type_name`], and may end with `*` to match
+//! all the types starting with the given prefix. The `clients` and `users` settings only apply to
+//! events about a specific client; all other events are written regardless of them. Backslashes
+//! may be used to escape commas, plus signs and backslashes in usernames. An empty filter, written
+//! as `all`, writes every event.
+//!
+//! The [`EventStreamFilter`] type, used by Sandstorm managers to filter the events streamed to
+//! them, is written the same way and also accepts a `listeners` setting: only send the events of
+//! the clients that connected through the socks5 sockets at these addresses.
+
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+    net::SocketAddr,
+    str::FromStr,
+};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    logging::{EventData, EventSeverity},
+    serialize::{ByteRead, ByteWrite},
+    u8_repr_enum::U8ReprEnum,
+};
+
+/// The character separating the settings of a filter.
+pub const SETTING_SEPARATOR_CHAR: char = ',';
+
+/// The character separating the values of a filter's list settings.
+pub const VALUE_SEPARATOR_CHAR: char = '+';
+
+/// The character separating a sink's name from a filter, as in `file:severity=info`.
+pub const SINK_SEPARATOR_CHAR: char = ':';
+
+/// The text representing a filter that writes every event.
+pub const ALL_EVENTS: &str = "all";
+
+const ESCAPE_CHAR: char = '\\';
+
+/// The server's log sinks, each of which has its own filter.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogSink {
+    Stdout = 0x00,
+    File = 0x01,
+    Syslog = 0x02,
+    Journald = 0x03,
+}
+
+impl U8ReprEnum for LogSink {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(Self::Stdout),
+            0x01 => Some(Self::File),
+            0x02 => Some(Self::Syslog),
+            0x03 => Some(Self::Journald),
+            _ => None,
+        }
+    }
+
+    fn into_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+impl LogSink {
+    /// All the log sinks, in the order they're listed in.
+    pub const ALL: [LogSink; 4] = [Self::Stdout, Self::File, Self::Syslog, Self::Journald];
+
+    /// Gets this `LogSink` represented by a `&'static str`.
+    pub const fn to_str(&self) -> &'static str {
+        match self {
+            Self::Stdout => "stdout",
+            Self::File => "file",
+            Self::Syslog => "syslog",
+            Self::Journald => "journald",
+        }
+    }
+}
+
+impl fmt::Display for LogSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+impl FromStr for LogSink {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        Self::ALL.into_iter().find(|sink| sink.to_str().eq_ignore_ascii_case(s)).ok_or(())
+    }
+}
+
+impl ByteWrite for LogSink {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        self.into_u8().write(writer).await
+    }
+}
+
+impl ByteRead for LogSink {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        match Self::from_u8(u8::read(reader).await?) {
+            Some(value) => Ok(value),
+            None => Err(Error::new(ErrorKind::InvalidData, "Invalid LogSink type byte")),
+        }
+    }
+}
+
+/// Decides which events a log sink writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFilter {
+    /// The minimum severity of the events to write.
+    pub min_severity: EventSeverity,
+
+    /// If not empty, only events whose type matches any of these patterns are written.
+    pub include: Vec<String>,
+
+    /// Events whose type matches any of these patterns are not written.
+    pub exclude: Vec<String>,
+
+    /// If not empty, only events about clients with these IDs are written.
+    pub client_ids: Vec<u64>,
+
+    /// If not empty, only events about clients authenticated as these users are written.
+    pub usernames: Vec<String>,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        LogFilter {
+            min_severity: EventSeverity::Debug,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            client_ids: Vec::new(),
+            usernames: Vec::new(),
+        }
+    }
+}
+
+/// Returns whether an event type matches a pattern, which may end with `*` to match by prefix.
+fn type_matches(pattern: &str, type_name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => type_name.starts_with(prefix),
+        None => pattern == type_name,
+    }
+}
+
+impl LogFilter {
+    /// Returns whether this filter writes every event.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Returns whether this filter uses the usernames of clients, which requires keeping track of
+    /// which user each client authenticated as.
+    pub fn filters_users(&self) -> bool {
+        !self.usernames.is_empty()
+    }
+
+    /// Returns whether an event passes this filter. `username` is the user the event's client
+    /// authenticated as, if the event is about a client and said client has authenticated.
+    pub fn matches(&self, data: &EventData, username: Option<&str>) -> bool {
+        if data.severity() > self.min_severity {
+            return false;
+        }
+
+        let type_name = data.type_name();
+        if !self.include.is_empty() && !self.include.iter().any(|p| type_matches(p, type_name)) {
+            return false;
+        }
+
+        if self.exclude.iter().any(|p| type_matches(p, type_name)) {
+            return false;
+        }
+
+        let client_id = match data.client_id() {
+            Some(client_id) => client_id,
+            None => return true,
+        };
+
+        if !self.client_ids.is_empty() && !self.client_ids.contains(&client_id) {
+            return false;
+        }
+
+        self.usernames.is_empty() || username.is_some_and(|username| self.usernames.iter().any(|u| u == username))
+    }
+}
+
+/// An error from parsing an invalid [`LogFilter`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum LogFilterParseError {
+    /// A setting is not in the `key=value` format, or its key is unknown.
+    InvalidSetting(String),
+
+    /// The severity is not one of the known severities.
+    InvalidSeverity(String),
+
+    /// An event type pattern contains characters other than lowercase letters, digits and
+    /// underscores, optionally followed by a `*`.
+    InvalidEventType(String),
+
+    /// A client ID is not a number.
+    InvalidClientId(String),
+
+    /// A username is empty or longer than 255 bytes.
+    InvalidUsername(String),
+
+    /// A listener is not a socket address.
+    InvalidListener(String),
+}
+
+impl fmt::Display for LogFilterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSetting(setting) => write!(f, "invalid setting \"{setting}\""),
+            Self::InvalidSeverity(severity) => write!(f, "invalid severity \"{severity}\""),
+            Self::InvalidEventType(event_type) => write!(f, "invalid event type \"{event_type}\""),
+            Self::InvalidClientId(client_id) => write!(f, "invalid client ID \"{client_id}\""),
+            Self::InvalidUsername(username) => write!(f, "invalid username \"{username}\""),
+            Self::InvalidListener(listener) => write!(f, "invalid listener \"{listener}\""),
+        }
+    }
+}
+
+/// Splits a string by a separator, ignoring separators escaped with a backslash. The escapes are
+/// kept in the returned parts.
+fn split_unescaped(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == ESCAPE_CHAR {
+            escaped = true;
+        } else if c == separator {
+            parts.push(&s[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+
+    parts.push(&s[start..]);
+    parts
+}
+
+fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut escaped = false;
+    for c in s.chars() {
+        if !escaped && c == ESCAPE_CHAR {
+            escaped = true;
+        } else {
+            result.push(c);
+            escaped = false;
+        }
+    }
+
+    result
+}
+
+fn write_escaped(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    for c in s.chars() {
+        if c == ESCAPE_CHAR || c == SETTING_SEPARATOR_CHAR || c == VALUE_SEPARATOR_CHAR {
+            write!(f, "{ESCAPE_CHAR}")?;
+        }
+        write!(f, "{c}")?;
+    }
+
+    Ok(())
+}
+
+fn parse_event_types(value: &str) -> Result<Vec<String>, LogFilterParseError> {
+    let mut types = Vec::new();
+    for pattern in value.split(VALUE_SEPARATOR_CHAR).map(|p| p.trim()) {
+        let name = pattern.strip_suffix('*').unwrap_or(pattern);
+        let is_valid = name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_');
+        if pattern.is_empty() || !is_valid {
+            return Err(LogFilterParseError::InvalidEventType(pattern.to_string()));
+        }
+
+        if !types.iter().any(|t| t == pattern) {
+            types.push(pattern.to_string());
+        }
+    }
+
+    Ok(types)
+}
+
+impl FromStr for LogFilter {
+    type Err = LogFilterParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = LogFilter::default();
+        let s = s.trim();
+        if s.is_empty() || s.eq_ignore_ascii_case(ALL_EVENTS) {
+            return Ok(filter);
+        }
+
+        for setting in split_unescaped(s, SETTING_SEPARATOR_CHAR) {
+            let (key, value) = match setting.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => return Err(LogFilterParseError::InvalidSetting(setting.to_string())),
+            };
+
+            if key.eq_ignore_ascii_case("severity") {
+                filter.min_severity = value.parse().map_err(|_| LogFilterParseError::InvalidSeverity(value.to_string()))?;
+            } else if key.eq_ignore_ascii_case("include") {
+                filter.include = parse_event_types(value)?;
+            } else if key.eq_ignore_ascii_case("exclude") {
+                filter.exclude = parse_event_types(value)?;
+            } else if key.eq_ignore_ascii_case("clients") {
+                filter.client_ids.clear();
+                for client_id in value.split(VALUE_SEPARATOR_CHAR).map(|c| c.trim()) {
+                    match client_id.parse::<u64>() {
+                        Ok(client_id) if !filter.client_ids.contains(&client_id) => filter.client_ids.push(client_id),
+                        Ok(_) => {}
+                        Err(_) => return Err(LogFilterParseError::InvalidClientId(client_id.to_string())),
+                    }
+                }
+            } else if key.eq_ignore_ascii_case("users") {
+                filter.usernames.clear();
+                for username in split_unescaped(value, VALUE_SEPARATOR_CHAR).into_iter().map(unescape) {
+                    if username.is_empty() || username.len() > u8::MAX as usize {
+                        return Err(LogFilterParseError::InvalidUsername(username));
+                    }
+
+                    if !filter.usernames.contains(&username) {
+                        filter.usernames.push(username);
+                    }
+                }
+            } else {
+                return Err(LogFilterParseError::InvalidSetting(setting.to_string()));
+            }
+        }
+
+        Ok(filter)
+    }
+}
+
+impl fmt::Display for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "{ALL_EVENTS}");
+        }
+
+        let mut separator = "";
+        if self.min_severity != EventSeverity::Debug {
+            write!(f, "severity={}", self.min_severity)?;
+            separator = ",";
+        }
+
+        for (key, types) in [("include", &self.include), ("exclude", &self.exclude)] {
+            if !types.is_empty() {
+                write!(f, "{separator}{key}={}", types.join("+"))?;
+                separator = ",";
+            }
+        }
+
+        if !self.client_ids.is_empty() {
+            write!(f, "{separator}clients=")?;
+            for (i, client_id) in self.client_ids.iter().enumerate() {
+                let plus = if i == 0 { "" } else { "+" };
+                write!(f, "{plus}{client_id}")?;
+            }
+            separator = ",";
+        }
+
+        if !self.usernames.is_empty() {
+            write!(f, "{separator}users=")?;
+            for (i, username) in self.usernames.iter().enumerate() {
+                if i != 0 {
+                    write!(f, "{VALUE_SEPARATOR_CHAR}")?;
+                }
+                write_escaped(f, username)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ByteWrite for LogFilter {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (
+            self.min_severity,
+            self.include.as_slice(),
+            self.exclude.as_slice(),
+            self.client_ids.as_slice(),
+            self.usernames.as_slice(),
+        )
+            .write(writer)
+            .await
+    }
+}
+
+impl ByteRead for LogFilter {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(LogFilter {
+            min_severity: EventSeverity::read(reader).await?,
+            include: <Vec<String> as ByteRead>::read(reader).await?,
+            exclude: <Vec<String> as ByteRead>::read(reader).await?,
+            client_ids: <Vec<u64> as ByteRead>::read(reader).await?,
+            usernames: <Vec<String> as ByteRead>::read(reader).await?,
+        })
+    }
+}
+
+/// Decides which events are streamed to a Sandstorm manager.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventStreamFilter {
+    /// The filter the events must pass, same as a log sink's.
+    pub filter: LogFilter,
+
+    /// If not empty, only events about clients that connected through the socks5 sockets at these
+    /// addresses are streamed.
+    pub listeners: Vec<SocketAddr>,
+}
+
+impl EventStreamFilter {
+    /// Returns whether this filter streams every event.
+    pub fn is_empty(&self) -> bool {
+        self.filter.is_empty() && self.listeners.is_empty()
+    }
+
+    /// Returns whether an event passes this filter. `username` and `listener` are the user the
+    /// event's client authenticated as and the socket it connected through, if the event is about
+    /// a client and these are known.
+    pub fn matches(&self, data: &EventData, username: Option<&str>, listener: Option<SocketAddr>) -> bool {
+        if !self.filter.matches(data, username) {
+            return false;
+        }
+
+        self.listeners.is_empty() || data.client_id().is_none() || listener.is_some_and(|listener| self.listeners.contains(&listener))
+    }
+}
+
+impl FromStr for EventStreamFilter {
+    type Err = LogFilterParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut listeners = Vec::new();
+        let mut settings = Vec::new();
+        for setting in split_unescaped(s.trim(), SETTING_SEPARATOR_CHAR) {
+            let value = match setting.split_once('=') {
+                Some((key, value)) if key.trim().eq_ignore_ascii_case("listeners") => value,
+                _ => {
+                    settings.push(setting);
+                    continue;
+                }
+            };
+
+            listeners.clear();
+            for listener in value.split(VALUE_SEPARATOR_CHAR).map(|l| l.trim()) {
+                match listener.parse::<SocketAddr>() {
+                    Ok(listener) if !listeners.contains(&listener) => listeners.push(listener),
+                    Ok(_) => {}
+                    Err(_) => return Err(LogFilterParseError::InvalidListener(listener.to_string())),
+                }
+            }
+        }
+
+        Ok(EventStreamFilter {
+            filter: settings.join(",").parse()?,
+            listeners,
+        })
+    }
+}
+
+impl fmt::Display for EventStreamFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.listeners.is_empty() {
+            return self.filter.fmt(f);
+        }
+
+        if !self.filter.is_empty() {
+            write!(f, "{}{SETTING_SEPARATOR_CHAR}", self.filter)?;
+        }
+
+        write!(f, "listeners=")?;
+        for (i, listener) in self.listeners.iter().enumerate() {
+            let plus = if i == 0 { "" } else { "+" };
+            write!(f, "{plus}{listener}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ByteWrite for EventStreamFilter {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (&self.filter, self.listeners.as_slice()).write(writer).await
+    }
+}
+
+impl ByteRead for EventStreamFilter {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(EventStreamFilter {
+            filter: LogFilter::read(reader).await?,
+            listeners: <Vec<SocketAddr> as ByteRead>::read(reader).await?,
+        })
+    }
+}
+
+/// Parses a filter optionally prefixed by a sink's name and a colon, as in `file:severity=info`.
+/// Returns `None` as the sink if no prefix is present, meaning the filter applies to all sinks.
+pub fn parse_sink_filter(s: &str) -> Result<(Option<LogSink>, LogFilter), LogFilterParseError> {
+    if let Some((sink, filter)) = s.split_once(SINK_SEPARATOR_CHAR) {
+        if let Ok(sink) = sink.parse::<LogSink>() {
+            return Ok((Some(sink), filter.parse()?));
+        }
+    }
+
+    Ok((None, s.parse()?))
+}
@@ -6,13 +6,18 @@ use std::{
     fmt,
     io::{Error, ErrorKind},
     net::SocketAddr,
+    str::FromStr,
 };
 
 use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::{
+    ip_filter::{IpFilterKind, IpNetwork},
+    log_filter::{LogFilter, LogSink},
+    sandstorm::{LockoutTarget, Metrics, SandstormCommandType},
     serialize::{ByteRead, ByteWrite, SmallReadString, SmallWriteString},
     socks5::{AuthMethod, SocksRequest, SocksRequestAddress},
+    u8_repr_enum::U8ReprEnum,
     users::{UserRole, UsersLoadingError},
 };
 
@@ -28,6 +33,172 @@ impl Event {
     }
 }
 
+/// The severity of an event. These have the same numeric values as syslog's severity levels, so a
+/// lower value means a more severe event.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventSeverity {
+    Error = 3,
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+    Debug = 7,
+}
+
+impl U8ReprEnum for EventSeverity {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            3 => Some(Self::Error),
+            4 => Some(Self::Warning),
+            5 => Some(Self::Notice),
+            6 => Some(Self::Info),
+            7 => Some(Self::Debug),
+            _ => None,
+        }
+    }
+
+    fn into_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+impl EventSeverity {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Notice => "notice",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        }
+    }
+}
+
+impl fmt::Display for EventSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_str())
+    }
+}
+
+impl FromStr for EventSeverity {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        [Self::Error, Self::Warning, Self::Notice, Self::Info, Self::Debug]
+            .into_iter()
+            .find(|severity| severity.to_str().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+impl ByteWrite for EventSeverity {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        self.into_u8().write(writer).await
+    }
+}
+
+impl ByteRead for EventSeverity {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        match Self::from_u8(u8::read(reader).await?) {
+            Some(value) => Ok(value),
+            None => Err(Error::new(ErrorKind::InvalidData, "Invalid EventSeverity type byte")),
+        }
+    }
+}
+
+/// A summary of a finished SOCKS5 client connection, which allows breaking down the server's
+/// metrics by user and destination.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ClientSummary {
+    /// The username the client authenticated as, or `None` if it didn't authenticate with a
+    /// username and password.
+    pub username: Option<String>,
+
+    /// The destination host the client requested, as a domain name or IP address, or `None` if
+    /// the client never got to make a request.
+    pub destination: Option<String>,
+
+    /// How long it took to connect to the destination, in microseconds, or `None` if the server
+    /// never connected to it.
+    pub connect_latency_micros: Option<u64>,
+
+    /// How long the client was connected for, in microseconds.
+    pub duration_micros: u64,
+
+    /// The total amount of bytes sent by the client to the destination.
+    pub bytes_sent: u64,
+
+    /// The total amount of bytes received by the client from the destination.
+    pub bytes_received: u64,
+
+    /// Whether the connection failed, either because the destination couldn't be reached or
+    /// because of an IO error.
+    pub failed: bool,
+}
+
+impl ByteRead for ClientSummary {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(ClientSummary {
+            username: <Option<String> as ByteRead>::read(reader).await?,
+            destination: <Option<String> as ByteRead>::read(reader).await?,
+            connect_latency_micros: <Option<u64> as ByteRead>::read(reader).await?,
+            duration_micros: u64::read(reader).await?,
+            bytes_sent: u64::read(reader).await?,
+            bytes_received: u64::read(reader).await?,
+            failed: bool::read(reader).await?,
+        })
+    }
+}
+
+impl ByteWrite for ClientSummary {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (&self.username, &self.destination, self.connect_latency_micros)
+            .write(writer)
+            .await?;
+        (self.duration_micros, self.bytes_sent, self.bytes_received, self.failed)
+            .write(writer)
+            .await
+    }
+}
+
+impl fmt::Display for ClientSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.username {
+            Some(username) => write!(f, "user {username}")?,
+            None => write!(f, "no user")?,
+        }
+
+        match &self.destination {
+            Some(destination) => write!(f, ", destination {destination}")?,
+            None => write!(f, ", no destination")?,
+        }
+
+        if let Some(latency) = self.connect_latency_micros {
+            write!(f, ", connected in {}", FormatMicros(latency))?;
+        }
+
+        write!(f, ", lasted {}", FormatMicros(self.duration_micros))?;
+        if self.failed {
+            write!(f, ", failed")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats an amount of microseconds as milliseconds or seconds, with up to 3 decimals.
+pub struct FormatMicros(pub u64);
+
+impl fmt::Display for FormatMicros {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            micros if micros < 1_000_000 => write!(f, "{}ms", micros as f64 / 1000.0),
+            micros => write!(f, "{}s", micros as f64 / 1_000_000.0),
+        }
+    }
+}
+
 /// All the possible server events that can be reported.
 pub enum EventData {
     NewSocks5Socket(SocketAddr),
@@ -79,8 +250,253 @@ pub enum EventData {
     AuthMethodToggledByManager(u64, AuthMethod, bool),
     BufferSizeChangedByManager(u64, u32),
     SandstormRequestedShutdown(u64),
+    SandstormPermissionDenied(u64, SandstormCommandType),
     SandstormConnectionFinished(u64, Result<(), Error>),
     ShutdownSignalReceived,
+    LoginLockoutStarted(LockoutTarget, u32, u64),
+    LoginLockoutEnded(LockoutTarget),
+    LockoutsClearedByManager(u64, Option<LockoutTarget>, u32),
+    ConnectionRejectedByIpFilter(SocketAddr, SocketAddr),
+    IpFilterAddedByManager(u64, SocketAddr, IpFilterKind, IpNetwork),
+    IpFilterRemovedByManager(u64, SocketAddr, IpFilterKind, IpNetwork),
+    RestoringStateFromFile(String),
+    Socks5SocketRestoredFromState(SocketAddr),
+    SandstormSocketRestoredFromState(SocketAddr),
+    AuthMethodRestoredFromState(AuthMethod, bool),
+    BufferSizeRestoredFromState(u32),
+    IpFilterRestoredFromState(SocketAddr, IpFilterKind, IpNetwork),
+    SavingStateToFile(String),
+    StateSavedToFile(String, Result<(), Error>),
+    DrainStarted(u64, u32),
+    DrainProgress(u64),
+    DrainCompleted,
+    DrainTimedOut(u64),
+    DrainAborted(u64),
+    ListenersInheritedFromHandoff(String),
+    Socks5SocketInherited(SocketAddr),
+    SandstormSocketInherited(SocketAddr),
+    HandoffReceiveFailed(String, Error),
+    ListeningForHandoff(String),
+    FailedBindHandoffSocket(String, Error),
+    ListenersHandedOff(u32),
+    HandoffSendFailed(Error),
+    ListenersInheritedFromSystemd(u32),
+    SystemdActivationFailed(Error),
+    SystemdNotifyFailed(Error),
+    NewSandstormUnixSocket(String),
+    FailedBindSandstormUnixSocket(String, Error),
+    NewSandstormUnixConnectionAccepted(u64, String),
+    SandstormUnixSocketRestoredFromState(String),
+    SandstormUnixSocketInherited(String),
+    LogFilterChangedByManager(u64, LogSink, LogFilter),
+    NewMetricsSocket(SocketAddr),
+    FailedBindMetricsSocket(SocketAddr, Error),
+    ClientConnectionSummary(u64, ClientSummary),
+    MetricsRestoredFromFile(String, Result<i64, Error>),
+    MetricsSavedToFile(String, Result<(), Error>),
+    ClientClosedByManager(u64, u64),
+    EventStreamLagged(u64, Option<Metrics>),
+}
+
+impl EventData {
+    /// Gets the name identifying this event's type, in snake_case. These names are stable, so
+    /// they may be relied upon by structured logs and filters.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::NewSocks5Socket(..) => "new_socks5_socket",
+            Self::FailedBindSocks5Socket(..) => "failed_bind_socks5_socket",
+            Self::FailedBindAnySocketAborting => "failed_bind_any_socket_aborting",
+            Self::RemovedSocks5Socket(..) => "removed_socks5_socket",
+            Self::NewSandstormSocket(..) => "new_sandstorm_socket",
+            Self::FailedBindSandstormSocket(..) => "failed_bind_sandstorm_socket",
+            Self::RemovedSandstormSocket(..) => "removed_sandstorm_socket",
+            Self::LoadingUsersFromFile(..) => "loading_users_from_file",
+            Self::UsersLoadedFromFile(..) => "users_loaded_from_file",
+            Self::StartingUpWithSingleDefaultUser(..) => "starting_up_with_single_default_user",
+            Self::SavingUsersToFile(..) => "saving_users_to_file",
+            Self::UsersSavedToFile(..) => "users_saved_to_file",
+            Self::UserRegisteredByArgs(..) => "user_registered_by_args",
+            Self::UserReplacedByArgs(..) => "user_replaced_by_args",
+            Self::NewClientConnectionAccepted(..) => "new_client_connection_accepted",
+            Self::ClientConnectionAcceptFailed(..) => "client_connection_accept_failed",
+            Self::ClientRequestedUnsupportedVersion(..) => "client_requested_unsupported_version",
+            Self::ClientRequestedUnsupportedCommand(..) => "client_requested_unsupported_command",
+            Self::ClientRequestedUnsupportedAtyp(..) => "client_requested_unsupported_atyp",
+            Self::ClientSelectedAuthMethod(..) => "client_selected_auth_method",
+            Self::ClientNoAcceptableAuthMethod(..) => "client_no_acceptable_auth_method",
+            Self::ClientRequestedUnsupportedUserpassVersion(..) => "client_requested_unsupported_userpass_version",
+            Self::ClientAuthenticatedWithUserpass(..) => "client_authenticated_with_userpass",
+            Self::ClientSocksRequest(..) => "client_socks_request",
+            Self::ClientDnsLookup(..) => "client_dns_lookup",
+            Self::ClientAttemptingConnect(..) => "client_attempting_connect",
+            Self::ClientConnectionAttemptBindFailed(..) => "client_connection_attempt_bind_failed",
+            Self::ClientConnectionAttemptConnectFailed(..) => "client_connection_attempt_connect_failed",
+            Self::ClientFailedToConnectToDestination(..) => "client_failed_to_connect_to_destination",
+            Self::ClientConnectedToDestination(..) => "client_connected_to_destination",
+            Self::ClientBytesSent(..) => "client_bytes_sent",
+            Self::ClientBytesReceived(..) => "client_bytes_received",
+            Self::ClientSourceShutdown(..) => "client_source_shutdown",
+            Self::ClientDestinationShutdown(..) => "client_destination_shutdown",
+            Self::ClientConnectionFinished(..) => "client_connection_finished",
+            Self::NewSandstormConnectionAccepted(..) => "new_sandstorm_connection_accepted",
+            Self::SandstormConnectionAcceptFailed(..) => "sandstorm_connection_accept_failed",
+            Self::SandstormRequestedUnsupportedVersion(..) => "sandstorm_requested_unsupported_version",
+            Self::SandstormAuthenticatedAs(..) => "sandstorm_authenticated_as",
+            Self::NewSocksSocketRequestedByManager(..) => "new_socks_socket_requested_by_manager",
+            Self::RemoveSocksSocketRequestedByManager(..) => "remove_socks_socket_requested_by_manager",
+            Self::NewSandstormSocketRequestedByManager(..) => "new_sandstorm_socket_requested_by_manager",
+            Self::RemoveSandstormSocketRequestedByManager(..) => "remove_sandstorm_socket_requested_by_manager",
+            Self::UserRegisteredByManager(..) => "user_registered_by_manager",
+            Self::UserUpdatedByManager(..) => "user_updated_by_manager",
+            Self::UserDeletedByManager(..) => "user_deleted_by_manager",
+            Self::AuthMethodToggledByManager(..) => "auth_method_toggled_by_manager",
+            Self::BufferSizeChangedByManager(..) => "buffer_size_changed_by_manager",
+            Self::SandstormRequestedShutdown(..) => "sandstorm_requested_shutdown",
+            Self::SandstormPermissionDenied(..) => "sandstorm_permission_denied",
+            Self::SandstormConnectionFinished(..) => "sandstorm_connection_finished",
+            Self::ShutdownSignalReceived => "shutdown_signal_received",
+            Self::LoginLockoutStarted(..) => "login_lockout_started",
+            Self::LoginLockoutEnded(..) => "login_lockout_ended",
+            Self::LockoutsClearedByManager(..) => "lockouts_cleared_by_manager",
+            Self::ConnectionRejectedByIpFilter(..) => "connection_rejected_by_ip_filter",
+            Self::IpFilterAddedByManager(..) => "ip_filter_added_by_manager",
+            Self::IpFilterRemovedByManager(..) => "ip_filter_removed_by_manager",
+            Self::RestoringStateFromFile(..) => "restoring_state_from_file",
+            Self::Socks5SocketRestoredFromState(..) => "socks5_socket_restored_from_state",
+            Self::SandstormSocketRestoredFromState(..) => "sandstorm_socket_restored_from_state",
+            Self::AuthMethodRestoredFromState(..) => "auth_method_restored_from_state",
+            Self::BufferSizeRestoredFromState(..) => "buffer_size_restored_from_state",
+            Self::IpFilterRestoredFromState(..) => "ip_filter_restored_from_state",
+            Self::SavingStateToFile(..) => "saving_state_to_file",
+            Self::StateSavedToFile(..) => "state_saved_to_file",
+            Self::DrainStarted(..) => "drain_started",
+            Self::DrainProgress(..) => "drain_progress",
+            Self::DrainCompleted => "drain_completed",
+            Self::DrainTimedOut(..) => "drain_timed_out",
+            Self::DrainAborted(..) => "drain_aborted",
+            Self::ListenersInheritedFromHandoff(..) => "listeners_inherited_from_handoff",
+            Self::Socks5SocketInherited(..) => "socks5_socket_inherited",
+            Self::SandstormSocketInherited(..) => "sandstorm_socket_inherited",
+            Self::HandoffReceiveFailed(..) => "handoff_receive_failed",
+            Self::ListeningForHandoff(..) => "listening_for_handoff",
+            Self::FailedBindHandoffSocket(..) => "failed_bind_handoff_socket",
+            Self::ListenersHandedOff(..) => "listeners_handed_off",
+            Self::HandoffSendFailed(..) => "handoff_send_failed",
+            Self::ListenersInheritedFromSystemd(..) => "listeners_inherited_from_systemd",
+            Self::SystemdActivationFailed(..) => "systemd_activation_failed",
+            Self::SystemdNotifyFailed(..) => "systemd_notify_failed",
+            Self::NewSandstormUnixSocket(..) => "new_sandstorm_unix_socket",
+            Self::FailedBindSandstormUnixSocket(..) => "failed_bind_sandstorm_unix_socket",
+            Self::NewSandstormUnixConnectionAccepted(..) => "new_sandstorm_unix_connection_accepted",
+            Self::SandstormUnixSocketRestoredFromState(..) => "sandstorm_unix_socket_restored_from_state",
+            Self::SandstormUnixSocketInherited(..) => "sandstorm_unix_socket_inherited",
+            Self::LogFilterChangedByManager(..) => "log_filter_changed_by_manager",
+            Self::NewMetricsSocket(..) => "new_metrics_socket",
+            Self::FailedBindMetricsSocket(..) => "failed_bind_metrics_socket",
+            Self::ClientConnectionSummary(..) => "client_connection_summary",
+            Self::MetricsRestoredFromFile(..) => "metrics_restored_from_file",
+            Self::MetricsSavedToFile(..) => "metrics_saved_to_file",
+            Self::ClientClosedByManager(..) => "client_closed_by_manager",
+            Self::EventStreamLagged(..) => "event_stream_lagged",
+        }
+    }
+    /// Gets the ID of the SOCKS5 client this event is about, or `None` if it isn't about a client.
+    pub fn client_id(&self) -> Option<u64> {
+        match self {
+            Self::NewClientConnectionAccepted(client_id, _)
+            | Self::ClientRequestedUnsupportedVersion(client_id, _)
+            | Self::ClientRequestedUnsupportedCommand(client_id, _)
+            | Self::ClientRequestedUnsupportedAtyp(client_id, _)
+            | Self::ClientSelectedAuthMethod(client_id, _)
+            | Self::ClientNoAcceptableAuthMethod(client_id)
+            | Self::ClientRequestedUnsupportedUserpassVersion(client_id, _)
+            | Self::ClientAuthenticatedWithUserpass(client_id, _, _)
+            | Self::ClientSocksRequest(client_id, _)
+            | Self::ClientDnsLookup(client_id, _)
+            | Self::ClientAttemptingConnect(client_id, _)
+            | Self::ClientConnectionAttemptBindFailed(client_id, _)
+            | Self::ClientConnectionAttemptConnectFailed(client_id, _)
+            | Self::ClientFailedToConnectToDestination(client_id)
+            | Self::ClientConnectedToDestination(client_id, _)
+            | Self::ClientBytesSent(client_id, _)
+            | Self::ClientBytesReceived(client_id, _)
+            | Self::ClientSourceShutdown(client_id)
+            | Self::ClientDestinationShutdown(client_id)
+            | Self::ClientConnectionFinished(client_id, _, _, _)
+            | Self::ClientConnectionSummary(client_id, _)
+            | Self::ClientClosedByManager(client_id, _) => Some(*client_id),
+            _ => None,
+        }
+    }
+
+    /// Gets the severity of this event. Failures are reported as warnings, or as errors if they
+    /// affect the whole server, while per-connection byte counts and shutdowns are debug events.
+    pub fn severity(&self) -> EventSeverity {
+        match self {
+            Self::FailedBindAnySocketAborting
+            | Self::UsersSavedToFile(_, Err(_))
+            | Self::StateSavedToFile(_, Err(_))
+            | Self::MetricsSavedToFile(_, Err(_))
+            | Self::HandoffSendFailed(..) => EventSeverity::Error,
+            Self::FailedBindSocks5Socket(..)
+            | Self::FailedBindSandstormSocket(..)
+            | Self::UsersLoadedFromFile(_, Err(_))
+            | Self::ClientConnectionAcceptFailed(..)
+            | Self::ClientRequestedUnsupportedVersion(..)
+            | Self::ClientRequestedUnsupportedCommand(..)
+            | Self::ClientRequestedUnsupportedAtyp(..)
+            | Self::ClientNoAcceptableAuthMethod(..)
+            | Self::ClientRequestedUnsupportedUserpassVersion(..)
+            | Self::ClientAuthenticatedWithUserpass(_, _, false)
+            | Self::ClientConnectionAttemptBindFailed(..)
+            | Self::ClientConnectionAttemptConnectFailed(..)
+            | Self::ClientFailedToConnectToDestination(..)
+            | Self::ClientConnectionFinished(_, _, _, Err(_))
+            | Self::SandstormConnectionAcceptFailed(..)
+            | Self::SandstormRequestedUnsupportedVersion(..)
+            | Self::SandstormAuthenticatedAs(_, _, false)
+            | Self::SandstormPermissionDenied(..)
+            | Self::SandstormConnectionFinished(_, Err(_))
+            | Self::LoginLockoutStarted(..)
+            | Self::ConnectionRejectedByIpFilter(..)
+            | Self::DrainTimedOut(..)
+            | Self::HandoffReceiveFailed(..)
+            | Self::FailedBindHandoffSocket(..)
+            | Self::SystemdActivationFailed(..)
+            | Self::SystemdNotifyFailed(..)
+            | Self::FailedBindSandstormUnixSocket(..)
+            | Self::FailedBindMetricsSocket(..)
+            | Self::MetricsRestoredFromFile(_, Err(_))
+            | Self::EventStreamLagged(..) => EventSeverity::Warning,
+            Self::NewSocksSocketRequestedByManager(..)
+            | Self::RemoveSocksSocketRequestedByManager(..)
+            | Self::NewSandstormSocketRequestedByManager(..)
+            | Self::RemoveSandstormSocketRequestedByManager(..)
+            | Self::UserRegisteredByManager(..)
+            | Self::UserUpdatedByManager(..)
+            | Self::UserDeletedByManager(..)
+            | Self::AuthMethodToggledByManager(..)
+            | Self::BufferSizeChangedByManager(..)
+            | Self::SandstormRequestedShutdown(..)
+            | Self::ShutdownSignalReceived
+            | Self::LockoutsClearedByManager(..)
+            | Self::LogFilterChangedByManager(..)
+            | Self::IpFilterAddedByManager(..)
+            | Self::IpFilterRemovedByManager(..)
+            | Self::ClientClosedByManager(..)
+            | Self::DrainStarted(..)
+            | Self::DrainCompleted
+            | Self::DrainAborted(..)
+            | Self::ListenersHandedOff(..) => EventSeverity::Notice,
+            Self::ClientBytesSent(..)
+            | Self::ClientBytesReceived(..)
+            | Self::ClientSourceShutdown(..)
+            | Self::ClientDestinationShutdown(..)
+            | Self::ClientConnectionSummary(..)
+            | Self::MetricsSavedToFile(_, Ok(())) => EventSeverity::Debug,
+            _ => EventSeverity::Info,
+        }
+    }
 }
 
 impl fmt::Display for EventData {
@@ -151,9 +567,59 @@ impl fmt::Display for EventData {
             Self::AuthMethodToggledByManager(manager_id, auth_method, true) => write!(f, "Manager {manager_id} enabled authentication method {auth_method}"),
             Self::BufferSizeChangedByManager(manager_id, buffer_size) => write!(f, "Manager {manager_id} set client buffer size to {buffer_size}"),
             Self::SandstormRequestedShutdown(manager_id) => write!(f, "Manager {manager_id} requested the server shuts down"),
+            Self::SandstormPermissionDenied(manager_id, command) => write!(f, "Manager {manager_id} was denied permission to {command}"),
             Self::SandstormConnectionFinished(manager_id, Ok(())) => write!(f, "Manager {manager_id} finished"),
             Self::SandstormConnectionFinished(manager_id, Err(io_error)) => write!(f, "Manager {manager_id} closed with IO error: {io_error}"),
             Self::ShutdownSignalReceived => write!(f, "Shutdown signal received"),
+            Self::LoginLockoutStarted(target, failures, seconds) => write!(f, "Locked out {target} for {seconds}s after {failures} failed login attempts"),
+            Self::LoginLockoutEnded(target) => write!(f, "Login lockout on {target} has expired"),
+            Self::LockoutsClearedByManager(manager_id, Some(target), _) => write!(f, "Manager {manager_id} cleared the login lockout on {target}"),
+            Self::LockoutsClearedByManager(manager_id, None, count) => write!(f, "Manager {manager_id} cleared all {count} login lockouts"),
+            Self::ConnectionRejectedByIpFilter(listener_address, address) => write!(f, "Rejected incoming connection from {address} at {listener_address} due to IP filters"),
+            Self::IpFilterAddedByManager(manager_id, listener_address, kind, network) => write!(f, "Manager {manager_id} added IP filter {kind} {network} at {listener_address}"),
+            Self::IpFilterRemovedByManager(manager_id, listener_address, kind, network) => write!(f, "Manager {manager_id} removed IP filter {kind} {network} at {listener_address}"),
+            Self::RestoringStateFromFile(filename) => write!(f, "Restoring runtime state from file {filename}"),
+            Self::Socks5SocketRestoredFromState(socket_address) => write!(f, "Restored socks5 socket at {socket_address} from state"),
+            Self::SandstormSocketRestoredFromState(socket_address) => write!(f, "Restored Sandstorm socket at {socket_address} from state"),
+            Self::AuthMethodRestoredFromState(auth_method, true) => write!(f, "Restored authentication method {auth_method} as enabled from state"),
+            Self::AuthMethodRestoredFromState(auth_method, false) => write!(f, "Restored authentication method {auth_method} as disabled from state"),
+            Self::BufferSizeRestoredFromState(buffer_size) => write!(f, "Restored client buffer size {buffer_size} from state"),
+            Self::IpFilterRestoredFromState(listener_address, kind, network) => write!(f, "Restored IP filter {kind} {network} at {listener_address} from state"),
+            Self::SavingStateToFile(filename) => write!(f, "Saving runtime state to file {filename}"),
+            Self::StateSavedToFile(filename, Ok(())) => write!(f, "Successfully saved runtime state to file {filename}"),
+            Self::StateSavedToFile(filename, Err(io_error)) => write!(f, "Failed to save runtime state to file {filename}: {io_error}"),
+            Self::DrainStarted(client_count, seconds) => write!(f, "No longer accepting socks5 clients, waiting up to {seconds}s for {client_count} clients to finish"),
+            Self::DrainProgress(client_count) => write!(f, "Waiting for {client_count} clients to finish"),
+            Self::DrainCompleted => write!(f, "All clients finished"),
+            Self::DrainTimedOut(client_count) => write!(f, "Drain timed out, closing {client_count} remaining clients"),
+            Self::DrainAborted(client_count) => write!(f, "Drain aborted, closing {client_count} remaining clients"),
+            Self::ListenersInheritedFromHandoff(path) => write!(f, "Inherited listening sockets from the server at handoff socket {path}"),
+            Self::Socks5SocketInherited(socket_address) => write!(f, "Inherited socks5 listening socket at {socket_address}"),
+            Self::SandstormSocketInherited(socket_address) => write!(f, "Inherited Sandstorm listening socket at {socket_address}"),
+            Self::HandoffReceiveFailed(path, io_error) => write!(f, "Failed to inherit listening sockets from handoff socket {path}, binding new ones instead: {io_error}"),
+            Self::ListeningForHandoff(path) => write!(f, "Listening for handoff requests at {path}"),
+            Self::FailedBindHandoffSocket(path, io_error) => write!(f, "Failed to bind handoff socket at {path}: {io_error}"),
+            Self::ListenersHandedOff(socket_count) => write!(f, "Handed off {socket_count} listening sockets to a new process"),
+            Self::HandoffSendFailed(io_error) => write!(f, "Failed to hand off listening sockets: {io_error}"),
+            Self::ListenersInheritedFromSystemd(socket_count) => write!(f, "Received {socket_count} listening sockets from systemd"),
+            Self::SystemdActivationFailed(io_error) => write!(f, "Failed to take listening sockets from systemd, binding new ones instead: {io_error}"),
+            Self::SystemdNotifyFailed(io_error) => write!(f, "Failed to notify systemd: {io_error}"),
+            Self::NewSandstormUnixSocket(path) => write!(f, "Listening for Sandstorm connections at Unix socket {path}"),
+            Self::FailedBindSandstormUnixSocket(path, io_error) => write!(f, "Failed to set up Sandstorm Unix socket at {path}: {io_error}"),
+            Self::NewSandstormUnixConnectionAccepted(manager_id, path) => write!(f, "New management connection through Unix socket {path} assigned ID {manager_id}"),
+            Self::SandstormUnixSocketRestoredFromState(path) => write!(f, "Restored Sandstorm Unix socket at {path} from state"),
+            Self::SandstormUnixSocketInherited(path) => write!(f, "Inherited Sandstorm listening Unix socket at {path}"),
+            Self::LogFilterChangedByManager(manager_id, sink, filter) => write!(f, "Manager {manager_id} set the {sink} log filter to {filter}"),
+            Self::NewMetricsSocket(socket_address) => write!(f, "Serving metrics over HTTP at {socket_address}"),
+            Self::FailedBindMetricsSocket(socket_address, io_error) => write!(f, "Failed to set up metrics socket at {socket_address}: {io_error}"),
+            Self::ClientConnectionSummary(client_id, summary) => write!(f, "Client {client_id} summary: {summary}"),
+            Self::MetricsRestoredFromFile(filename, Ok(counter_epoch)) => write!(f, "Restored metrics from file {filename}, counted since timestamp {counter_epoch}"),
+            Self::MetricsRestoredFromFile(filename, Err(io_error)) => write!(f, "Failed to restore metrics from file {filename}, they won't be saved: {io_error}"),
+            Self::MetricsSavedToFile(filename, Ok(())) => write!(f, "Successfully saved metrics to file {filename}"),
+            Self::MetricsSavedToFile(filename, Err(io_error)) => write!(f, "Failed to save metrics to file {filename}: {io_error}"),
+            Self::ClientClosedByManager(client_id, manager_id) => write!(f, "Manager {manager_id} closed client {client_id}"),
+            Self::EventStreamLagged(count, None) => write!(f, "Event stream fell behind, {count} events were dropped"),
+            Self::EventStreamLagged(count, Some(_)) => write!(f, "Event stream fell behind, {count} events were dropped and metrics were resynchronized"),
         }
     }
 }
@@ -325,6 +791,112 @@ impl ByteRead for EventData {
                 <Result<(), Error> as ByteRead>::read(reader).await?,
             )),
             0x33 => Ok(Self::ShutdownSignalReceived),
+            0x34 => Ok(Self::SandstormPermissionDenied(
+                u64::read(reader).await?,
+                SandstormCommandType::read(reader).await?,
+            )),
+            0x35 => Ok(Self::LoginLockoutStarted(
+                LockoutTarget::read(reader).await?,
+                u32::read(reader).await?,
+                u64::read(reader).await?,
+            )),
+            0x36 => Ok(Self::LoginLockoutEnded(LockoutTarget::read(reader).await?)),
+            0x37 => Ok(Self::LockoutsClearedByManager(
+                u64::read(reader).await?,
+                <Option<LockoutTarget> as ByteRead>::read(reader).await?,
+                u32::read(reader).await?,
+            )),
+            0x38 => Ok(Self::ConnectionRejectedByIpFilter(
+                SocketAddr::read(reader).await?,
+                SocketAddr::read(reader).await?,
+            )),
+            0x39 => Ok(Self::IpFilterAddedByManager(
+                u64::read(reader).await?,
+                SocketAddr::read(reader).await?,
+                IpFilterKind::read(reader).await?,
+                IpNetwork::read(reader).await?,
+            )),
+            0x3A => Ok(Self::IpFilterRemovedByManager(
+                u64::read(reader).await?,
+                SocketAddr::read(reader).await?,
+                IpFilterKind::read(reader).await?,
+                IpNetwork::read(reader).await?,
+            )),
+            0x3B => Ok(Self::RestoringStateFromFile(String::read(reader).await?)),
+            0x3C => Ok(Self::Socks5SocketRestoredFromState(SocketAddr::read(reader).await?)),
+            0x3D => Ok(Self::SandstormSocketRestoredFromState(SocketAddr::read(reader).await?)),
+            0x3E => Ok(Self::AuthMethodRestoredFromState(
+                AuthMethod::read(reader).await?,
+                bool::read(reader).await?,
+            )),
+            0x3F => Ok(Self::BufferSizeRestoredFromState(u32::read(reader).await?)),
+            0x40 => Ok(Self::IpFilterRestoredFromState(
+                SocketAddr::read(reader).await?,
+                IpFilterKind::read(reader).await?,
+                IpNetwork::read(reader).await?,
+            )),
+            0x41 => Ok(Self::SavingStateToFile(String::read(reader).await?)),
+            0x42 => Ok(Self::StateSavedToFile(
+                String::read(reader).await?,
+                <Result<(), Error> as ByteRead>::read(reader).await?,
+            )),
+            0x43 => Ok(Self::DrainStarted(u64::read(reader).await?, u32::read(reader).await?)),
+            0x44 => Ok(Self::DrainProgress(u64::read(reader).await?)),
+            0x45 => Ok(Self::DrainCompleted),
+            0x46 => Ok(Self::DrainTimedOut(u64::read(reader).await?)),
+            0x47 => Ok(Self::DrainAborted(u64::read(reader).await?)),
+            0x48 => Ok(Self::ListenersInheritedFromHandoff(String::read(reader).await?)),
+            0x49 => Ok(Self::Socks5SocketInherited(SocketAddr::read(reader).await?)),
+            0x4A => Ok(Self::SandstormSocketInherited(SocketAddr::read(reader).await?)),
+            0x4B => Ok(Self::HandoffReceiveFailed(String::read(reader).await?, Error::read(reader).await?)),
+            0x4C => Ok(Self::ListeningForHandoff(String::read(reader).await?)),
+            0x4D => Ok(Self::FailedBindHandoffSocket(
+                String::read(reader).await?,
+                Error::read(reader).await?,
+            )),
+            0x4E => Ok(Self::ListenersHandedOff(u32::read(reader).await?)),
+            0x4F => Ok(Self::HandoffSendFailed(Error::read(reader).await?)),
+            0x50 => Ok(Self::ListenersInheritedFromSystemd(u32::read(reader).await?)),
+            0x51 => Ok(Self::SystemdActivationFailed(Error::read(reader).await?)),
+            0x52 => Ok(Self::SystemdNotifyFailed(Error::read(reader).await?)),
+            0x53 => Ok(Self::NewSandstormUnixSocket(String::read(reader).await?)),
+            0x54 => Ok(Self::FailedBindSandstormUnixSocket(
+                String::read(reader).await?,
+                Error::read(reader).await?,
+            )),
+            0x55 => Ok(Self::NewSandstormUnixConnectionAccepted(
+                u64::read(reader).await?,
+                String::read(reader).await?,
+            )),
+            0x56 => Ok(Self::SandstormUnixSocketRestoredFromState(String::read(reader).await?)),
+            0x57 => Ok(Self::SandstormUnixSocketInherited(String::read(reader).await?)),
+            0x58 => Ok(Self::LogFilterChangedByManager(
+                u64::read(reader).await?,
+                LogSink::read(reader).await?,
+                LogFilter::read(reader).await?,
+            )),
+            0x59 => Ok(Self::NewMetricsSocket(SocketAddr::read(reader).await?)),
+            0x5A => Ok(Self::FailedBindMetricsSocket(
+                SocketAddr::read(reader).await?,
+                Error::read(reader).await?,
+            )),
+            0x5B => Ok(Self::ClientConnectionSummary(
+                u64::read(reader).await?,
+                ClientSummary::read(reader).await?,
+            )),
+            0x5C => Ok(Self::MetricsRestoredFromFile(
+                String::read(reader).await?,
+                <Result<i64, Error> as ByteRead>::read(reader).await?,
+            )),
+            0x5D => Ok(Self::MetricsSavedToFile(
+                String::read(reader).await?,
+                <Result<(), Error> as ByteRead>::read(reader).await?,
+            )),
+            0x5E => Ok(Self::ClientClosedByManager(u64::read(reader).await?, u64::read(reader).await?)),
+            0x5F => Ok(Self::EventStreamLagged(
+                u64::read(reader).await?,
+                <Option<Metrics> as ByteRead>::read(reader).await?,
+            )),
             _ => Err(Error::new(ErrorKind::InvalidData, "Invalid EventData type byte")),
         }
     }
@@ -402,6 +974,56 @@ impl ByteWrite for EventData {
             Self::SandstormRequestedShutdown(manager_id) => (0x31u8, manager_id).write(writer).await,
             Self::SandstormConnectionFinished(manager_id, result) => (0x32u8, manager_id, result).write(writer).await,
             Self::ShutdownSignalReceived => 0x33u8.write(writer).await,
+            Self::SandstormPermissionDenied(manager_id, command) => (0x34u8, manager_id, command).write(writer).await,
+            Self::LoginLockoutStarted(target, failures, seconds) => (0x35u8, target, failures, seconds).write(writer).await,
+            Self::LoginLockoutEnded(target) => (0x36u8, target).write(writer).await,
+            Self::LockoutsClearedByManager(manager_id, target, count) => (0x37u8, manager_id, target, count).write(writer).await,
+            Self::ConnectionRejectedByIpFilter(listener_address, address) => (0x38u8, listener_address, address).write(writer).await,
+            Self::IpFilterAddedByManager(manager_id, listener_address, kind, network) => {
+                (0x39u8, manager_id, listener_address, kind, network).write(writer).await
+            }
+            Self::IpFilterRemovedByManager(manager_id, listener_address, kind, network) => {
+                (0x3Au8, manager_id, listener_address, kind, network).write(writer).await
+            }
+            Self::RestoringStateFromFile(filename) => (0x3Bu8, filename).write(writer).await,
+            Self::Socks5SocketRestoredFromState(socket_address) => (0x3Cu8, socket_address).write(writer).await,
+            Self::SandstormSocketRestoredFromState(socket_address) => (0x3Du8, socket_address).write(writer).await,
+            Self::AuthMethodRestoredFromState(auth_method, enabled) => (0x3Eu8, auth_method, enabled).write(writer).await,
+            Self::BufferSizeRestoredFromState(buffer_size) => (0x3Fu8, buffer_size).write(writer).await,
+            Self::IpFilterRestoredFromState(listener_address, kind, network) => {
+                (0x40u8, listener_address, kind, network).write(writer).await
+            }
+            Self::SavingStateToFile(filename) => (0x41u8, filename).write(writer).await,
+            Self::StateSavedToFile(filename, result) => (0x42u8, filename, result).write(writer).await,
+            Self::DrainStarted(client_count, seconds) => (0x43u8, client_count, seconds).write(writer).await,
+            Self::DrainProgress(client_count) => (0x44u8, client_count).write(writer).await,
+            Self::DrainCompleted => 0x45u8.write(writer).await,
+            Self::DrainTimedOut(client_count) => (0x46u8, client_count).write(writer).await,
+            Self::DrainAborted(client_count) => (0x47u8, client_count).write(writer).await,
+            Self::ListenersInheritedFromHandoff(path) => (0x48u8, path).write(writer).await,
+            Self::Socks5SocketInherited(socket_address) => (0x49u8, socket_address).write(writer).await,
+            Self::SandstormSocketInherited(socket_address) => (0x4Au8, socket_address).write(writer).await,
+            Self::HandoffReceiveFailed(path, io_error) => (0x4Bu8, path, io_error).write(writer).await,
+            Self::ListeningForHandoff(path) => (0x4Cu8, path).write(writer).await,
+            Self::FailedBindHandoffSocket(path, io_error) => (0x4Du8, path, io_error).write(writer).await,
+            Self::ListenersHandedOff(socket_count) => (0x4Eu8, socket_count).write(writer).await,
+            Self::HandoffSendFailed(io_error) => (0x4Fu8, io_error).write(writer).await,
+            Self::ListenersInheritedFromSystemd(socket_count) => (0x50u8, socket_count).write(writer).await,
+            Self::SystemdActivationFailed(io_error) => (0x51u8, io_error).write(writer).await,
+            Self::SystemdNotifyFailed(io_error) => (0x52u8, io_error).write(writer).await,
+            Self::NewSandstormUnixSocket(path) => (0x53u8, path).write(writer).await,
+            Self::FailedBindSandstormUnixSocket(path, io_error) => (0x54u8, path, io_error).write(writer).await,
+            Self::NewSandstormUnixConnectionAccepted(manager_id, path) => (0x55u8, manager_id, path).write(writer).await,
+            Self::SandstormUnixSocketRestoredFromState(path) => (0x56u8, path).write(writer).await,
+            Self::SandstormUnixSocketInherited(path) => (0x57u8, path).write(writer).await,
+            Self::LogFilterChangedByManager(manager_id, sink, filter) => (0x58u8, manager_id, sink, filter).write(writer).await,
+            Self::NewMetricsSocket(socket_address) => (0x59u8, socket_address).write(writer).await,
+            Self::FailedBindMetricsSocket(socket_address, io_error) => (0x5Au8, socket_address, io_error).write(writer).await,
+            Self::ClientConnectionSummary(client_id, summary) => (0x5Bu8, client_id, summary).write(writer).await,
+            Self::MetricsRestoredFromFile(filename, result) => (0x5Cu8, filename, result).write(writer).await,
+            Self::MetricsSavedToFile(filename, result) => (0x5Du8, filename, result).write(writer).await,
+            Self::ClientClosedByManager(client_id, manager_id) => (0x5Eu8, client_id, manager_id).write(writer).await,
+            Self::EventStreamLagged(count, metrics) => (0x5Fu8, count, metrics).write(writer).await,
         }
     }
 }
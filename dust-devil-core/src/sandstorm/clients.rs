@@ -0,0 +1,170 @@
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+    net::{IpAddr, SocketAddr},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::serialize::{ByteRead, ByteWrite, SmallReadString, SmallWriteString};
+
+use super::SandstormCommandType;
+
+/// A socks5 client currently connected to the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveClient {
+    /// The client's ID, as used in the events about it.
+    pub client_id: u64,
+
+    /// The client's source address.
+    pub address: SocketAddr,
+
+    /// The username the client authenticated as, or `None` if it hasn't authenticated with a
+    /// username and password.
+    pub username: Option<String>,
+
+    /// The destination the client requested, as a host and port, or `None` if it hasn't sent its
+    /// request yet.
+    pub destination: Option<String>,
+
+    /// The time at which the client connected, as a unix timestamp in seconds.
+    pub connected_at: i64,
+
+    /// The amount of bytes sent by the client to its destination so far.
+    pub bytes_sent: u64,
+
+    /// The amount of bytes received by the client from its destination so far.
+    pub bytes_received: u64,
+}
+
+impl ByteRead for ActiveClient {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(ActiveClient {
+            client_id: u64::read(reader).await?,
+            address: SocketAddr::read(reader).await?,
+            username: <Option<String> as ByteRead>::read(reader).await?,
+            destination: <Option<String> as ByteRead>::read(reader).await?,
+            connected_at: i64::read(reader).await?,
+            bytes_sent: u64::read(reader).await?,
+            bytes_received: u64::read(reader).await?,
+        })
+    }
+}
+
+impl ByteWrite for ActiveClient {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (self.client_id, self.address, &self.username, &self.destination)
+            .write(writer)
+            .await?;
+        (self.connected_at, self.bytes_sent, self.bytes_received).write(writer).await
+    }
+}
+
+/// A Sandstorm list clients request.
+pub struct ListClientsRequest;
+
+/// A Sandstorm list clients response.
+pub struct ListClientsResponse(
+    /// The socks5 clients currently connected to the server, ordered by client ID.
+    pub Vec<ActiveClient>,
+);
+
+impl ByteRead for ListClientsRequest {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(_reader: &mut R) -> Result<Self, Error> {
+        Ok(Self)
+    }
+}
+
+impl ByteWrite for ListClientsRequest {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        SandstormCommandType::ListClients.write(writer).await
+    }
+}
+
+impl ByteRead for ListClientsResponse {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(<Vec<ActiveClient> as ByteRead>::read(reader).await?))
+    }
+}
+
+impl ByteWrite for ListClientsResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::ListClients, self.0.as_slice()).write(writer).await
+    }
+}
+
+/// The socks5 clients to close: a single client by its ID, all the clients authenticated as a
+/// username, or all the clients connecting from a source IP address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CloseClientsTarget {
+    Client(u64),
+    Username(String),
+    Address(IpAddr),
+}
+
+impl fmt::Display for CloseClientsTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Client(client_id) => write!(f, "client {client_id}"),
+            Self::Username(username) => write!(f, "user {username}"),
+            Self::Address(address) => write!(f, "address {address}"),
+        }
+    }
+}
+
+impl ByteWrite for CloseClientsTarget {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        match self {
+            Self::Client(client_id) => (0x00u8, client_id).write(writer).await,
+            Self::Username(username) => (0x01u8, SmallWriteString(username)).write(writer).await,
+            Self::Address(address) => (0x02u8, address).write(writer).await,
+        }
+    }
+}
+
+impl ByteRead for CloseClientsTarget {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        match u8::read(reader).await? {
+            0x00 => Ok(Self::Client(u64::read(reader).await?)),
+            0x01 => Ok(Self::Username(SmallReadString::read(reader).await?.0)),
+            0x02 => Ok(Self::Address(IpAddr::read(reader).await?)),
+            _ => Err(Error::new(ErrorKind::InvalidData, "Invalid CloseClientsTarget type byte")),
+        }
+    }
+}
+
+/// A Sandstorm close clients request.
+pub struct CloseClientsRequest(
+    /// The clients to close.
+    pub CloseClientsTarget,
+);
+
+/// A Sandstorm close clients response.
+pub struct CloseClientsResponse(
+    /// The amount of clients that were closed.
+    pub u32,
+);
+
+impl ByteRead for CloseClientsRequest {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(CloseClientsTarget::read(reader).await?))
+    }
+}
+
+impl ByteWrite for CloseClientsRequest {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::CloseClients, &self.0).write(writer).await
+    }
+}
+
+impl ByteRead for CloseClientsResponse {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(u32::read(reader).await?))
+    }
+}
+
+impl ByteWrite for CloseClientsResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::CloseClients, self.0).write(writer).await
+    }
+}
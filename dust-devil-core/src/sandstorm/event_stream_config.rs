@@ -2,7 +2,10 @@ use std::io::{Error, ErrorKind};
 
 use tokio::io::{AsyncRead, AsyncWrite};
 
-use crate::serialize::{ByteRead, ByteWrite};
+use crate::{
+    log_filter::EventStreamFilter,
+    serialize::{ByteRead, ByteWrite},
+};
 
 use super::SandstormCommandType;
 
@@ -12,7 +15,8 @@ pub struct Metrics {
     /// The amount of currently connected client connections.
     pub current_client_connections: u32,
 
-    /// The total amount of incoming client connections received by the server since startup.
+    /// The total amount of incoming client connections received by the server since the counter
+    /// epoch.
     pub historic_client_connections: u64,
 
     /// The total amount of bytes sent by clients to remote destinations.
@@ -24,8 +28,17 @@ pub struct Metrics {
     /// The amount of currently connected Sandstorm connections.
     pub current_sandstorm_connections: u32,
 
-    /// The total amount of incoming Sandstorm connections received by the server since startup.
+    /// The total amount of incoming Sandstorm connections received by the server since the
+    /// counter epoch.
     pub historic_sandstorm_connections: u64,
+
+    /// The time at which the server started, as seconds since the UNIX epoch.
+    pub start_time: i64,
+
+    /// The time since which the historic counters have been counting, as seconds since the UNIX
+    /// epoch. This equals `start_time` unless the server restored its metrics from a previous run,
+    /// so a change in this value indicates the counters were reset.
+    pub counter_epoch: i64,
 }
 
 impl ByteRead for Metrics {
@@ -37,6 +50,8 @@ impl ByteRead for Metrics {
             client_bytes_received: u64::read(reader).await?,
             current_sandstorm_connections: u32::read(reader).await?,
             historic_sandstorm_connections: u64::read(reader).await?,
+            start_time: i64::read(reader).await?,
+            counter_epoch: i64::read(reader).await?,
         })
     }
 }
@@ -48,7 +63,42 @@ impl ByteWrite for Metrics {
         self.client_bytes_sent.write(writer).await?;
         self.client_bytes_received.write(writer).await?;
         self.current_sandstorm_connections.write(writer).await?;
-        self.historic_sandstorm_connections.write(writer).await
+        self.historic_sandstorm_connections.write(writer).await?;
+        self.start_time.write(writer).await?;
+        self.counter_epoch.write(writer).await
+    }
+}
+
+/// A value in the format of version 1 of the protocol, for the values whose format changed since.
+/// Version 1's [`Metrics`] lack the `start_time` and `counter_epoch` fields, which are read as 0,
+/// its [`ShutdownRequest`](super::ShutdownRequest) lacks the drain timeout, which is read as
+/// `None`, and its socks5 socket requests and responses lack the sockets'
+/// [`ListenerProfile`](crate::listener_profile::ListenerProfile)s, which are read as the default.
+pub struct Version1<T>(pub T);
+
+impl ByteRead for Version1<Metrics> {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(Metrics {
+            current_client_connections: u32::read(reader).await?,
+            historic_client_connections: u64::read(reader).await?,
+            client_bytes_sent: u64::read(reader).await?,
+            client_bytes_received: u64::read(reader).await?,
+            current_sandstorm_connections: u32::read(reader).await?,
+            historic_sandstorm_connections: u64::read(reader).await?,
+            start_time: 0,
+            counter_epoch: 0,
+        }))
+    }
+}
+
+impl ByteWrite for Version1<&Metrics> {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        self.0.current_client_connections.write(writer).await?;
+        self.0.historic_client_connections.write(writer).await?;
+        self.0.client_bytes_sent.write(writer).await?;
+        self.0.client_bytes_received.write(writer).await?;
+        self.0.current_sandstorm_connections.write(writer).await?;
+        self.0.historic_sandstorm_connections.write(writer).await
     }
 }
 
@@ -86,12 +136,44 @@ impl ByteWrite for CurrentMetricsResponse {
     }
 }
 
+impl ByteRead for Version1<CurrentMetricsResponse> {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let metrics = <Option<Version1<Metrics>> as ByteRead>::read(reader).await?;
+        Ok(Self(CurrentMetricsResponse(metrics.map(|m| m.0))))
+    }
+}
+
+impl ByteWrite for Version1<CurrentMetricsResponse> {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::RequestCurrentMetrics, self.0 .0.as_ref().map(Version1))
+            .write(writer)
+            .await
+    }
+}
+
 /// A Sandstorm event stream config request, used to enable or disable event streaming.
 pub struct EventStreamConfigRequest(
     /// The desired status for the event stream (`true` = enabled, `false` = disabled).
     pub bool,
+    /// The filter to apply to the streamed events, or `None` to stream all events. Ignored if
+    /// disabling the event stream.
+    pub Option<EventStreamFilter>,
+    /// Which of the recent events, if any, the server should replay before streaming new ones.
+    /// Ignored if disabling the event stream or if it is already enabled.
+    pub Option<EventReplay>,
 );
 
+/// Specifies which of the server's recently logged events to replay when enabling the event
+/// stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventReplay {
+    /// Replay up to this many of the most recent events.
+    Last(u16),
+
+    /// Replay the events that ocurred at or after this time, as seconds since the UNIX epoch.
+    Since(i64),
+}
+
 /// A Sandstorm event stream config response.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EventStreamConfigResponse {
@@ -105,17 +187,59 @@ pub enum EventStreamConfigResponse {
 
     /// Indicates that the event stream was already enabled.
     WasAlreadyEnabled,
+
+    /// Indicates that the event stream is now enabled, with a metrics snapshot like
+    /// [`EventStreamConfigResponse::Enabled`], and that the next this many events in the stream
+    /// are replayed past events (already accounted for in the metrics snapshot).
+    EnabledWithHistory(Metrics, u16),
+}
+
+impl ByteRead for EventReplay {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        match u8::read(reader).await? {
+            0x00 => Ok(Self::Last(u16::read(reader).await?)),
+            0x01 => Ok(Self::Since(i64::read(reader).await?)),
+            _ => Err(Error::new(ErrorKind::InvalidData, "Invalid EventReplay type byte")),
+        }
+    }
+}
+
+impl ByteWrite for EventReplay {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        match self {
+            Self::Last(count) => (0x00u8, count).write(writer).await,
+            Self::Since(timestamp) => (0x01u8, timestamp).write(writer).await,
+        }
+    }
 }
 
 impl ByteRead for EventStreamConfigRequest {
     async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
-        Ok(Self(bool::read(reader).await?))
+        match u8::read(reader).await? {
+            0x00 => Ok(Self(false, None, None)),
+            0x01 => Ok(Self(true, None, None)),
+            0x02 => Ok(Self(true, Some(EventStreamFilter::read(reader).await?), None)),
+            0x03 => {
+                let filter = <Option<EventStreamFilter> as ByteRead>::read(reader).await?;
+                Ok(Self(true, filter, Some(EventReplay::read(reader).await?)))
+            }
+            _ => Err(Error::new(ErrorKind::InvalidData, "Invalid EventStreamConfigRequest type byte")),
+        }
     }
 }
 
 impl ByteWrite for EventStreamConfigRequest {
     async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
-        (SandstormCommandType::EventStreamConfig, self.0).write(writer).await
+        match self {
+            Self(false, _, _) => (SandstormCommandType::EventStreamConfig, 0x00u8).write(writer).await,
+            Self(true, None, None) => (SandstormCommandType::EventStreamConfig, 0x01u8).write(writer).await,
+            Self(true, Some(filter), None) => (SandstormCommandType::EventStreamConfig, 0x02u8, filter).write(writer).await,
+            Self(true, filter, Some(replay)) => {
+                (SandstormCommandType::EventStreamConfig, 0x03u8, filter, replay)
+                    .write(writer)
+                    .await
+            }
+        }
     }
 }
 
@@ -126,26 +250,57 @@ impl ByteRead for EventStreamConfigResponse {
             0x00 => Ok(Self::Disabled),
             0x01 => Ok(Self::Enabled(Metrics::read(reader).await?)),
             0x02 => Ok(Self::WasAlreadyEnabled),
+            0x03 => Ok(Self::EnabledWithHistory(Metrics::read(reader).await?, u16::read(reader).await?)),
             _ => Err(Error::new(ErrorKind::InvalidData, "Invalid EventStreamConfigResponse type byte")),
         }
     }
 }
 
-impl ByteWrite for EventStreamConfigResponse {
-    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
-        SandstormCommandType::EventStreamConfig.write(writer).await?;
-
-        let type_byte: u8 = match self {
+impl EventStreamConfigResponse {
+    fn type_byte(&self) -> u8 {
+        match self {
             Self::Disabled => 0x00,
             Self::Enabled(_) => 0x01,
             Self::WasAlreadyEnabled => 0x02,
-        };
-        type_byte.write(writer).await?;
+            Self::EnabledWithHistory(_, _) => 0x03,
+        }
+    }
+}
 
-        if let Self::Enabled(metrics) = self {
-            metrics.write(writer).await?;
+impl ByteWrite for EventStreamConfigResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::EventStreamConfig, self.type_byte()).write(writer).await?;
+
+        match self {
+            Self::Enabled(metrics) => metrics.write(writer).await,
+            Self::EnabledWithHistory(metrics, count) => (metrics, count).write(writer).await,
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ByteRead for Version1<EventStreamConfigResponse> {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let value = u8::read(reader).await?;
+        match value {
+            0x00 => Ok(Self(EventStreamConfigResponse::Disabled)),
+            0x01 => Ok(Self(EventStreamConfigResponse::Enabled(Version1::<Metrics>::read(reader).await?.0))),
+            0x02 => Ok(Self(EventStreamConfigResponse::WasAlreadyEnabled)),
+            _ => Err(Error::new(ErrorKind::InvalidData, "Invalid EventStreamConfigResponse type byte")),
         }
+    }
+}
+
+impl ByteWrite for Version1<EventStreamConfigResponse> {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::EventStreamConfig, self.0.type_byte()).write(writer).await?;
 
-        Ok(())
+        match &self.0 {
+            EventStreamConfigResponse::Enabled(metrics) => Version1(metrics).write(writer).await,
+            EventStreamConfigResponse::EnabledWithHistory(_, _) => {
+                Err(Error::new(ErrorKind::InvalidInput, "Version 1 of the protocol has no event replay"))
+            }
+            _ => Ok(()),
+        }
     }
 }
@@ -0,0 +1,89 @@
+use std::io::{Error, ErrorKind};
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::serialize::{ByteRead, ByteWrite};
+
+use super::SandstormCommandType;
+
+/// A wrapper for writing a client request framed with the length of its payload, as is done from
+/// version [`NEGOTIATED_SANDSTORM_VERSION`](super::NEGOTIATED_SANDSTORM_VERSION) onwards.
+///
+/// The request is first written to an in-memory buffer in order to know its length, which is then
+/// written after the command type byte and before the payload.
+pub struct FramedRequest<T>(pub T);
+
+/// A wrapper for writing a client request framed with the length of its payload and tagged with
+/// an ID, as is done when the [`RequestIds`](super::SandstormCapability::RequestIds) capability
+/// was negotiated.
+pub struct IdentifiedRequest<T>(pub u32, pub T);
+
+/// A wrapper for writing a server response tagged with the ID of the request it answers, as is
+/// done when the [`RequestIds`](super::SandstormCapability::RequestIds) capability was negotiated.
+///
+/// The response is first written to an in-memory buffer, so the ID can be inserted right after the
+/// command type byte.
+pub struct IdentifiedResponse<T>(pub u32, pub T);
+
+/// Writes a message to an in-memory buffer and splits it into its command type byte and payload.
+async fn write_split<T: ByteWrite>(message: &T) -> Result<(u8, Vec<u8>), Error> {
+    let mut buf = Vec::new();
+    message.write(&mut buf).await?;
+
+    if buf.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "Message has no command type byte"));
+    }
+
+    let command = buf.remove(0);
+    Ok((command, buf))
+}
+
+fn payload_len(payload: &[u8]) -> Result<u16, Error> {
+    match payload.len() {
+        len if len > u16::MAX as usize => Err(Error::new(ErrorKind::InvalidInput, "Request payload is too long (>= 64KB)")),
+        len => Ok(len as u16),
+    }
+}
+
+impl<T: ByteWrite> ByteWrite for FramedRequest<T> {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        let (command, payload) = write_split(&self.0).await?;
+        (command, payload_len(&payload)?).write(writer).await?;
+        writer.write_all(&payload).await
+    }
+}
+
+impl<T: ByteWrite> ByteWrite for IdentifiedRequest<T> {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        let (command, payload) = write_split(&self.1).await?;
+        (command, self.0, payload_len(&payload)?).write(writer).await?;
+        writer.write_all(&payload).await
+    }
+}
+
+impl<T: ByteWrite> ByteWrite for IdentifiedResponse<T> {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        let (command, payload) = write_split(&self.1).await?;
+        (command, self.0).write(writer).await?;
+        writer.write_all(&payload).await
+    }
+}
+
+/// A Sandstorm unsupported command response, sent by the server in place of the regular response
+/// when it doesn't know or doesn't accept the requested command type.
+pub struct UnsupportedCommandResponse(
+    /// The command type byte of the request, which might not be a known [`SandstormCommandType`].
+    pub u8,
+);
+
+impl ByteRead for UnsupportedCommandResponse {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(u8::read(reader).await?))
+    }
+}
+
+impl ByteWrite for UnsupportedCommandResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::UnsupportedCommand, self.0).write(writer).await
+    }
+}
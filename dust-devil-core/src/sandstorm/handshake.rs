@@ -1,4 +1,7 @@
-use std::io::{Error, ErrorKind};
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+};
 
 use tokio::io::{AsyncRead, AsyncWrite};
 
@@ -7,22 +10,149 @@ use crate::{
     u8_repr_enum::U8ReprEnum,
 };
 
-/// A Sandstorm handshake client request, implicitly version 1.
+/// The latest version of the Sandstorm protocol, and the one implemented by this crate.
+pub const SANDSTORM_VERSION: u8 = 2;
+
+/// The first version of the Sandstorm protocol in which the handshake negotiates capabilities and
+/// client requests are framed with their length.
+pub const NEGOTIATED_SANDSTORM_VERSION: u8 = 2;
+
+/// The optional features of the Sandstorm protocol, and their bit in a [`SandstormCapabilities`]
+/// set.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandstormCapability {
+    /// Filtering the event stream with an `EventStreamFilter`.
+    EventStreamFilter = 0,
+
+    /// Replaying recent events when enabling the event stream.
+    EventReplay = 1,
+
+    /// Sending an `EventStreamLagged` event in place of the events a slow client fell behind on.
+    EventStreamLagged = 2,
+
+    /// Tagging requests with an ID, which the server echoes back in their responses.
+    RequestIds = 3,
+}
+
+/// All the capabilities known to this crate.
+pub const ALL_CAPABILITIES: [SandstormCapability; 4] = [
+    SandstormCapability::EventStreamFilter,
+    SandstormCapability::EventReplay,
+    SandstormCapability::EventStreamLagged,
+    SandstormCapability::RequestIds,
+];
+
+impl SandstormCapability {
+    /// Gets this `SandstormCapability` represented by a `&'static str`.
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Self::EventStreamFilter => "event_stream_filter",
+            Self::EventReplay => "event_replay",
+            Self::EventStreamLagged => "event_stream_lagged",
+            Self::RequestIds => "request_ids",
+        }
+    }
+}
+
+impl fmt::Display for SandstormCapability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+/// A set of Sandstorm capabilities, stored as a bitset where each bit corresponds to the `u8`
+/// value of a [`SandstormCapability`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SandstormCapabilities(u32);
+
+impl SandstormCapabilities {
+    /// A capability set with none of the capabilities, as is implicitly the case for version 1.
+    pub const fn none() -> Self {
+        Self(0)
+    }
+
+    /// A capability set with all of the capabilities known to this crate.
+    pub const fn all() -> Self {
+        let mut bits = 0u32;
+        let mut i = 0;
+        while i < ALL_CAPABILITIES.len() {
+            bits |= 1u32 << (ALL_CAPABILITIES[i] as u8);
+            i += 1;
+        }
+
+        Self(bits)
+    }
+
+    /// Returns whether this set has the given capability.
+    pub fn has(&self, capability: SandstormCapability) -> bool {
+        self.0 & (1u32 << (capability as u8)) != 0
+    }
+
+    /// Returns the capabilities present in both this set and the other.
+    pub fn intersection(&self, other: SandstormCapabilities) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    /// Returns an iterator over the capabilities in this set.
+    pub fn iter(&self) -> impl Iterator<Item = SandstormCapability> + '_ {
+        ALL_CAPABILITIES.iter().copied().filter(|c| self.has(*c))
+    }
+}
+
+impl ByteWrite for SandstormCapabilities {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        self.0.write(writer).await
+    }
+}
+
+impl ByteRead for SandstormCapabilities {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        // Capabilities unknown to this crate are ignored, as they can't be agreed upon anyway.
+        Ok(Self(u32::read(reader).await? & Self::all().0))
+    }
+}
+
+/// A Sandstorm handshake client request.
 pub struct SandstormHandshake {
+    /// The highest version of the protocol supported by the client.
+    pub version: u8,
+
     /// The username of the user to log in as.
     pub username: String,
 
     /// The password of the user to log in as.
     pub password: String,
+
+    /// The capabilities supported by the client. Always empty for version 1.
+    pub capabilities: SandstormCapabilities,
 }
 
 /// A borrowed version of [`SandstormHandshake`].
 pub struct SandstormHandshakeRef<'a> {
+    /// The highest version of the protocol supported by the client.
+    pub version: u8,
+
     /// The username of the user to log in as.
     pub username: &'a str,
 
     /// The password of the user to log in as.
     pub password: &'a str,
+
+    /// The capabilities supported by the client. Not written for version 1.
+    pub capabilities: SandstormCapabilities,
+}
+
+/// Sent by the server right after a [`SandstormHandshakeStatus::Ok`] to a client with a version of
+/// [`NEGOTIATED_SANDSTORM_VERSION`] or higher, indicating what both ends agreed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SandstormNegotiation {
+    /// The version of the protocol to use for the rest of the session, the highest supported by
+    /// both the client and the server.
+    pub version: u8,
+
+    /// The capabilities supported by both the client and the server.
+    pub capabilities: SandstormCapabilities,
 }
 
 /// An error from reading a [`SandstormHandshake`].
@@ -41,33 +171,105 @@ impl From<Error> for ParseHandshakeError {
 }
 
 impl SandstormHandshake {
+    /// Creates a handshake for the latest version, with all the capabilities known to this crate.
     pub fn new(username: String, password: String) -> Self {
-        Self { username, password }
+        Self {
+            version: SANDSTORM_VERSION,
+            username,
+            password,
+            capabilities: SandstormCapabilities::all(),
+        }
     }
 
     pub fn as_ref(&self) -> SandstormHandshakeRef {
         SandstormHandshakeRef {
+            version: self.version,
             username: &self.username,
             password: &self.password,
+            capabilities: self.capabilities,
         }
     }
 
+    /// Reads a handshake of any version from 1 onwards. All the versions from
+    /// [`NEGOTIATED_SANDSTORM_VERSION`] onwards share the same format, so a client with a newer
+    /// version than this crate's can still negotiate down to it.
     pub async fn read_with_version_check<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, ParseHandshakeError> {
         let version = u8::read(reader).await?;
-        if version != 1 {
-            Err(ParseHandshakeError::InvalidVersion(version))
-        } else {
-            Ok(Self {
-                username: SmallReadString::read(reader).await?.0,
-                password: SmallReadString::read(reader).await?.0,
-            })
+        if version == 0 {
+            return Err(ParseHandshakeError::InvalidVersion(version));
+        }
+
+        let username = SmallReadString::read(reader).await?.0;
+        let password = SmallReadString::read(reader).await?.0;
+        let capabilities = match version {
+            1 => SandstormCapabilities::none(),
+            _ => SandstormCapabilities::read(reader).await?,
+        };
+
+        Ok(Self {
+            version,
+            username,
+            password,
+            capabilities,
+        })
+    }
+
+    /// Gets the result of negotiating with this client, as a server with the given capabilities.
+    /// Returns `None` if the client's version predates negotiation.
+    pub fn negotiate(&self, capabilities: SandstormCapabilities) -> Option<SandstormNegotiation> {
+        match self.version {
+            v if v < NEGOTIATED_SANDSTORM_VERSION => None,
+            v => Some(SandstormNegotiation {
+                version: v.min(SANDSTORM_VERSION),
+                capabilities: self.capabilities.intersection(capabilities),
+            }),
         }
     }
 }
 
 impl<'a> SandstormHandshakeRef<'a> {
+    /// Creates a handshake for the latest version, with all the capabilities known to this crate.
     pub fn new(username: &'a str, password: &'a str) -> Self {
-        Self { username, password }
+        Self {
+            version: SANDSTORM_VERSION,
+            username,
+            password,
+            capabilities: SandstormCapabilities::all(),
+        }
+    }
+}
+
+impl SandstormNegotiation {
+    /// The implicit result of a handshake with a version 1 client, which doesn't negotiate.
+    pub const fn version1() -> Self {
+        Self {
+            version: 1,
+            capabilities: SandstormCapabilities::none(),
+        }
+    }
+
+    /// Returns whether client requests are framed with their length in the negotiated version.
+    pub fn frames_requests(&self) -> bool {
+        self.version >= NEGOTIATED_SANDSTORM_VERSION
+    }
+
+    /// Returns whether [`Metrics`](super::Metrics) include their `start_time` and `counter_epoch`
+    /// in the negotiated version. Otherwise, they're sent as [`Version1`](super::Version1).
+    pub fn includes_metrics_times(&self) -> bool {
+        self.version >= NEGOTIATED_SANDSTORM_VERSION
+    }
+
+    /// Returns whether a [`ShutdownRequest`](super::ShutdownRequest) includes its drain timeout in
+    /// the negotiated version. Otherwise, it's sent as [`Version1`](super::Version1).
+    pub fn includes_drain_timeout(&self) -> bool {
+        self.version >= NEGOTIATED_SANDSTORM_VERSION
+    }
+
+    /// Returns whether the List SOCKS5 Sockets and Add SOCKS5 Socket messages include the sockets'
+    /// [`ListenerProfile`](crate::listener_profile::ListenerProfile)s in the negotiated version.
+    /// Otherwise, they're sent as [`Version1`](super::Version1).
+    pub fn includes_listener_profiles(&self) -> bool {
+        self.version >= NEGOTIATED_SANDSTORM_VERSION
     }
 }
 
@@ -91,9 +293,29 @@ impl ByteWrite for SandstormHandshake {
 
 impl<'a> ByteWrite for SandstormHandshakeRef<'a> {
     async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
-        (0x01u8, SmallWriteString(self.username), SmallWriteString(self.password))
+        (self.version, SmallWriteString(self.username), SmallWriteString(self.password))
             .write(writer)
-            .await
+            .await?;
+
+        match self.version {
+            1 => Ok(()),
+            _ => self.capabilities.write(writer).await,
+        }
+    }
+}
+
+impl ByteRead for SandstormNegotiation {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self {
+            version: u8::read(reader).await?,
+            capabilities: SandstormCapabilities::read(reader).await?,
+        })
+    }
+}
+
+impl ByteWrite for SandstormNegotiation {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (self.version, self.capabilities).write(writer).await
     }
 }
 
@@ -0,0 +1,131 @@
+use std::{io::Error, net::SocketAddr};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    ip_filter::{IpFilterKind, IpNetwork},
+    serialize::{ByteRead, ByteWrite},
+};
+
+use super::SandstormCommandType;
+
+/// A Sandstorm list IP filters request.
+pub struct ListIpFiltersRequest;
+
+/// A Sandstorm list IP filters response.
+pub struct ListIpFiltersResponse(
+    /// The IP filters, each as the listener's socket address, the filter kind and the network.
+    pub Vec<(SocketAddr, IpFilterKind, IpNetwork)>,
+);
+
+impl ByteRead for ListIpFiltersRequest {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(_reader: &mut R) -> Result<Self, Error> {
+        Ok(Self)
+    }
+}
+
+impl ByteWrite for ListIpFiltersRequest {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        SandstormCommandType::ListIpFilters.write(writer).await
+    }
+}
+
+impl ByteRead for ListIpFiltersResponse {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(<Vec<(SocketAddr, IpFilterKind, IpNetwork)> as ByteRead>::read(reader).await?))
+    }
+}
+
+impl ByteWrite for ListIpFiltersResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::ListIpFilters, self.0.as_slice()).write(writer).await
+    }
+}
+
+/// A Sandstorm add IP filter request.
+pub struct AddIpFilterRequest(
+    /// The socket address of the listener the filter applies to.
+    pub SocketAddr,
+    /// Whether the filter allows or denies the network.
+    pub IpFilterKind,
+    /// The network the filter matches.
+    pub IpNetwork,
+);
+
+/// A Sandstorm add IP filter response.
+pub struct AddIpFilterResponse(
+    /// Whether the filter was added, or `false` if an identical filter already existed.
+    pub bool,
+);
+
+impl ByteRead for AddIpFilterRequest {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(
+            SocketAddr::read(reader).await?,
+            IpFilterKind::read(reader).await?,
+            IpNetwork::read(reader).await?,
+        ))
+    }
+}
+
+impl ByteWrite for AddIpFilterRequest {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::AddIpFilter, self.0, self.1, self.2).write(writer).await
+    }
+}
+
+impl ByteRead for AddIpFilterResponse {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(bool::read(reader).await?))
+    }
+}
+
+impl ByteWrite for AddIpFilterResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::AddIpFilter, self.0).write(writer).await
+    }
+}
+
+/// A Sandstorm remove IP filter request.
+pub struct RemoveIpFilterRequest(
+    /// The socket address of the listener the filter applies to.
+    pub SocketAddr,
+    /// Whether the filter allows or denies the network.
+    pub IpFilterKind,
+    /// The network the filter matches.
+    pub IpNetwork,
+);
+
+/// A Sandstorm remove IP filter response.
+pub struct RemoveIpFilterResponse(
+    /// Whether the filter was found and removed.
+    pub bool,
+);
+
+impl ByteRead for RemoveIpFilterRequest {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(
+            SocketAddr::read(reader).await?,
+            IpFilterKind::read(reader).await?,
+            IpNetwork::read(reader).await?,
+        ))
+    }
+}
+
+impl ByteWrite for RemoveIpFilterRequest {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::RemoveIpFilter, self.0, self.1, self.2).write(writer).await
+    }
+}
+
+impl ByteRead for RemoveIpFilterResponse {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(bool::read(reader).await?))
+    }
+}
+
+impl ByteWrite for RemoveIpFilterResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::RemoveIpFilter, self.0).write(writer).await
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+    net::IpAddr,
+};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::serialize::{ByteRead, ByteWrite, SmallReadString, SmallWriteString};
+
+use super::SandstormCommandType;
+
+/// The subject of a login lockout: either a source IP address, the user ID of the local processes
+/// connecting through Unix sockets, a username when logging in from one of these sources, or a
+/// username when logging in from any source.
+///
+/// When clearing lockouts, a `Username` also stands for all of that user's lockouts per source.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LockoutTarget {
+    Address(IpAddr),
+    Username(String),
+    UnixUser(u32),
+    /// A username when logging in from the given source, which is an `Address` or `UnixUser`.
+    UserFrom(Box<LockoutTarget>, String),
+}
+
+impl LockoutTarget {
+    /// Returns whether clearing this target clears the lockout on the given target.
+    pub fn covers(&self, other: &LockoutTarget) -> bool {
+        match (self, other) {
+            (Self::Username(username), Self::UserFrom(_, other_username)) => username == other_username,
+            _ => self == other,
+        }
+    }
+}
+
+impl fmt::Display for LockoutTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Address(address) => write!(f, "address {address}"),
+            Self::Username(username) => write!(f, "user {username}"),
+            Self::UnixUser(uid) => write!(f, "Unix socket uid {uid}"),
+            Self::UserFrom(source, username) => write!(f, "user {username} from {source}"),
+        }
+    }
+}
+
+impl ByteWrite for LockoutTarget {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        match self {
+            Self::Address(address) => (0x00u8, address).write(writer).await,
+            Self::Username(username) => (0x01u8, SmallWriteString(username)).write(writer).await,
+            Self::UnixUser(uid) => (0x02u8, uid).write(writer).await,
+            Self::UserFrom(source, username) => {
+                0x03u8.write(writer).await?;
+                match source.as_ref() {
+                    Self::Address(address) => (0x00u8, address).write(writer).await?,
+                    Self::UnixUser(uid) => (0x02u8, uid).write(writer).await?,
+                    _ => return Err(Error::new(ErrorKind::InvalidInput, "Invalid LockoutTarget source")),
+                }
+                SmallWriteString(username).write(writer).await
+            }
+        }
+    }
+}
+
+impl ByteRead for LockoutTarget {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        match u8::read(reader).await? {
+            0x00 => Ok(Self::Address(IpAddr::read(reader).await?)),
+            0x01 => Ok(Self::Username(SmallReadString::read(reader).await?.0)),
+            0x02 => Ok(Self::UnixUser(u32::read(reader).await?)),
+            0x03 => {
+                let source = match u8::read(reader).await? {
+                    0x00 => Self::Address(IpAddr::read(reader).await?),
+                    0x02 => Self::UnixUser(u32::read(reader).await?),
+                    _ => return Err(Error::new(ErrorKind::InvalidData, "Invalid LockoutTarget source type byte")),
+                };
+                Ok(Self::UserFrom(Box::new(source), SmallReadString::read(reader).await?.0))
+            }
+            _ => Err(Error::new(ErrorKind::InvalidData, "Invalid LockoutTarget type byte")),
+        }
+    }
+}
+
+/// A Sandstorm list lockouts request.
+pub struct ListLockoutsRequest;
+
+/// A Sandstorm list lockouts response.
+pub struct ListLockoutsResponse(
+    /// The currently active lockouts, alongside the amount of seconds remaining until each expires.
+    pub Vec<(LockoutTarget, u64)>,
+);
+
+impl ByteRead for ListLockoutsRequest {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(_reader: &mut R) -> Result<Self, Error> {
+        Ok(Self)
+    }
+}
+
+impl ByteWrite for ListLockoutsRequest {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        SandstormCommandType::ListLockouts.write(writer).await
+    }
+}
+
+impl ByteRead for ListLockoutsResponse {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(<Vec<(LockoutTarget, u64)> as ByteRead>::read(reader).await?))
+    }
+}
+
+impl ByteWrite for ListLockoutsResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::ListLockouts, self.0.as_slice()).write(writer).await
+    }
+}
+
+/// A Sandstorm clear lockouts request.
+pub struct ClearLockoutsRequest(
+    /// The lockout to clear, or `None` to clear all active lockouts.
+    pub Option<LockoutTarget>,
+);
+
+/// A Sandstorm clear lockouts response.
+pub struct ClearLockoutsResponse(
+    /// The amount of lockouts that were cleared.
+    pub u32,
+);
+
+impl ByteRead for ClearLockoutsRequest {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(<Option<LockoutTarget> as ByteRead>::read(reader).await?))
+    }
+}
+
+impl ByteWrite for ClearLockoutsRequest {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::ClearLockouts, &self.0).write(writer).await
+    }
+}
+
+impl ByteRead for ClearLockoutsResponse {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(u32::read(reader).await?))
+    }
+}
+
+impl ByteWrite for ClearLockoutsResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::ClearLockouts, self.0).write(writer).await
+    }
+}
@@ -0,0 +1,81 @@
+use std::io::Error;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    log_filter::{LogFilter, LogSink},
+    serialize::{ByteRead, ByteWrite},
+};
+
+use super::SandstormCommandType;
+
+/// A Sandstorm list log filters request.
+pub struct ListLogFiltersRequest;
+
+/// A Sandstorm list log filters response.
+pub struct ListLogFiltersResponse(
+    /// The server's active log sinks, each with the filter applied to the events written to it.
+    pub Vec<(LogSink, LogFilter)>,
+);
+
+impl ByteRead for ListLogFiltersRequest {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(_reader: &mut R) -> Result<Self, Error> {
+        Ok(Self)
+    }
+}
+
+impl ByteWrite for ListLogFiltersRequest {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        SandstormCommandType::ListLogFilters.write(writer).await
+    }
+}
+
+impl ByteRead for ListLogFiltersResponse {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(<Vec<(LogSink, LogFilter)> as ByteRead>::read(reader).await?))
+    }
+}
+
+impl ByteWrite for ListLogFiltersResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::ListLogFilters, self.0.as_slice()).write(writer).await
+    }
+}
+
+/// A Sandstorm set log filter request.
+pub struct SetLogFilterRequest(
+    /// The log sink whose filter to replace.
+    pub LogSink,
+    /// The new filter for the log sink.
+    pub LogFilter,
+);
+
+/// A Sandstorm set log filter response.
+pub struct SetLogFilterResponse(
+    /// Whether the filter was set, or `false` if the server has no such active log sink.
+    pub bool,
+);
+
+impl ByteRead for SetLogFilterRequest {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(LogSink::read(reader).await?, LogFilter::read(reader).await?))
+    }
+}
+
+impl ByteWrite for SetLogFilterRequest {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::SetLogFilter, self.0, &self.1).write(writer).await
+    }
+}
+
+impl ByteRead for SetLogFilterResponse {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(bool::read(reader).await?))
+    }
+}
+
+impl ByteWrite for SetLogFilterResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::SetLogFilter, self.0).write(writer).await
+    }
+}
@@ -0,0 +1,211 @@
+use std::io::{Error, ErrorKind};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::serialize::{ByteRead, ByteWrite};
+
+use super::SandstormCommandType;
+
+/// The metrics of the finished clients attributed to a single user or destination host.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BreakdownEntry {
+    /// The username or destination host these metrics are attributed to.
+    pub name: String,
+
+    /// The amount of client connections.
+    pub connections: u64,
+
+    /// The amount of client connections that failed to reach their destination or ended with an
+    /// IO error.
+    pub failures: u64,
+
+    /// The total amount of bytes sent by the clients to their destinations.
+    pub bytes_sent: u64,
+
+    /// The total amount of bytes received by the clients from their destinations.
+    pub bytes_received: u64,
+}
+
+impl BreakdownEntry {
+    /// Creates an empty `BreakdownEntry` with the given name.
+    pub fn new(name: String) -> Self {
+        BreakdownEntry {
+            name,
+            ..Default::default()
+        }
+    }
+
+    /// Gets the total amount of bytes transferred, in both directions.
+    pub fn total_bytes(&self) -> u64 {
+        self.bytes_sent.saturating_add(self.bytes_received)
+    }
+}
+
+impl ByteRead for BreakdownEntry {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(BreakdownEntry {
+            name: String::read(reader).await?,
+            connections: u64::read(reader).await?,
+            failures: u64::read(reader).await?,
+            bytes_sent: u64::read(reader).await?,
+            bytes_received: u64::read(reader).await?,
+        })
+    }
+}
+
+impl ByteWrite for BreakdownEntry {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (&self.name, self.connections, self.failures, self.bytes_sent, self.bytes_received)
+            .write(writer)
+            .await
+    }
+}
+
+/// A histogram of durations measured in microseconds.
+///
+/// Each bucket counts the values less than or equal to its upper bound and greater than the
+/// previous bucket's, with a final bucket for the values greater than all the bounds. There is
+/// therefore always one more count than there are bounds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    bounds: Vec<u64>,
+    counts: Vec<u64>,
+    sum: u64,
+}
+
+impl Histogram {
+    /// Creates an empty `Histogram` with the given bucket upper bounds, in ascending order.
+    pub fn new(bounds: &[u64]) -> Self {
+        Histogram {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+            sum: 0,
+        }
+    }
+
+    /// Records a value into the histogram.
+    pub fn record(&mut self, value: u64) {
+        let index = self.bounds.partition_point(|bound| *bound < value);
+        self.counts[index] += 1;
+        self.sum = self.sum.saturating_add(value);
+    }
+
+    /// Gets the buckets' upper bounds.
+    pub fn bounds(&self) -> &[u64] {
+        &self.bounds
+    }
+
+    /// Gets the amount of values in each bucket, with the last one being the values greater than
+    /// all the bounds.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Gets the sum of all the recorded values.
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    /// Gets the amount of recorded values.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+impl ByteRead for Histogram {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let bounds = <Vec<u64> as ByteRead>::read(reader).await?;
+        let counts = <Vec<u64> as ByteRead>::read(reader).await?;
+        let sum = u64::read(reader).await?;
+
+        if counts.len() != bounds.len() + 1 {
+            return Err(Error::new(ErrorKind::InvalidData, "Histogram must have one more count than bounds"));
+        }
+
+        Ok(Histogram { bounds, counts, sum })
+    }
+}
+
+impl ByteWrite for Histogram {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (self.bounds.as_slice(), self.counts.as_slice(), self.sum).write(writer).await
+    }
+}
+
+/// A breakdown of the server's metrics by user and destination, alongside histograms of how long
+/// connecting to destinations took and how long clients stayed connected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetricsBreakdown {
+    /// The metrics of the users with the most bytes transferred, in descending order.
+    pub users: Vec<BreakdownEntry>,
+
+    /// The metrics of the destination hosts with the most bytes transferred, in descending order.
+    pub destinations: Vec<BreakdownEntry>,
+
+    /// How long it took to connect to the clients' destinations, in microseconds.
+    pub connect_latency: Histogram,
+
+    /// How long clients stayed connected, in microseconds.
+    pub connection_duration: Histogram,
+}
+
+impl ByteRead for MetricsBreakdown {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(MetricsBreakdown {
+            users: <Vec<BreakdownEntry> as ByteRead>::read(reader).await?,
+            destinations: <Vec<BreakdownEntry> as ByteRead>::read(reader).await?,
+            connect_latency: Histogram::read(reader).await?,
+            connection_duration: Histogram::read(reader).await?,
+        })
+    }
+}
+
+impl ByteWrite for MetricsBreakdown {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (
+            self.users.as_slice(),
+            self.destinations.as_slice(),
+            &self.connect_latency,
+            &self.connection_duration,
+        )
+            .write(writer)
+            .await
+    }
+}
+
+/// A Sandstorm metrics breakdown request.
+pub struct MetricsBreakdownRequest(
+    /// The maximum amount of users and of destinations to include, or 0 to include all of them.
+    pub u16,
+);
+
+/// A Sandstorm metrics breakdown response.
+pub struct MetricsBreakdownResponse(
+    /// The metrics breakdown returned by the server, or `None` if the server is not tracking
+    /// metrics.
+    pub Option<MetricsBreakdown>,
+);
+
+impl ByteRead for MetricsBreakdownRequest {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(u16::read(reader).await?))
+    }
+}
+
+impl ByteWrite for MetricsBreakdownRequest {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::RequestMetricsBreakdown, self.0).write(writer).await
+    }
+}
+
+impl ByteRead for MetricsBreakdownResponse {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(<Option<MetricsBreakdown> as ByteRead>::read(reader).await?))
+    }
+}
+
+impl ByteWrite for MetricsBreakdownResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::RequestMetricsBreakdown, &self.0).write(writer).await
+    }
+}
@@ -17,6 +17,15 @@
 //! [`ListSocks5SocketsResponseRef`]), which make use of `&str` and `&[T]` and allow writing (but
 //! not reading) these requests or responses without needing additional memory allocations.
 //!
+//! # Versions
+//! The handshake negotiates a protocol version and a set of [`SandstormCapabilities`]. From
+//! version 2 onwards, client requests must be written wrapped in a [`FramedRequest`], which
+//! prefixes their payload with its length so the server can skip requests it doesn't support and
+//! answer them with an [`UnsupportedCommandResponse`]. If the
+//! [`RequestIds`](SandstormCapability::RequestIds) capability was negotiated, requests are instead
+//! wrapped in an [`IdentifiedRequest`], and the server answers them wrapped in an
+//! [`IdentifiedResponse`] with the same ID.
+//!
 //! # Usage
 //! Since these types implement [`ByteRead`] and [`ByteWrite`], they are all easily used in the
 //! same fashion:
@@ -52,7 +61,10 @@
 //! responses for different simultaneous requests may not even come back in the same order, leading
 //! to a faster protocol, but at the cost of the complexity of implementations.
 
-use std::io::{Error, ErrorKind};
+use std::{
+    fmt,
+    io::{Error, ErrorKind},
+};
 
 use tokio::io::{AsyncRead, AsyncWrite};
 
@@ -63,10 +75,17 @@ use crate::{
 
 mod auth_methods;
 mod buffer_size;
+mod clients;
 mod event_stream;
 mod event_stream_config;
+mod framing;
 mod handshake;
+mod ip_filters;
+mod lockouts;
+mod log_filters;
 mod meow;
+mod metrics_breakdown;
+mod permissions;
 mod sandstorm_sockets;
 mod shutdown;
 mod socks5_sockets;
@@ -74,15 +93,26 @@ mod users;
 
 pub use auth_methods::*;
 pub use buffer_size::*;
+pub use clients::*;
 pub use event_stream::*;
 pub use event_stream_config::*;
+pub use framing::*;
 pub use handshake::*;
+pub use ip_filters::*;
+pub use lockouts::*;
+pub use log_filters::*;
 pub use meow::*;
+pub use metrics_breakdown::*;
+pub use permissions::*;
 pub use sandstorm_sockets::*;
 pub use shutdown::*;
 pub use socks5_sockets::*;
 pub use users::*;
 
+/// The prefix indicating a Sandstorm socket address refers to a Unix domain socket's path, as in
+/// `unix:/run/dust-devil/sandstorm.sock`, rather than a TCP socket address.
+pub const UNIX_SOCKET_PREFIX: &str = "unix:";
+
 /// The Sandstorm command types, and their identifying `u8` value.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -105,6 +135,19 @@ pub enum SandstormCommandType {
     RequestCurrentMetrics = 0x0F,
     GetBufferSize = 0x10,
     SetBufferSize = 0x11,
+    GetPermissions = 0x12,
+    PermissionDenied = 0x13,
+    ListLockouts = 0x14,
+    ClearLockouts = 0x15,
+    ListIpFilters = 0x16,
+    AddIpFilter = 0x17,
+    RemoveIpFilter = 0x18,
+    ListLogFilters = 0x19,
+    SetLogFilter = 0x1A,
+    RequestMetricsBreakdown = 0x1B,
+    ListClients = 0x1C,
+    CloseClients = 0x1D,
+    UnsupportedCommand = 0x1E,
     Meow = 0xFF,
 }
 
@@ -129,6 +172,19 @@ impl U8ReprEnum for SandstormCommandType {
             0x0F => Some(Self::RequestCurrentMetrics),
             0x10 => Some(Self::GetBufferSize),
             0x11 => Some(Self::SetBufferSize),
+            0x12 => Some(Self::GetPermissions),
+            0x13 => Some(Self::PermissionDenied),
+            0x14 => Some(Self::ListLockouts),
+            0x15 => Some(Self::ClearLockouts),
+            0x16 => Some(Self::ListIpFilters),
+            0x17 => Some(Self::AddIpFilter),
+            0x18 => Some(Self::RemoveIpFilter),
+            0x19 => Some(Self::ListLogFilters),
+            0x1A => Some(Self::SetLogFilter),
+            0x1B => Some(Self::RequestMetricsBreakdown),
+            0x1C => Some(Self::ListClients),
+            0x1D => Some(Self::CloseClients),
+            0x1E => Some(Self::UnsupportedCommand),
             0xFF => Some(Self::Meow),
             _ => None,
         }
@@ -139,6 +195,58 @@ impl U8ReprEnum for SandstormCommandType {
     }
 }
 
+impl SandstormCommandType {
+    /// Gets this `SandstormCommandType` represented by a `&'static str`.
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Self::Shutdown => "shutdown",
+            Self::EventStreamConfig => "event_stream_config",
+            Self::EventStream => "event_stream",
+            Self::ListSocks5Sockets => "list_socks5_sockets",
+            Self::AddSocks5Socket => "add_socks5_socket",
+            Self::RemoveSocks5Socket => "remove_socks5_socket",
+            Self::ListSandstormSockets => "list_sandstorm_sockets",
+            Self::AddSandstormSocket => "add_sandstorm_socket",
+            Self::RemoveSandstormSocket => "remove_sandstorm_socket",
+            Self::ListUsers => "list_users",
+            Self::AddUser => "add_user",
+            Self::UpdateUser => "update_user",
+            Self::DeleteUser => "delete_user",
+            Self::ListAuthMethods => "list_auth_methods",
+            Self::ToggleAuthMethod => "toggle_auth_method",
+            Self::RequestCurrentMetrics => "current_metrics",
+            Self::GetBufferSize => "get_buffer_size",
+            Self::SetBufferSize => "set_buffer_size",
+            Self::GetPermissions => "get_permissions",
+            Self::PermissionDenied => "permission_denied",
+            Self::ListLockouts => "list_lockouts",
+            Self::ClearLockouts => "clear_lockouts",
+            Self::ListIpFilters => "list_ip_filters",
+            Self::AddIpFilter => "add_ip_filter",
+            Self::RemoveIpFilter => "remove_ip_filter",
+            Self::ListLogFilters => "list_log_filters",
+            Self::SetLogFilter => "set_log_filter",
+            Self::RequestMetricsBreakdown => "metrics_breakdown",
+            Self::ListClients => "list_clients",
+            Self::CloseClients => "close_clients",
+            Self::UnsupportedCommand => "unsupported_command",
+            Self::Meow => "meow",
+        }
+    }
+
+    /// Returns whether this command type may be sent by a client, as opposed to only being sent by
+    /// the server.
+    pub fn is_request(&self) -> bool {
+        !matches!(self, Self::EventStream | Self::PermissionDenied | Self::UnsupportedCommand)
+    }
+}
+
+impl fmt::Display for SandstormCommandType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
 impl ByteWrite for SandstormCommandType {
     async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
         self.into_u8().write(writer).await
@@ -0,0 +1,164 @@
+use std::io::Error;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::serialize::{ByteRead, ByteWrite};
+
+use super::SandstormCommandType;
+
+/// The command types that can be granted or revoked through a [`SandstormPermissions`] set. Any
+/// command type not in this list is always allowed (for example, meow or querying one's own
+/// permissions).
+pub const RESTRICTABLE_COMMANDS: [SandstormCommandType; 27] = [
+    SandstormCommandType::Shutdown,
+    SandstormCommandType::EventStreamConfig,
+    SandstormCommandType::ListSocks5Sockets,
+    SandstormCommandType::AddSocks5Socket,
+    SandstormCommandType::RemoveSocks5Socket,
+    SandstormCommandType::ListSandstormSockets,
+    SandstormCommandType::AddSandstormSocket,
+    SandstormCommandType::RemoveSandstormSocket,
+    SandstormCommandType::ListUsers,
+    SandstormCommandType::AddUser,
+    SandstormCommandType::UpdateUser,
+    SandstormCommandType::DeleteUser,
+    SandstormCommandType::ListAuthMethods,
+    SandstormCommandType::ToggleAuthMethod,
+    SandstormCommandType::RequestCurrentMetrics,
+    SandstormCommandType::GetBufferSize,
+    SandstormCommandType::SetBufferSize,
+    SandstormCommandType::ListLockouts,
+    SandstormCommandType::ClearLockouts,
+    SandstormCommandType::ListIpFilters,
+    SandstormCommandType::AddIpFilter,
+    SandstormCommandType::RemoveIpFilter,
+    SandstormCommandType::ListLogFilters,
+    SandstormCommandType::SetLogFilter,
+    SandstormCommandType::RequestMetricsBreakdown,
+    SandstormCommandType::ListClients,
+    SandstormCommandType::CloseClients,
+];
+
+/// A set of Sandstorm command types a user is allowed to issue, stored as a bitset where each bit
+/// corresponds to the `u8` value of a [`SandstormCommandType`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SandstormPermissions(u64);
+
+impl SandstormPermissions {
+    /// A permission set that grants none of the restrictable commands.
+    pub const fn none() -> Self {
+        Self(0)
+    }
+
+    /// A permission set that grants all of the restrictable commands.
+    pub const fn all() -> Self {
+        let mut bits = 0u64;
+        let mut i = 0;
+        while i < RESTRICTABLE_COMMANDS.len() {
+            bits |= 1u64 << (RESTRICTABLE_COMMANDS[i] as u8);
+            i += 1;
+        }
+
+        Self(bits)
+    }
+
+    /// Returns whether the given command type can be granted or revoked.
+    pub fn is_restrictable(command: SandstormCommandType) -> bool {
+        RESTRICTABLE_COMMANDS.contains(&command)
+    }
+
+    /// Returns whether this permission set allows issuing the given command type. Commands that
+    /// aren't restrictable are always allowed.
+    pub fn allows(&self, command: SandstormCommandType) -> bool {
+        !Self::is_restrictable(command) || self.0 & (1u64 << (command as u8)) != 0
+    }
+
+    /// Grants the given command type. Does nothing if the command type isn't restrictable.
+    pub fn grant(&mut self, command: SandstormCommandType) {
+        if Self::is_restrictable(command) {
+            self.0 |= 1u64 << (command as u8);
+        }
+    }
+
+    /// Revokes the given command type. Does nothing if the command type isn't restrictable.
+    pub fn revoke(&mut self, command: SandstormCommandType) {
+        if Self::is_restrictable(command) {
+            self.0 &= !(1u64 << (command as u8));
+        }
+    }
+
+    /// Returns whether every command type granted by this permission set is also granted by the
+    /// other.
+    pub fn is_subset_of(&self, other: SandstormPermissions) -> bool {
+        self.0 & !other.0 == 0
+    }
+
+    /// Returns an iterator over the restrictable command types granted by this permission set.
+    pub fn granted(&self) -> impl Iterator<Item = SandstormCommandType> + '_ {
+        RESTRICTABLE_COMMANDS.iter().copied().filter(|c| self.allows(*c))
+    }
+}
+
+impl ByteWrite for SandstormPermissions {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        self.0.write(writer).await
+    }
+}
+
+impl ByteRead for SandstormPermissions {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(u64::read(reader).await? & Self::all().0))
+    }
+}
+
+/// A Sandstorm get permissions request.
+pub struct GetPermissionsRequest;
+
+/// A Sandstorm get permissions response.
+pub struct GetPermissionsResponse(
+    /// The effective permissions of the current session.
+    pub SandstormPermissions,
+);
+
+impl ByteRead for GetPermissionsRequest {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(_reader: &mut R) -> Result<Self, Error> {
+        Ok(Self)
+    }
+}
+
+impl ByteWrite for GetPermissionsRequest {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        SandstormCommandType::GetPermissions.write(writer).await
+    }
+}
+
+impl ByteRead for GetPermissionsResponse {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(SandstormPermissions::read(reader).await?))
+    }
+}
+
+impl ByteWrite for GetPermissionsResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::GetPermissions, self.0).write(writer).await
+    }
+}
+
+/// A Sandstorm permission denied response, sent by the server in place of the regular response
+/// when the session isn't allowed to issue the requested command.
+pub struct PermissionDeniedResponse(
+    /// The command type that was denied.
+    pub SandstormCommandType,
+);
+
+impl ByteRead for PermissionDeniedResponse {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(SandstormCommandType::read(reader).await?))
+    }
+}
+
+impl ByteWrite for PermissionDeniedResponse {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::PermissionDenied, self.0).write(writer).await
+    }
+}
@@ -4,21 +4,34 @@ use tokio::io::{AsyncRead, AsyncWrite};
 
 use crate::serialize::{ByteRead, ByteWrite};
 
-use super::SandstormCommandType;
+use super::{SandstormCommandType, Version1};
 
-/// A Sandstorm shutdown request.
-pub struct ShutdownRequest;
+/// A Sandstorm shutdown request, with an optional drain timeout in seconds. If specified, this
+/// overrides the server's configured drain timeout. A timeout of zero shuts down immediately.
+pub struct ShutdownRequest(pub Option<u32>);
 
 /// A Sandstorm shutdown response.
 pub struct ShutdownResponse;
 
 impl ByteRead for ShutdownRequest {
-    async fn read<R: AsyncRead + Unpin + ?Sized>(_reader: &mut R) -> Result<Self, Error> {
-        Ok(Self)
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(<Option<u32> as ByteRead>::read(reader).await?))
     }
 }
 
 impl ByteWrite for ShutdownRequest {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::Shutdown, self.0).write(writer).await
+    }
+}
+
+impl ByteRead for Version1<ShutdownRequest> {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(_reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(ShutdownRequest(None)))
+    }
+}
+
+impl ByteWrite for Version1<ShutdownRequest> {
     async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
         SandstormCommandType::Shutdown.write(writer).await
     }
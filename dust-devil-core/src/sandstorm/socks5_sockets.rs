@@ -2,23 +2,28 @@ use std::{io::Error, net::SocketAddr};
 
 use tokio::io::{AsyncRead, AsyncWrite};
 
-use crate::serialize::{ByteRead, ByteWrite};
+use crate::{
+    listener_profile::ListenerProfile,
+    serialize::{ByteRead, ByteWrite},
+};
 
-use super::{RemoveSocketResponse, SandstormCommandType};
+use super::{RemoveSocketResponse, SandstormCommandType, Version1};
 
 /// A Sandstorm list-socks5-sockets request.
 pub struct ListSocks5SocketsRequest;
 
 /// A Sandstorm list-socks5-sockets response.
 pub struct ListSocks5SocketsResponse(
-    /// The list of sockets listening for incoming socks5 connections sent by the server.
-    pub Vec<SocketAddr>,
+    /// The list of sockets listening for incoming socks5 connections sent by the server, alongside
+    /// each socket's profile.
+    pub Vec<(SocketAddr, ListenerProfile)>,
 );
 
 /// A borrowed version of [`ListSocks5SocketsResponse`].
 pub struct ListSocks5SocketsResponseRef<'a>(
-    /// The list of sockets listening for incoming socks5 connections sent by the server.
-    &'a [SocketAddr],
+    /// The list of sockets listening for incoming socks5 connections sent by the server, alongside
+    /// each socket's profile.
+    &'a [(SocketAddr, ListenerProfile)],
 );
 
 impl ListSocks5SocketsResponse {
@@ -41,7 +46,7 @@ impl ByteWrite for ListSocks5SocketsRequest {
 
 impl ByteRead for ListSocks5SocketsResponse {
     async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
-        Ok(Self(<Vec<SocketAddr> as ByteRead>::read(reader).await?))
+        Ok(Self(<Vec<(SocketAddr, ListenerProfile)> as ByteRead>::read(reader).await?))
     }
 }
 
@@ -57,10 +62,27 @@ impl<'a> ByteWrite for ListSocks5SocketsResponseRef<'a> {
     }
 }
 
+impl ByteRead for Version1<ListSocks5SocketsResponse> {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let addresses = <Vec<SocketAddr> as ByteRead>::read(reader).await?;
+        let sockets = addresses.into_iter().map(|address| (address, ListenerProfile::default()));
+        Ok(Self(ListSocks5SocketsResponse(sockets.collect())))
+    }
+}
+
+impl ByteWrite for Version1<ListSocks5SocketsResponse> {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        let addresses: Vec<SocketAddr> = self.0 .0.iter().map(|(address, _)| *address).collect();
+        (SandstormCommandType::ListSocks5Sockets, addresses.as_slice()).write(writer).await
+    }
+}
+
 /// A Sandstorm add-socks5-socket request.
 pub struct AddSocks5SocketRequest(
     /// The address of the new socket to open.
     pub SocketAddr,
+    /// The profile to attach to the new socket.
+    pub ListenerProfile,
 );
 
 /// A Sandstorm add-socks5-socket response.
@@ -83,13 +105,28 @@ impl AddSocks5SocketResponse {
 
 impl ByteRead for AddSocks5SocketRequest {
     async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
-        Ok(Self(SocketAddr::read(reader).await?))
+        Ok(Self(SocketAddr::read(reader).await?, ListenerProfile::read(reader).await?))
     }
 }
 
 impl ByteWrite for AddSocks5SocketRequest {
     async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
-        (SandstormCommandType::AddSocks5Socket, &self.0).write(writer).await
+        (SandstormCommandType::AddSocks5Socket, &self.0, &self.1).write(writer).await
+    }
+}
+
+impl ByteRead for Version1<AddSocks5SocketRequest> {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        Ok(Self(AddSocks5SocketRequest(
+            SocketAddr::read(reader).await?,
+            ListenerProfile::default(),
+        )))
+    }
+}
+
+impl ByteWrite for Version1<AddSocks5SocketRequest> {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        (SandstormCommandType::AddSocks5Socket, &self.0 .0).write(writer).await
     }
 }
 
@@ -2,8 +2,8 @@
 //!
 //! This includes `()`, [`bool`], [`u8`], [`u16`], [`u32`], [`u64`], [`i64`] and [`char`], as well
 //! as more complex types, including [`str`] (write-only), [`String`], `[T]` (write-only),
-//! [`Vec<T>`], [`Ipv4Addr`], [`Ipv6Addr`], [`SocketAddrV4`], [`SocketAddrV6`], [`SocketAddr`],
-//! [`Option<T>`], [`Result<T, E>`] and [`Error`].
+//! [`Vec<T>`], [`Ipv4Addr`], [`Ipv6Addr`], [`IpAddr`], [`SocketAddrV4`], [`SocketAddrV6`],
+//! [`SocketAddr`], [`Option<T>`], [`Result<T, E>`] and [`Error`].
 //!
 //! # Serialization of [`Option<T>`] and [`Result<T, E>`]
 //! [`Option<T>`] types have [`ByteRead`] and [`ByteWrite`] implemented for `T: ByteRead`
@@ -46,7 +46,7 @@
 
 use std::{
     io::{Error, ErrorKind},
-    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
 };
 
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
@@ -204,6 +204,32 @@ impl ByteRead for Ipv6Addr {
     }
 }
 
+impl ByteWrite for IpAddr {
+    async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        match self {
+            IpAddr::V4(v4) => {
+                writer.write_u8(4).await?;
+                v4.write(writer).await
+            }
+            IpAddr::V6(v6) => {
+                writer.write_u8(6).await?;
+                v6.write(writer).await
+            }
+        }
+    }
+}
+
+impl ByteRead for IpAddr {
+    async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+        let addr_type = reader.read_u8().await?;
+        match addr_type {
+            4 => Ok(IpAddr::V4(Ipv4Addr::read(reader).await?)),
+            6 => Ok(IpAddr::V6(Ipv6Addr::read(reader).await?)),
+            v => Err(Error::new(ErrorKind::InvalidData, format!("Invalid IP address type, {v}"))),
+        }
+    }
+}
+
 impl ByteWrite for SocketAddrV4 {
     async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
         self.ip().write(writer).await?;
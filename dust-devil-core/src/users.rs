@@ -25,6 +25,21 @@ pub const REGULAR_PREFIX_CHAR: char = '#';
 /// A character used for escape sequences when specifying users.
 pub const ESCAPE_CHAR: char = '\\';
 
+/// A character that opens a user's Sandstorm permissions list, right after the role char.
+pub const PERMISSIONS_START_CHAR: char = '[';
+
+/// A character that closes a user's Sandstorm permissions list.
+pub const PERMISSIONS_END_CHAR: char = ']';
+
+/// A character that separates entries of a user's Sandstorm permissions list.
+pub const PERMISSIONS_SEPARATOR_CHAR: char = ',';
+
+/// A character that, prefixed to an entry of a permissions list, revokes instead of granting it.
+pub const PERMISSION_REVOKE_CHAR: char = '-';
+
+/// A permissions list entry that grants all permissions.
+pub const ALL_PERMISSIONS_NAME: &str = "all";
+
 /// The default user username.
 pub const DEFAULT_USER_USERNAME: &str = "admin";
 
@@ -107,6 +122,8 @@ pub enum UsersLoadingError {
     EmptyPassword(u32, u32),
     PasswordTooLong(u32, u32),
     NoUsers,
+    ExpectedPermissionsEndGotEOF(u32, u32),
+    InvalidPermission(u32, u32, String),
 }
 
 impl PartialEq for UsersLoadingError {
@@ -133,6 +150,8 @@ impl PartialEq for UsersLoadingError {
             Self::EmptyPassword(a, b) => matches!(other, Self::EmptyPassword(a2, b2) if (a, b) == (a2, b2)),
             Self::PasswordTooLong(a, b) => matches!(other, Self::PasswordTooLong(a2, b2) if (a, b) == (a2, b2)),
             Self::NoUsers => matches!(other, Self::NoUsers),
+            Self::ExpectedPermissionsEndGotEOF(a, b) => matches!(other, Self::ExpectedPermissionsEndGotEOF(a2, b2) if (a, b) == (a2, b2)),
+            Self::InvalidPermission(a, b, c) => matches!(other, Self::InvalidPermission(a2, b2, c2) if (a, b, c) == (a2, b2, c2)),
         }
     }
 }
@@ -164,6 +183,15 @@ impl fmt::Display for UsersLoadingError {
             UsersLoadingError::EmptyPassword(line_number, char_at) => write!(f, "Empty password field at {line_number}:{char_at}"),
             UsersLoadingError::PasswordTooLong(line_number, char_at) => write!(f, "Password too long at {line_number}:{char_at}"),
             UsersLoadingError::NoUsers => write!(f, "No users"),
+            UsersLoadingError::ExpectedPermissionsEndGotEOF(line_number, char_at) => {
+                write!(
+                    f,
+                    "Unexpected EOF (expected '{PERMISSIONS_END_CHAR}' after permissions) at {line_number}:{char_at}"
+                )
+            }
+            UsersLoadingError::InvalidPermission(line_number, char_at, name) => {
+                write!(f, "Invalid permission '{name}' at {line_number}:{char_at}")
+            }
         }
     }
 }
@@ -182,6 +210,8 @@ impl ByteWrite for UsersLoadingError {
             UsersLoadingError::EmptyPassword(line_number, char_at) => (9u8, line_number, char_at).write(writer).await,
             UsersLoadingError::PasswordTooLong(line_number, char_at) => (10u8, line_number, char_at).write(writer).await,
             UsersLoadingError::NoUsers => 11u8.write(writer).await,
+            UsersLoadingError::ExpectedPermissionsEndGotEOF(line_number, char_at) => (12u8, line_number, char_at).write(writer).await,
+            UsersLoadingError::InvalidPermission(line_number, char_at, name) => (13u8, line_number, char_at, name).write(writer).await,
         }
     }
 }
@@ -224,6 +254,15 @@ impl ByteRead for UsersLoadingError {
                 u32::read(reader).await?,
             )),
             11 => Ok(UsersLoadingError::NoUsers),
+            12 => Ok(UsersLoadingError::ExpectedPermissionsEndGotEOF(
+                u32::read(reader).await?,
+                u32::read(reader).await?,
+            )),
+            13 => Ok(UsersLoadingError::InvalidPermission(
+                u32::read(reader).await?,
+                u32::read(reader).await?,
+                String::read(reader).await?,
+            )),
             _ => Err(Error::new(ErrorKind::InvalidData, "Invalid UsersLoadingError type byte")),
         }
     }
@@ -2,14 +2,19 @@
 //! `parse_arguments` function, which takes in an iterator of `String`s and returns a `Result` with
 //! either an `ArgumentsRequest` on success, or an `ArgumentsError` on error.
 //!
-//! `ArgumentsRequest` is an enum with three variants; `Help`, `Version`, and
+//! `ArgumentsRequest` is an enum with four variants; `Help`, `Version`, `CheckConfig`, and
 //! `Run(StartupArguments)`. This is to differentiate between when the user requests information to
 //! the program, such as version or the help menu (and after displaying it the program should
 //! close), or when the program should actually run a socks5 server, in which case that variant
 //! provides a `StartupArguments` with the arguments parsed into a struct, including things like
 //! the sockets to open, the path to the users file, which authentication methods are enabled, etc.
 //! The `StartupArguments` instance is filled with default values for those not specified via
-//! parameters.
+//! parameters. `CheckConfig` carries the same information as `Run`, but requests that the
+//! effective settings be printed instead of running the server.
+//!
+//! If a state file is specified with `--state-file` or a configuration file with `--config`, they
+//! are loaded once all the arguments have been parsed and merged into the result, with the
+//! arguments taking precedence. See the `config` module for more information.
 //!
 //! The `ArgumentsError` enum provides fine-detailed information on why the arguments are invalid.
 //! This can include an unknown argument, as well as improper use of a valid argument. That said,
@@ -22,21 +27,34 @@
 
 use std::{
     collections::HashMap,
-    fmt,
+    fmt, fs,
     io::ErrorKind,
     net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs},
 };
 
 use dust_devil_core::{
     buffer_size::{parse_pretty_buffer_size, PrettyBufferSizeParseError},
+    ip_filter::{parse_ip_filter, IpFilterKind, IpFilterParseError, IpNetwork},
+    listener_profile::{ListenerProfile, ListenerProfileParseError, PROFILE_START_CHAR},
+    log_filter::{parse_sink_filter, LogFilter, LogFilterParseError, LogSink},
+    sandstorm::UNIX_SOCKET_PREFIX,
+    socks5::AuthMethod,
     users::REGULAR_PREFIX_CHAR,
 };
 
-use crate::users::{self, UserData};
+use crate::{
+    config::{self, ConfigErrorType, RestoredState},
+    log_file::LogRotation,
+    log_format::LogFormat,
+    sandstorm::EventLagPolicy,
+    system_log::{SyslogTarget, SystemLog, DEFAULT_SYSLOG_PORT, UDP_TARGET_PREFIX, UNIX_TARGET_PREFIX},
+    users::{self, UserData},
+};
 
 pub const DEFAULT_USERS_FILE: &str = "users.txt";
 pub const DEFAULT_SOCKS5_PORT: u16 = 1080;
 pub const DEFAULT_SANDSTORM_PORT: u16 = 2222;
+pub const DEFAULT_METRICS_PORT: u16 = 9180;
 pub const DEFAULT_BUFFER_SIZE: u32 = 0x2000;
 
 pub fn get_version_string() -> String {
@@ -57,27 +75,96 @@ pub fn get_help_string() -> &'static str {
         "  -s, --silent                    Do not print logs to stdout\n",
         "  -d, --disable-events            Disables events, logs, and all data collection\n",
         "  -o, --log-file <path>           Append logs to the specified file\n",
+        "      --log-rotate-size <size>    Rotate the log file once it reaches this size\n",
+        "      --log-rotate-interval <t>   Rotate the log file periodically, every this long\n",
+        "      --log-keep <count>          Keep this many rotated log files, deleting older ones\n",
+        "      --log-compress              Compress rotated log files with gzip\n",
+        "      --log-format <format>       Write logs as \"text\" or \"json\", optionally only for stdout= or file=\n",
+        "      --syslog <target>           Send logs to a syslog daemon at a unix:<path> or UDP address\n",
+        "      --journald                  Send logs to journald\n",
+        "      --log-filter <filter>       Only log some events, optionally only for stdout:, file:, syslog: or journald:\n",
         "  -l, --listen <address>          Specify a socket address to listen for incoming SOCKS5 clients\n",
-        "  -m, --management <address>      Specify a socket address to listen for incoming Sandstorm clients\n",
+        "  -m, --management <address>      Specify a socket address or unix:<path> to listen for incoming Sandstorm clients\n",
+        "      --metrics-listen <address>  Serve metrics over HTTP for Prometheus at this socket address\n",
+        "      --metrics-file <path>       Periodically save the metrics to this file and restore them on startup\n",
         "  -U, --users-file <path>         Load and save users to/from this file\n",
         "  -u, --user <user>               Adds a new user\n",
         "  -A, --auth-enable <auth_type>   Enables an authentication method\n",
         "  -a, --auth-disable <auth_type>  Disables an authentication method\n",
         "  -b, --buffer-size <size>        Sets the size of the buffer for client connections\n",
+        "  -f, --allow <filter>            Only allow connections from a network to a listening socket\n",
+        "  -F, --deny <filter>             Deny connections from a network to a listening socket\n",
+        "  -c, --config <path>             Load settings from the specified TOML configuration file\n",
+        "      --check-config              Validate the settings, print the effective values and exit\n",
+        "  -S, --state-file <path>         Save runtime changes to this file and restore them on startup\n",
+        "  -D, --drain-timeout <seconds>   Wait up to this long for clients to finish when shutting down\n",
+        "      --event-lag-policy <policy> What to do when a manager's event stream falls behind\n",
+        "  -H, --handoff-socket <path>     Hand off or take over listening sockets through this Unix socket\n",
         "\n",
         "By default, the server will print logs to stdout, but not to any file. Logging may be enabled to both stdout and ",
         "to file at the same time. If a log sink is not fast enough to keep up the pace with the server, then messages on ",
         "said sink may be lost, indicated by an error message printed only to said sink.\n",
         "\n",
+        "The log file may be rotated once it reaches a size, specified in the same format as buffer sizes, and/or ",
+        "periodically, with the interval specified in seconds or with a suffix of s, m, h, d or w (for example '12h' or ",
+        "'7d'). When rotated, the log file is renamed by appending \".1\" to its name, with previously rotated files ",
+        "shifted to \".2\", \".3\" and so on. By default 5 rotated files are kept, and if compression is enabled these ",
+        "are compressed with gzip and get an additional \".gz\" suffix. On Unix platforms, sending SIGUSR1 to the server ",
+        "makes it reopen the log file, which allows external tools such as logrotate to rotate it instead.\n",
+        "\n",
+        "Logs are written as text by default. With --log-format json, each event is instead written as a JSON object on ",
+        "its own line, with the event's timestamp, type name and typed fields such as client IDs, addresses, usernames, ",
+        "byte counts and errors. The format may be set for a single sink with a prefix, and multiple sinks may be set ",
+        "separated by commas, for example --log-format stdout=text,file=json.\n",
+        "\n",
+        "Logs may also be sent to the system's log. With --syslog, events are sent as RFC 5424 messages with the daemon ",
+        "facility, either to a Unix datagram socket such as unix:/dev/log or over UDP to a host, on port 514 if none is ",
+        "specified. With --journald, events are sent to journald through its native protocol, including the event's type ",
+        "and typed fields. Failures are logged as warnings or errors, byte counts as debug, and changes made through ",
+        "Sandstorm as notices. Unix sockets and journald are only supported on Unix platforms.\n",
+        "\n",
+        "Each log sink may filter which events it writes with --log-filter, which takes a comma-separated list of ",
+        "settings: \"severity=<level>\" only logs events at least as severe as the given level (\"error\", \"warning\", ",
+        "\"notice\", \"info\" or \"debug\"), \"include=<types>\" and \"exclude=<types>\" only log or don't log the given ",
+        "event types (which may end with '*' to match by prefix), and \"clients=<ids>\" and \"users=<usernames>\" only ",
+        "log the client events of the given clients or users. Lists are separated by '+'. A filter applies to all sinks ",
+        "unless prefixed by a sink and a colon, and later filters replace earlier ones. For example, --log-filter ",
+        "severity=info --log-filter file:exclude=client_bytes_*. Filters may be changed at runtime through Sandstorm.\n",
+        "\n",
         "Socket addresses may be specified as an IPv4 or IPv6 address, or a domainname, and may include a port number. ",
         "The -l/--listen and -m/--management parameter may be specified multiple times to listen on many addresses. If no ",
         "port is specified, then the default port of 1080 will be used for socks5 and 2222 for Sandstorm. If no --listen ",
         "parameter is specified, then [::]:1080 and 0.0.0.0:1080 will be used, and if no Sandstorm sockets are specified, ",
         "then [::]:2222 and 0.0.0.0:2222 will be used.\n",
         "\n",
+        "A Sandstorm socket may also be a Unix socket, specified as its path prefixed by \"unix:\", for example ",
+        "-m unix:/run/dust-devil/sandstorm.sock. Access to the management interface can then be restricted with the ",
+        "socket file's permissions instead of exposing it on a TCP port. If a file already exists at that path but no ",
+        "server is listening on it, it is replaced. IP filters don't apply to Unix sockets, and these aren't listed, ",
+        "added or removed through Sandstorm's socket commands. Unix sockets are only supported on Unix platforms.\n",
+        "\n",
+        "With --metrics-listen, the server serves its metrics over HTTP at /metrics in the OpenMetrics text format, ",
+        "which Prometheus can scrape. These include the connection and byte counts shown by Sandstorm, as well as failed ",
+        "authentications and connections by reason, DNS lookups and the amount of listening sockets. The default port ",
+        "is 9180, the option may be specified multiple times, and it requires events to be enabled.\n",
+        "\n",
+        "With --metrics-file, the historic counters, such as the amount of connections and bytes transferred, are saved ",
+        "to the given file every minute and when the server shuts down, and added back to the counters on startup, so ",
+        "they aren't reset by restarts. The metrics report the server's start time and the time since which the ",
+        "counters have been counting. If the file can't be read or is invalid, the metrics aren't saved, so the file ",
+        "isn't overwritten. This option also requires events to be enabled.\n",
+        "\n",
+        "A SOCKS5 socket address may be followed by an equals sign and a profile, which overrides the server-wide ",
+        "settings for clients connecting through that socket. A profile is a comma-separated list of options: \"noauth\" ",
+        "or \"-noauth\" and \"userpass\" or \"-userpass\" enable or disable an authentication method, \"buffer:<size>\" ",
+        "sets the buffer size, and \"role:<role>\" and \"user:<username>\" only allow users with one of the given roles ",
+        "and one of the given usernames to log in (so if both are given, a user must match both). For example, ",
+        "-l \"127.0.0.1=noauth\" -l \"0.0.0.0=-noauth,userpass,buffer:16K\".\n",
+        "\n",
         "Users are specified in the same format as each line on the users file, but for regular users you may drop the ",
         "role character. For example, -u \"pedro:1234\" would have the same effect as --user \"#pedro:1234\", and admins ",
-        "may be added with, for example \"@admin:secret\".\n",
+        "may be added with, for example \"@admin:secret\". An admin may be restricted to only some Sandstorm commands by ",
+        "listing permissions between brackets after the role char, for example \"@[all,-shutdown]admin:secret\".\n",
         "\n",
         "For enabling or disabling authentication, the available authentication types are \"noauth\" and \"userpass\". ",
         "All authentication methods are enabled by default.\n",
@@ -86,6 +173,47 @@ pub fn get_help_string() -> &'static str {
         "megabytes ('-b 1M') or gigabytes ('-b 1G' if you respect your computer, please don't) but may not be equal to ",
         "nor larger than 4GBs.\n",
         "\n",
+        "IP filters are specified as the listening socket's address, an equals sign, and a network, for example ",
+        "\"[::]:2222=10.5.0.0/16\" or \"0.0.0.0:1080=192.168.1.10\". A connection is rejected if its source address ",
+        "matches any deny filter for the socket it arrived at, or if that socket has any allow filters and none of them ",
+        "match. Filters may be specified multiple times and apply to both SOCKS5 and Sandstorm sockets.\n",
+        "\n",
+        "A configuration file's keys are the long names of the options above, with values in the same format. The ",
+        "options that may be specified multiple times are arrays named \"listen\", \"management\", \"users\", \"allow\" ",
+        "and \"deny\", and authentication methods are toggled with the \"noauth\" and \"userpass\" booleans. For example:\n",
+        "    log-file = \"logs.txt\"\n",
+        "    buffer-size = \"16K\"\n",
+        "    noauth = false\n",
+        "    listen = [\"0.0.0.0:1080\", \"127.0.0.1:1081=noauth\"]\n",
+        "    users = [\"@pedro:1234\"]\n",
+        "Options given on the command line take precedence over the file. Listening sockets from the file are ignored if ",
+        "any are given on the command line, while users and IP filters from both are combined.\n",
+        "\n",
+        "A state file records the listening sockets, authentication methods, buffer size and IP filters when the server ",
+        "shuts down, including any changes made through Sandstorm, in the same format as a configuration file. These ",
+        "are restored the next time the server starts with the same state file, unless overridden on the command line. ",
+        "The state file takes precedence over the configuration file, and is created if it doesn't exist.\n",
+        "\n",
+        "By default the server closes all client connections as soon as it shuts down. If a drain timeout is specified, ",
+        "the server instead stops accepting new SOCKS5 clients and waits up to that many seconds for the existing ones ",
+        "to finish, periodically reporting how many remain. Signaling the server again or sending another shutdown ",
+        "request through Sandstorm while draining shuts it down immediately.\n",
+        "\n",
+        "If a Sandstorm manager's connection can't keep up with the rate at which events are generated, its event stream ",
+        "falls behind and some events are lost. The event lag policy decides what happens then: \"disconnect\" (the ",
+        "default) closes the manager's connection, \"drop\" sends the manager an event indicating how many events were ",
+        "dropped and continues, and \"resync\" does the same but also sends a new metrics snapshot along with it.\n",
+        "\n",
+        "A handoff socket allows restarting or upgrading the server without refusing any connections. When starting, ",
+        "if another server is listening at the handoff socket's path, its listening sockets are taken over instead of ",
+        "binding new ones, and the other server stops accepting connections and drains its existing ones (up to the ",
+        "drain timeout, or 5 minutes if none is specified). Users are saved by the old server before handing off, so ",
+        "the new server loads any changes made to them. Handoffs are only supported on Unix platforms.\n",
+        "\n",
+        "When started through systemd socket activation, the server uses the listening sockets passed by systemd ",
+        "instead of binding its own. Sockets named \"sandstorm\" are used for Sandstorm, and all others for SOCKS5. The ",
+        "server also sends readiness, status and watchdog notifications to systemd if NOTIFY_SOCKET is set.\n",
+        "\n",
         "\n",
         "Examples:\n",
         "\n",
@@ -106,42 +234,76 @@ pub fn get_help_string() -> &'static str {
     )
 }
 
+#[allow(clippy::large_enum_variant)] // Only ever constructed once, at startup.
 #[derive(Debug, PartialEq)]
 pub enum ArgumentsRequest {
     Help,
     Version,
+    CheckConfig(StartupArguments),
     Run(StartupArguments),
 }
 
 #[derive(Debug, PartialEq)]
 pub struct StartupArguments {
     pub socks5_bind_sockets: Vec<SocketAddr>,
+    pub socks5_profiles: HashMap<SocketAddr, ListenerProfile>,
     pub sandstorm_bind_sockets: Vec<SocketAddr>,
+    pub sandstorm_unix_sockets: Vec<String>,
+    pub metrics_bind_sockets: Vec<SocketAddr>,
+    pub metrics_file: Option<String>,
     pub verbose: bool,
     pub silent: bool,
     pub events_enabled: bool,
     pub log_file: Option<String>,
+    pub log_rotation: LogRotation,
+    pub stdout_log_format: Option<LogFormat>,
+    pub file_log_format: Option<LogFormat>,
+    pub syslog_targets: Vec<SyslogTarget>,
+    pub journald: bool,
+    pub log_filters: Vec<(Option<LogSink>, LogFilter)>,
     pub users_file: String,
     pub users: HashMap<String, UserData>,
     pub no_auth_enabled: bool,
     pub userpass_auth_enabled: bool,
     pub buffer_size: u32,
+    pub ip_filters: Vec<(SocketAddr, IpFilterKind, IpNetwork)>,
+    pub state_file: Option<String>,
+    pub restored_state: Option<RestoredState>,
+    pub drain_timeout: Option<u32>,
+    pub event_lag_policy: Option<EventLagPolicy>,
+    pub handoff_socket: Option<String>,
 }
 
 impl StartupArguments {
     pub fn empty() -> Self {
         StartupArguments {
             socks5_bind_sockets: Vec::new(),
+            socks5_profiles: HashMap::new(),
             sandstorm_bind_sockets: Vec::new(),
+            sandstorm_unix_sockets: Vec::new(),
+            metrics_bind_sockets: Vec::new(),
+            metrics_file: None,
             verbose: false,
             silent: false,
             events_enabled: true,
             log_file: None,
+            log_rotation: LogRotation::default(),
+            stdout_log_format: None,
+            file_log_format: None,
+            syslog_targets: Vec::new(),
+            journald: false,
+            log_filters: Vec::new(),
             users_file: String::new(),
             users: HashMap::new(),
             no_auth_enabled: true,
             userpass_auth_enabled: true,
             buffer_size: 0,
+            ip_filters: Vec::new(),
+            state_file: None,
+            restored_state: None,
+            drain_timeout: None,
+            event_lag_policy: None,
+            handoff_socket: None,
         }
     }
 
@@ -153,7 +315,7 @@ impl StartupArguments {
                 .push(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, DEFAULT_SOCKS5_PORT)));
         }
 
-        if self.sandstorm_bind_sockets.is_empty() {
+        if !self.has_sandstorm_sockets() {
             self.sandstorm_bind_sockets.push(SocketAddr::V6(SocketAddrV6::new(
                 Ipv6Addr::UNSPECIFIED,
                 DEFAULT_SANDSTORM_PORT,
@@ -172,6 +334,27 @@ impl StartupArguments {
             self.buffer_size = DEFAULT_BUFFER_SIZE;
         }
     }
+
+    /// Gets the system logs events are sent to, both syslog targets and journald.
+    pub fn system_logs(&self) -> Vec<SystemLog> {
+        let mut system_logs: Vec<_> = self.syslog_targets.iter().cloned().map(SystemLog::Syslog).collect();
+        if self.journald {
+            system_logs.push(SystemLog::Journald);
+        }
+
+        system_logs
+    }
+
+    /// Returns whether events are written anywhere other than stdout, which requires events to be
+    /// enabled.
+    pub fn has_log_sinks(&self) -> bool {
+        self.log_file.is_some() || !self.syslog_targets.is_empty() || self.journald
+    }
+
+    /// Returns whether any Sandstorm sockets are specified, either TCP or Unix sockets.
+    pub fn has_sandstorm_sockets(&self) -> bool {
+        !self.sandstorm_bind_sockets.is_empty() || !self.sandstorm_unix_sockets.is_empty()
+    }
 }
 
 impl Default for StartupArguments {
@@ -186,27 +369,61 @@ impl Default for StartupArguments {
 pub enum ArgumentsError {
     UnknownArgument(String),
     CannotLogWithEventsDisabled,
+    CannotServeMetricsWithEventsDisabled,
+    CannotSaveMetricsWithEventsDisabled,
     LogFileError(FileErrorType),
+    LogRotationError(LogRotationErrorType),
+    LogRotationWithoutLogFile,
+    LogFormatError(LogFormatErrorType),
+    SyslogError(SyslogErrorType),
+    LogFilterError(LogFilterErrorType),
     Socks5ListenError(SocketErrorType),
     SandstormListenError(SocketErrorType),
+    MetricsListenError(SocketErrorType),
+    MetricsFileError(FileErrorType),
     UsersFileError(FileErrorType),
     NewUserError(NewUserErrorType),
     AuthToggleError(AuthToggleErrorType),
     BufferSizeError(BufferSizeErrorType),
+    IpFilterError(IpFilterErrorType),
+    ConfigFileError(FileErrorType),
+    InvalidConfig(String, ConfigErrorType),
+    StateFileError(FileErrorType),
+    InvalidState(String, ConfigErrorType),
+    DrainTimeoutError(DrainTimeoutErrorType),
+    EventLagPolicyError(EventLagPolicyErrorType),
+    HandoffSocketError(FileErrorType),
 }
 
 impl fmt::Display for ArgumentsError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::UnknownArgument(arg) => write!(f, "Unknown argument: {arg}"),
-            Self::CannotLogWithEventsDisabled => write!(f, "Cannot log to file with events disabled"),
+            Self::CannotLogWithEventsDisabled => write!(f, "Cannot log to a file or system log with events disabled"),
+            Self::CannotServeMetricsWithEventsDisabled => write!(f, "Cannot serve metrics with events disabled"),
+            Self::CannotSaveMetricsWithEventsDisabled => write!(f, "Cannot save metrics to a file with events disabled"),
             Self::LogFileError(log_file_error) => fmt_file_error_type(log_file_error, "log", f),
+            Self::LogRotationError(log_rotation_error) => log_rotation_error.fmt(f),
+            Self::LogRotationWithoutLogFile => write!(f, "Log rotation requires a log file"),
+            Self::LogFormatError(log_format_error) => log_format_error.fmt(f),
+            Self::SyslogError(syslog_error) => syslog_error.fmt(f),
+            Self::LogFilterError(log_filter_error) => log_filter_error.fmt(f),
             Self::Socks5ListenError(listen_error) => listen_error.fmt(f),
             Self::SandstormListenError(listen_error) => listen_error.fmt(f),
+            Self::MetricsListenError(listen_error) => listen_error.fmt(f),
+            Self::MetricsFileError(metrics_file_error) => fmt_file_error_type(metrics_file_error, "metrics", f),
             Self::UsersFileError(users_file_error) => fmt_file_error_type(users_file_error, "users", f),
             Self::NewUserError(new_user_error) => new_user_error.fmt(f),
             Self::AuthToggleError(auth_toggle_error) => auth_toggle_error.fmt(f),
             Self::BufferSizeError(buffer_size_error) => buffer_size_error.fmt(f),
+            Self::IpFilterError(ip_filter_error) => ip_filter_error.fmt(f),
+            Self::ConfigFileError(config_file_error) => fmt_file_error_type(config_file_error, "config", f),
+            Self::InvalidConfig(path, config_error) => write!(f, "Invalid config file {path}: {config_error}"),
+            Self::StateFileError(state_file_error) => fmt_file_error_type(state_file_error, "state", f),
+            Self::InvalidState(path, state_error) => write!(f, "Invalid state file {path}: {state_error}"),
+            Self::DrainTimeoutError(drain_timeout_error) => drain_timeout_error.fmt(f),
+            Self::EventLagPolicyError(event_lag_policy_error) => event_lag_policy_error.fmt(f),
+            Self::HandoffSocketError(handoff_socket_error) => fmt_file_error_type(handoff_socket_error, "handoff socket", f),
         }
     }
 }
@@ -226,7 +443,7 @@ fn fmt_file_error_type(this: &FileErrorType, s: &str, f: &mut fmt::Formatter<'_>
     }
 }
 
-fn parse_file_arg(result: &mut String, arg: String, maybe_arg2: Option<String>) -> Result<(), FileErrorType> {
+pub fn parse_file_arg(result: &mut String, arg: String, maybe_arg2: Option<String>) -> Result<(), FileErrorType> {
     let arg2 = match maybe_arg2 {
         Some(arg2) => arg2,
         None => return Err(FileErrorType::UnexpectedEnd(arg)),
@@ -246,6 +463,7 @@ fn parse_file_arg(result: &mut String, arg: String, maybe_arg2: Option<String>)
 pub enum SocketErrorType {
     UnexpectedEnd(String),
     InvalidSocketAddress(String, String),
+    InvalidProfile(String, String, ListenerProfileParseError),
 }
 
 impl fmt::Display for SocketErrorType {
@@ -253,11 +471,12 @@ impl fmt::Display for SocketErrorType {
         match self {
             Self::UnexpectedEnd(arg) => write!(f, "Expected socket address after {arg}"),
             Self::InvalidSocketAddress(arg, addr) => write!(f, "Invalid socket address after {arg}: {addr}"),
+            Self::InvalidProfile(arg, addr, error) => write!(f, "Invalid socket profile after {arg}: {addr}: {error}"),
         }
     }
 }
 
-fn parse_socket_arg(
+pub fn parse_socket_arg(
     result_vec: &mut Vec<SocketAddr>,
     arg: String,
     maybe_arg2: Option<String>,
@@ -286,6 +505,62 @@ fn parse_socket_arg(
     Ok(())
 }
 
+pub fn parse_socks5_socket_arg(result: &mut StartupArguments, arg: String, maybe_arg2: Option<String>) -> Result<(), SocketErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(value) => value,
+        None => return Err(SocketErrorType::UnexpectedEnd(arg)),
+    };
+
+    let (address, profile) = match arg2.split_once(PROFILE_START_CHAR) {
+        Some((address, profile)) => match profile.parse::<ListenerProfile>() {
+            Ok(profile) => (address.to_string(), profile),
+            Err(error) => return Err(SocketErrorType::InvalidProfile(arg, arg2, error)),
+        },
+        None => (arg2.clone(), ListenerProfile::default()),
+    };
+
+    let mut addresses = Vec::new();
+    parse_socket_arg(&mut addresses, arg, Some(address), DEFAULT_SOCKS5_PORT).map_err(|error| match error {
+        SocketErrorType::InvalidSocketAddress(arg, _) => SocketErrorType::InvalidSocketAddress(arg, arg2),
+        other => other,
+    })?;
+
+    for sockaddr in addresses {
+        if !result.socks5_bind_sockets.contains(&sockaddr) {
+            result.socks5_bind_sockets.push(sockaddr);
+        }
+
+        if profile.is_default() {
+            result.socks5_profiles.remove(&sockaddr);
+        } else {
+            result.socks5_profiles.insert(sockaddr, profile.clone());
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses a Sandstorm socket argument, which may be either a socket address or a Unix socket's path
+/// prefixed by [`UNIX_SOCKET_PREFIX`].
+pub fn parse_sandstorm_socket_arg(result: &mut StartupArguments, arg: String, maybe_arg2: Option<String>) -> Result<(), SocketErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(value) => value,
+        None => return Err(SocketErrorType::UnexpectedEnd(arg)),
+    };
+
+    match arg2.strip_prefix(UNIX_SOCKET_PREFIX) {
+        Some("") => Err(SocketErrorType::InvalidSocketAddress(arg, arg2)),
+        Some(path) => {
+            if !result.sandstorm_unix_sockets.iter().any(|p| p == path) {
+                result.sandstorm_unix_sockets.push(path.to_string());
+            }
+
+            Ok(())
+        }
+        None => parse_socket_arg(&mut result.sandstorm_bind_sockets, arg, Some(arg2), DEFAULT_SANDSTORM_PORT),
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum NewUserErrorType {
     UnexpectedEnd(String),
@@ -309,7 +584,7 @@ impl From<NewUserErrorType> for ArgumentsError {
     }
 }
 
-fn parse_new_user_arg(result: &mut StartupArguments, arg: String, maybe_arg2: Option<String>) -> Result<(), NewUserErrorType> {
+pub fn parse_new_user_arg(result: &mut StartupArguments, arg: String, maybe_arg2: Option<String>) -> Result<(), NewUserErrorType> {
     let arg2 = match maybe_arg2 {
         Some(arg2) => arg2,
         None => return Err(NewUserErrorType::UnexpectedEnd(arg)),
@@ -358,7 +633,12 @@ impl From<AuthToggleErrorType> for ArgumentsError {
     }
 }
 
-fn parse_auth_arg(result: &mut StartupArguments, enable: bool, arg: String, maybe_arg2: Option<String>) -> Result<(), AuthToggleErrorType> {
+fn parse_auth_arg(
+    result: &mut StartupArguments,
+    enable: bool,
+    arg: String,
+    maybe_arg2: Option<String>,
+) -> Result<AuthMethod, AuthToggleErrorType> {
     let arg2 = match maybe_arg2 {
         Some(arg2) => arg2,
         None => return Err(AuthToggleErrorType::UnexpectedEnd(arg)),
@@ -366,13 +646,13 @@ fn parse_auth_arg(result: &mut StartupArguments, enable: bool, arg: String, mayb
 
     if arg2.eq_ignore_ascii_case("noauth") {
         result.no_auth_enabled = enable;
+        Ok(AuthMethod::NoAuth)
     } else if arg2.eq_ignore_ascii_case("userpass") {
         result.userpass_auth_enabled = enable;
+        Ok(AuthMethod::UsernameAndPassword)
     } else {
-        return Err(AuthToggleErrorType::InvalidAuthType(arg, arg2));
+        Err(AuthToggleErrorType::InvalidAuthType(arg, arg2))
     }
-
-    Ok(())
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -406,7 +686,7 @@ impl From<BufferSizeErrorType> for ArgumentsError {
     }
 }
 
-fn parse_buffer_size_arg(result: &mut StartupArguments, arg: String, maybe_arg2: Option<String>) -> Result<(), BufferSizeErrorType> {
+pub fn parse_buffer_size_arg(result: &mut StartupArguments, arg: String, maybe_arg2: Option<String>) -> Result<(), BufferSizeErrorType> {
     let arg2 = match maybe_arg2 {
         Some(arg2) => arg2,
         None => return Err(BufferSizeErrorType::UnexpectedEnd(arg)),
@@ -433,11 +713,398 @@ fn parse_buffer_size_arg(result: &mut StartupArguments, arg: String, maybe_arg2:
     Ok(())
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum DrainTimeoutErrorType {
+    UnexpectedEnd(String),
+    AlreadySpecified(String),
+    InvalidValue(String, String),
+}
+
+impl fmt::Display for DrainTimeoutErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected drain timeout in seconds after {arg}"),
+            Self::AlreadySpecified(arg) => write!(f, "Drain timeout already specified at {arg}"),
+            Self::InvalidValue(arg, arg2) => write!(f, "Invalid drain timeout at {arg} {arg2}"),
+        }
+    }
+}
+
+impl From<DrainTimeoutErrorType> for ArgumentsError {
+    fn from(value: DrainTimeoutErrorType) -> Self {
+        Self::DrainTimeoutError(value)
+    }
+}
+
+pub fn parse_drain_timeout_arg(
+    result: &mut StartupArguments,
+    arg: String,
+    maybe_arg2: Option<String>,
+) -> Result<(), DrainTimeoutErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(arg2) => arg2,
+        None => return Err(DrainTimeoutErrorType::UnexpectedEnd(arg)),
+    };
+
+    if result.drain_timeout.is_some() {
+        return Err(DrainTimeoutErrorType::AlreadySpecified(arg));
+    }
+
+    match arg2.trim().parse::<u32>() {
+        Ok(seconds) => result.drain_timeout = Some(seconds),
+        Err(_) => return Err(DrainTimeoutErrorType::InvalidValue(arg, arg2)),
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EventLagPolicyErrorType {
+    UnexpectedEnd(String),
+    AlreadySpecified(String),
+    InvalidValue(String, String),
+}
+
+impl fmt::Display for EventLagPolicyErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected event lag policy after {arg}"),
+            Self::AlreadySpecified(arg) => write!(f, "Event lag policy already specified at {arg}"),
+            Self::InvalidValue(arg, arg2) => write!(f, "Invalid event lag policy at {arg} {arg2}"),
+        }
+    }
+}
+
+impl From<EventLagPolicyErrorType> for ArgumentsError {
+    fn from(value: EventLagPolicyErrorType) -> Self {
+        Self::EventLagPolicyError(value)
+    }
+}
+
+pub fn parse_event_lag_policy_arg(
+    result: &mut StartupArguments,
+    arg: String,
+    maybe_arg2: Option<String>,
+) -> Result<(), EventLagPolicyErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(arg2) => arg2,
+        None => return Err(EventLagPolicyErrorType::UnexpectedEnd(arg)),
+    };
+
+    if result.event_lag_policy.is_some() {
+        return Err(EventLagPolicyErrorType::AlreadySpecified(arg));
+    }
+
+    match arg2.parse::<EventLagPolicy>() {
+        Ok(policy) => result.event_lag_policy = Some(policy),
+        Err(()) => return Err(EventLagPolicyErrorType::InvalidValue(arg, arg2)),
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LogRotationErrorType {
+    UnexpectedEnd(String),
+    AlreadySpecified(String),
+    InvalidSize(String, String),
+    InvalidInterval(String, String),
+    InvalidCount(String, String),
+}
+
+impl fmt::Display for LogRotationErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected value after {arg}"),
+            Self::AlreadySpecified(arg) => write!(f, "Log rotation setting already specified at {arg}"),
+            Self::InvalidSize(arg, arg2) => write!(f, "Invalid log rotation size at {arg} {arg2}"),
+            Self::InvalidInterval(arg, arg2) => write!(f, "Invalid log rotation interval at {arg} {arg2}"),
+            Self::InvalidCount(arg, arg2) => write!(f, "Invalid amount of log files to keep at {arg} {arg2}"),
+        }
+    }
+}
+
+impl From<LogRotationErrorType> for ArgumentsError {
+    fn from(value: LogRotationErrorType) -> Self {
+        Self::LogRotationError(value)
+    }
+}
+
+/// Parses a time interval in seconds, optionally followed by a suffix indicating another unit, such
+/// as `90`, `45s`, `30m`, `12h`, `7d` or `2w`. The interval may not be zero.
+pub fn parse_interval(s: &str) -> Option<u32> {
+    let s = s.trim();
+    let (number, multiplier) = match s.chars().next_back()?.to_ascii_lowercase() {
+        's' => (&s[..(s.len() - 1)], 1),
+        'm' => (&s[..(s.len() - 1)], 60),
+        'h' => (&s[..(s.len() - 1)], 60 * 60),
+        'd' => (&s[..(s.len() - 1)], 24 * 60 * 60),
+        'w' => (&s[..(s.len() - 1)], 7 * 24 * 60 * 60),
+        _ => (s, 1),
+    };
+
+    match number.parse::<u32>() {
+        Ok(0) | Err(_) => None,
+        Ok(value) => value.checked_mul(multiplier),
+    }
+}
+
+fn parse_log_rotation_value<T>(
+    current: &Option<T>,
+    arg: String,
+    maybe_arg2: Option<String>,
+) -> Result<(String, String), LogRotationErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(arg2) => arg2,
+        None => return Err(LogRotationErrorType::UnexpectedEnd(arg)),
+    };
+
+    if current.is_some() {
+        return Err(LogRotationErrorType::AlreadySpecified(arg));
+    }
+
+    Ok((arg, arg2))
+}
+
+pub fn parse_log_rotate_size_arg(
+    result: &mut StartupArguments,
+    arg: String,
+    maybe_arg2: Option<String>,
+) -> Result<(), LogRotationErrorType> {
+    let (arg, arg2) = parse_log_rotation_value(&result.log_rotation.max_size, arg, maybe_arg2)?;
+    match parse_pretty_buffer_size(&arg2) {
+        Ok(size) => result.log_rotation.max_size = Some(size),
+        Err(_) => return Err(LogRotationErrorType::InvalidSize(arg, arg2)),
+    }
+
+    Ok(())
+}
+
+pub fn parse_log_rotate_interval_arg(
+    result: &mut StartupArguments,
+    arg: String,
+    maybe_arg2: Option<String>,
+) -> Result<(), LogRotationErrorType> {
+    let (arg, arg2) = parse_log_rotation_value(&result.log_rotation.interval, arg, maybe_arg2)?;
+    match parse_interval(&arg2) {
+        Some(interval) => result.log_rotation.interval = Some(interval),
+        None => return Err(LogRotationErrorType::InvalidInterval(arg, arg2)),
+    }
+
+    Ok(())
+}
+
+pub fn parse_log_keep_arg(result: &mut StartupArguments, arg: String, maybe_arg2: Option<String>) -> Result<(), LogRotationErrorType> {
+    let (arg, arg2) = parse_log_rotation_value(&result.log_rotation.keep, arg, maybe_arg2)?;
+    match arg2.trim().parse::<u32>() {
+        Ok(keep) => result.log_rotation.keep = Some(keep),
+        Err(_) => return Err(LogRotationErrorType::InvalidCount(arg, arg2)),
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LogFormatErrorType {
+    UnexpectedEnd(String),
+    InvalidFormat(String, String),
+}
+
+impl fmt::Display for LogFormatErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected log format after {arg}"),
+            Self::InvalidFormat(arg, arg2) => write!(f, "Invalid log format at {arg} {arg2}"),
+        }
+    }
+}
+
+impl From<LogFormatErrorType> for ArgumentsError {
+    fn from(value: LogFormatErrorType) -> Self {
+        Self::LogFormatError(value)
+    }
+}
+
+/// Parses a comma-separated list of log formats, each of which applies to all sinks unless
+/// prefixed by a sink's name and an equals sign, as in `stdout=text,file=json`.
+pub fn parse_log_format_arg(result: &mut StartupArguments, arg: String, maybe_arg2: Option<String>) -> Result<(), LogFormatErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(arg2) => arg2,
+        None => return Err(LogFormatErrorType::UnexpectedEnd(arg)),
+    };
+
+    let mut stdout_log_format = result.stdout_log_format;
+    let mut file_log_format = result.file_log_format;
+    for part in arg2.split(',') {
+        let (sinks, format) = match part.split_once('=') {
+            Some((sink, format)) if sink.trim().eq_ignore_ascii_case("stdout") => ((true, false), format),
+            Some((sink, format)) if sink.trim().eq_ignore_ascii_case("file") => ((false, true), format),
+            Some(_) => return Err(LogFormatErrorType::InvalidFormat(arg, arg2)),
+            None => ((true, true), part),
+        };
+
+        let format = match format.parse::<LogFormat>() {
+            Ok(format) => format,
+            Err(()) => return Err(LogFormatErrorType::InvalidFormat(arg, arg2)),
+        };
+
+        if sinks.0 {
+            stdout_log_format = Some(format);
+        }
+        if sinks.1 {
+            file_log_format = Some(format);
+        }
+    }
+
+    result.stdout_log_format = stdout_log_format;
+    result.file_log_format = file_log_format;
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SyslogErrorType {
+    UnexpectedEnd(String),
+    InvalidTarget(String, String),
+}
+
+impl fmt::Display for SyslogErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected syslog target after {arg}"),
+            Self::InvalidTarget(arg, arg2) => write!(f, "Invalid syslog target at {arg} {arg2}"),
+        }
+    }
+}
+
+impl From<SyslogErrorType> for ArgumentsError {
+    fn from(value: SyslogErrorType) -> Self {
+        Self::SyslogError(value)
+    }
+}
+
+/// Parses a syslog target, which is a Unix socket if prefixed by `unix:` or if it's an absolute
+/// path, or otherwise a UDP address optionally prefixed by `udp:`, with port 514 by default.
+pub fn parse_syslog_arg(result: &mut StartupArguments, arg: String, maybe_arg2: Option<String>) -> Result<(), SyslogErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(arg2) => arg2,
+        None => return Err(SyslogErrorType::UnexpectedEnd(arg)),
+    };
+
+    let target = if let Some(path) = arg2.strip_prefix(UNIX_TARGET_PREFIX) {
+        if path.is_empty() {
+            return Err(SyslogErrorType::InvalidTarget(arg, arg2));
+        }
+
+        SyslogTarget::Unix(path.to_string())
+    } else if arg2.starts_with('/') {
+        SyslogTarget::Unix(arg2.clone())
+    } else {
+        let address = arg2.strip_prefix(UDP_TARGET_PREFIX).unwrap_or(&arg2);
+        let mut addresses = Vec::new();
+        if parse_socket_arg(&mut addresses, arg.clone(), Some(address.to_string()), DEFAULT_SYSLOG_PORT).is_err() || addresses.is_empty() {
+            return Err(SyslogErrorType::InvalidTarget(arg, arg2));
+        }
+
+        SyslogTarget::Udp(addresses[0])
+    };
+
+    if !result.syslog_targets.contains(&target) {
+        result.syslog_targets.push(target);
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LogFilterErrorType {
+    UnexpectedEnd(String),
+    InvalidFilter(String, String, LogFilterParseError),
+}
+
+impl fmt::Display for LogFilterErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected log filter after {arg}"),
+            Self::InvalidFilter(arg, arg2, error) => write!(f, "Invalid log filter at {arg} {arg2}: {error}"),
+        }
+    }
+}
+
+impl From<LogFilterErrorType> for ArgumentsError {
+    fn from(value: LogFilterErrorType) -> Self {
+        Self::LogFilterError(value)
+    }
+}
+
+/// Parses a log filter, optionally prefixed by the sink it applies to.
+pub fn parse_log_filter_arg(result: &mut StartupArguments, arg: String, maybe_arg2: Option<String>) -> Result<(), LogFilterErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(arg2) => arg2,
+        None => return Err(LogFilterErrorType::UnexpectedEnd(arg)),
+    };
+
+    match parse_sink_filter(&arg2) {
+        Ok(filter) => {
+            result.log_filters.push(filter);
+            Ok(())
+        }
+        Err(error) => Err(LogFilterErrorType::InvalidFilter(arg, arg2, error)),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IpFilterErrorType {
+    UnexpectedEnd(String),
+    InvalidFilter(String, String, IpFilterParseError),
+}
+
+impl fmt::Display for IpFilterErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected IP filter after {arg}"),
+            Self::InvalidFilter(arg, arg2, error) => write!(f, "Invalid IP filter at {arg} {arg2}: {error}"),
+        }
+    }
+}
+
+impl From<IpFilterErrorType> for ArgumentsError {
+    fn from(value: IpFilterErrorType) -> Self {
+        Self::IpFilterError(value)
+    }
+}
+
+pub fn parse_ip_filter_arg(
+    result: &mut StartupArguments,
+    kind: IpFilterKind,
+    arg: String,
+    maybe_arg2: Option<String>,
+) -> Result<(), IpFilterErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(arg2) => arg2,
+        None => return Err(IpFilterErrorType::UnexpectedEnd(arg)),
+    };
+
+    let (listener, network) = match parse_ip_filter(&arg2) {
+        Ok(filter) => filter,
+        Err(error) => return Err(IpFilterErrorType::InvalidFilter(arg, arg2, error)),
+    };
+
+    let filter = (listener, kind, network);
+    if !result.ip_filters.contains(&filter) {
+        result.ip_filters.push(filter);
+    }
+
+    Ok(())
+}
+
 pub fn parse_arguments<T>(mut args: T) -> Result<ArgumentsRequest, ArgumentsError>
 where
     T: Iterator<Item = String>,
 {
     let mut result = StartupArguments::empty();
+    let mut config_file = String::new();
+    let mut check_config = false;
+    let mut auth_toggles = Vec::new();
 
     // Ignore the first argument, as it's by convention the name of the program
     args.next();
@@ -454,9 +1121,15 @@ where
         } else if arg.eq("-s") || arg.eq_ignore_ascii_case("--silent") {
             result.silent = true;
         } else if arg.eq("-d") || arg.eq_ignore_ascii_case("--disable-events") {
-            if result.log_file.is_some() {
+            if result.has_log_sinks() {
                 return Err(ArgumentsError::CannotLogWithEventsDisabled);
             }
+            if !result.metrics_bind_sockets.is_empty() {
+                return Err(ArgumentsError::CannotServeMetricsWithEventsDisabled);
+            }
+            if result.metrics_file.is_some() {
+                return Err(ArgumentsError::CannotSaveMetricsWithEventsDisabled);
+            }
             result.events_enabled = false;
             result.silent = true;
         } else if arg.eq("-o") || arg.eq_ignore_ascii_case("--log-file") {
@@ -466,27 +1139,109 @@ where
             let mut log_file = result.log_file.unwrap_or_default();
             parse_file_arg(&mut log_file, arg, args.next()).map_err(ArgumentsError::LogFileError)?;
             result.log_file = Some(log_file);
+        } else if arg.eq_ignore_ascii_case("--log-rotate-size") {
+            parse_log_rotate_size_arg(&mut result, arg, args.next())?;
+        } else if arg.eq_ignore_ascii_case("--log-rotate-interval") {
+            parse_log_rotate_interval_arg(&mut result, arg, args.next())?;
+        } else if arg.eq_ignore_ascii_case("--log-keep") {
+            parse_log_keep_arg(&mut result, arg, args.next())?;
+        } else if arg.eq_ignore_ascii_case("--log-compress") {
+            result.log_rotation.compress = true;
+        } else if arg.eq_ignore_ascii_case("--log-format") {
+            parse_log_format_arg(&mut result, arg, args.next())?;
+        } else if arg.eq_ignore_ascii_case("--syslog") {
+            if !result.events_enabled {
+                return Err(ArgumentsError::CannotLogWithEventsDisabled);
+            }
+            parse_syslog_arg(&mut result, arg, args.next())?;
+        } else if arg.eq_ignore_ascii_case("--journald") {
+            if !result.events_enabled {
+                return Err(ArgumentsError::CannotLogWithEventsDisabled);
+            }
+            result.journald = true;
+        } else if arg.eq_ignore_ascii_case("--log-filter") {
+            parse_log_filter_arg(&mut result, arg, args.next())?;
         } else if arg.eq("-l") || arg.eq_ignore_ascii_case("--listen") {
-            parse_socket_arg(&mut result.socks5_bind_sockets, arg, args.next(), DEFAULT_SOCKS5_PORT)
-                .map_err(ArgumentsError::Socks5ListenError)?;
+            parse_socks5_socket_arg(&mut result, arg, args.next()).map_err(ArgumentsError::Socks5ListenError)?;
         } else if arg.eq("-m") || arg.eq_ignore_ascii_case("--management") {
-            parse_socket_arg(&mut result.sandstorm_bind_sockets, arg, args.next(), DEFAULT_SANDSTORM_PORT)
-                .map_err(ArgumentsError::SandstormListenError)?;
+            parse_sandstorm_socket_arg(&mut result, arg, args.next()).map_err(ArgumentsError::SandstormListenError)?;
+        } else if arg.eq_ignore_ascii_case("--metrics-listen") {
+            if !result.events_enabled {
+                return Err(ArgumentsError::CannotServeMetricsWithEventsDisabled);
+            }
+            parse_socket_arg(&mut result.metrics_bind_sockets, arg, args.next(), DEFAULT_METRICS_PORT)
+                .map_err(ArgumentsError::MetricsListenError)?;
+        } else if arg.eq_ignore_ascii_case("--metrics-file") {
+            if !result.events_enabled {
+                return Err(ArgumentsError::CannotSaveMetricsWithEventsDisabled);
+            }
+            let mut metrics_file = result.metrics_file.unwrap_or_default();
+            parse_file_arg(&mut metrics_file, arg, args.next()).map_err(ArgumentsError::MetricsFileError)?;
+            result.metrics_file = Some(metrics_file);
         } else if arg.eq("-U") || arg.eq_ignore_ascii_case("--users-file") {
             parse_file_arg(&mut result.users_file, arg, args.next()).map_err(ArgumentsError::UsersFileError)?;
         } else if arg.eq("-u") || arg.eq_ignore_ascii_case("--user") {
             parse_new_user_arg(&mut result, arg, args.next())?;
         } else if arg.eq("-A") || arg.eq_ignore_ascii_case("--auth-enable") {
-            parse_auth_arg(&mut result, true, arg, args.next())?;
+            auth_toggles.push(parse_auth_arg(&mut result, true, arg, args.next())?);
         } else if arg.eq("-a") || arg.eq_ignore_ascii_case("--auth-disable") {
-            parse_auth_arg(&mut result, false, arg, args.next())?;
+            auth_toggles.push(parse_auth_arg(&mut result, false, arg, args.next())?);
         } else if arg.eq("-b") || arg.eq_ignore_ascii_case("--buffer-size") {
             parse_buffer_size_arg(&mut result, arg, args.next())?;
+        } else if arg.eq("-f") || arg.eq_ignore_ascii_case("--allow") {
+            parse_ip_filter_arg(&mut result, IpFilterKind::Allow, arg, args.next())?;
+        } else if arg.eq("-F") || arg.eq_ignore_ascii_case("--deny") {
+            parse_ip_filter_arg(&mut result, IpFilterKind::Deny, arg, args.next())?;
+        } else if arg.eq("-c") || arg.eq_ignore_ascii_case("--config") {
+            parse_file_arg(&mut config_file, arg, args.next()).map_err(ArgumentsError::ConfigFileError)?;
+        } else if arg.eq_ignore_ascii_case("--check-config") {
+            check_config = true;
+        } else if arg.eq("-S") || arg.eq_ignore_ascii_case("--state-file") {
+            let mut state_file = result.state_file.unwrap_or_default();
+            parse_file_arg(&mut state_file, arg, args.next()).map_err(ArgumentsError::StateFileError)?;
+            result.state_file = Some(state_file);
+        } else if arg.eq("-D") || arg.eq_ignore_ascii_case("--drain-timeout") {
+            parse_drain_timeout_arg(&mut result, arg, args.next())?;
+        } else if arg.eq_ignore_ascii_case("--event-lag-policy") {
+            parse_event_lag_policy_arg(&mut result, arg, args.next())?;
+        } else if arg.eq("-H") || arg.eq_ignore_ascii_case("--handoff-socket") {
+            let mut handoff_socket = result.handoff_socket.unwrap_or_default();
+            parse_file_arg(&mut handoff_socket, arg, args.next()).map_err(ArgumentsError::HandoffSocketError)?;
+            result.handoff_socket = Some(handoff_socket);
         } else {
             return Err(ArgumentsError::UnknownArgument(arg));
         }
     }
 
+    if let Some(state_file) = result.state_file.clone() {
+        match fs::read_to_string(&state_file) {
+            Ok(source) => match config::apply_state(&mut result, &mut auth_toggles, &source) {
+                Ok(restored_state) => result.restored_state = Some(restored_state),
+                Err(error) => return Err(ArgumentsError::InvalidState(state_file, error)),
+            },
+            Err(error) if error.kind() == ErrorKind::NotFound => {}
+            Err(error) => return Err(ArgumentsError::InvalidState(state_file, ConfigErrorType::IO(error.kind()))),
+        }
+    }
+
+    if !config_file.is_empty() {
+        let source = match fs::read_to_string(&config_file) {
+            Ok(source) => source,
+            Err(error) => return Err(ArgumentsError::InvalidConfig(config_file, ConfigErrorType::IO(error.kind()))),
+        };
+
+        if let Err(error) = config::apply_config(&mut result, &auth_toggles, &source) {
+            return Err(ArgumentsError::InvalidConfig(config_file, error));
+        }
+    }
+
+    if result.log_rotation.is_specified() && result.log_file.is_none() {
+        return Err(ArgumentsError::LogRotationWithoutLogFile);
+    }
+
     result.fill_empty_fields_with_defaults();
-    Ok(ArgumentsRequest::Run(result))
+    match check_config {
+        true => Ok(ArgumentsRequest::CheckConfig(result)),
+        false => Ok(ArgumentsRequest::Run(result)),
+    }
 }
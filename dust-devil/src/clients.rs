@@ -0,0 +1,149 @@
+//! A registry of the socks5 clients currently connected to the server, so they can be listed
+//! through Sandstorm without having to rebuild that state from the event stream.
+//!
+//! Each client's [`ClientContext`](crate::context::ClientContext) registers itself when created
+//! and unregisters itself when dropped, updating its entry as the connection progresses. Each
+//! entry also holds a cancellation token through which the client can be closed on its own,
+//! independently of the token shared by all the clients for shutting down.
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+};
+
+use dashmap::DashMap;
+use dust_devil_core::sandstorm::{ActiveClient, CloseClientsTarget};
+use time::OffsetDateTime;
+use tokio_util::sync::CancellationToken;
+
+/// The live state of a connected client, shared between its task and the registry.
+#[derive(Debug)]
+pub struct ClientEntry {
+    address: SocketAddr,
+    listener: Option<SocketAddr>,
+    connected_at: i64,
+    username: OnceLock<String>,
+    destination: OnceLock<String>,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    close_token: CancellationToken,
+}
+
+impl ClientEntry {
+    pub fn set_username(&self, username: String) {
+        let _ = self.username.set(username);
+    }
+
+    pub fn set_destination(&self, destination: String) {
+        let _ = self.destination.set(destination);
+    }
+
+    pub fn add_bytes_sent(&self, count: u64) {
+        self.bytes_sent.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn add_bytes_received(&self, count: u64) {
+        self.bytes_received.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Gets the token that's cancelled when this client is closed through the registry.
+    pub fn close_token(&self) -> &CancellationToken {
+        &self.close_token
+    }
+
+    fn matches(&self, client_id: u64, target: &CloseClientsTarget) -> bool {
+        match target {
+            CloseClientsTarget::Client(id) => client_id == *id,
+            CloseClientsTarget::Username(username) => self.username.get() == Some(username),
+            // Clients accepted on a dual-stack socket have IPv4-mapped IPv6 addresses, so those
+            // are compared as their IPv4 counterparts.
+            CloseClientsTarget::Address(address) => self.address.ip().to_canonical() == address.to_canonical(),
+        }
+    }
+
+    fn snapshot(&self, client_id: u64) -> ActiveClient {
+        ActiveClient {
+            client_id,
+            address: self.address,
+            username: self.username.get().cloned(),
+            destination: self.destination.get().cloned(),
+            connected_at: self.connected_at,
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ClientRegistry {
+    clients: DashMap<u64, Arc<ClientEntry>>,
+}
+
+impl ClientRegistry {
+    pub fn new() -> Self {
+        ClientRegistry { clients: DashMap::new() }
+    }
+
+    /// Registers a newly connected client, returning the entry through which its state is updated.
+    /// `listener` is the address of the socks5 socket the client connected through, if known.
+    pub fn register(&self, client_id: u64, address: SocketAddr, listener: Option<SocketAddr>) -> Arc<ClientEntry> {
+        let entry = Arc::new(ClientEntry {
+            address,
+            listener,
+            connected_at: OffsetDateTime::now_utc().unix_timestamp(),
+            username: OnceLock::new(),
+            destination: OnceLock::new(),
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            close_token: CancellationToken::new(),
+        });
+
+        self.clients.insert(client_id, Arc::clone(&entry));
+        entry
+    }
+
+    pub fn unregister(&self, client_id: u64) {
+        self.clients.remove(&client_id);
+    }
+
+    /// Gets the username a registered client authenticated as, if any, and the address of the
+    /// socks5 socket it connected through, if known. Returns `None` if no such client is
+    /// registered.
+    pub fn lookup(&self, client_id: u64) -> Option<(Option<String>, Option<SocketAddr>)> {
+        self.clients
+            .get(&client_id)
+            .map(|entry| (entry.username.get().cloned(), entry.listener))
+    }
+
+    /// Gets a snapshot of the registered clients, ordered by client ID. Since a Sandstorm list can
+    /// hold at most `u16::MAX` elements, only the oldest clients are returned past that amount.
+    pub fn list(&self) -> Vec<ActiveClient> {
+        let mut list: Vec<ActiveClient> = self.clients.iter().map(|entry| entry.value().snapshot(*entry.key())).collect();
+        list.sort_unstable_by_key(|client| client.client_id);
+        list.truncate(u16::MAX as usize);
+        list
+    }
+
+    /// Closes the registered clients matching the given target, ordered by client ID, and returns
+    /// how many were closed. Clients already being closed are skipped. `on_close` is called with
+    /// each client's ID right before it's closed.
+    pub fn close<F: FnMut(u64)>(&self, target: &CloseClientsTarget, mut on_close: F) -> u32 {
+        let mut matching: Vec<(u64, Arc<ClientEntry>)> = self
+            .clients
+            .iter()
+            .filter(|entry| !entry.value().close_token.is_cancelled() && entry.value().matches(*entry.key(), target))
+            .map(|entry| (*entry.key(), Arc::clone(entry.value())))
+            .collect();
+        matching.sort_unstable_by_key(|(client_id, _)| *client_id);
+
+        for (client_id, entry) in &matching {
+            on_close(*client_id);
+            entry.close_token.cancel();
+        }
+
+        matching.len() as u32
+    }
+}
@@ -0,0 +1,763 @@
+//! Support for loading the server's startup settings from a TOML configuration file, specified
+//! with the `-c`/`--config` argument.
+//!
+//! The file's keys mirror the long names of the command-line arguments, and values are written in
+//! the same format the arguments take. For example:
+//! ```toml
+//! verbose = true
+//! log-file = "logs.txt"
+//! log-rotate-size = "10M"
+//! log-rotate-interval = "1d"
+//! log-keep = 7
+//! log-compress = true
+//! log-format = "stdout=text,file=json"
+//! syslog = ["unix:/dev/log"]
+//! journald = false
+//! log-filter = ["severity=info", "file:exclude=client_bytes_*"]
+//! users-file = "users.txt"
+//! buffer-size = "16K"
+//! drain-timeout = 30
+//! event-lag-policy = "resync"
+//! noauth = false
+//! listen = ["0.0.0.0:1080", "127.0.0.1:1081=noauth"]
+//! management = ["[::1]:2222", "unix:/run/dust-devil/sandstorm.sock"]
+//! metrics-listen = ["127.0.0.1:9180"]
+//! metrics-file = "metrics.toml"
+//! users = ["@pedro:1234", "juan:carlos"]
+//! allow = ["[::1]:2222=::1/128"]
+//! deny = ["0.0.0.0:1080=10.0.0.0/8"]
+//! ```
+//!
+//! Settings specified on the command line take precedence over those in the file. Listening
+//! sockets from the file are only used if none are specified on the command line, users from the
+//! file are only added if no user with the same name is specified on the command line, and IP
+//! filters from both are combined. Log filters from the file are applied before those from the
+//! command line, so the latter replace the former.
+//!
+//! Errors in the file are reported with the line and column they occurred at.
+//!
+//! The same format is used for the state file, specified with the `-S`/`--state-file` argument,
+//! in which the server records at shutdown the settings that may be changed at runtime through
+//! Sandstorm: the listening sockets, the authentication methods, the buffer size and the IP
+//! filters. On startup these are restored with the command line's settings taking precedence, and
+//! the state file's settings in turn taking precedence over the configuration file's.
+//!
+//! The metrics file, specified with the `--metrics-file` argument, is also a TOML file, in which
+//! the server periodically records its historic metrics counters so they persist across restarts.
+//! For example:
+//! ```toml
+//! counter-epoch = 1709317800
+//! historic-client-connections = 1200
+//! client-bytes-sent = 4500000
+//! client-bytes-received = 91000000
+//! historic-sandstorm-connections = 12
+//! dns-lookups = 800
+//! auth-failures = { socks5_invalid_credentials = 3, sandstorm_invalid_credentials = 1 }
+//! connection-failures = { destination_unreachable = 25, io_error = 4 }
+//! ```
+//!
+//! Omitted counters are taken as zero, and failure reasons not known to this version are ignored.
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt,
+    io::ErrorKind,
+    net::SocketAddr,
+    ops::Range,
+};
+
+use dust_devil_core::{
+    ip_filter::{IpFilterKind, IpNetwork},
+    log_filter::SINK_SEPARATOR_CHAR,
+    sandstorm::UNIX_SOCKET_PREFIX,
+    socks5::AuthMethod,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use toml::{Spanned, Value};
+
+use crate::{
+    args::{
+        parse_buffer_size_arg, parse_drain_timeout_arg, parse_event_lag_policy_arg, parse_file_arg, parse_ip_filter_arg,
+        parse_log_filter_arg, parse_log_format_arg, parse_log_keep_arg, parse_log_rotate_interval_arg, parse_log_rotate_size_arg,
+        parse_new_user_arg, parse_sandstorm_socket_arg, parse_socket_arg, parse_socks5_socket_arg, parse_syslog_arg, ArgumentsError,
+        StartupArguments, DEFAULT_METRICS_PORT,
+    },
+    metrics::{AuthFailure, ConnectionFailure, ServerMetrics},
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum NumberOrText {
+    Number(u64),
+    Text(String),
+}
+
+impl NumberOrText {
+    fn into_string(self) -> String {
+        match self {
+            Self::Number(number) => number.to_string(),
+            Self::Text(text) => text,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ConfigFile {
+    verbose: bool,
+    silent: bool,
+    disable_events: Option<Spanned<bool>>,
+    log_file: Option<Spanned<String>>,
+    log_rotate_size: Option<Spanned<NumberOrText>>,
+    log_rotate_interval: Option<Spanned<NumberOrText>>,
+    log_keep: Option<Spanned<i64>>,
+    log_compress: bool,
+    log_format: Option<Spanned<String>>,
+    syslog: Vec<Spanned<String>>,
+    journald: Option<Spanned<bool>>,
+    log_filter: Vec<Spanned<String>>,
+    users_file: Option<Spanned<String>>,
+    handoff_socket: Option<Spanned<String>>,
+    buffer_size: Option<Spanned<NumberOrText>>,
+    drain_timeout: Option<Spanned<i64>>,
+    event_lag_policy: Option<Spanned<String>>,
+    noauth: Option<bool>,
+    userpass: Option<bool>,
+    listen: Vec<Spanned<String>>,
+    management: Vec<Spanned<String>>,
+    metrics_listen: Vec<Spanned<String>>,
+    metrics_file: Option<Spanned<String>>,
+    users: Vec<Spanned<String>>,
+    allow: Vec<Spanned<String>>,
+    deny: Vec<Spanned<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct StateFile {
+    buffer_size: Option<Spanned<NumberOrText>>,
+    noauth: Option<bool>,
+    userpass: Option<bool>,
+    listen: Vec<Spanned<String>>,
+    management: Vec<Spanned<String>>,
+    allow: Vec<Spanned<String>>,
+    deny: Vec<Spanned<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct MetricsFile {
+    counter_epoch: i64,
+    #[serde(default)]
+    historic_client_connections: u64,
+    #[serde(default)]
+    client_bytes_sent: u64,
+    #[serde(default)]
+    client_bytes_received: u64,
+    #[serde(default)]
+    historic_sandstorm_connections: u64,
+    #[serde(default)]
+    dns_lookups: u64,
+    #[serde(default)]
+    auth_failures: HashMap<String, u64>,
+    #[serde(default)]
+    connection_failures: HashMap<String, u64>,
+}
+
+fn auth_failure_key(failure: AuthFailure) -> String {
+    format!("{}_{}", failure.protocol(), failure.reason())
+}
+
+/// The settings restored from a state file, which are reported once the server starts.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RestoredState {
+    pub socks5_sockets: Vec<SocketAddr>,
+    pub sandstorm_sockets: Vec<SocketAddr>,
+    pub sandstorm_unix_sockets: Vec<String>,
+    pub auth_methods: Vec<(AuthMethod, bool)>,
+    pub buffer_size: Option<u32>,
+    pub ip_filters: Vec<(SocketAddr, IpFilterKind, IpNetwork)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigErrorType {
+    IO(ErrorKind),
+    Syntax(u32, u32, String),
+    InvalidValue(u32, u32, Box<ArgumentsError>),
+}
+
+impl fmt::Display for ConfigErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IO(kind) => write!(f, "IO error: {kind}"),
+            Self::Syntax(line_number, char_at, message) => write!(f, "{message} at {line_number}:{char_at}"),
+            Self::InvalidValue(line_number, char_at, error) => write!(f, "{error} at {line_number}:{char_at}"),
+        }
+    }
+}
+
+/// Calculates the line number and character position, both starting at 1, of a byte offset.
+fn position_of(source: &str, offset: usize) -> (u32, u32) {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_number = before.matches('\n').count() + 1;
+    let char_at = before[line_start..].chars().count() + 1;
+    (line_number as u32, char_at as u32)
+}
+
+fn invalid_value(source: &str, span: Range<usize>, error: impl Into<ArgumentsError>) -> ConfigErrorType {
+    let (line_number, char_at) = position_of(source, span.start);
+    ConfigErrorType::InvalidValue(line_number, char_at, Box::new(error.into()))
+}
+
+fn parse_toml<T: DeserializeOwned>(source: &str) -> Result<T, ConfigErrorType> {
+    toml::from_str(source).map_err(|error| {
+        let (line_number, char_at) = position_of(source, error.span().map(|s| s.start).unwrap_or(0));
+        ConfigErrorType::Syntax(line_number, char_at, error.message().to_string())
+    })
+}
+
+fn parse_listen_values(file: &mut StartupArguments, source: &str, values: Vec<Spanned<String>>) -> Result<(), ConfigErrorType> {
+    for value in values {
+        let span = value.span();
+        parse_socks5_socket_arg(file, String::from("listen"), Some(value.into_inner()))
+            .map_err(|e| invalid_value(source, span, ArgumentsError::Socks5ListenError(e)))?;
+    }
+
+    Ok(())
+}
+
+fn parse_management_values(file: &mut StartupArguments, source: &str, values: Vec<Spanned<String>>) -> Result<(), ConfigErrorType> {
+    for value in values {
+        let span = value.span();
+        parse_sandstorm_socket_arg(file, String::from("management"), Some(value.into_inner()))
+            .map_err(|e| invalid_value(source, span, ArgumentsError::SandstormListenError(e)))?;
+    }
+
+    Ok(())
+}
+
+fn parse_ip_filter_values(
+    file: &mut StartupArguments,
+    source: &str,
+    allow: Vec<Spanned<String>>,
+    deny: Vec<Spanned<String>>,
+) -> Result<(), ConfigErrorType> {
+    let filters = allow.into_iter().map(|v| (IpFilterKind::Allow, "allow", v));
+    for (kind, key, value) in filters.chain(deny.into_iter().map(|v| (IpFilterKind::Deny, "deny", v))) {
+        let span = value.span();
+        parse_ip_filter_arg(file, kind, String::from(key), Some(value.into_inner())).map_err(|e| invalid_value(source, span, e))?;
+    }
+
+    Ok(())
+}
+
+fn parse_buffer_size_value(file: &mut StartupArguments, source: &str, value: Option<Spanned<NumberOrText>>) -> Result<(), ConfigErrorType> {
+    if let Some(value) = value {
+        let span = value.span();
+        let value = value.into_inner().into_string();
+        parse_buffer_size_arg(file, String::from("buffer-size"), Some(value)).map_err(|e| invalid_value(source, span, e))?;
+    }
+
+    Ok(())
+}
+
+fn parse_log_rotation_values(
+    file: &mut StartupArguments,
+    source: &str,
+    max_size: Option<Spanned<NumberOrText>>,
+    interval: Option<Spanned<NumberOrText>>,
+    keep: Option<Spanned<i64>>,
+) -> Result<(), ConfigErrorType> {
+    if let Some(value) = max_size {
+        let span = value.span();
+        parse_log_rotate_size_arg(file, String::from("log-rotate-size"), Some(value.into_inner().into_string()))
+            .map_err(|e| invalid_value(source, span, e))?;
+    }
+
+    if let Some(value) = interval {
+        let span = value.span();
+        parse_log_rotate_interval_arg(file, String::from("log-rotate-interval"), Some(value.into_inner().into_string()))
+            .map_err(|e| invalid_value(source, span, e))?;
+    }
+
+    if let Some(value) = keep {
+        let span = value.span();
+        parse_log_keep_arg(file, String::from("log-keep"), Some(value.into_inner().to_string()))
+            .map_err(|e| invalid_value(source, span, e))?;
+    }
+
+    Ok(())
+}
+
+/// Parses a configuration file and merges it into arguments parsed from the command line, with
+/// the command line's settings taking precedence. `auth_toggles` lists the authentication methods
+/// that were already explicitly enabled or disabled, and will not be overridden.
+pub fn apply_config(result: &mut StartupArguments, auth_toggles: &[AuthMethod], source: &str) -> Result<(), ConfigErrorType> {
+    let config: ConfigFile = parse_toml(source)?;
+
+    // All the values are parsed into a separate instance first, so they are validated even if
+    // they end up overridden by the command line.
+    let mut file = StartupArguments::empty();
+
+    parse_listen_values(&mut file, source, config.listen)?;
+    parse_management_values(&mut file, source, config.management)?;
+
+    let mut metrics_listen_span = None;
+    for value in config.metrics_listen {
+        let span = value.span();
+        parse_socket_arg(
+            &mut file.metrics_bind_sockets,
+            String::from("metrics-listen"),
+            Some(value.into_inner()),
+            DEFAULT_METRICS_PORT,
+        )
+        .map_err(|e| invalid_value(source, span.clone(), ArgumentsError::MetricsListenError(e)))?;
+        metrics_listen_span = metrics_listen_span.or(Some(span));
+    }
+
+    for value in config.users {
+        let span = value.span();
+        parse_new_user_arg(&mut file, String::from("users"), Some(value.into_inner())).map_err(|e| invalid_value(source, span, e))?;
+    }
+
+    parse_ip_filter_values(&mut file, source, config.allow, config.deny)?;
+
+    if let Some(value) = config.users_file {
+        let span = value.span();
+        parse_file_arg(&mut file.users_file, String::from("users-file"), Some(value.into_inner()))
+            .map_err(|e| invalid_value(source, span, ArgumentsError::UsersFileError(e)))?;
+    }
+
+    let mut metrics_file_span = None;
+    if let Some(value) = config.metrics_file {
+        let span = value.span();
+        let mut metrics_file = String::new();
+        parse_file_arg(&mut metrics_file, String::from("metrics-file"), Some(value.into_inner()))
+            .map_err(|e| invalid_value(source, span.clone(), ArgumentsError::MetricsFileError(e)))?;
+        file.metrics_file = Some(metrics_file);
+        metrics_file_span = Some(span);
+    }
+
+    if let Some(value) = config.handoff_socket {
+        let span = value.span();
+        let mut handoff_socket = String::new();
+        parse_file_arg(&mut handoff_socket, String::from("handoff-socket"), Some(value.into_inner()))
+            .map_err(|e| invalid_value(source, span, ArgumentsError::HandoffSocketError(e)))?;
+        file.handoff_socket = Some(handoff_socket);
+    }
+
+    let mut log_file_span = None;
+    if let Some(value) = config.log_file {
+        let span = value.span();
+        let mut log_file = String::new();
+        parse_file_arg(&mut log_file, String::from("log-file"), Some(value.into_inner()))
+            .map_err(|e| invalid_value(source, span.clone(), ArgumentsError::LogFileError(e)))?;
+        file.log_file = Some(log_file);
+        log_file_span = Some(span);
+    }
+
+    parse_log_rotation_values(
+        &mut file,
+        source,
+        config.log_rotate_size,
+        config.log_rotate_interval,
+        config.log_keep,
+    )?;
+    parse_buffer_size_value(&mut file, source, config.buffer_size)?;
+
+    if let Some(value) = config.log_format {
+        let span = value.span();
+        parse_log_format_arg(&mut file, String::from("log-format"), Some(value.into_inner()))
+            .map_err(|e| invalid_value(source, span, e))?;
+    }
+
+    let mut system_log_span = None;
+    for value in config.syslog {
+        let span = value.span();
+        parse_syslog_arg(&mut file, String::from("syslog"), Some(value.into_inner()))
+            .map_err(|e| invalid_value(source, span.clone(), e))?;
+        system_log_span = system_log_span.or(Some(span));
+    }
+
+    if let Some(value) = config.journald {
+        if *value.get_ref() {
+            file.journald = true;
+            system_log_span = system_log_span.or(Some(value.span()));
+        }
+    }
+
+    for value in config.log_filter {
+        let span = value.span();
+        parse_log_filter_arg(&mut file, String::from("log-filter"), Some(value.into_inner()))
+            .map_err(|e| invalid_value(source, span, e))?;
+    }
+
+    if let Some(value) = config.drain_timeout {
+        let span = value.span();
+        parse_drain_timeout_arg(&mut file, String::from("drain-timeout"), Some(value.into_inner().to_string()))
+            .map_err(|e| invalid_value(source, span, e))?;
+    }
+
+    if let Some(value) = config.event_lag_policy {
+        let span = value.span();
+        parse_event_lag_policy_arg(&mut file, String::from("event-lag-policy"), Some(value.into_inner()))
+            .map_err(|e| invalid_value(source, span, e))?;
+    }
+
+    // Merge the file's settings into the command line's.
+    result.verbose |= config.verbose;
+    result.silent |= config.silent;
+
+    let disable_events_span = match config.disable_events {
+        Some(value) if *value.get_ref() => {
+            result.events_enabled = false;
+            result.silent = true;
+            Some(value.span())
+        }
+        _ => None,
+    };
+
+    if result.log_file.is_none() {
+        result.log_file = file.log_file;
+    }
+
+    let rotation = &mut result.log_rotation;
+    rotation.max_size = rotation.max_size.or(file.log_rotation.max_size);
+    rotation.interval = rotation.interval.or(file.log_rotation.interval);
+    rotation.keep = rotation.keep.or(file.log_rotation.keep);
+    rotation.compress |= config.log_compress;
+    result.stdout_log_format = result.stdout_log_format.or(file.stdout_log_format);
+    result.file_log_format = result.file_log_format.or(file.file_log_format);
+
+    if result.syslog_targets.is_empty() {
+        result.syslog_targets = file.syslog_targets;
+    }
+    result.journald |= file.journald;
+
+    let mut log_filters = file.log_filters;
+    log_filters.append(&mut result.log_filters);
+    result.log_filters = log_filters;
+
+    if !result.events_enabled && result.has_log_sinks() {
+        let span = log_file_span.or(system_log_span).or(disable_events_span).unwrap_or(0..0);
+        return Err(invalid_value(source, span, ArgumentsError::CannotLogWithEventsDisabled));
+    }
+
+    if result.metrics_bind_sockets.is_empty() {
+        result.metrics_bind_sockets = file.metrics_bind_sockets;
+    }
+
+    if !result.events_enabled && !result.metrics_bind_sockets.is_empty() {
+        let span = metrics_listen_span.or(disable_events_span).unwrap_or(0..0);
+        return Err(invalid_value(source, span, ArgumentsError::CannotServeMetricsWithEventsDisabled));
+    }
+
+    if result.metrics_file.is_none() {
+        result.metrics_file = file.metrics_file;
+    }
+
+    if !result.events_enabled && result.metrics_file.is_some() {
+        let span = metrics_file_span.or(disable_events_span).unwrap_or(0..0);
+        return Err(invalid_value(source, span, ArgumentsError::CannotSaveMetricsWithEventsDisabled));
+    }
+
+    if result.socks5_bind_sockets.is_empty() {
+        result.socks5_bind_sockets = file.socks5_bind_sockets;
+        result.socks5_profiles = file.socks5_profiles;
+    }
+
+    if !result.has_sandstorm_sockets() {
+        result.sandstorm_bind_sockets = file.sandstorm_bind_sockets;
+        result.sandstorm_unix_sockets = file.sandstorm_unix_sockets;
+    }
+
+    if result.users_file.is_empty() {
+        result.users_file = file.users_file;
+    }
+
+    if result.buffer_size == 0 {
+        result.buffer_size = file.buffer_size;
+    }
+
+    if result.drain_timeout.is_none() {
+        result.drain_timeout = file.drain_timeout;
+    }
+
+    if result.event_lag_policy.is_none() {
+        result.event_lag_policy = file.event_lag_policy;
+    }
+
+    if result.handoff_socket.is_none() {
+        result.handoff_socket = file.handoff_socket;
+    }
+
+    for (username, user_data) in file.users {
+        if let Entry::Vacant(vacant) = result.users.entry(username) {
+            vacant.insert(user_data);
+        }
+    }
+
+    for filter in file.ip_filters {
+        if !result.ip_filters.contains(&filter) {
+            result.ip_filters.push(filter);
+        }
+    }
+
+    if let Some(enabled) = config.noauth.filter(|_| !auth_toggles.contains(&AuthMethod::NoAuth)) {
+        result.no_auth_enabled = enabled;
+    }
+
+    if let Some(enabled) = config.userpass.filter(|_| !auth_toggles.contains(&AuthMethod::UsernameAndPassword)) {
+        result.userpass_auth_enabled = enabled;
+    }
+
+    Ok(())
+}
+
+/// Parses a state file and merges it into arguments parsed from the command line, with the
+/// command line's settings taking precedence. Any authentication methods restored from the state
+/// are added to `auth_toggles`, so they aren't later overridden by a configuration file.
+///
+/// Returns the settings that were restored.
+pub fn apply_state(
+    result: &mut StartupArguments,
+    auth_toggles: &mut Vec<AuthMethod>,
+    source: &str,
+) -> Result<RestoredState, ConfigErrorType> {
+    let state: StateFile = parse_toml(source)?;
+
+    let mut file = StartupArguments::empty();
+    parse_listen_values(&mut file, source, state.listen)?;
+    parse_management_values(&mut file, source, state.management)?;
+    parse_ip_filter_values(&mut file, source, state.allow, state.deny)?;
+    parse_buffer_size_value(&mut file, source, state.buffer_size)?;
+
+    let mut restored = RestoredState::default();
+
+    if result.socks5_bind_sockets.is_empty() {
+        restored.socks5_sockets.clone_from(&file.socks5_bind_sockets);
+        result.socks5_bind_sockets = file.socks5_bind_sockets;
+        result.socks5_profiles = file.socks5_profiles;
+    }
+
+    if !result.has_sandstorm_sockets() {
+        restored.sandstorm_sockets.clone_from(&file.sandstorm_bind_sockets);
+        restored.sandstorm_unix_sockets.clone_from(&file.sandstorm_unix_sockets);
+        result.sandstorm_bind_sockets = file.sandstorm_bind_sockets;
+        result.sandstorm_unix_sockets = file.sandstorm_unix_sockets;
+    }
+
+    if result.buffer_size == 0 && file.buffer_size != 0 {
+        result.buffer_size = file.buffer_size;
+        restored.buffer_size = Some(file.buffer_size);
+    }
+
+    for filter in file.ip_filters {
+        if !result.ip_filters.contains(&filter) {
+            result.ip_filters.push(filter);
+            restored.ip_filters.push(filter);
+        }
+    }
+
+    let auth_methods = [
+        (AuthMethod::NoAuth, state.noauth),
+        (AuthMethod::UsernameAndPassword, state.userpass),
+    ];
+    for (auth_method, enabled) in auth_methods {
+        if let Some(enabled) = enabled.filter(|_| !auth_toggles.contains(&auth_method)) {
+            match auth_method {
+                AuthMethod::NoAuth => result.no_auth_enabled = enabled,
+                AuthMethod::UsernameAndPassword => result.userpass_auth_enabled = enabled,
+            }
+
+            auth_toggles.push(auth_method);
+            restored.auth_methods.push((auth_method, enabled));
+        }
+    }
+
+    Ok(restored)
+}
+
+fn toml_string_array<I: Iterator<Item = String>>(iter: I) -> String {
+    let values: Vec<String> = iter.map(|s| Value::String(s).to_string()).collect();
+    format!("[{}]", values.join(", "))
+}
+
+fn push_state_lines(lines: &mut Vec<String>, args: &StartupArguments) {
+    lines.push(format!("buffer-size = {}", args.buffer_size));
+    lines.push(format!("noauth = {}", args.no_auth_enabled));
+    lines.push(format!("userpass = {}", args.userpass_auth_enabled));
+
+    let listen = args
+        .socks5_bind_sockets
+        .iter()
+        .map(|address| match args.socks5_profiles.get(address) {
+            Some(profile) => format!("{address}={profile}"),
+            None => address.to_string(),
+        });
+    lines.push(format!("listen = {}", toml_string_array(listen)));
+
+    let management = args.sandstorm_bind_sockets.iter().map(|address| address.to_string());
+    let management = management.chain(args.sandstorm_unix_sockets.iter().map(|path| format!("{UNIX_SOCKET_PREFIX}{path}")));
+    lines.push(format!("management = {}", toml_string_array(management)));
+
+    for kind in [IpFilterKind::Allow, IpFilterKind::Deny] {
+        let filters = args.ip_filters.iter().filter(|(_, k, _)| *k == kind);
+        let filters = filters.map(|(listener, _, network)| format!("{listener}={network}"));
+        lines.push(format!("{kind} = {}", toml_string_array(filters)));
+    }
+}
+
+/// Gets the settings that are recorded in a state file for a set of startup arguments, written in
+/// the configuration file format.
+pub fn get_state_string(args: &StartupArguments) -> String {
+    let mut lines = Vec::new();
+    push_state_lines(&mut lines, args);
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Gets the effective settings for a set of startup arguments, written in the configuration file
+/// format. Users' passwords are not included.
+pub fn get_effective_config_string(args: &StartupArguments) -> String {
+    let mut lines = vec![
+        format!("verbose = {}", args.verbose),
+        format!("silent = {}", args.silent),
+        format!("disable-events = {}", !args.events_enabled),
+    ];
+
+    if let Some(log_file) = &args.log_file {
+        lines.push(format!("log-file = {}", Value::String(log_file.clone())));
+    }
+
+    let rotation = &args.log_rotation;
+    if let Some(max_size) = rotation.max_size {
+        lines.push(format!("log-rotate-size = {max_size}"));
+    }
+
+    if let Some(interval) = rotation.interval {
+        lines.push(format!("log-rotate-interval = {interval}"));
+    }
+
+    if let Some(keep) = rotation.keep {
+        lines.push(format!("log-keep = {keep}"));
+    }
+
+    if rotation.compress {
+        lines.push(String::from("log-compress = true"));
+    }
+
+    let log_formats: Vec<_> = [("stdout", args.stdout_log_format), ("file", args.file_log_format)]
+        .into_iter()
+        .filter_map(|(sink, format)| format.map(|format| format!("{sink}={format}")))
+        .collect();
+    if !log_formats.is_empty() {
+        lines.push(format!("log-format = {}", Value::String(log_formats.join(","))));
+    }
+
+    if !args.syslog_targets.is_empty() {
+        lines.push(format!(
+            "syslog = {}",
+            toml_string_array(args.syslog_targets.iter().map(|t| t.to_string()))
+        ));
+    }
+
+    if args.journald {
+        lines.push(String::from("journald = true"));
+    }
+
+    if !args.log_filters.is_empty() {
+        lines.push(format!(
+            "log-filter = {}",
+            toml_string_array(args.log_filters.iter().map(|(sink, filter)| match sink {
+                Some(sink) => format!("{sink}{SINK_SEPARATOR_CHAR}{filter}"),
+                None => filter.to_string(),
+            }))
+        ));
+    }
+
+    lines.push(format!("users-file = {}", Value::String(args.users_file.clone())));
+    if let Some(handoff_socket) = &args.handoff_socket {
+        lines.push(format!("handoff-socket = {}", Value::String(handoff_socket.clone())));
+    }
+
+    if let Some(drain_timeout) = args.drain_timeout {
+        lines.push(format!("drain-timeout = {drain_timeout}"));
+    }
+
+    if let Some(event_lag_policy) = args.event_lag_policy {
+        lines.push(format!("event-lag-policy = {}", Value::String(event_lag_policy.to_string())));
+    }
+
+    push_state_lines(&mut lines, args);
+
+    if !args.metrics_bind_sockets.is_empty() {
+        lines.push(format!(
+            "metrics-listen = {}",
+            toml_string_array(args.metrics_bind_sockets.iter().map(|address| address.to_string()))
+        ));
+    }
+
+    if let Some(metrics_file) = &args.metrics_file {
+        lines.push(format!("metrics-file = {}", Value::String(metrics_file.clone())));
+    }
+
+    let mut users: Vec<_> = args
+        .users
+        .iter()
+        .map(|(username, data)| format!("{username} ({})", data.role))
+        .collect();
+    users.sort_unstable();
+    if !users.is_empty() {
+        lines.push(format!("# users: {}", users.join(", ")));
+    }
+
+    lines.join("\n")
+}
+
+/// Parses a metrics file into the historic counters it records, alongside its counter epoch.
+pub fn parse_metrics_file(source: &str) -> Result<ServerMetrics, ConfigErrorType> {
+    let file: MetricsFile = parse_toml(source)?;
+
+    let mut metrics = ServerMetrics {
+        historic_client_connections: file.historic_client_connections,
+        client_bytes_sent: file.client_bytes_sent,
+        client_bytes_received: file.client_bytes_received,
+        historic_sandstorm_connections: file.historic_sandstorm_connections,
+        dns_lookups: file.dns_lookups,
+        counter_epoch: file.counter_epoch,
+        ..Default::default()
+    };
+
+    for failure in AuthFailure::ALL {
+        metrics.auth_failures[failure as usize] = file.auth_failures.get(&auth_failure_key(failure)).copied().unwrap_or(0);
+    }
+
+    for failure in ConnectionFailure::ALL {
+        metrics.connection_failures[failure as usize] = file.connection_failures.get(failure.reason()).copied().unwrap_or(0);
+    }
+
+    Ok(metrics)
+}
+
+/// Gets the historic counters of a set of metrics, written in the metrics file format.
+pub fn get_metrics_file_string(metrics: &ServerMetrics) -> String {
+    let auth_failures = AuthFailure::ALL.map(|failure| format!("{} = {}", auth_failure_key(failure), metrics.auth_failures(failure)));
+    let connection_failures =
+        ConnectionFailure::ALL.map(|failure| format!("{} = {}", failure.reason(), metrics.connection_failures(failure)));
+
+    let lines = [
+        format!("counter-epoch = {}", metrics.counter_epoch),
+        format!("historic-client-connections = {}", metrics.historic_client_connections),
+        format!("client-bytes-sent = {}", metrics.client_bytes_sent),
+        format!("client-bytes-received = {}", metrics.client_bytes_received),
+        format!("historic-sandstorm-connections = {}", metrics.historic_sandstorm_connections),
+        format!("dns-lookups = {}", metrics.dns_lookups),
+        format!("auth-failures = {{ {} }}", auth_failures.join(", ")),
+        format!("connection-failures = {{ {} }}", connection_failures.join(", ")),
+        String::new(),
+    ];
+
+    lines.join("\n")
+}
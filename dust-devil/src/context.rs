@@ -2,68 +2,195 @@ use std::{
     io::Error,
     net::SocketAddr,
     sync::{
-        atomic::{AtomicBool, AtomicU32, Ordering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
 use dust_devil_core::{
-    logging::{Event, EventData},
-    sandstorm::{AddUserResponse, DeleteUserResponse, Metrics, RemoveSocketResponse, UpdateUserResponse},
+    ip_filter::{IpFilterKind, IpNetwork},
+    listener_profile::ListenerProfile,
+    log_filter::{LogFilter, LogSink},
+    logging::{ClientSummary, EventData},
+    sandstorm::{
+        ActiveClient, AddUserResponse, CloseClientsTarget, DeleteUserResponse, EventReplay, LockoutTarget, Metrics, MetricsBreakdown,
+        RemoveSocketResponse, SandstormCommandType, SandstormNegotiation, SandstormPermissions, UpdateUserResponse,
+    },
     socks5::AuthMethod,
     users::UserRole,
 };
 use tokio::sync::{
-    broadcast,
     mpsc::Sender,
     oneshot::{self, Receiver},
+    Notify,
 };
+use tokio_util::sync::CancellationToken;
 
 use crate::{
-    logger::{LogSender, MetricsRequester},
+    clients::{ClientEntry, ClientRegistry},
+    ip_filters::IpFilterManager,
+    lockout::LockoutManager,
+    log_filters::LogFilterManager,
+    logger::{LogManager, LogSender, MetricsAndSubscription, MetricsRequester},
     messaging::MessageType,
-    users::UserManager,
+    sandstorm::EventLagPolicy,
+    users::{UserChangeError, UserManager},
 };
 
 pub struct ServerState {
     users: UserManager,
+    lockouts: LockoutManager,
+    ip_filters: IpFilterManager,
+    clients: ClientRegistry,
     no_auth_enabled: AtomicBool,
     userpass_auth_enabled: AtomicBool,
     buffer_size: AtomicU32,
+    active_clients: AtomicU64,
+    clients_finished: Notify,
     message_sender: Sender<MessageType>,
     metrics_requester: Option<MetricsRequester>,
+    log_filters: Option<Arc<LogFilterManager>>,
 }
 
 impl ServerState {
     pub fn new(
         users: UserManager,
+        ip_filters: IpFilterManager,
         no_auth_enabled: bool,
         userpass_auth_enabled: bool,
         buffer_size: u32,
         message_sender: Sender<MessageType>,
-        metrics_requester: Option<MetricsRequester>,
+        logger: Option<&LogManager>,
     ) -> Self {
         ServerState {
             users,
+            lockouts: LockoutManager::new(),
+            ip_filters,
+            clients: ClientRegistry::new(),
             no_auth_enabled: AtomicBool::new(no_auth_enabled),
             userpass_auth_enabled: AtomicBool::new(userpass_auth_enabled),
             buffer_size: AtomicU32::new(buffer_size),
+            active_clients: AtomicU64::new(0),
+            clients_finished: Notify::new(),
             message_sender,
-            metrics_requester,
+            metrics_requester: logger.map(|l| l.new_requester()),
+            log_filters: logger.map(|l| l.filter_manager()),
         }
     }
 
     pub fn users(&self) -> &UserManager {
         &self.users
     }
+
+    pub fn lockouts(&self) -> &LockoutManager {
+        &self.lockouts
+    }
+
+    pub fn ip_filters(&self) -> &IpFilterManager {
+        &self.ip_filters
+    }
+
+    pub fn clients(&self) -> &ClientRegistry {
+        &self.clients
+    }
+
+    pub fn no_auth_enabled(&self) -> bool {
+        self.no_auth_enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn userpass_auth_enabled(&self) -> bool {
+        self.userpass_auth_enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn buffer_size(&self) -> u32 {
+        self.buffer_size.load(Ordering::Relaxed)
+    }
+
+    /// Gets the amount of socks5 clients currently being handled.
+    pub fn active_clients(&self) -> u64 {
+        self.active_clients.load(Ordering::Relaxed)
+    }
+
+    /// Registers a new socks5 client, which must be followed by a call to [`Self::client_finished`]
+    /// once said client is done.
+    pub fn client_started(&self) {
+        self.active_clients.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn client_finished(&self) {
+        if self.active_clients.fetch_sub(1, Ordering::Relaxed) == 1 {
+            self.clients_finished.notify_waiters();
+        }
+    }
+
+    /// Waits until there are no more active socks5 clients.
+    pub async fn wait_clients_finished(&self) {
+        loop {
+            let notified = self.clients_finished.notified();
+            if self.active_clients() == 0 {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Attempts a login through the user manager, unless either the source address or the username
+    /// are locked out, in which case the login fails immediately. Failed and successful attempts
+    /// are registered for the address, the username from that address, and the username.
+    fn try_login(
+        &self,
+        source: LockoutTarget,
+        username: &str,
+        password: &str,
+        log_sender: &Option<LogSender>,
+    ) -> Option<(UserRole, SandstormPermissions)> {
+        // A username is locked out from everywhere only after more failures than from a single
+        // source, so a few failed attempts can't easily lock a user out everywhere, while guessing
+        // their password from many sources is still limited. Only existing users are tracked, so
+        // attempts with made up usernames don't fill up the lockout records.
+        let now = Instant::now();
+        let mut targets = vec![source.clone()];
+        if self.users.contains(username) {
+            targets.push(LockoutTarget::UserFrom(Box::new(source), username.to_string()));
+            targets.push(LockoutTarget::Username(username.to_string()));
+        }
+
+        if targets.iter().any(|t| self.lockouts.is_locked_out(t, now)) {
+            return None;
+        }
+
+        let result = self.users.try_login(username, password);
+        for target in targets {
+            if result.is_some() {
+                self.lockouts.register_success(&target);
+            } else if let Some((failures, duration)) = self.lockouts.register_failure(target.clone(), now) {
+                if let Some(sender) = log_sender {
+                    sender.send(EventData::LoginLockoutStarted(target, failures, duration.as_secs()));
+                }
+            }
+        }
+
+        result
+    }
 }
 
 pub struct ClientContext {
     pub client_id: u64,
+    pub address: SocketAddr,
     pub bytes_sent: u64,
     pub bytes_received: u64,
+    pending_bytes_sent: u64,
+    pending_bytes_received: u64,
+    pub profile: Arc<ListenerProfile>,
     pub state: Arc<ServerState>,
     pub log_sender: Option<LogSender>,
+    pub username: Option<String>,
+    pub destination: Option<String>,
+    pub started_at: Instant,
+    pub connect_latency: Option<Duration>,
+    entry: Arc<ClientEntry>,
 }
 
 #[macro_export]
@@ -76,40 +203,132 @@ macro_rules! log {
 }
 
 impl ClientContext {
-    pub fn create(client_id: u64, state: &Arc<ServerState>, log_sender: Option<LogSender>) -> Self {
+    pub fn create(
+        client_id: u64,
+        address: SocketAddr,
+        listener: Option<SocketAddr>,
+        profile: Arc<ListenerProfile>,
+        state: &Arc<ServerState>,
+        log_sender: Option<LogSender>,
+    ) -> Self {
         ClientContext {
             client_id,
+            address,
             bytes_sent: 0,
             bytes_received: 0,
+            pending_bytes_sent: 0,
+            pending_bytes_received: 0,
+            profile,
             state: Arc::clone(state),
             log_sender,
+            username: None,
+            destination: None,
+            started_at: Instant::now(),
+            connect_latency: None,
+            entry: state.clients.register(client_id, address, listener),
         }
     }
 
     pub fn buffer_size(&self) -> usize {
-        self.state.buffer_size.load(Ordering::Relaxed) as usize
+        match self.profile.buffer_size {
+            Some(buffer_size) => buffer_size as usize,
+            None => self.state.buffer_size.load(Ordering::Relaxed) as usize,
+        }
     }
 
     pub fn is_noauth_enabled(&self) -> bool {
-        self.state.no_auth_enabled.load(Ordering::Relaxed)
+        match self.profile.no_auth_enabled {
+            Some(enabled) => enabled,
+            None => self.state.no_auth_enabled.load(Ordering::Relaxed),
+        }
     }
 
     pub fn is_userpass_enabled(&self) -> bool {
-        self.state.userpass_auth_enabled.load(Ordering::Relaxed)
+        match self.profile.userpass_auth_enabled {
+            Some(enabled) => enabled,
+            None => self.state.userpass_auth_enabled.load(Ordering::Relaxed),
+        }
     }
 
     pub fn try_login(&self, username: &str, password: &str) -> bool {
-        self.state.users.try_login(username, password).is_some()
+        self.state
+            .try_login(LockoutTarget::Address(self.address.ip()), username, password, &self.log_sender)
+            .is_some_and(|(role, _)| self.profile.allows_user(username, role))
+    }
+
+    /// Gets the token that's cancelled when this client is closed by a manager.
+    pub fn close_token(&self) -> CancellationToken {
+        self.entry.close_token().clone()
     }
 
+    pub fn set_username(&mut self, username: String) {
+        self.entry.set_username(username.clone());
+        self.username = Some(username);
+    }
+
+    /// Sets the destination host requested by this client, alongside the port it requested.
+    pub fn set_destination(&mut self, host: String, port: u16) {
+        let destination = if host.contains(':') {
+            format!("[{host}]:{port}")
+        } else {
+            format!("{host}:{port}")
+        };
+
+        self.entry.set_destination(destination);
+        self.destination = Some(host);
+    }
+
+    /// Registers bytes sent by the client to its destination. These are not logged right away, but
+    /// accumulated until the next call to [`Self::flush_bytes`].
     pub fn register_bytes_sent(&mut self, count: u64) {
         self.bytes_sent += count;
-        log!(self, EventData::ClientBytesSent(self.client_id, count));
+        self.pending_bytes_sent += count;
+        self.entry.add_bytes_sent(count);
     }
 
+    /// Registers bytes received by the client from its destination. These are not logged right
+    /// away, but accumulated until the next call to [`Self::flush_bytes`].
     pub fn register_bytes_received(&mut self, count: u64) {
         self.bytes_received += count;
-        log!(self, EventData::ClientBytesReceived(self.client_id, count));
+        self.pending_bytes_received += count;
+        self.entry.add_bytes_received(count);
+    }
+
+    /// Logs the bytes sent and received since the last flush, if any, as a single event for each
+    /// direction. This must be called before logging any event that should come after them.
+    pub fn flush_bytes(&mut self) {
+        if self.pending_bytes_sent != 0 {
+            log!(self, EventData::ClientBytesSent(self.client_id, self.pending_bytes_sent));
+            self.pending_bytes_sent = 0;
+        }
+
+        if self.pending_bytes_received != 0 {
+            log!(self, EventData::ClientBytesReceived(self.client_id, self.pending_bytes_received));
+            self.pending_bytes_received = 0;
+        }
+    }
+
+    /// Summarizes this client's connection, which is considered failed if it ended with an IO
+    /// error or its destination couldn't be reached.
+    pub fn summary(&self, io_error: bool) -> ClientSummary {
+        ClientSummary {
+            username: self.username.clone(),
+            destination: self.destination.clone(),
+            connect_latency_micros: self.connect_latency.map(|latency| latency.as_micros() as u64),
+            duration_micros: self.started_at.elapsed().as_micros() as u64,
+            bytes_sent: self.bytes_sent,
+            bytes_received: self.bytes_received,
+            failed: io_error || (self.destination.is_some() && self.connect_latency.is_none()),
+        }
+    }
+}
+
+impl Drop for ClientContext {
+    fn drop(&mut self) {
+        // If the server is shutting down the client is dropped without logging it finished, but
+        // its bytes must still be counted.
+        self.flush_bytes();
+        self.state.clients.unregister(self.client_id);
     }
 }
 
@@ -127,6 +346,18 @@ macro_rules! log_socks_finished {
     };
 }
 
+#[macro_export]
+macro_rules! log_socks_summary {
+    ($cx:expr, $io_error:expr) => {
+        if let Some(sender) = &$cx.log_sender {
+            sender.send(dust_devil_core::logging::EventData::ClientConnectionSummary(
+                $cx.client_id,
+                $cx.summary($io_error),
+            ));
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! log_socks_unsupported_version {
     ($cx:expr, $version:expr) => {
@@ -295,46 +526,75 @@ macro_rules! log_socks_destination_shutdown {
 
 pub struct SandstormContext {
     pub manager_id: u64,
+    /// Identifies where this manager connected from, for its failed logins to count against.
+    pub source: LockoutTarget,
+    pub permissions: SandstormPermissions,
     pub state: Arc<ServerState>,
     pub log_sender: Option<LogSender>,
+    pub event_lag_policy: EventLagPolicy,
+    pub negotiation: SandstormNegotiation,
 }
 
 impl SandstormContext {
-    pub fn create(manager_id: u64, state: &Arc<ServerState>, log_sender: Option<LogSender>) -> Self {
+    pub fn create(
+        manager_id: u64,
+        source: LockoutTarget,
+        state: &Arc<ServerState>,
+        log_sender: Option<LogSender>,
+        event_lag_policy: EventLagPolicy,
+    ) -> Self {
         SandstormContext {
             manager_id,
+            source,
+            permissions: SandstormPermissions::none(),
             state: Arc::clone(state),
             log_sender,
+            event_lag_policy,
+            negotiation: SandstormNegotiation::version1(),
         }
     }
 
-    pub fn try_login(&self, username: &str, password: &str) -> Option<bool> {
-        self.state.users.try_login(username, password).map(|u| u == UserRole::Admin)
+    pub fn try_login(&mut self, username: &str, password: &str) -> Option<bool> {
+        let (role, permissions) = self.state.try_login(self.source.clone(), username, password, &self.log_sender)?;
+        if role != UserRole::Admin {
+            return Some(false);
+        }
+
+        self.permissions = permissions;
+        Some(true)
     }
 
-    pub async fn request_shutdown(&self) -> Receiver<()> {
+    pub fn permission_denied(&self, command: SandstormCommandType) {
+        log!(self, EventData::SandstormPermissionDenied(self.manager_id, command));
+    }
+
+    pub async fn request_shutdown(&self, drain_timeout: Option<u32>) -> Receiver<()> {
         log!(self, EventData::SandstormRequestedShutdown(self.manager_id));
         let (result_tx, result_rx) = oneshot::channel();
-        let _ = self.state.message_sender.send(MessageType::ShutdownRequest(result_tx)).await;
+        let _ = self
+            .state
+            .message_sender
+            .send(MessageType::ShutdownRequest(drain_timeout, result_tx))
+            .await;
 
         result_rx
     }
 
-    pub async fn list_socks5_sockets(&self) -> Receiver<Vec<SocketAddr>> {
+    pub async fn list_socks5_sockets(&self) -> Receiver<Vec<(SocketAddr, ListenerProfile)>> {
         let (result_tx, result_rx) = oneshot::channel();
         let _ = self.state.message_sender.send(MessageType::ListSocks5Sockets(result_tx)).await;
 
         result_rx
     }
 
-    pub async fn add_socks5_socket(&self, socket_address: SocketAddr) -> Receiver<Result<(), Error>> {
+    pub async fn add_socks5_socket(&self, socket_address: SocketAddr, profile: ListenerProfile) -> Receiver<Result<(), Error>> {
         log!(self, EventData::NewSocksSocketRequestedByManager(self.manager_id, socket_address));
 
         let (result_tx, result_rx) = oneshot::channel();
         let _ = self
             .state
             .message_sender
-            .send(MessageType::AddSocks5Socket(socket_address, result_tx))
+            .send(MessageType::AddSocks5Socket(socket_address, profile, result_tx))
             .await;
 
         result_rx
@@ -406,7 +666,13 @@ impl SandstormContext {
             }
         }
 
-        if self.state.users.insert(username.clone(), password, role) {
+        // A manager can't create an admin with more permissions than its own.
+        let permissions = match role {
+            UserRole::Admin => self.permissions,
+            UserRole::Regular => SandstormPermissions::all(),
+        };
+
+        if self.state.users.insert(username.clone(), password, role, permissions) {
             log!(self, EventData::UserRegisteredByManager(self.manager_id, username, role));
 
             AddUserResponse::Ok
@@ -415,31 +681,47 @@ impl SandstormContext {
         }
     }
 
-    pub fn update_user(&self, username: String, password: Option<String>, role: Option<UserRole>) -> UpdateUserResponse {
+    /// Updates a user, returning `None` if the user is an admin with permissions this session
+    /// doesn't have.
+    pub fn update_user(&self, username: String, password: Option<String>, role: Option<UserRole>) -> Option<UpdateUserResponse> {
         if password.is_none() && role.is_none() {
-            return UpdateUserResponse::NothingWasRequested;
+            return Some(UpdateUserResponse::NothingWasRequested);
         }
 
         let has_password = password.is_some();
-        match self.state.users.update(username.clone(), password, role) {
+        let response = match self.state.users.update(username.clone(), password, role, self.permissions) {
             Ok(Some(role)) => {
                 log!(self, EventData::UserUpdatedByManager(self.manager_id, username, role, has_password));
                 UpdateUserResponse::Ok
             }
             Ok(None) => UpdateUserResponse::CannotDeleteOnlyAdmin,
-            Err(()) => UpdateUserResponse::UserNotFound,
-        }
+            Err(UserChangeError::NotFound) => UpdateUserResponse::UserNotFound,
+            Err(UserChangeError::PermissionDenied) => {
+                self.permission_denied(SandstormCommandType::UpdateUser);
+                return None;
+            }
+        };
+
+        Some(response)
     }
 
-    pub fn delete_user(&self, username: String) -> DeleteUserResponse {
-        match self.state.users.delete(username) {
+    /// Deletes a user, returning `None` if the user is an admin with permissions this session
+    /// doesn't have.
+    pub fn delete_user(&self, username: String) -> Option<DeleteUserResponse> {
+        let response = match self.state.users.delete(username, self.permissions) {
             Ok(Some((username, role))) => {
                 log!(self, EventData::UserDeletedByManager(self.manager_id, username, role));
                 DeleteUserResponse::Ok
             }
             Ok(None) => DeleteUserResponse::CannotDeleteOnlyAdmin,
-            Err(()) => DeleteUserResponse::UserNotFound,
-        }
+            Err(UserChangeError::NotFound) => DeleteUserResponse::UserNotFound,
+            Err(UserChangeError::PermissionDenied) => {
+                self.permission_denied(SandstormCommandType::DeleteUser);
+                return None;
+            }
+        };
+
+        Some(response)
     }
 
     pub fn get_auth_methods(&self) -> Vec<(AuthMethod, bool)> {
@@ -470,13 +752,85 @@ impl SandstormContext {
         }
     }
 
-    pub async fn request_metrics_and_subscribe(&self) -> Option<Receiver<(Metrics, broadcast::Receiver<Arc<Event>>)>> {
+    pub async fn request_metrics_and_subscribe(&self, replay: Option<EventReplay>) -> Option<Receiver<MetricsAndSubscription>> {
+        match &self.state.metrics_requester {
+            Some(requester) => requester.request_metrics_and_subscribe(replay).await,
+            None => None,
+        }
+    }
+
+    pub async fn request_metrics_breakdown(&self, top: u16) -> Option<Receiver<MetricsBreakdown>> {
         match &self.state.metrics_requester {
-            Some(requester) => requester.request_metrics_and_subscribe().await,
+            Some(requester) => requester.request_breakdown(top).await,
             None => None,
         }
     }
 
+    pub fn list_clients(&self) -> Vec<ActiveClient> {
+        self.state.clients.list()
+    }
+
+    pub fn close_clients(&self, target: CloseClientsTarget) -> u32 {
+        self.state.clients.close(&target, |client_id| {
+            log!(self, EventData::ClientClosedByManager(client_id, self.manager_id));
+        })
+    }
+
+    pub fn list_lockouts(&self) -> Vec<(LockoutTarget, u64)> {
+        self.state.lockouts.snapshot(Instant::now())
+    }
+
+    pub fn clear_lockouts(&self, target: Option<LockoutTarget>) -> u32 {
+        let count = self.state.lockouts.clear(target.as_ref(), Instant::now());
+        if count != 0 {
+            log!(self, EventData::LockoutsClearedByManager(self.manager_id, target, count));
+        }
+
+        count
+    }
+
+    pub fn list_ip_filters(&self) -> Vec<(SocketAddr, IpFilterKind, IpNetwork)> {
+        self.state.ip_filters.take_snapshot()
+    }
+
+    pub fn add_ip_filter(&self, listener: SocketAddr, kind: IpFilterKind, network: IpNetwork) -> bool {
+        let added = self.state.ip_filters.insert(listener, kind, network);
+        if added {
+            log!(self, EventData::IpFilterAddedByManager(self.manager_id, listener, kind, network));
+        }
+
+        added
+    }
+
+    pub fn remove_ip_filter(&self, listener: SocketAddr, kind: IpFilterKind, network: IpNetwork) -> bool {
+        let removed = self.state.ip_filters.remove(listener, kind, network);
+        if removed {
+            log!(self, EventData::IpFilterRemovedByManager(self.manager_id, listener, kind, network));
+        }
+
+        removed
+    }
+
+    pub fn list_log_filters(&self) -> Vec<(LogSink, LogFilter)> {
+        match &self.state.log_filters {
+            Some(log_filters) => log_filters.list(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn set_log_filter(&self, sink: LogSink, filter: LogFilter) -> bool {
+        let set = match &self.state.log_filters {
+            Some(log_filters) => log_filters.set(sink, filter.clone()),
+            None => false,
+        };
+
+        if set {
+            log!(self, EventData::LogFilterChangedByManager(self.manager_id, sink, filter));
+        }
+
+        set
+    }
+
     pub fn get_buffer_size(&self) -> u32 {
         self.state.buffer_size.load(Ordering::Relaxed)
     }
@@ -0,0 +1,53 @@
+//! A bounded history of the most recently logged events, kept so Sandstorm managers can have the
+//! events that happened before they connected replayed to them.
+//!
+//! The history holds up to `EVENT_HISTORY_SIZE` events, discarding the oldest ones as new events
+//! come in. It is kept by the metrics tracker task, so that a replay is always consistent with the
+//! metrics snapshot sent alongside it.
+
+use std::{collections::VecDeque, sync::Arc};
+
+use dust_devil_core::{logging::Event, sandstorm::EventReplay};
+
+pub const EVENT_HISTORY_SIZE: usize = 0x400;
+
+pub struct EventHistory {
+    events: VecDeque<Arc<Event>>,
+    capacity: usize,
+}
+
+impl EventHistory {
+    pub fn new() -> Self {
+        Self::with_capacity(EVENT_HISTORY_SIZE)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        EventHistory {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, event: Arc<Event>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+
+        self.events.push_back(event);
+    }
+
+    /// Returns the events selected by the given replay, in the order in which they were logged.
+    pub fn replay(&self, replay: EventReplay) -> Vec<Arc<Event>> {
+        let skip = match replay {
+            EventReplay::Last(count) => self.events.len().saturating_sub(count as usize),
+            // Events are logged in chronological order, so we can search from the back.
+            EventReplay::Since(timestamp) => self.events.len() - self.events.iter().rev().take_while(|e| e.timestamp >= timestamp).count(),
+        };
+
+        self.events.iter().skip(skip).cloned().collect()
+    }
+}
@@ -0,0 +1,430 @@
+//! Handing off the server's listening sockets to a new dust-devil process, for restarting or
+//! upgrading the server without refusing any connections.
+//!
+//! A server started with `--handoff-socket <path>` listens for handoff requests on a Unix socket
+//! at that path. When a new process is started with the same path, it connects to the running
+//! server, which sends it all its listening sockets' file descriptors (via `SCM_RIGHTS`) along
+//! with which of them are SOCKS5 or Sandstorm sockets and their listener profiles. The new process
+//! then starts accepting through these sockets instead of binding its own, and takes over the
+//! handoff socket's path for future handoffs, while the old process stops accepting and drains
+//! its connections.
+//!
+//! The old process also hands off its runtime settings (the authentication methods, buffer size and
+//! IP filters), which may have been changed through Sandstorm since it started, and which replace
+//! the ones the new process loaded at startup.
+//!
+//! The exchange over the Unix socket goes as follows:
+//! - The old process sends a `Vec<(u8, ListenerProfile)>` with one entry for each socket, where
+//!   the `u8` indicates whether it's a SOCKS5 (0), Sandstorm (1) or Sandstorm Unix (2) socket.
+//! - The old process sends its `HandedOffSettings`.
+//! - The old process sends a single byte, carrying the sockets' file descriptors in the same order.
+//! - The new process replies with a single byte once it has taken the sockets, after which the old
+//!   process may stop listening on them.
+//!
+//! Both processes check that the process at the other end of the Unix socket runs under the same
+//! effective user, and give up on the exchange if it doesn't complete within `HANDOFF_TIMEOUT`.
+//!
+//! Handoffs are only supported on Unix platforms.
+
+use std::net::SocketAddr;
+
+use dust_devil_core::{
+    ip_filter::{IpFilterKind, IpNetwork},
+    listener_profile::ListenerProfile,
+};
+use tokio::net::TcpListener;
+
+use crate::utils::unix_socket::UnixListener;
+
+/// The listening sockets received from another process through a handoff.
+#[derive(Default)]
+pub struct InheritedListeners {
+    pub socks5: Vec<(TcpListener, ListenerProfile)>,
+    pub sandstorm: Vec<TcpListener>,
+    pub sandstorm_unix: Vec<UnixListener>,
+    /// The other process's runtime settings, or `None` if the listeners didn't come from a handoff.
+    pub settings: Option<HandedOffSettings>,
+}
+
+/// The runtime settings handed off alongside the listening sockets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HandedOffSettings {
+    pub no_auth_enabled: bool,
+    pub userpass_auth_enabled: bool,
+    pub buffer_size: u32,
+    pub ip_filters: Vec<(SocketAddr, IpFilterKind, IpNetwork)>,
+}
+
+impl InheritedListeners {
+    /// Gets the total amount of listening sockets.
+    pub fn count(&self) -> usize {
+        self.socks5.len() + self.sandstorm.len() + self.sandstorm_unix.len()
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{receive_listeners, send_listeners, HandoffListener, HandoffStream};
+
+#[cfg(not(unix))]
+pub use unsupported::{receive_listeners, send_listeners, HandoffListener, HandoffStream};
+
+/// Accepts an incoming handoff request on the given listener, or waits forever if there's none.
+pub async fn accept_handoff(listener: &Option<HandoffListener>) -> Result<HandoffStream, std::io::Error> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        collections::HashMap,
+        fs,
+        io::{Error, ErrorKind},
+        mem,
+        net::SocketAddr,
+        os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        ptr,
+        sync::Arc,
+        time::Duration,
+    };
+
+    use dust_devil_core::{
+        ip_filter::{IpFilterKind, IpNetwork},
+        listener_profile::ListenerProfile,
+        serialize::{ByteRead, ByteWrite},
+    };
+    use tokio::{
+        io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, Interest},
+        net::{TcpListener, UnixListener, UnixStream},
+        time::timeout,
+    };
+
+    use crate::utils::unix_socket;
+
+    use super::{HandedOffSettings, InheritedListeners};
+
+    impl ByteRead for HandedOffSettings {
+        async fn read<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Self, Error> {
+            Ok(Self {
+                no_auth_enabled: bool::read(reader).await?,
+                userpass_auth_enabled: bool::read(reader).await?,
+                buffer_size: u32::read(reader).await?,
+                ip_filters: <Vec<(SocketAddr, IpFilterKind, IpNetwork)> as ByteRead>::read(reader).await?,
+            })
+        }
+    }
+
+    impl ByteWrite for HandedOffSettings {
+        async fn write<W: AsyncWrite + Unpin + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+            (self.no_auth_enabled, self.userpass_auth_enabled, self.buffer_size)
+                .write(writer)
+                .await?;
+            self.ip_filters.as_slice().write(writer).await
+        }
+    }
+
+    /// The kind of a socket that is handed off, sent alongside its listener profile.
+    const SOCKS5_KIND: u8 = 0;
+    const SANDSTORM_KIND: u8 = 1;
+    const SANDSTORM_UNIX_KIND: u8 = 2;
+
+    /// The byte sent along with the file descriptors.
+    const FDS_MARKER: u8 = 0x46;
+
+    /// The byte the new process replies with once it has taken the sockets.
+    const HANDOFF_ACK: u8 = 0x06;
+
+    /// The maximum amount of file descriptors that may be sent in a single message.
+    const MAX_HANDOFF_FDS: usize = 253;
+
+    /// The maximum amount of time the exchange of sockets may take, so neither process hangs
+    /// waiting on the other (for example, if the other process isn't accepting handoffs).
+    const HANDOFF_TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub type HandoffStream = UnixStream;
+
+    pub struct HandoffListener {
+        listener: UnixListener,
+    }
+
+    impl HandoffListener {
+        /// Binds a Unix socket at the given path to listen for handoff requests, replacing any
+        /// existing file at said path.
+        pub fn bind(path: &str) -> Result<Self, Error> {
+            match fs::remove_file(path) {
+                Err(error) if error.kind() != ErrorKind::NotFound => return Err(error),
+                _ => {}
+            }
+
+            Ok(HandoffListener {
+                listener: UnixListener::bind(path)?,
+            })
+        }
+
+        pub async fn accept(&self) -> Result<HandoffStream, Error> {
+            let (stream, _) = self.listener.accept().await?;
+            check_peer_user(&stream)?;
+            Ok(stream)
+        }
+    }
+
+    /// Checks that the process at the other end of a handoff stream runs under our effective user,
+    /// as otherwise any local user able to reach the socket could take our listening sockets.
+    fn check_peer_user(stream: &UnixStream) -> Result<(), Error> {
+        let peer_uid = stream.peer_cred()?.uid();
+        match peer_uid == unsafe { libc::geteuid() } {
+            true => Ok(()),
+            false => Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!("The handoff's peer process runs under another user (uid {peer_uid})"),
+            )),
+        }
+    }
+
+    fn timed_out() -> Error {
+        Error::new(ErrorKind::TimedOut, "The handoff timed out")
+    }
+
+    /// Connects to a running server's handoff socket at the given path and receives its listening
+    /// sockets. Returns `Ok(None)` if there is no server listening at said path.
+    pub async fn receive_listeners(path: &str) -> Result<Option<InheritedListeners>, Error> {
+        let stream = match UnixStream::connect(path).await {
+            Ok(stream) => stream,
+            Err(error) if matches!(error.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        check_peer_user(&stream)?;
+        match timeout(HANDOFF_TIMEOUT, receive_listeners_from(stream)).await {
+            Ok(result) => result.map(Some),
+            Err(_) => Err(timed_out()),
+        }
+    }
+
+    async fn receive_listeners_from(mut stream: UnixStream) -> Result<InheritedListeners, Error> {
+        let kinds = <Vec<(u8, ListenerProfile)> as ByteRead>::read(&mut stream).await?;
+        let settings = HandedOffSettings::read(&mut stream).await?;
+        let fds = stream
+            .async_io(Interest::READABLE, || recv_fds(stream.as_raw_fd(), kinds.len()))
+            .await?;
+
+        if fds.len() != kinds.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Received a different amount of sockets than expected",
+            ));
+        }
+
+        let mut inherited = InheritedListeners {
+            settings: Some(settings),
+            ..Default::default()
+        };
+
+        for ((kind, profile), fd) in kinds.into_iter().zip(fds) {
+            if kind == SANDSTORM_UNIX_KIND {
+                match unix_socket::UnixListener::from_fd(fd, true)? {
+                    Some(listener) => inherited.sandstorm_unix.push(listener),
+                    None => return Err(Error::new(ErrorKind::InvalidData, "Received a Unix socket without a path")),
+                }
+
+                continue;
+            }
+
+            let listener = std::net::TcpListener::from(fd);
+            listener.set_nonblocking(true)?;
+            let listener = TcpListener::from_std(listener)?;
+
+            match kind {
+                SANDSTORM_KIND => inherited.sandstorm.push(listener),
+                _ => inherited.socks5.push((listener, profile)),
+            }
+        }
+
+        stream.write_u8(HANDOFF_ACK).await?;
+        Ok(inherited)
+    }
+
+    /// Sends the given listening sockets and runtime settings to a new process through a handoff
+    /// request's stream, and waits for it to confirm it has taken them.
+    pub async fn send_listeners(
+        mut stream: HandoffStream,
+        socks5: &[TcpListener],
+        profiles: &HashMap<SocketAddr, Arc<ListenerProfile>>,
+        sandstorm: &[TcpListener],
+        sandstorm_unix: &[unix_socket::UnixListener],
+        settings: &HandedOffSettings,
+    ) -> Result<(), Error> {
+        let (kinds, fds) = gather_listeners(socks5, profiles, sandstorm, sandstorm_unix);
+        if fds.len() > MAX_HANDOFF_FDS {
+            return Err(Error::new(ErrorKind::InvalidInput, "Too many listening sockets to hand off"));
+        }
+
+        let exchange = async {
+            kinds.as_slice().write(&mut stream).await?;
+            settings.write(&mut stream).await?;
+            stream.async_io(Interest::WRITABLE, || send_fds(stream.as_raw_fd(), &fds)).await?;
+            stream.read_u8().await
+        };
+
+        match timeout(HANDOFF_TIMEOUT, exchange).await {
+            Ok(Ok(HANDOFF_ACK)) => Ok(()),
+            Ok(Ok(_)) => Err(Error::new(ErrorKind::InvalidData, "Invalid handoff acknowledgement")),
+            Ok(Err(error)) => Err(error),
+            Err(_) => Err(timed_out()),
+        }
+    }
+
+    fn gather_listeners(
+        socks5: &[TcpListener],
+        profiles: &HashMap<SocketAddr, Arc<ListenerProfile>>,
+        sandstorm: &[TcpListener],
+        sandstorm_unix: &[unix_socket::UnixListener],
+    ) -> (Vec<(u8, ListenerProfile)>, Vec<RawFd>) {
+        let capacity = socks5.len() + sandstorm.len() + sandstorm_unix.len();
+        let mut kinds = Vec::with_capacity(capacity);
+        let mut fds = Vec::with_capacity(capacity);
+
+        for listener in socks5 {
+            let profile = listener.local_addr().ok().and_then(|a| profiles.get(&a));
+            kinds.push((SOCKS5_KIND, profile.map(|p| p.as_ref().clone()).unwrap_or_default()));
+            fds.push(listener.as_raw_fd());
+        }
+
+        for listener in sandstorm {
+            kinds.push((SANDSTORM_KIND, ListenerProfile::default()));
+            fds.push(listener.as_raw_fd());
+        }
+
+        for listener in sandstorm_unix {
+            kinds.push((SANDSTORM_UNIX_KIND, ListenerProfile::default()));
+            fds.push(listener.as_raw_fd());
+        }
+
+        (kinds, fds)
+    }
+
+    fn send_fds(socket: RawFd, fds: &[RawFd]) -> Result<(), Error> {
+        let data = [FDS_MARKER];
+        let mut iov = libc::iovec {
+            iov_base: data.as_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        };
+
+        let fds_size = mem::size_of_val(fds) as libc::c_uint;
+        let control_size = unsafe { libc::CMSG_SPACE(fds_size) } as usize;
+        let mut control = vec![0u64; control_size.div_ceil(mem::size_of::<u64>())];
+
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = control_size as _;
+
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(fds_size) as _;
+            ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg).cast::<RawFd>(), fds.len());
+
+            match libc::sendmsg(socket, &msg, 0) {
+                -1 => Err(Error::last_os_error()),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    fn recv_fds(socket: RawFd, max_fds: usize) -> Result<Vec<OwnedFd>, Error> {
+        let mut data = [0u8; 1];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr().cast(),
+            iov_len: data.len(),
+        };
+
+        let control_size = unsafe { libc::CMSG_SPACE((max_fds * mem::size_of::<RawFd>()) as libc::c_uint) } as usize;
+        let mut control = vec![0u64; control_size.div_ceil(mem::size_of::<u64>())];
+
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = control_size as _;
+
+        match unsafe { libc::recvmsg(socket, &mut msg, 0) } {
+            -1 => return Err(Error::last_os_error()),
+            0 => return Err(Error::from(ErrorKind::UnexpectedEof)),
+            _ => {}
+        }
+
+        // Take ownership of the received file descriptors first, so they get closed on error.
+        let mut fds = Vec::new();
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                    let data_size = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                    let data_ptr = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+                    for i in 0..(data_size / mem::size_of::<RawFd>()) {
+                        fds.push(OwnedFd::from_raw_fd(ptr::read_unaligned(data_ptr.add(i))));
+                    }
+                }
+
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+
+        if data[0] != FDS_MARKER || msg.msg_flags & libc::MSG_CTRUNC != 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid or truncated handoff message"));
+        }
+
+        Ok(fds)
+    }
+}
+
+#[cfg(not(unix))]
+mod unsupported {
+    use std::{
+        collections::HashMap,
+        io::{Error, ErrorKind},
+        net::SocketAddr,
+        sync::Arc,
+    };
+
+    use dust_devil_core::listener_profile::ListenerProfile;
+    use tokio::net::TcpListener;
+
+    use crate::utils::unix_socket::UnixListener;
+
+    use super::{HandedOffSettings, InheritedListeners};
+
+    pub enum HandoffStream {}
+
+    pub enum HandoffListener {}
+
+    impl HandoffListener {
+        pub fn bind(_path: &str) -> Result<Self, Error> {
+            Err(Error::new(ErrorKind::Unsupported, "Handoffs are only supported on Unix platforms"))
+        }
+
+        pub async fn accept(&self) -> Result<HandoffStream, Error> {
+            match *self {}
+        }
+    }
+
+    pub async fn receive_listeners(_path: &str) -> Result<Option<InheritedListeners>, Error> {
+        Ok(None)
+    }
+
+    pub async fn send_listeners(
+        stream: HandoffStream,
+        _socks5: &[TcpListener],
+        _profiles: &HashMap<SocketAddr, Arc<ListenerProfile>>,
+        _sandstorm: &[TcpListener],
+        _sandstorm_unix: &[UnixListener],
+        _settings: &HandedOffSettings,
+    ) -> Result<(), Error> {
+        match stream {}
+    }
+}
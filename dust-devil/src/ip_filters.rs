@@ -0,0 +1,90 @@
+//! Per-listener source IP allow/deny lists.
+//!
+//! Filters are keyed by the local address of the listening socket they apply to, and are kept
+//! regardless of whether a listener with that address is currently open. A connection is rejected
+//! if its source address matches any of the listener's deny filters, or if the listener has at
+//! least one allow filter and the source address matches none of them.
+
+use std::net::{IpAddr, SocketAddr};
+
+use dashmap::DashMap;
+use dust_devil_core::ip_filter::{IpFilterKind, IpNetwork};
+
+#[derive(Debug, Default)]
+struct ListenerFilters {
+    allow: Vec<IpNetwork>,
+    deny: Vec<IpNetwork>,
+}
+
+impl ListenerFilters {
+    fn list_mut(&mut self, kind: IpFilterKind) -> &mut Vec<IpNetwork> {
+        match kind {
+            IpFilterKind::Allow => &mut self.allow,
+            IpFilterKind::Deny => &mut self.deny,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct IpFilterManager {
+    filters: DashMap<SocketAddr, ListenerFilters>,
+}
+
+impl IpFilterManager {
+    pub fn new() -> Self {
+        IpFilterManager { filters: DashMap::new() }
+    }
+
+    /// Returns whether a connection from the given address to the listener at the given local
+    /// address is allowed by the filters.
+    pub fn allows(&self, listener: SocketAddr, address: IpAddr) -> bool {
+        match self.filters.get(&listener) {
+            Some(filters) => {
+                !filters.deny.iter().any(|n| n.contains(address))
+                    && (filters.allow.is_empty() || filters.allow.iter().any(|n| n.contains(address)))
+            }
+            None => true,
+        }
+    }
+
+    /// Adds a filter to a listener. Returns false if an identical filter already existed.
+    pub fn insert(&self, listener: SocketAddr, kind: IpFilterKind, network: IpNetwork) -> bool {
+        let mut entry = self.filters.entry(listener).or_default();
+        let list = entry.list_mut(kind);
+        if list.contains(&network) {
+            false
+        } else {
+            list.push(network);
+            true
+        }
+    }
+
+    /// Removes a filter from a listener. Returns false if no such filter existed.
+    pub fn remove(&self, listener: SocketAddr, kind: IpFilterKind, network: IpNetwork) -> bool {
+        let removed = match self.filters.get_mut(&listener) {
+            Some(mut entry) => {
+                let list = entry.list_mut(kind);
+                let len_before = list.len();
+                list.retain(|n| *n != network);
+                list.len() != len_before
+            }
+            None => false,
+        };
+
+        self.filters.remove_if(&listener, |_, f| f.allow.is_empty() && f.deny.is_empty());
+        removed
+    }
+
+    /// Returns all the filters, sorted by listener address and with allow filters first.
+    pub fn take_snapshot(&self) -> Vec<(SocketAddr, IpFilterKind, IpNetwork)> {
+        let mut result = Vec::new();
+        for entry in self.filters.iter() {
+            let listener = *entry.key();
+            result.extend(entry.allow.iter().map(|n| (listener, IpFilterKind::Allow, *n)));
+            result.extend(entry.deny.iter().map(|n| (listener, IpFilterKind::Deny, *n)));
+        }
+
+        result.sort_by_key(|(listener, kind, _)| (*listener, *kind as u8));
+        result
+    }
+}
@@ -0,0 +1,226 @@
+//! Brute-force protection for logins, by tracking failed login attempts per source (an IP address,
+//! or the user ID of a process connecting through a Unix socket), per username from each source,
+//! and per username from any source.
+//!
+//! Once a target accumulates `FAILURES_BEFORE_LOCKOUT` consecutive failed attempts (or
+//! `USERNAME_FAILURES_BEFORE_LOCKOUT` for a username from any source), it is locked out for
+//! `BASE_LOCKOUT_DURATION`. Each subsequent lockout of the same target doubles that duration, up to
+//! `MAX_LOCKOUT_DURATION`. While a lockout is active, logins involving that target fail immediately
+//! without the credentials being checked.
+//!
+//! A target's record (and with it, the lockout escalation) is forgotten once it has been inactive
+//! for `FORGET_AFTER`. At most around `MAX_RECORDS` records are kept, so when a new target fails to
+//! log in with no room left, the least recently active record not under a lockout is forgotten to
+//! make room. If every record is under a lockout, the new target's failure isn't recorded.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use dust_devil_core::sandstorm::LockoutTarget;
+
+pub const FAILURES_BEFORE_LOCKOUT: u32 = 5;
+pub const USERNAME_FAILURES_BEFORE_LOCKOUT: u32 = 20;
+pub const BASE_LOCKOUT_DURATION: Duration = Duration::from_secs(30);
+pub const MAX_LOCKOUT_DURATION: Duration = Duration::from_secs(60 * 60);
+pub const FORGET_AFTER: Duration = Duration::from_secs(15 * 60);
+pub const MAX_RECORDS: usize = 10000;
+
+/// Returns the amount of consecutive failed attempts that cause the given target to be locked out.
+fn failures_before_lockout(target: &LockoutTarget) -> u32 {
+    match target {
+        LockoutTarget::Username(_) => USERNAME_FAILURES_BEFORE_LOCKOUT,
+        _ => FAILURES_BEFORE_LOCKOUT,
+    }
+}
+
+#[derive(Debug)]
+struct LockoutRecord {
+    id: u64,
+    failures: u32,
+    lockout_count: u32,
+    locked_until: Option<Instant>,
+    last_activity: Instant,
+}
+
+impl LockoutRecord {
+    fn is_locked_out(&self, now: Instant) -> bool {
+        self.locked_until.is_some_and(|until| now < until)
+    }
+}
+
+#[derive(Debug, Default)]
+struct LockoutRecords {
+    records: HashMap<LockoutTarget, LockoutRecord>,
+    /// The records' targets ordered by their last activity (and ID, to tell apart records with the
+    /// same last activity), so the least active one is found without going through all of them.
+    by_activity: BTreeMap<(Instant, u64), LockoutTarget>,
+    next_id: u64,
+}
+
+/// Sets a record's last activity, keeping its position in `by_activity` up to date.
+fn set_last_activity(
+    by_activity: &mut BTreeMap<(Instant, u64), LockoutTarget>,
+    target: &LockoutTarget,
+    record: &mut LockoutRecord,
+    last_activity: Instant,
+) {
+    by_activity.remove(&(record.last_activity, record.id));
+    record.last_activity = last_activity;
+    by_activity.insert((last_activity, record.id), target.clone());
+}
+
+impl LockoutRecords {
+    /// Forgets the least recently active record, unless it is under a lockout. A record under a
+    /// lockout has its last activity set to the lockout's end, so if the least recently active
+    /// record is under a lockout, so are all the others. Returns whether a record was forgotten.
+    fn forget_least_active(&mut self, now: Instant) -> bool {
+        let target = match self.by_activity.first_key_value() {
+            Some((_, target)) => target,
+            None => return false,
+        };
+
+        if self.records.get(target).is_some_and(|r| r.is_locked_out(now)) {
+            return false;
+        }
+
+        if let Some((_, target)) = self.by_activity.pop_first() {
+            self.records.remove(&target);
+        }
+
+        true
+    }
+
+    /// Forgets the records that match the given predicate.
+    fn retain<F: FnMut(&LockoutTarget, &mut LockoutRecord) -> bool>(&mut self, mut f: F) {
+        let by_activity = &mut self.by_activity;
+        self.records.retain(|target, record| {
+            let keep = f(target, record);
+            if !keep {
+                by_activity.remove(&(record.last_activity, record.id));
+            }
+            keep
+        });
+    }
+}
+
+#[derive(Debug)]
+pub struct LockoutManager {
+    records: Mutex<LockoutRecords>,
+}
+
+impl LockoutManager {
+    pub fn new() -> Self {
+        LockoutManager {
+            records: Mutex::new(LockoutRecords::default()),
+        }
+    }
+
+    /// Returns whether the given target currently has an active lockout.
+    pub fn is_locked_out(&self, target: &LockoutTarget, now: Instant) -> bool {
+        let records = self.records.lock().unwrap();
+        records.records.get(target).is_some_and(|r| r.is_locked_out(now))
+    }
+
+    /// Registers a failed login attempt for the given target. If this causes the target to be
+    /// locked out, returns the amount of failed attempts that caused it and the lockout's duration.
+    pub fn register_failure(&self, target: LockoutTarget, now: Instant) -> Option<(u32, Duration)> {
+        let mut guard = self.records.lock().unwrap();
+        let records = &mut *guard;
+        if records.records.len() >= MAX_RECORDS && !records.records.contains_key(&target) && !records.forget_least_active(now) {
+            return None;
+        }
+
+        let failures_before_lockout = failures_before_lockout(&target);
+        let next_id = &mut records.next_id;
+        let record = records.records.entry(target.clone()).or_insert_with(|| {
+            *next_id += 1;
+            LockoutRecord {
+                id: *next_id,
+                failures: 0,
+                lockout_count: 0,
+                locked_until: None,
+                last_activity: now,
+            }
+        });
+
+        if record.is_locked_out(now) {
+            return None;
+        }
+
+        record.failures += 1;
+        if record.failures < failures_before_lockout {
+            set_last_activity(&mut records.by_activity, &target, record, now);
+            return None;
+        }
+
+        let failures = record.failures;
+        let multiplier = 1u32.checked_shl(record.lockout_count).unwrap_or(u32::MAX);
+        let duration = BASE_LOCKOUT_DURATION.saturating_mul(multiplier).min(MAX_LOCKOUT_DURATION);
+
+        record.failures = 0;
+        record.lockout_count += 1;
+        record.locked_until = Some(now + duration);
+        set_last_activity(&mut records.by_activity, &target, record, now + duration);
+        Some((failures, duration))
+    }
+
+    /// Registers a successful login attempt for the given target, resetting its failure count.
+    pub fn register_success(&self, target: &LockoutTarget) {
+        let mut records = self.records.lock().unwrap();
+        if let Some(record) = records.records.get_mut(target) {
+            record.failures = 0;
+        }
+    }
+
+    /// Ends all the lockouts that have expired and forgets the records that have been inactive for
+    /// long enough. Returns the targets whose lockouts ended.
+    pub fn expire(&self, now: Instant) -> Vec<LockoutTarget> {
+        let mut ended = Vec::new();
+        self.records.lock().unwrap().retain(|target, record| {
+            if record.locked_until.is_some_and(|until| until <= now) {
+                record.locked_until = None;
+                ended.push(target.clone());
+            }
+
+            record.locked_until.is_some() || now.saturating_duration_since(record.last_activity) < FORGET_AFTER
+        });
+
+        ended
+    }
+
+    /// Returns the currently active lockouts alongside the amount of seconds remaining on each.
+    pub fn snapshot(&self, now: Instant) -> Vec<(LockoutTarget, u64)> {
+        let records = self.records.lock().unwrap();
+        records
+            .records
+            .iter()
+            .filter_map(|(target, r)| {
+                let until = r.locked_until.filter(|until| now < *until)?;
+                Some((target.clone(), until.saturating_duration_since(now).as_secs_f64().ceil() as u64))
+            })
+            .collect()
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        let records = self.records.lock().unwrap();
+        debug_assert_eq!(records.records.len(), records.by_activity.len());
+        records.records.len()
+    }
+
+    /// Clears the lockouts on the given target (which, for a username, also include those on said
+    /// username from each source), or all active lockouts if `None`, also forgetting their escalation.
+    /// Returns the amount of lockouts cleared.
+    pub fn clear(&self, target: Option<&LockoutTarget>, now: Instant) -> u32 {
+        let mut count = 0;
+        self.records.lock().unwrap().retain(|key, r| {
+            let clear = target.map_or(true, |t| t.covers(key)) && r.is_locked_out(now);
+            count += clear as u32;
+            !clear
+        });
+        count
+    }
+}
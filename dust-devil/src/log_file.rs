@@ -0,0 +1,326 @@
+//! Writing logs to a file, with support for rotating it.
+//!
+//! The log file may be rotated once it reaches a maximum size, once a fixed interval has passed
+//! since it was opened or last rotated, or both. When rotated, the file at `<path>` is renamed to
+//! `<path>.1`, previously rotated files are shifted to `<path>.2`, `<path>.3`, and so on, and those
+//! beyond the configured number of retained files are deleted. Rotated files may optionally be
+//! compressed with gzip, in which case a `.gz` suffix is added to their names. Compression runs in
+//! the background, so the logger doesn't stop writing events while a file is compressed.
+//!
+//! On Unix platforms, receiving a SIGUSR1 signal makes the log file be closed and opened again,
+//! so it may instead be rotated by an external tool such as logrotate.
+
+use std::{
+    io::{Error, ErrorKind},
+    path::PathBuf,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    fs::{self, File},
+    io::{AsyncWrite, AsyncWriteExt, BufWriter},
+    select,
+    task::JoinHandle,
+    time::Instant,
+};
+
+use crate::{
+    logger::{LogMaintenance, LogWriter},
+    printlnif,
+    utils::gzip,
+};
+
+#[cfg(unix)]
+use unix::ReopenSignal;
+
+#[cfg(not(unix))]
+use unsupported::ReopenSignal;
+
+/// The amount of rotated log files kept by default.
+pub const DEFAULT_LOG_KEEP: u32 = 5;
+
+/// The suffix added to the names of rotated log files once they're compressed.
+pub const COMPRESSED_SUFFIX: &str = ".gz";
+
+const FILE_BUFFER_SIZE: usize = 0x2000;
+
+/// The settings for rotating the log file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogRotation {
+    /// The size in bytes the log file may reach before it's rotated.
+    pub max_size: Option<u32>,
+
+    /// The amount of seconds after which the log file is rotated.
+    pub interval: Option<u32>,
+
+    /// The amount of rotated log files to keep, or `None` for [`DEFAULT_LOG_KEEP`].
+    pub keep: Option<u32>,
+
+    /// Whether to compress rotated log files with gzip.
+    pub compress: bool,
+}
+
+impl LogRotation {
+    /// Returns whether any of the rotation settings are specified.
+    pub fn is_specified(&self) -> bool {
+        self.max_size.is_some() || self.interval.is_some() || self.keep.is_some() || self.compress
+    }
+
+    pub fn keep_count(&self) -> u32 {
+        self.keep.unwrap_or(DEFAULT_LOG_KEEP)
+    }
+}
+
+/// Gets the path a log file is moved to after being rotated `index` times.
+pub fn rotated_path(path: &str, index: u32, compressed: bool) -> String {
+    match compressed {
+        true => format!("{path}.{index}{COMPRESSED_SUFFIX}"),
+        false => format!("{path}.{index}"),
+    }
+}
+
+/// A log file, which is rotated according to a [`LogRotation`] and reopened on SIGUSR1.
+pub struct LogFile {
+    verbose: bool,
+    path: String,
+    rotation: LogRotation,
+    writer: BufWriter<File>,
+    size: u64,
+    next_rotation: Option<Instant>,
+    reopen_signal: ReopenSignal,
+    compression_handle: Option<JoinHandle<()>>,
+}
+
+async fn open_file(path: &str) -> Result<File, Error> {
+    File::options().write(true).read(false).create(true).append(true).open(path).await
+}
+
+async fn remove_if_exists(path: &str) -> Result<(), Error> {
+    match fs::remove_file(path).await {
+        Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+async fn rename_if_exists(from: &str, to: &str) -> Result<(), Error> {
+    match fs::rename(from, to).await {
+        Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+fn compress_file(source: &str, destination: &str) -> Result<(), Error> {
+    let reader = std::fs::File::open(source)?;
+    let writer = std::io::BufWriter::new(std::fs::File::create(destination)?);
+    if let Err(error) = gzip::compress(reader, writer) {
+        let _ = std::fs::remove_file(destination);
+        return Err(error);
+    }
+
+    std::fs::remove_file(source)
+}
+
+impl LogFile {
+    /// Opens a log file for appending, creating it and its parent directories if they don't exist.
+    pub async fn open(verbose: bool, path: &str, rotation: LogRotation) -> Result<Self, Error> {
+        if let Some(parent) = PathBuf::from(path).parent() {
+            printlnif!(verbose, "Creating directory for logger file: {parent:?}");
+            tokio::fs::DirBuilder::new().recursive(true).create(parent).await?;
+        }
+
+        printlnif!(verbose, "Logger opening up file: {path}");
+        let file = open_file(path).await?;
+        let size = file.metadata().await?.len();
+
+        let next_rotation = rotation.interval.map(|secs| Instant::now() + Duration::from_secs(secs as u64));
+
+        Ok(LogFile {
+            verbose,
+            path: path.to_string(),
+            rotation,
+            writer: BufWriter::with_capacity(FILE_BUFFER_SIZE, file),
+            size,
+            next_rotation,
+            reopen_signal: ReopenSignal::new(),
+            compression_handle: None,
+        })
+    }
+
+    /// Flushes and closes the log file, then opens it again at the same path.
+    async fn reopen(&mut self) -> Result<(), Error> {
+        self.writer.flush().await?;
+        let file = open_file(&self.path).await?;
+        self.size = file.metadata().await?.len();
+        self.writer = BufWriter::with_capacity(FILE_BUFFER_SIZE, file);
+        Ok(())
+    }
+
+    async fn shift_rotated_files(&self, keep: u32) -> Result<(), Error> {
+        for compressed in [false, true] {
+            remove_if_exists(&rotated_path(&self.path, keep, compressed)).await?;
+        }
+
+        for index in (1..keep).rev() {
+            for compressed in [false, true] {
+                let from = rotated_path(&self.path, index, compressed);
+                rename_if_exists(&from, &rotated_path(&self.path, index + 1, compressed)).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn rotate(&mut self) -> Result<(), Error> {
+        printlnif!(self.verbose, "Rotating log file {}", self.path);
+        self.writer.flush().await?;
+
+        // The previous rotated file must finish compressing before it's shifted.
+        if let Some(handle) = self.compression_handle.take() {
+            let _ = handle.await;
+        }
+
+        let keep = self.rotation.keep_count();
+        if keep == 0 {
+            remove_if_exists(&self.path).await?;
+        } else {
+            self.shift_rotated_files(keep).await?;
+            fs::rename(&self.path, rotated_path(&self.path, 1, false)).await?;
+        }
+
+        self.reopen().await?;
+
+        if self.rotation.compress && keep != 0 {
+            let verbose = self.verbose;
+            let source = rotated_path(&self.path, 1, false);
+            let destination = rotated_path(&self.path, 1, true);
+            self.compression_handle = Some(tokio::task::spawn_blocking(move || match compress_file(&source, &destination) {
+                Ok(()) => printlnif!(verbose, "Compressed rotated log file into {destination}"),
+                Err(error) => eprintln!("Failed to compress rotated log file {source}: {error}"),
+            }));
+        }
+
+        Ok(())
+    }
+}
+
+impl LogWriter for LogFile {
+    async fn maintenance_needed(&mut self) -> LogMaintenance {
+        if self.rotation.max_size.is_some_and(|max_size| self.size >= max_size as u64) {
+            return LogMaintenance::Rotate;
+        }
+
+        let next_rotation = self.next_rotation;
+        let rotation_timer = async move {
+            match next_rotation {
+                Some(instant) => tokio::time::sleep_until(instant).await,
+                None => std::future::pending().await,
+            }
+        };
+
+        select! {
+            _ = rotation_timer => LogMaintenance::Rotate,
+            _ = self.reopen_signal.recv() => LogMaintenance::Reopen,
+        }
+    }
+
+    async fn maintain(&mut self, maintenance: LogMaintenance) -> Result<(), Error> {
+        match maintenance {
+            LogMaintenance::Rotate => {
+                self.next_rotation = self.rotation.interval.map(|secs| Instant::now() + Duration::from_secs(secs as u64));
+                let result = self.rotate().await;
+
+                // If rotating failed, don't try again until the file grows by another max size.
+                if result.is_err() {
+                    self.size = 0;
+                }
+
+                result
+            }
+            LogMaintenance::Reopen => {
+                printlnif!(self.verbose, "Reopening log file {}", self.path);
+                self.reopen().await
+            }
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), Error> {
+        let result = self.writer.shutdown().await;
+        if let Some(handle) = self.compression_handle.take() {
+            let _ = handle.await;
+        }
+
+        result
+    }
+}
+
+impl AsyncWrite for LogFile {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.writer).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = result {
+            this.size += written as u64;
+        }
+
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        Pin::new(&mut self.get_mut().writer).poll_shutdown(cx)
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use tokio::signal::unix::{signal, Signal, SignalKind};
+
+    /// Listens for the SIGUSR1 signal, which requests the log file to be reopened.
+    pub struct ReopenSignal {
+        signal: Option<Signal>,
+    }
+
+    impl ReopenSignal {
+        pub fn new() -> Self {
+            let signal = match signal(SignalKind::user_defined1()) {
+                Ok(signal) => Some(signal),
+                Err(error) => {
+                    eprintln!("Failed to listen for SIGUSR1, the log file won't be reopened on signal: {error}");
+                    None
+                }
+            };
+
+            ReopenSignal { signal }
+        }
+
+        pub async fn recv(&mut self) {
+            let received = match &mut self.signal {
+                Some(signal) => signal.recv().await,
+                None => None,
+            };
+
+            if received.is_none() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod unsupported {
+    pub struct ReopenSignal;
+
+    impl ReopenSignal {
+        pub fn new() -> Self {
+            ReopenSignal
+        }
+
+        pub async fn recv(&mut self) {
+            std::future::pending().await
+        }
+    }
+}
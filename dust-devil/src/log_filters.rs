@@ -0,0 +1,158 @@
+//! The event filters of each of the log sinks, which may be replaced at runtime.
+//!
+//! Each active log sink gets a [`SinkFilter`], through which its logger task decides which events
+//! to write. The filters are replaced through the [`LogFilterManager`], and the logger tasks see
+//! the new filter starting from the next event. All syslog targets share the same filter.
+//!
+//! Sandstorm managers may also filter the events streamed to them, each through its own
+//! [`StreamFilter`]. Since a manager may start streaming at any time, the details of the clients
+//! that connected before that are looked up in the [`ClientRegistry`].
+
+use std::{collections::HashMap, net::SocketAddr};
+
+use dust_devil_core::{
+    log_filter::{EventStreamFilter, LogFilter, LogSink},
+    logging::EventData,
+};
+use tokio::sync::watch;
+
+use crate::clients::ClientRegistry;
+
+#[derive(Debug)]
+pub struct LogFilterManager {
+    filters: Vec<(LogSink, watch::Sender<LogFilter>)>,
+}
+
+impl LogFilterManager {
+    pub fn new() -> Self {
+        LogFilterManager { filters: Vec::new() }
+    }
+
+    /// Registers a sink as active and creates a [`SinkFilter`] for it, starting with the last of
+    /// the given filters that applies to it, either because it's for that sink or for all sinks.
+    pub fn register(&mut self, sink: LogSink, initial_filters: &[(Option<LogSink>, LogFilter)]) -> SinkFilter {
+        if let Some((_, sender)) = self.filters.iter().find(|(s, _)| *s == sink) {
+            return SinkFilter::new(sender.subscribe());
+        }
+
+        let filter = initial_filters
+            .iter()
+            .rev()
+            .find(|(s, _)| s.is_none() || *s == Some(sink))
+            .map(|(_, filter)| filter.clone())
+            .unwrap_or_default();
+
+        let (sender, receiver) = watch::channel(filter);
+        self.filters.push((sink, sender));
+        SinkFilter::new(receiver)
+    }
+
+    /// Gets the filters of the active sinks.
+    pub fn list(&self) -> Vec<(LogSink, LogFilter)> {
+        self.filters.iter().map(|(sink, sender)| (*sink, sender.borrow().clone())).collect()
+    }
+
+    /// Replaces the filter of a sink. Returns false if the sink is not active.
+    pub fn set(&self, sink: LogSink, filter: LogFilter) -> bool {
+        match self.filters.iter().find(|(s, _)| *s == sink) {
+            Some((_, sender)) => {
+                sender.send_replace(filter);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// A log sink's view of its filter.
+pub struct SinkFilter {
+    receiver: watch::Receiver<LogFilter>,
+    usernames: HashMap<u64, String>,
+}
+
+impl SinkFilter {
+    fn new(receiver: watch::Receiver<LogFilter>) -> Self {
+        SinkFilter {
+            receiver,
+            usernames: HashMap::new(),
+        }
+    }
+
+    /// Returns whether an event should be written to the sink. This must be called with every
+    /// event, in order, as it keeps track of which user each client authenticated as.
+    pub fn accepts(&mut self, data: &EventData) -> bool {
+        // Usernames are always tracked, so a filter set later applies to clients that already
+        // authenticated.
+        if let EventData::ClientAuthenticatedWithUserpass(client_id, username, true) = data {
+            self.usernames.insert(*client_id, username.clone());
+        }
+
+        let username = data.client_id().and_then(|client_id| self.usernames.get(&client_id));
+        let accepted = self.receiver.borrow().matches(data, username.map(|s| s.as_str()));
+
+        if let EventData::ClientConnectionFinished(client_id, _, _, _) = data {
+            self.usernames.remove(client_id);
+        }
+
+        accepted
+    }
+}
+
+/// A Sandstorm manager's event stream filter.
+pub struct StreamFilter {
+    filter: EventStreamFilter,
+    clients: HashMap<u64, (Option<String>, Option<SocketAddr>)>,
+}
+
+impl StreamFilter {
+    pub fn new(filter: EventStreamFilter) -> Self {
+        StreamFilter {
+            filter,
+            clients: HashMap::new(),
+        }
+    }
+
+    /// Replaces the filter, keeping track of the clients seen so far.
+    pub fn set(&mut self, filter: EventStreamFilter) {
+        self.filter = filter;
+    }
+
+    /// Forgets the clients seen so far, for when events were missed. They're looked up in the
+    /// registry again as their events come in.
+    pub fn reset(&mut self) {
+        self.clients.clear();
+    }
+
+    /// Returns whether an event should be streamed to the manager. This must be called with every
+    /// event, in order, as it keeps track of which user each client authenticated as. Clients not
+    /// seen before are looked up in the registry, which doesn't know about clients that already
+    /// disconnected (as may happen with replayed events).
+    pub fn accepts(&mut self, data: &EventData, registry: &ClientRegistry) -> bool {
+        let mut client = data.client_id().map(|client_id| {
+            self.clients.entry(client_id).or_insert_with(|| {
+                let (username, listener) = registry.lookup(client_id).unwrap_or_default();
+                match data {
+                    // The registry may already know which user a client authenticated as, but at
+                    // this event the client hadn't yet.
+                    EventData::NewClientConnectionAccepted(_, _) => (None, listener),
+                    _ => (username, listener),
+                }
+            })
+        });
+
+        if let (Some((username, _)), EventData::ClientAuthenticatedWithUserpass(_, new_username, true)) = (&mut client, data) {
+            *username = Some(new_username.clone());
+        }
+
+        let accepted = match client {
+            Some((username, listener)) => self.filter.matches(data, username.as_deref(), *listener),
+            None => self.filter.matches(data, None, None),
+        };
+
+        if let EventData::ClientConnectionFinished(client_id, _, _, _) = data {
+            self.clients.remove(client_id);
+        }
+
+        accepted
+    }
+}
@@ -0,0 +1,534 @@
+//! Formatting events into log lines, either as human-readable text or as JSON.
+//!
+//! The text format writes each event's timestamp followed by its [`fmt::Display`] message, for
+//! example `[2024-03-01 18:30:00] Client 5 sent 1024 bytes`.
+//!
+//! The JSON format writes each event as a JSON object on its own line. These objects always have
+//! the following fields:
+//! * `timestamp`: The time of the event, as seconds since the UNIX epoch.
+//! * `time`: The time of the event in RFC 3339 format, in the server's local UTC offset.
+//! * `type`: The event's type, as returned by [`EventData::type_name`], such as `client_bytes_sent`.
+//! * `message`: The same human-readable message written by the text format.
+//!
+//! Followed by fields specific to the event's type, which always have the same names and types:
+//! * `client_id`, `manager_id`: The ID of the SOCKS5 client or Sandstorm manager (number).
+//! * `address`: The socket address a connection came from (string).
+//! * `listener`: The address of a listening socket (string, or `null` if unknown).
+//! * `destination`: The address a client connects to (string). For `client_socks_request` events
+//!   this is the requested host, alongside `destination_type` (`ipv4`, `ipv6` or `domainname`)
+//!   and `port` (number).
+//! * `domainname`: A domainname being resolved (string).
+//! * `path`: The path to a file or Unix socket (string).
+//! * `username`, `role`, `user`: A user's name, role, or full specification (string).
+//! * `success`, `enabled`, `password_changed`, `failed`, `resynced`: Flags for authentications,
+//!   toggles, connection outcomes and event stream resynchronizations (boolean).
+//! * `auth_method`, `command`, `filter_kind`, `network`, `lockout_target`: Names of auth methods,
+//!   Sandstorm commands, IP filter kinds, networks and lockout targets (string).
+//! * `bytes`, `bytes_sent`, `bytes_received`: Byte counts (number).
+//! * `count`, `failures`, `seconds`, `buffer_size`, `version`, `atyp`: Other amounts (number).
+//! * `counter_epoch`: The time since which the historic metrics have been counted, as seconds
+//!   since the UNIX epoch (number).
+//! * `connect_latency_micros`, `duration_micros`: Durations in microseconds (number, or `null` if
+//!   unknown).
+//! * `error`: The error message for events describing failures (string). For events that may
+//!   either succeed or fail, such as `client_connection_finished`, this is omitted on success.
+//!
+//! If a log sink falls behind and events are lost, an object with `type` set to `events_lost` and
+//! the amount of events lost in `count` is written instead.
+//!
+//! New fields and event types may be added, but existing ones are never renamed or removed.
+
+use std::{
+    fmt::{self, Write as _},
+    io::Write,
+    str::FromStr,
+};
+
+use dust_devil_core::{
+    logging::{Event, EventData},
+    socks5::SocksRequestAddress,
+};
+use time::{OffsetDateTime, UtcOffset};
+
+/// The format log lines are written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            s if s.eq_ignore_ascii_case("text") => Ok(Self::Text),
+            s if s.eq_ignore_ascii_case("json") => Ok(Self::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+impl LogFormat {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Json => "json",
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
+
+fn local_time(timestamp: i64, utc_offset: UtcOffset) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .map(|t| t.to_offset(utc_offset))
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
+
+/// Writes an event as a single line, terminated by a newline, in the given format.
+pub fn write_event(out: &mut Vec<u8>, format: LogFormat, event: &Event, utc_offset: UtcOffset) {
+    let t = local_time(event.timestamp, utc_offset);
+    match format {
+        LogFormat::Text => {
+            let _ = writeln!(
+                out,
+                "[{:04}-{:02}-{:02} {:02}:{:02}:{:02}] {}",
+                t.year(),
+                t.month() as u8,
+                t.day(),
+                t.hour(),
+                t.minute(),
+                t.second(),
+                event.data
+            );
+        }
+        LogFormat::Json => {
+            let mut object = JsonObject::begin(out);
+            object.number("timestamp", event.timestamp);
+            object.string("time", Rfc3339(t));
+            object.string("type", event.data.type_name());
+            object.string("message", &event.data);
+            write_event_fields(&mut object, &event.data);
+            object.end();
+        }
+    }
+}
+
+/// Writes a line indicating that some events were lost, in the given format.
+pub fn write_lost_events(out: &mut Vec<u8>, format: LogFormat, lost_count: u64, utc_offset: UtcOffset) {
+    match format {
+        LogFormat::Text => {
+            let _ = writeln!(out, "ERROR!! {lost_count} events lost due to slowdown!");
+        }
+        LogFormat::Json => {
+            let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+            let mut object = JsonObject::begin(out);
+            object.number("timestamp", timestamp);
+            object.string("time", Rfc3339(local_time(timestamp, utc_offset)));
+            object.string("type", "events_lost");
+            object.number("count", lost_count);
+            object.end();
+        }
+    }
+}
+
+struct Rfc3339(OffsetDateTime);
+
+impl fmt::Display for Rfc3339 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let t = self.0;
+        let offset = t.offset();
+        let sign = if offset.is_negative() { '-' } else { '+' };
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{sign}{:02}:{:02}",
+            t.year(),
+            t.month() as u8,
+            t.day(),
+            t.hour(),
+            t.minute(),
+            t.second(),
+            offset.whole_hours().unsigned_abs(),
+            offset.minutes_past_hour().unsigned_abs(),
+        )
+    }
+}
+
+/// Writes a single-line JSON object into a buffer.
+struct JsonObject<'a> {
+    out: &'a mut Vec<u8>,
+    empty: bool,
+}
+
+impl<'a> JsonObject<'a> {
+    fn begin(out: &'a mut Vec<u8>) -> Self {
+        out.push(b'{');
+        JsonObject { out, empty: true }
+    }
+
+    fn key(&mut self, name: &str) {
+        if !self.empty {
+            self.out.push(b',');
+        }
+
+        self.empty = false;
+        write_json_string(self.out, name);
+        self.out.push(b':');
+    }
+
+    fn end(self) {
+        self.out.extend_from_slice(b"}\n");
+    }
+}
+
+impl EventFieldWriter for JsonObject<'_> {
+    fn string(&mut self, name: &str, value: impl fmt::Display) {
+        self.key(name);
+        let mut s = String::new();
+        let _ = write!(s, "{value}");
+        write_json_string(self.out, &s);
+    }
+
+    fn null(&mut self, name: &str) {
+        self.key(name);
+        self.out.extend_from_slice(b"null");
+    }
+
+    fn number(&mut self, name: &str, value: impl Into<i128>) {
+        self.key(name);
+        let _ = write!(self.out, "{}", value.into());
+    }
+
+    fn boolean(&mut self, name: &str, value: bool) {
+        self.key(name);
+        let _ = write!(self.out, "{value}");
+    }
+}
+
+fn write_json_string(out: &mut Vec<u8>, s: &str) {
+    out.push(b'"');
+    for c in s.chars() {
+        match c {
+            '"' => out.extend_from_slice(b"\\\""),
+            '\\' => out.extend_from_slice(b"\\\\"),
+            '\n' => out.extend_from_slice(b"\\n"),
+            '\r' => out.extend_from_slice(b"\\r"),
+            '\t' => out.extend_from_slice(b"\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => {
+                let mut buf = [0u8; 4];
+                out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+    }
+    out.push(b'"');
+}
+
+/// Receives the fields specific to an event's type, as described in the module documentation.
+pub trait EventFieldWriter {
+    fn string(&mut self, name: &str, value: impl fmt::Display);
+    fn null(&mut self, name: &str);
+    fn number(&mut self, name: &str, value: impl Into<i128>);
+    fn boolean(&mut self, name: &str, value: bool);
+
+    fn optional_string(&mut self, name: &str, value: Option<impl fmt::Display>) {
+        match value {
+            Some(value) => self.string(name, value),
+            None => self.null(name),
+        }
+    }
+}
+
+/// Writes the fields specific to an event's type into an [`EventFieldWriter`].
+pub fn write_event_fields<W: EventFieldWriter>(o: &mut W, data: &EventData) {
+    match data {
+        EventData::NewSocks5Socket(listener)
+        | EventData::RemovedSocks5Socket(listener)
+        | EventData::NewSandstormSocket(listener)
+        | EventData::RemovedSandstormSocket(listener)
+        | EventData::Socks5SocketRestoredFromState(listener)
+        | EventData::SandstormSocketRestoredFromState(listener)
+        | EventData::Socks5SocketInherited(listener)
+        | EventData::SandstormSocketInherited(listener)
+        | EventData::NewMetricsSocket(listener) => {
+            o.string("listener", listener);
+        }
+        EventData::FailedBindSocks5Socket(listener, error)
+        | EventData::FailedBindSandstormSocket(listener, error)
+        | EventData::FailedBindMetricsSocket(listener, error) => {
+            o.string("listener", listener);
+            o.string("error", error);
+        }
+        EventData::FailedBindAnySocketAborting | EventData::ShutdownSignalReceived | EventData::DrainCompleted => {}
+        EventData::LoadingUsersFromFile(path)
+        | EventData::SavingUsersToFile(path)
+        | EventData::RestoringStateFromFile(path)
+        | EventData::SavingStateToFile(path)
+        | EventData::ListenersInheritedFromHandoff(path)
+        | EventData::ListeningForHandoff(path)
+        | EventData::NewSandstormUnixSocket(path)
+        | EventData::SandstormUnixSocketRestoredFromState(path)
+        | EventData::SandstormUnixSocketInherited(path) => {
+            o.string("path", path);
+        }
+        EventData::UsersLoadedFromFile(path, result) => {
+            o.string("path", path);
+            match result {
+                Ok(count) => o.number("count", *count),
+                Err(error) => o.string("error", error),
+            }
+        }
+        EventData::UsersSavedToFile(path, result) => {
+            o.string("path", path);
+            match result {
+                Ok(count) => o.number("count", *count),
+                Err(error) => o.string("error", error),
+            }
+        }
+        EventData::MetricsRestoredFromFile(path, result) => {
+            o.string("path", path);
+            match result {
+                Ok(counter_epoch) => o.number("counter_epoch", *counter_epoch),
+                Err(error) => o.string("error", error),
+            }
+        }
+        EventData::StateSavedToFile(path, result) | EventData::MetricsSavedToFile(path, result) => {
+            o.string("path", path);
+            if let Err(error) = result {
+                o.string("error", error);
+            }
+        }
+        EventData::HandoffReceiveFailed(path, error)
+        | EventData::FailedBindHandoffSocket(path, error)
+        | EventData::FailedBindSandstormUnixSocket(path, error) => {
+            o.string("path", path);
+            o.string("error", error);
+        }
+        EventData::StartingUpWithSingleDefaultUser(user) => {
+            o.string("user", user);
+        }
+        EventData::UserRegisteredByArgs(username, role) | EventData::UserReplacedByArgs(username, role) => {
+            o.string("username", username);
+            o.string("role", role);
+        }
+        EventData::NewClientConnectionAccepted(client_id, address) => {
+            o.number("client_id", *client_id);
+            o.string("address", address);
+        }
+        EventData::ClientConnectionAcceptFailed(listener, error) | EventData::SandstormConnectionAcceptFailed(listener, error) => {
+            o.optional_string("listener", listener.as_ref());
+            o.string("error", error);
+        }
+        EventData::ClientRequestedUnsupportedVersion(client_id, version)
+        | EventData::ClientRequestedUnsupportedUserpassVersion(client_id, version) => {
+            o.number("client_id", *client_id);
+            o.number("version", *version);
+        }
+        EventData::ClientRequestedUnsupportedCommand(client_id, command) => {
+            o.number("client_id", *client_id);
+            o.number("command", *command);
+        }
+        EventData::ClientRequestedUnsupportedAtyp(client_id, atyp) => {
+            o.number("client_id", *client_id);
+            o.number("atyp", *atyp);
+        }
+        EventData::ClientSelectedAuthMethod(client_id, auth_method) => {
+            o.number("client_id", *client_id);
+            o.string("auth_method", auth_method);
+        }
+        EventData::ClientNoAcceptableAuthMethod(client_id)
+        | EventData::ClientFailedToConnectToDestination(client_id)
+        | EventData::ClientSourceShutdown(client_id)
+        | EventData::ClientDestinationShutdown(client_id) => {
+            o.number("client_id", *client_id);
+        }
+        EventData::ClientAuthenticatedWithUserpass(client_id, username, success) => {
+            o.number("client_id", *client_id);
+            o.string("username", username);
+            o.boolean("success", *success);
+        }
+        EventData::ClientSocksRequest(client_id, request) => {
+            o.number("client_id", *client_id);
+            match &request.destination {
+                SocksRequestAddress::IPv4(ipv4) => {
+                    o.string("destination_type", "ipv4");
+                    o.string("destination", ipv4);
+                }
+                SocksRequestAddress::IPv6(ipv6) => {
+                    o.string("destination_type", "ipv6");
+                    o.string("destination", ipv6);
+                }
+                SocksRequestAddress::Domainname(domainname) => {
+                    o.string("destination_type", "domainname");
+                    o.string("destination", domainname);
+                }
+            }
+            o.number("port", request.port);
+        }
+        EventData::ClientDnsLookup(client_id, domainname) => {
+            o.number("client_id", *client_id);
+            o.string("domainname", domainname);
+        }
+        EventData::ClientAttemptingConnect(client_id, destination) | EventData::ClientConnectedToDestination(client_id, destination) => {
+            o.number("client_id", *client_id);
+            o.string("destination", destination);
+        }
+        EventData::ClientConnectionAttemptBindFailed(client_id, error)
+        | EventData::ClientConnectionAttemptConnectFailed(client_id, error) => {
+            o.number("client_id", *client_id);
+            o.string("error", error);
+        }
+        EventData::ClientBytesSent(client_id, bytes) | EventData::ClientBytesReceived(client_id, bytes) => {
+            o.number("client_id", *client_id);
+            o.number("bytes", *bytes);
+        }
+        EventData::ClientConnectionSummary(client_id, summary) => {
+            o.number("client_id", *client_id);
+            o.optional_string("username", summary.username.as_ref());
+            o.optional_string("destination", summary.destination.as_ref());
+            match summary.connect_latency_micros {
+                Some(micros) => o.number("connect_latency_micros", micros),
+                None => o.null("connect_latency_micros"),
+            }
+            o.number("duration_micros", summary.duration_micros);
+            o.number("bytes_sent", summary.bytes_sent);
+            o.number("bytes_received", summary.bytes_received);
+            o.boolean("failed", summary.failed);
+        }
+        EventData::ClientClosedByManager(client_id, manager_id) => {
+            o.number("client_id", *client_id);
+            o.number("manager_id", *manager_id);
+        }
+        EventData::EventStreamLagged(count, metrics) => {
+            o.number("count", *count);
+            o.boolean("resynced", metrics.is_some());
+        }
+        EventData::ClientConnectionFinished(client_id, bytes_sent, bytes_received, result) => {
+            o.number("client_id", *client_id);
+            o.number("bytes_sent", *bytes_sent);
+            o.number("bytes_received", *bytes_received);
+            if let Err(error) = result {
+                o.string("error", error);
+            }
+        }
+        EventData::NewSandstormConnectionAccepted(manager_id, address) => {
+            o.number("manager_id", *manager_id);
+            o.string("address", address);
+        }
+        EventData::NewSandstormUnixConnectionAccepted(manager_id, path) => {
+            o.number("manager_id", *manager_id);
+            o.string("path", path);
+        }
+        EventData::SandstormRequestedUnsupportedVersion(manager_id, version) => {
+            o.number("manager_id", *manager_id);
+            o.number("version", *version);
+        }
+        EventData::SandstormAuthenticatedAs(manager_id, username, success) => {
+            o.number("manager_id", *manager_id);
+            o.string("username", username);
+            o.boolean("success", *success);
+        }
+        EventData::NewSocksSocketRequestedByManager(manager_id, listener)
+        | EventData::RemoveSocksSocketRequestedByManager(manager_id, listener)
+        | EventData::NewSandstormSocketRequestedByManager(manager_id, listener)
+        | EventData::RemoveSandstormSocketRequestedByManager(manager_id, listener) => {
+            o.number("manager_id", *manager_id);
+            o.string("listener", listener);
+        }
+        EventData::UserRegisteredByManager(manager_id, username, role) | EventData::UserDeletedByManager(manager_id, username, role) => {
+            o.number("manager_id", *manager_id);
+            o.string("username", username);
+            o.string("role", role);
+        }
+        EventData::UserUpdatedByManager(manager_id, username, role, password_changed) => {
+            o.number("manager_id", *manager_id);
+            o.string("username", username);
+            o.string("role", role);
+            o.boolean("password_changed", *password_changed);
+        }
+        EventData::AuthMethodToggledByManager(manager_id, auth_method, enabled) => {
+            o.number("manager_id", *manager_id);
+            o.string("auth_method", auth_method);
+            o.boolean("enabled", *enabled);
+        }
+        EventData::BufferSizeChangedByManager(manager_id, buffer_size) => {
+            o.number("manager_id", *manager_id);
+            o.number("buffer_size", *buffer_size);
+        }
+        EventData::SandstormRequestedShutdown(manager_id) => {
+            o.number("manager_id", *manager_id);
+        }
+        EventData::SandstormPermissionDenied(manager_id, command) => {
+            o.number("manager_id", *manager_id);
+            o.string("command", command);
+        }
+        EventData::SandstormConnectionFinished(manager_id, result) => {
+            o.number("manager_id", *manager_id);
+            if let Err(error) = result {
+                o.string("error", error);
+            }
+        }
+        EventData::LoginLockoutStarted(target, failures, seconds) => {
+            o.string("lockout_target", target);
+            o.number("failures", *failures);
+            o.number("seconds", *seconds);
+        }
+        EventData::LoginLockoutEnded(target) => {
+            o.string("lockout_target", target);
+        }
+        EventData::LockoutsClearedByManager(manager_id, target, count) => {
+            o.number("manager_id", *manager_id);
+            o.optional_string("lockout_target", target.as_ref());
+            o.number("count", *count);
+        }
+        EventData::ConnectionRejectedByIpFilter(listener, address) => {
+            o.string("listener", listener);
+            o.string("address", address);
+        }
+        EventData::IpFilterAddedByManager(manager_id, listener, kind, network)
+        | EventData::IpFilterRemovedByManager(manager_id, listener, kind, network) => {
+            o.number("manager_id", *manager_id);
+            o.string("listener", listener);
+            o.string("filter_kind", kind);
+            o.string("network", network);
+        }
+        EventData::AuthMethodRestoredFromState(auth_method, enabled) => {
+            o.string("auth_method", auth_method);
+            o.boolean("enabled", *enabled);
+        }
+        EventData::BufferSizeRestoredFromState(buffer_size) => {
+            o.number("buffer_size", *buffer_size);
+        }
+        EventData::IpFilterRestoredFromState(listener, kind, network) => {
+            o.string("listener", listener);
+            o.string("filter_kind", kind);
+            o.string("network", network);
+        }
+        EventData::DrainStarted(count, seconds) => {
+            o.number("count", *count);
+            o.number("seconds", *seconds);
+        }
+        EventData::DrainProgress(count) | EventData::DrainTimedOut(count) | EventData::DrainAborted(count) => {
+            o.number("count", *count);
+        }
+        EventData::ListenersHandedOff(count) | EventData::ListenersInheritedFromSystemd(count) => {
+            o.number("count", *count);
+        }
+        EventData::HandoffSendFailed(error) | EventData::SystemdActivationFailed(error) | EventData::SystemdNotifyFailed(error) => {
+            o.string("error", error);
+        }
+        EventData::LogFilterChangedByManager(manager_id, sink, filter) => {
+            o.number("manager_id", *manager_id);
+            o.string("sink", sink);
+            o.string("filter", filter);
+        }
+    }
+}
@@ -1,13 +1,13 @@
-use std::{io::Error, path::PathBuf, sync::Arc};
+use std::{future, io::Error, sync::Arc};
 
 use dust_devil_core::{
+    log_filter::{LogFilter, LogSink},
     logging::{Event, EventData},
-    sandstorm::Metrics,
+    sandstorm::{EventReplay, Metrics, MetricsBreakdown},
 };
-use time::{OffsetDateTime, UtcOffset};
+use time::UtcOffset;
 use tokio::{
-    fs::File,
-    io::{stdout, AsyncWrite, AsyncWriteExt, BufWriter},
+    io::{stdout, AsyncWrite, AsyncWriteExt, BufWriter, Stdout},
     select,
     sync::{
         broadcast::{self, error::RecvError, Receiver, Sender},
@@ -16,13 +16,18 @@ use tokio::{
     task::{JoinError, JoinHandle},
 };
 
-use std::io::Write;
-
-use crate::printlnif;
+use crate::{
+    event_history::EventHistory,
+    log_file::{LogFile, LogRotation},
+    log_filters::{LogFilterManager, SinkFilter},
+    log_format::{self, LogFormat},
+    metrics::{ServerBreakdown, ServerMetrics},
+    printlnif,
+    system_log::{self, SystemLog, SystemLogSocket},
+};
 
 const EVENT_LOG_BUFFER: usize = 0x1000;
 const STDOUT_BUFFER_SIZE: usize = 0x2000;
-const FILE_BUFFER_SIZE: usize = 0x2000;
 const PARSE_VEC_SIZE: usize = 0x100;
 const METRICS_REQUEST_CHANNEL_SIZE: usize = 0x10;
 
@@ -32,6 +37,8 @@ pub struct LogManager {
     metrics_task_handle: JoinHandle<()>,
     log_stdout_task_handle: Option<JoinHandle<()>>,
     log_file_task_handle: Option<JoinHandle<()>>,
+    system_log_task_handles: Vec<JoinHandle<()>>,
+    filters: Arc<LogFilterManager>,
 }
 
 pub struct LogSender {
@@ -40,47 +47,80 @@ pub struct LogSender {
 
 enum MetricsRequest {
     Metrics(oneshot::Sender<Metrics>),
-    MetricsAndSubscribe(oneshot::Sender<(Metrics, Receiver<Arc<Event>>)>),
+    MetricsAndSubscribe(Option<EventReplay>, oneshot::Sender<MetricsAndSubscription>),
+    Snapshot(oneshot::Sender<ServerMetrics>),
+    Breakdown(u16, oneshot::Sender<MetricsBreakdown>),
+    Restore(Box<ServerMetrics>),
 }
 
+/// A metrics snapshot, the replayed events requested (if any) and a receiver for the events logged
+/// after them.
+pub type MetricsAndSubscription = (Metrics, Vec<Arc<Event>>, Receiver<Arc<Event>>);
+
+#[derive(Clone)]
 pub struct MetricsRequester {
     request_sender: mpsc::Sender<MetricsRequest>,
 }
 
+/// A kind of maintenance a [`LogWriter`] may need.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogMaintenance {
+    Rotate,
+    Reopen,
+}
+
+/// A sink the logger tasks write events to, which may occasionally need maintenance, such as a log
+/// file that needs to be rotated.
+pub trait LogWriter: AsyncWrite + Unpin {
+    /// Waits until this writer needs maintenance. By default this never completes.
+    async fn maintenance_needed(&mut self) -> LogMaintenance {
+        future::pending().await
+    }
+
+    /// Performs maintenance on this writer. Errors are reported, but the logger keeps writing.
+    async fn maintain(&mut self, _maintenance: LogMaintenance) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Flushes and closes this writer once there are no more events to write.
+    async fn close(&mut self) -> Result<(), Error> {
+        self.shutdown().await
+    }
+}
+
+impl LogWriter for BufWriter<Stdout> {}
+
 async fn logger_task<W>(
     verbose: bool,
     mut log_receiver: Receiver<Arc<Event>>,
     utc_offset: UtcOffset,
+    format: LogFormat,
+    mut filter: SinkFilter,
     writer: &mut W,
     name: &str,
 ) -> Result<(), Error>
 where
-    W: AsyncWrite + Unpin + ?Sized,
+    W: LogWriter,
 {
     let mut parse_vec = Vec::<u8>::with_capacity(PARSE_VEC_SIZE);
 
     loop {
-        match log_receiver.recv().await {
-            Ok(event) => {
-                let t = OffsetDateTime::from_unix_timestamp(event.timestamp)
-                    .map(|t| t.to_offset(utc_offset))
-                    .unwrap_or(OffsetDateTime::UNIX_EPOCH);
-
-                let _ = writeln!(
-                    parse_vec,
-                    "[{:04}-{:02}-{:02} {:02}:{:02}:{:02}] {}",
-                    t.year(),
-                    t.month() as u8,
-                    t.day(),
-                    t.hour(),
-                    t.minute(),
-                    t.second(),
-                    event.data
-                );
-            }
-            Err(RecvError::Lagged(lost_count)) => {
-                let _ = writeln!(parse_vec, "ERROR!! {lost_count} events lost due to slowdown!");
+        // Maintenance is done in between events, so no events are lost while it happens.
+        let received = select! {
+            biased;
+            maintenance = writer.maintenance_needed() => {
+                if let Err(error) = writer.maintain(maintenance).await {
+                    eprintln!("Logger task for {name} failed to perform {maintenance:?} maintenance: {error}");
+                }
+                continue;
             }
+            received = log_receiver.recv() => received,
+        };
+
+        match received {
+            Ok(event) if filter.accepts(&event.data) => log_format::write_event(&mut parse_vec, format, &event, utc_offset),
+            Ok(_) => continue,
+            Err(RecvError::Lagged(lost_count)) => log_format::write_lost_events(&mut parse_vec, format, lost_count, utc_offset),
             Err(RecvError::Closed) => break,
         }
 
@@ -94,17 +134,23 @@ where
 
     printlnif!(verbose, "Logger task for {name} exited event receiving loop, shutting down");
 
-    writer.shutdown().await?;
-    Ok(())
+    writer.close().await
 }
 
-async fn logger_task_wrapper<W>(verbose: bool, log_receiver: Receiver<Arc<Event>>, utc_offset: UtcOffset, mut writer: W, name: &str)
-where
-    W: AsyncWrite + Unpin,
+async fn logger_task_wrapper<W>(
+    verbose: bool,
+    log_receiver: Receiver<Arc<Event>>,
+    utc_offset: UtcOffset,
+    format: LogFormat,
+    filter: SinkFilter,
+    mut writer: W,
+    name: &str,
+) where
+    W: LogWriter,
 {
     printlnif!(verbose, "Logger task for {name} started");
 
-    match logger_task(verbose, log_receiver, utc_offset, &mut writer, name).await {
+    match logger_task(verbose, log_receiver, utc_offset, format, filter, &mut writer, name).await {
         Ok(()) => printlnif!(verbose, "Logger task for {name} finished"),
         Err(error) => eprintln!("Logger task for {name} finished with error: {error}"),
     }
@@ -113,48 +159,22 @@ where
 async fn metrics_task(verbose: bool, mut log_receiver: Receiver<Arc<Event>>, mut request_receiver: mpsc::Receiver<MetricsRequest>) {
     printlnif!(verbose, "Metrics tracker task started");
 
-    let mut current_client_connections: u32 = 0;
-    let mut historic_client_connections: u64 = 0;
-    let mut client_bytes_sent: u64 = 0;
-    let mut client_bytes_received: u64 = 0;
-    let mut current_sandstorm_connections: u32 = 0;
-    let mut historic_sandstorm_connections: u64 = 0;
+    let mut metrics = ServerMetrics::starting_at(time::OffsetDateTime::now_utc().unix_timestamp());
+    let mut breakdown = ServerBreakdown::default();
+    let mut history = EventHistory::new();
 
     loop {
         select! {
             biased;
             event = log_receiver.recv() => {
-                let event = match event {
-                    Ok(evt) => evt,
-                    Err(RecvError::Lagged(amount)) => {
-                        eprintln!("Warning! Metrics tracker lagged behind {amount} events!");
-                        continue;
+                match event {
+                    Ok(event) => {
+                        metrics.record(&event.data);
+                        breakdown.record(&event.data);
+                        history.push(event);
                     }
+                    Err(RecvError::Lagged(amount)) => eprintln!("Warning! Metrics tracker lagged behind {amount} events!"),
                     Err(RecvError::Closed) => break,
-                };
-
-                match event.data {
-                    EventData::NewClientConnectionAccepted(_, _) => {
-                        current_client_connections += 1;
-                        historic_client_connections += 1;
-                    }
-                    EventData::ClientConnectionFinished(_, _, _, _) => {
-                        current_client_connections -= 1;
-                    }
-                    EventData::ClientBytesSent(_, count) => {
-                        client_bytes_sent += count;
-                    }
-                    EventData::ClientBytesReceived(_, count) => {
-                        client_bytes_received += count;
-                    }
-                    EventData::NewSandstormConnectionAccepted(_, _) => {
-                        current_sandstorm_connections += 1;
-                        historic_sandstorm_connections += 1;
-                    }
-                    EventData::SandstormConnectionFinished(_, _) => {
-                        current_sandstorm_connections -= 1;
-                    }
-                    _ => {}
                 }
             }
             request = request_receiver.recv() => {
@@ -163,20 +183,18 @@ async fn metrics_task(verbose: bool, mut log_receiver: Receiver<Arc<Event>>, mut
                     None => break,
                 };
 
-                let metrics = Metrics {
-                    current_client_connections,
-                    historic_client_connections,
-                    client_bytes_sent,
-                    client_bytes_received,
-                    current_sandstorm_connections,
-                    historic_sandstorm_connections,
-                };
-
                 match request {
                     MetricsRequest::Metrics(sender) => {
-                        let _ = sender.send(metrics);
+                        let _ = sender.send(metrics.sandstorm_metrics());
                     },
-                    MetricsRequest::MetricsAndSubscribe(sender) => {
+                    MetricsRequest::Snapshot(sender) => {
+                        let _ = sender.send(metrics.clone());
+                    }
+                    MetricsRequest::Breakdown(top, sender) => {
+                        let _ = sender.send(breakdown.top(top));
+                    }
+                    MetricsRequest::Restore(previous) => metrics.restore(&previous),
+                    MetricsRequest::MetricsAndSubscribe(replay, sender) => {
                         // Note: log_receiver.resubcribe() makes a new receiver that receives values _after_ the resubcribe.
                         // This means, in the Sandstorm protocol, the metrics sent won't be truly synchronized with the event
                         // stream. Note however that this branch is last in a biased select! block, and therefore won't
@@ -187,7 +205,10 @@ async fn metrics_task(verbose: bool, mut log_receiver: Receiver<Arc<Event>>, mut
                         // Since the option to fully fix this would be to have a second broadcast channel that is synchronized
                         // with the events, and all events are relayed from the original broadcast to this second broadcast by
                         // this task, for performance reasons it makes more sense to just accept this minor issue.
-                        let _ = sender.send((metrics, log_receiver.resubscribe()));
+                        // The replayed events are taken from the same point in the stream, so they're consistent with both the
+                        // metrics snapshot and the new receiver (modulo the issue described above).
+                        let replayed = replay.map(|r| history.replay(r)).unwrap_or_default();
+                        let _ = sender.send((metrics.sandstorm_metrics(), replayed, log_receiver.resubscribe()));
                     }
                 }
             }
@@ -201,8 +222,8 @@ fn setup_logger_tasks(
     verbose: bool,
     log_receiver: Receiver<Arc<Event>>,
     metrics_request_receiver: mpsc::Receiver<MetricsRequest>,
-    log_to_stdout: bool,
-    file: Option<File>,
+    stdout_sink: Option<(LogFormat, SinkFilter)>,
+    file: Option<(LogFile, LogFormat, SinkFilter)>,
 ) -> (JoinHandle<()>, Option<JoinHandle<()>>, Option<JoinHandle<()>>) {
     let log_receiver1 = log_receiver.resubscribe();
     let metrics_tracker_task = tokio::spawn(async move {
@@ -222,70 +243,123 @@ fn setup_logger_tasks(
 
     // Note: `Stdout` is already buffered, as it's wrapped in a `LineWriter` that internally uses a `BufWriter`
     // (not the tokio one, the std one). However, this buffer is (currently) only 1024 bytes.
-    let maybe_stdout_task_handle = if log_to_stdout {
+    let maybe_stdout_task_handle = if let Some((stdout_format, stdout_filter)) = stdout_sink {
         let stdout_writer = BufWriter::with_capacity(STDOUT_BUFFER_SIZE, stdout());
         let log_receiver1 = log_receiver.resubscribe();
         let log_stdout_task_handle = tokio::spawn(async move {
-            logger_task_wrapper(verbose, log_receiver1, local_utc_offset, stdout_writer, "stdout").await;
+            logger_task_wrapper(
+                verbose,
+                log_receiver1,
+                local_utc_offset,
+                stdout_format,
+                stdout_filter,
+                stdout_writer,
+                "stdout",
+            )
+            .await;
         });
         Some(log_stdout_task_handle)
     } else {
         None
     };
 
-    let maybe_file_task_handle = file.map(|f| {
-        let file_writer = BufWriter::with_capacity(FILE_BUFFER_SIZE, f);
+    let maybe_file_task_handle = file.map(|(file_writer, file_format, file_filter)| {
         tokio::spawn(async move {
-            logger_task_wrapper(verbose, log_receiver, local_utc_offset, file_writer, "file").await;
+            logger_task_wrapper(
+                verbose,
+                log_receiver,
+                local_utc_offset,
+                file_format,
+                file_filter,
+                file_writer,
+                "file",
+            )
+            .await;
         })
     });
 
     (metrics_tracker_task, maybe_stdout_task_handle, maybe_file_task_handle)
 }
 
-async fn create_file(verbose: bool, path: &str) -> Option<File> {
-    if let Some(parent) = PathBuf::from(path).parent() {
-        printlnif!(verbose, "Creating directory for logger file: {parent:?}");
-        if let Err(error) = tokio::fs::DirBuilder::new().recursive(true).create(parent).await {
-            eprintln!("Failed to create directory for log file {path}: {error}");
-            return None;
+async fn create_file(verbose: bool, path: &str, rotation: LogRotation) -> Option<LogFile> {
+    match LogFile::open(verbose, path, rotation).await {
+        Ok(file) => {
+            printlnif!(verbose, "Logger successfully opened up file: {path}");
+            Some(file)
+        }
+        Err(err) => {
+            eprintln!("Failed to open log file {path}: {err}");
+            None
         }
     }
+}
 
-    printlnif!(verbose, "Logger opening up file: {path}");
-    let file_result = File::options().write(true).read(false).create(true).append(true).open(path).await;
-
-    match file_result {
-        Ok(f) => {
-            printlnif!(verbose, "Logger successfully opened up file: {path}");
-            Some(f)
+async fn connect_system_log(verbose: bool, target: &SystemLog) -> Option<SystemLogSocket> {
+    match SystemLogSocket::connect(target).await {
+        Ok(socket) => {
+            printlnif!(verbose, "Logger successfully connected to {target}");
+            Some(socket)
         }
         Err(err) => {
-            eprintln!("Failed to open log file {path}: {err}");
+            eprintln!("Failed to connect to {target}: {err}");
             None
         }
     }
 }
 
 impl LogManager {
-    pub async fn new(verbose: bool, log_to_stdout: bool, log_to_file: Option<&str>) -> Self {
+    /// Creates a `LogManager` and starts its tasks. Logs are written to stdout if a format is given
+    /// for it, to a file if a path is given, rotated according to `log_rotation`, and sent to each
+    /// of the given system logs. Each of these sinks starts with the last of `log_filters` that
+    /// applies to it.
+    pub async fn new(
+        verbose: bool,
+        stdout_format: Option<LogFormat>,
+        log_to_file: Option<&str>,
+        file_format: LogFormat,
+        log_rotation: LogRotation,
+        system_logs: &[SystemLog],
+        log_filters: &[(Option<LogSink>, LogFilter)],
+    ) -> Self {
         let (log_sender, log_receiver) = broadcast::channel::<Arc<Event>>(EVENT_LOG_BUFFER);
+        let mut filters = LogFilterManager::new();
+
+        let stdout_sink = stdout_format.map(|format| (format, filters.register(LogSink::Stdout, log_filters)));
 
         let file = if let Some(path) = log_to_file {
-            create_file(verbose, path).await
+            create_file(verbose, path, log_rotation)
+                .await
+                .map(|file| (file, file_format, filters.register(LogSink::File, log_filters)))
         } else {
             printlnif!(
-                !log_to_stdout && verbose,
+                stdout_format.is_none() && system_logs.is_empty() && verbose,
                 "Logger doesn't have stdout nor file enabled. Yet you did turn on verbose prints? The audacity."
             );
 
             None
         };
 
+        let mut system_log_task_handles = Vec::with_capacity(system_logs.len());
+        for target in system_logs {
+            if let Some(socket) = connect_system_log(verbose, target).await {
+                let sink = match target {
+                    SystemLog::Syslog(_) => LogSink::Syslog,
+                    SystemLog::Journald => LogSink::Journald,
+                };
+
+                let filter = filters.register(sink, log_filters);
+                let log_receiver1 = log_receiver.resubscribe();
+                let target = target.clone();
+                system_log_task_handles.push(tokio::spawn(async move {
+                    system_log::system_log_task(verbose, log_receiver1, target, socket, filter).await;
+                }));
+            }
+        }
+
         let (metrics_request_sender, metrics_request_receiver) = mpsc::channel(METRICS_REQUEST_CHANNEL_SIZE);
 
         let (metrics_task_handle, log_stdout_task_handle, log_file_task_handle) =
-            setup_logger_tasks(verbose, log_receiver, metrics_request_receiver, log_to_stdout, file);
+            setup_logger_tasks(verbose, log_receiver, metrics_request_receiver, stdout_sink, file);
 
         LogManager {
             log_sender,
@@ -293,6 +367,8 @@ impl LogManager {
             metrics_task_handle,
             log_stdout_task_handle,
             log_file_task_handle,
+            system_log_task_handles,
+            filters: Arc::new(filters),
         }
     }
 
@@ -304,6 +380,10 @@ impl LogManager {
         MetricsRequester::new(self.metrics_request_sender.clone())
     }
 
+    pub fn filter_manager(&self) -> Arc<LogFilterManager> {
+        Arc::clone(&self.filters)
+    }
+
     pub async fn join(self) -> Result<(), JoinError> {
         drop(self.log_sender);
         drop(self.metrics_request_sender);
@@ -318,6 +398,10 @@ impl LogManager {
             handle.await?;
         }
 
+        for handle in self.system_log_task_handles {
+            handle.await?;
+        }
+
         Ok(())
     }
 }
@@ -327,6 +411,13 @@ impl LogSender {
         LogSender { log_sender }
     }
 
+    /// Creates a sender whose events are received by the returned receiver, with no logger behind.
+    #[cfg(test)]
+    pub fn channel() -> (Self, Receiver<Arc<Event>>) {
+        let (log_sender, log_receiver) = broadcast::channel(EVENT_LOG_BUFFER);
+        (Self::new(log_sender), log_receiver)
+    }
+
     pub fn send(&self, data: EventData) -> bool {
         let timestamp = time::OffsetDateTime::now_utc().unix_timestamp();
         self.log_sender.send(Arc::new(Event::new(timestamp, data))).is_ok()
@@ -348,10 +439,43 @@ impl MetricsRequester {
         }
     }
 
-    pub async fn request_metrics_and_subscribe(&self) -> Option<oneshot::Receiver<(Metrics, broadcast::Receiver<Arc<Event>>)>> {
+    /// Requests all the metrics the server tracks. These are sent once the metrics tracker task
+    /// has caught up with the events, so requests never hold back the event pipeline.
+    pub async fn request_snapshot(&self) -> Option<oneshot::Receiver<ServerMetrics>> {
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let result = self.request_sender.send(MetricsRequest::Snapshot(result_tx)).await;
+        match result {
+            Ok(()) => Some(result_rx),
+            Err(_) => None,
+        }
+    }
+
+    /// Requests the metrics broken down by user and destination, with up to `top` of each, or all
+    /// of them if `top` is 0.
+    pub async fn request_breakdown(&self, top: u16) -> Option<oneshot::Receiver<MetricsBreakdown>> {
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let result = self.request_sender.send(MetricsRequest::Breakdown(top, result_tx)).await;
+        match result {
+            Ok(()) => Some(result_rx),
+            Err(_) => None,
+        }
+    }
+
+    /// Adds the historic counters from a previous run, as loaded from a metrics file, to the
+    /// metrics being tracked.
+    pub async fn restore(&self, previous: ServerMetrics) -> bool {
+        self.request_sender.send(MetricsRequest::Restore(Box::new(previous))).await.is_ok()
+    }
+
+    pub async fn request_metrics_and_subscribe(&self, replay: Option<EventReplay>) -> Option<oneshot::Receiver<MetricsAndSubscription>> {
         let (result_tx, result_rx) = oneshot::channel();
 
-        let result = self.request_sender.send(MetricsRequest::MetricsAndSubscribe(result_tx)).await;
+        let result = self
+            .request_sender
+            .send(MetricsRequest::MetricsAndSubscribe(replay, result_tx))
+            .await;
         match result {
             Ok(()) => Some(result_rx),
             Err(_) => None,
@@ -1,14 +1,30 @@
 use std::{env, process::exit};
 
-use crate::args::{get_help_string, get_version_string, ArgumentsRequest};
+use crate::{
+    args::{get_help_string, get_version_string, ArgumentsRequest},
+    config::get_effective_config_string,
+};
 
 mod args;
+mod clients;
+mod config;
 mod context;
+mod event_history;
+mod handoff;
+mod ip_filters;
+mod lockout;
+mod log_file;
+mod log_filters;
+mod log_format;
 mod logger;
 mod messaging;
+mod metrics;
+mod metrics_server;
 mod sandstorm;
 mod server;
 mod socks5;
+mod system_log;
+mod systemd;
 mod users;
 mod utils;
 
@@ -34,6 +50,10 @@ fn main() {
             println!("{}", get_help_string());
             return;
         }
+        ArgumentsRequest::CheckConfig(startup_args) => {
+            println!("{}", get_effective_config_string(&startup_args));
+            return;
+        }
         ArgumentsRequest::Run(startup_args) => startup_args,
     };
 
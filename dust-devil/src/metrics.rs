@@ -0,0 +1,436 @@
+//! The server's metrics, which the logger's metrics task keeps track of from the events, and
+//! their rendering in the OpenMetrics text format.
+
+use std::{
+    collections::HashMap,
+    fmt::{self, Write},
+};
+
+use dust_devil_core::{
+    logging::{ClientSummary, EventData},
+    sandstorm::{BreakdownEntry, Histogram, Metrics, MetricsBreakdown},
+};
+
+/// A reason for a failed authentication, labeled by protocol and reason.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthFailure {
+    Socks5InvalidCredentials,
+    Socks5NoAcceptableMethod,
+    Socks5UnsupportedUserpassVersion,
+    SandstormInvalidCredentials,
+}
+
+impl AuthFailure {
+    pub const ALL: [AuthFailure; 4] = [
+        Self::Socks5InvalidCredentials,
+        Self::Socks5NoAcceptableMethod,
+        Self::Socks5UnsupportedUserpassVersion,
+        Self::SandstormInvalidCredentials,
+    ];
+
+    pub fn protocol(self) -> &'static str {
+        match self {
+            Self::SandstormInvalidCredentials => "sandstorm",
+            _ => "socks5",
+        }
+    }
+
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::Socks5InvalidCredentials | Self::SandstormInvalidCredentials => "invalid_credentials",
+            Self::Socks5NoAcceptableMethod => "no_acceptable_method",
+            Self::Socks5UnsupportedUserpassVersion => "unsupported_userpass_version",
+        }
+    }
+}
+
+/// A reason for a client connection to fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionFailure {
+    AcceptFailed,
+    RejectedByIpFilter,
+    UnsupportedVersion,
+    UnsupportedCommand,
+    UnsupportedAddressType,
+    DestinationUnreachable,
+    IoError,
+}
+
+impl ConnectionFailure {
+    pub const ALL: [ConnectionFailure; 7] = [
+        Self::AcceptFailed,
+        Self::RejectedByIpFilter,
+        Self::UnsupportedVersion,
+        Self::UnsupportedCommand,
+        Self::UnsupportedAddressType,
+        Self::DestinationUnreachable,
+        Self::IoError,
+    ];
+
+    pub fn reason(self) -> &'static str {
+        match self {
+            Self::AcceptFailed => "accept_failed",
+            Self::RejectedByIpFilter => "rejected_by_ip_filter",
+            Self::UnsupportedVersion => "unsupported_version",
+            Self::UnsupportedCommand => "unsupported_command",
+            Self::UnsupportedAddressType => "unsupported_address_type",
+            Self::DestinationUnreachable => "destination_unreachable",
+            Self::IoError => "io_error",
+        }
+    }
+}
+
+/// A kind of listening socket, counted by protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenerKind {
+    Socks5,
+    Sandstorm,
+    Metrics,
+}
+
+impl ListenerKind {
+    pub const ALL: [ListenerKind; 3] = [Self::Socks5, Self::Sandstorm, Self::Metrics];
+
+    pub fn protocol(self) -> &'static str {
+        match self {
+            Self::Socks5 => "socks5",
+            Self::Sandstorm => "sandstorm",
+            Self::Metrics => "metrics",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerMetrics {
+    pub current_client_connections: u32,
+    pub historic_client_connections: u64,
+    pub client_bytes_sent: u64,
+    pub client_bytes_received: u64,
+    pub current_sandstorm_connections: u32,
+    pub historic_sandstorm_connections: u64,
+    pub auth_failures: [u64; AuthFailure::ALL.len()],
+    pub connection_failures: [u64; ConnectionFailure::ALL.len()],
+    pub dns_lookups: u64,
+    pub listeners: [u32; ListenerKind::ALL.len()],
+    pub start_time: i64,
+    pub counter_epoch: i64,
+}
+
+impl ServerMetrics {
+    pub fn auth_failures(&self, failure: AuthFailure) -> u64 {
+        self.auth_failures[failure as usize]
+    }
+
+    pub fn connection_failures(&self, failure: ConnectionFailure) -> u64 {
+        self.connection_failures[failure as usize]
+    }
+
+    pub fn listeners(&self, kind: ListenerKind) -> u32 {
+        self.listeners[kind as usize]
+    }
+
+    /// Creates empty metrics for a server started at the given time, which is also the counter
+    /// epoch.
+    pub fn starting_at(start_time: i64) -> Self {
+        ServerMetrics {
+            start_time,
+            counter_epoch: start_time,
+            ..Default::default()
+        }
+    }
+
+    /// Adds the historic counters from a previous run to these metrics, and takes its counter
+    /// epoch. Current values, such as the amount of connected clients, are not affected.
+    pub fn restore(&mut self, previous: &ServerMetrics) {
+        self.historic_client_connections += previous.historic_client_connections;
+        self.client_bytes_sent += previous.client_bytes_sent;
+        self.client_bytes_received += previous.client_bytes_received;
+        self.historic_sandstorm_connections += previous.historic_sandstorm_connections;
+        for (count, previous_count) in self.auth_failures.iter_mut().zip(previous.auth_failures) {
+            *count += previous_count;
+        }
+        for (count, previous_count) in self.connection_failures.iter_mut().zip(previous.connection_failures) {
+            *count += previous_count;
+        }
+        self.dns_lookups += previous.dns_lookups;
+        self.counter_epoch = previous.counter_epoch;
+    }
+
+    /// Updates the metrics with an event.
+    pub fn record(&mut self, data: &EventData) {
+        match data {
+            EventData::NewClientConnectionAccepted(_, _) => {
+                self.current_client_connections += 1;
+                self.historic_client_connections += 1;
+            }
+            EventData::ClientConnectionFinished(_, _, _, result) => {
+                self.current_client_connections = self.current_client_connections.saturating_sub(1);
+                if result.is_err() {
+                    self.connection_failures[ConnectionFailure::IoError as usize] += 1;
+                }
+            }
+            EventData::ClientBytesSent(_, count) => self.client_bytes_sent += count,
+            EventData::ClientBytesReceived(_, count) => self.client_bytes_received += count,
+            EventData::NewSandstormConnectionAccepted(_, _) | EventData::NewSandstormUnixConnectionAccepted(_, _) => {
+                self.current_sandstorm_connections += 1;
+                self.historic_sandstorm_connections += 1;
+            }
+            EventData::SandstormConnectionFinished(_, _) => {
+                self.current_sandstorm_connections = self.current_sandstorm_connections.saturating_sub(1);
+            }
+            EventData::ClientAuthenticatedWithUserpass(_, _, false) => {
+                self.auth_failures[AuthFailure::Socks5InvalidCredentials as usize] += 1
+            }
+            EventData::ClientNoAcceptableAuthMethod(_) => self.auth_failures[AuthFailure::Socks5NoAcceptableMethod as usize] += 1,
+            EventData::ClientRequestedUnsupportedUserpassVersion(_, _) => {
+                self.auth_failures[AuthFailure::Socks5UnsupportedUserpassVersion as usize] += 1;
+            }
+            EventData::SandstormAuthenticatedAs(_, _, false) => self.auth_failures[AuthFailure::SandstormInvalidCredentials as usize] += 1,
+            EventData::ClientConnectionAcceptFailed(_, _) => self.connection_failures[ConnectionFailure::AcceptFailed as usize] += 1,
+            EventData::ConnectionRejectedByIpFilter(_, _) => self.connection_failures[ConnectionFailure::RejectedByIpFilter as usize] += 1,
+            EventData::ClientRequestedUnsupportedVersion(_, _) => {
+                self.connection_failures[ConnectionFailure::UnsupportedVersion as usize] += 1;
+            }
+            EventData::ClientRequestedUnsupportedCommand(_, _) => {
+                self.connection_failures[ConnectionFailure::UnsupportedCommand as usize] += 1;
+            }
+            EventData::ClientRequestedUnsupportedAtyp(_, _) => {
+                self.connection_failures[ConnectionFailure::UnsupportedAddressType as usize] += 1;
+            }
+            EventData::ClientFailedToConnectToDestination(_) => {
+                self.connection_failures[ConnectionFailure::DestinationUnreachable as usize] += 1;
+            }
+            EventData::ClientDnsLookup(_, _) => self.dns_lookups += 1,
+            EventData::NewSocks5Socket(_) | EventData::Socks5SocketInherited(_) => self.listeners[ListenerKind::Socks5 as usize] += 1,
+            EventData::RemovedSocks5Socket(_) => {
+                let count = &mut self.listeners[ListenerKind::Socks5 as usize];
+                *count = count.saturating_sub(1);
+            }
+            EventData::NewSandstormSocket(_)
+            | EventData::SandstormSocketInherited(_)
+            | EventData::NewSandstormUnixSocket(_)
+            | EventData::SandstormUnixSocketInherited(_) => self.listeners[ListenerKind::Sandstorm as usize] += 1,
+            EventData::RemovedSandstormSocket(_) => {
+                let count = &mut self.listeners[ListenerKind::Sandstorm as usize];
+                *count = count.saturating_sub(1);
+            }
+            EventData::ListenersHandedOff(_) => {
+                self.listeners[ListenerKind::Socks5 as usize] = 0;
+                self.listeners[ListenerKind::Sandstorm as usize] = 0;
+            }
+            EventData::NewMetricsSocket(_) => self.listeners[ListenerKind::Metrics as usize] += 1,
+            _ => {}
+        }
+    }
+
+    /// Gets the subset of these metrics that is sent over Sandstorm.
+    pub fn sandstorm_metrics(&self) -> Metrics {
+        Metrics {
+            current_client_connections: self.current_client_connections,
+            historic_client_connections: self.historic_client_connections,
+            client_bytes_sent: self.client_bytes_sent,
+            client_bytes_received: self.client_bytes_received,
+            current_sandstorm_connections: self.current_sandstorm_connections,
+            historic_sandstorm_connections: self.historic_sandstorm_connections,
+            start_time: self.start_time,
+            counter_epoch: self.counter_epoch,
+        }
+    }
+}
+
+/// The upper bounds of the connect latency histogram's buckets, in microseconds.
+pub const CONNECT_LATENCY_BOUNDS: [u64; 12] = [
+    1_000, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000, 1_000_000, 2_500_000, 5_000_000, 10_000_000,
+];
+
+/// The upper bounds of the connection duration histogram's buckets, in microseconds.
+pub const CONNECTION_DURATION_BOUNDS: [u64; 10] = [
+    1_000_000,
+    5_000_000,
+    10_000_000,
+    30_000_000,
+    60_000_000,
+    300_000_000,
+    900_000_000,
+    3_600_000_000,
+    21_600_000_000,
+    86_400_000_000,
+];
+
+/// The maximum amount of distinct destinations tracked. Once reached, finished clients going to
+/// new destinations are attributed to [`OTHER_DESTINATIONS`] instead, so a client can't make the
+/// server's memory usage grow unboundedly by connecting to many different hosts.
+pub const MAX_TRACKED_DESTINATIONS: usize = 4096;
+
+/// The name under which destinations past [`MAX_TRACKED_DESTINATIONS`] are grouped.
+pub const OTHER_DESTINATIONS: &str = "(other)";
+
+/// The server's metrics broken down by user and destination, built from the summaries of
+/// finished clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerBreakdown {
+    users: HashMap<String, BreakdownEntry>,
+    destinations: HashMap<String, BreakdownEntry>,
+    connect_latency: Histogram,
+    connection_duration: Histogram,
+}
+
+impl Default for ServerBreakdown {
+    fn default() -> Self {
+        ServerBreakdown {
+            users: HashMap::new(),
+            destinations: HashMap::new(),
+            connect_latency: Histogram::new(&CONNECT_LATENCY_BOUNDS),
+            connection_duration: Histogram::new(&CONNECTION_DURATION_BOUNDS),
+        }
+    }
+}
+
+fn add_to_entry(map: &mut HashMap<String, BreakdownEntry>, name: &str, summary: &ClientSummary) {
+    let entry = match map.get_mut(name) {
+        Some(entry) => entry,
+        None => map.entry(name.to_string()).or_insert_with(|| BreakdownEntry::new(name.to_string())),
+    };
+
+    entry.connections += 1;
+    entry.failures += summary.failed as u64;
+    entry.bytes_sent = entry.bytes_sent.saturating_add(summary.bytes_sent);
+    entry.bytes_received = entry.bytes_received.saturating_add(summary.bytes_received);
+}
+
+/// Gets up to `top` entries with the most bytes transferred, or all of them if `top` is 0.
+fn top_entries(map: &HashMap<String, BreakdownEntry>, top: u16) -> Vec<BreakdownEntry> {
+    let mut entries: Vec<&BreakdownEntry> = map.values().collect();
+    entries.sort_unstable_by(|a, b| {
+        b.total_bytes()
+            .cmp(&a.total_bytes())
+            .then(b.connections.cmp(&a.connections))
+            .then(a.name.cmp(&b.name))
+    });
+
+    if top != 0 {
+        entries.truncate(top as usize);
+    }
+
+    entries.into_iter().cloned().collect()
+}
+
+impl ServerBreakdown {
+    /// Updates the breakdown with an event. Only client summaries are taken into account.
+    pub fn record(&mut self, data: &EventData) {
+        if let EventData::ClientConnectionSummary(_, summary) = data {
+            self.record_summary(summary);
+        }
+    }
+
+    fn record_summary(&mut self, summary: &ClientSummary) {
+        if let Some(username) = &summary.username {
+            add_to_entry(&mut self.users, username, summary);
+        }
+
+        if let Some(destination) = &summary.destination {
+            let name = if self.destinations.len() < MAX_TRACKED_DESTINATIONS || self.destinations.contains_key(destination) {
+                destination.as_str()
+            } else {
+                OTHER_DESTINATIONS
+            };
+            add_to_entry(&mut self.destinations, name, summary);
+        }
+
+        if let Some(latency) = summary.connect_latency_micros {
+            self.connect_latency.record(latency);
+        }
+
+        self.connection_duration.record(summary.duration_micros);
+    }
+
+    /// Gets the breakdown with up to `top` users and `top` destinations, those with the most bytes
+    /// transferred, or all of them if `top` is 0.
+    pub fn top(&self, top: u16) -> MetricsBreakdown {
+        MetricsBreakdown {
+            users: top_entries(&self.users, top),
+            destinations: top_entries(&self.destinations, top),
+            connect_latency: self.connect_latency.clone(),
+            connection_duration: self.connection_duration.clone(),
+        }
+    }
+}
+
+/// Writes the metric family header lines, which must precede the family's samples.
+fn write_family(out: &mut String, name: &str, kind: &str, unit: Option<&str>, help: &str) -> fmt::Result {
+    writeln!(out, "# TYPE {name} {kind}")?;
+    if let Some(unit) = unit {
+        writeln!(out, "# UNIT {name} {unit}")?;
+    }
+    writeln!(out, "# HELP {name} {help}")
+}
+
+/// Writes a counter's sample, with its labels if any, followed by its creation time, which is the
+/// counter epoch.
+fn write_counter(out: &mut String, name: &str, labels: &str, value: u64, metrics: &ServerMetrics) -> fmt::Result {
+    writeln!(out, "{name}_total{labels} {value}")?;
+    writeln!(out, "{name}_created{labels} {}", metrics.counter_epoch)
+}
+
+/// Renders metrics in the OpenMetrics text format, including the terminating `# EOF` line.
+pub fn write_openmetrics(out: &mut String, metrics: &ServerMetrics) -> fmt::Result {
+    let name = "dust_devil_client_connections";
+    write_family(out, name, "gauge", None, "Socks5 clients currently connected.")?;
+    writeln!(out, "{name} {}", metrics.current_client_connections)?;
+
+    let name = "dust_devil_client_connections_accepted";
+    write_family(out, name, "counter", None, "Socks5 client connections accepted.")?;
+    write_counter(out, name, "", metrics.historic_client_connections, metrics)?;
+
+    let name = "dust_devil_client_sent_bytes";
+    write_family(out, name, "counter", Some("bytes"), "Bytes sent by socks5 clients.")?;
+    write_counter(out, name, "", metrics.client_bytes_sent, metrics)?;
+
+    let name = "dust_devil_client_received_bytes";
+    write_family(out, name, "counter", Some("bytes"), "Bytes received by socks5 clients.")?;
+    write_counter(out, name, "", metrics.client_bytes_received, metrics)?;
+
+    let name = "dust_devil_sandstorm_connections";
+    write_family(out, name, "gauge", None, "Sandstorm managers currently connected.")?;
+    writeln!(out, "{name} {}", metrics.current_sandstorm_connections)?;
+
+    let name = "dust_devil_sandstorm_connections_accepted";
+    write_family(out, name, "counter", None, "Sandstorm connections accepted.")?;
+    write_counter(out, name, "", metrics.historic_sandstorm_connections, metrics)?;
+
+    let name = "dust_devil_auth_failures";
+    write_family(out, name, "counter", None, "Failed authentications, by protocol and reason.")?;
+    for failure in AuthFailure::ALL {
+        let labels = format!("{{protocol=\"{}\",reason=\"{}\"}}", failure.protocol(), failure.reason());
+        write_counter(out, name, &labels, metrics.auth_failures(failure), metrics)?;
+    }
+
+    let name = "dust_devil_connection_failures";
+    write_family(out, name, "counter", None, "Failed socks5 client connections, by reason.")?;
+    for failure in ConnectionFailure::ALL {
+        let labels = format!("{{reason=\"{}\"}}", failure.reason());
+        write_counter(out, name, &labels, metrics.connection_failures(failure), metrics)?;
+    }
+
+    let name = "dust_devil_dns_lookups";
+    write_family(out, name, "counter", None, "DNS lookups made for socks5 clients.")?;
+    write_counter(out, name, "", metrics.dns_lookups, metrics)?;
+
+    let name = "dust_devil_listeners";
+    write_family(out, name, "gauge", None, "Listening sockets, by protocol.")?;
+    for kind in ListenerKind::ALL {
+        writeln!(out, "{name}{{protocol=\"{}\"}} {}", kind.protocol(), metrics.listeners(kind))?;
+    }
+
+    let name = "dust_devil_start_time_seconds";
+    write_family(
+        out,
+        name,
+        "gauge",
+        Some("seconds"),
+        "Time the server started, in seconds since the UNIX epoch.",
+    )?;
+    writeln!(out, "{name} {}", metrics.start_time)?;
+
+    writeln!(out, "# EOF")
+}
@@ -0,0 +1,180 @@
+//! A minimal HTTP server that serves the server's metrics at `/metrics` in the OpenMetrics text
+//! format, for Prometheus and compatible scrapers.
+//!
+//! Each scrape requests a snapshot from the logger's metrics task, which only answers once it has
+//! caught up with the events, so scrapes never hold back the event pipeline. The metrics are
+//! rendered by the connection's own task.
+
+use std::{io::Error, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    select,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{logger::MetricsRequester, metrics::write_openmetrics, printlnif, utils::accept_from_any::accept_from_any};
+
+const MAX_REQUEST_HEAD_SIZE: usize = 0x2000;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const METRICS_PATH: &str = "/metrics";
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Serves metrics over the given listeners until the cancellation token is cancelled.
+pub async fn serve_metrics(verbose: bool, listeners: Vec<TcpListener>, requester: MetricsRequester, cancel_token: CancellationToken) {
+    printlnif!(verbose, "Metrics server task started");
+
+    loop {
+        let accept_result = select! {
+            accept_result = accept_from_any(&listeners) => accept_result,
+            _ = cancel_token.cancelled() => break,
+        };
+
+        match accept_result {
+            Ok((_, stream, address)) => {
+                let requester = requester.clone();
+                let cancel_token = cancel_token.clone();
+                tokio::spawn(async move {
+                    select! {
+                        result = handle_connection(stream, &requester) => {
+                            if let Err(error) = result {
+                                printlnif!(verbose, "Metrics connection from {address} failed: {error}");
+                            }
+                        }
+                        _ = cancel_token.cancelled() => {}
+                    }
+                });
+            }
+            Err((listener, error)) => {
+                printlnif!(
+                    verbose,
+                    "Failed to accept metrics connection at {:?}: {error}",
+                    listener.local_addr()
+                );
+            }
+        }
+    }
+
+    printlnif!(verbose, "Metrics server task finished");
+}
+
+async fn handle_connection(mut stream: TcpStream, requester: &MetricsRequester) -> Result<(), Error> {
+    let head = match tokio::time::timeout(REQUEST_TIMEOUT, read_request_head(&mut stream)).await {
+        Ok(result) => result?,
+        Err(_) => return Ok(()),
+    };
+
+    let response = match head {
+        Some(head) => respond(&head, requester).await,
+        None => HttpResponse::text("400 Bad Request", "Bad Request\n"),
+    };
+
+    let mut buf = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    if let Some(allow) = response.allow {
+        buf.push_str(&format!("Allow: {allow}\r\n"));
+    }
+    buf.push_str("\r\n");
+
+    stream.write_all(buf.as_bytes()).await?;
+    if response.include_body {
+        stream.write_all(response.body.as_bytes()).await?;
+    }
+    stream.shutdown().await
+}
+
+/// Reads an HTTP request's head, up to the empty line that ends it. Returns `None` if the head is
+/// too large, isn't valid UTF-8 or the connection is closed before it ends.
+async fn read_request_head(stream: &mut TcpStream) -> Result<Option<String>, Error> {
+    let mut buf = Vec::with_capacity(0x400);
+    let mut chunk = [0u8; 0x400];
+
+    loop {
+        let count = stream.read(&mut chunk).await?;
+        if count == 0 {
+            return Ok(None);
+        }
+
+        buf.extend_from_slice(&chunk[..count]);
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            buf.truncate(end);
+            return Ok(String::from_utf8(buf).ok());
+        }
+
+        if buf.len() > MAX_REQUEST_HEAD_SIZE {
+            return Ok(None);
+        }
+    }
+}
+
+struct HttpResponse {
+    status: &'static str,
+    content_type: &'static str,
+    allow: Option<&'static str>,
+    body: String,
+    include_body: bool,
+}
+
+impl HttpResponse {
+    fn text(status: &'static str, body: &str) -> Self {
+        HttpResponse {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            allow: None,
+            body: body.to_string(),
+            include_body: true,
+        }
+    }
+}
+
+async fn respond(head: &str, requester: &MetricsRequester) -> HttpResponse {
+    let request_line = head.lines().next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (method, target) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None) if version.starts_with("HTTP/1.") => (method, target),
+        _ => return HttpResponse::text("400 Bad Request", "Bad Request\n"),
+    };
+
+    let path = target.split_once('?').map_or(target, |(path, _)| path);
+    if path != METRICS_PATH {
+        return HttpResponse::text("404 Not Found", "Not Found\n");
+    }
+
+    let include_body = match method {
+        "GET" => true,
+        "HEAD" => false,
+        _ => {
+            let mut response = HttpResponse::text("405 Method Not Allowed", "Method Not Allowed\n");
+            response.allow = Some("GET, HEAD");
+            return response;
+        }
+    };
+
+    let metrics = match requester.request_snapshot().await {
+        Some(receiver) => receiver.await.ok(),
+        None => None,
+    };
+
+    let mut response = match metrics {
+        Some(metrics) => {
+            let mut body = String::new();
+            let _ = write_openmetrics(&mut body, &metrics);
+            HttpResponse {
+                status: "200 OK",
+                content_type: OPENMETRICS_CONTENT_TYPE,
+                allow: None,
+                body,
+                include_body: true,
+            }
+        }
+        None => HttpResponse::text("503 Service Unavailable", "Service Unavailable\n"),
+    };
+
+    response.include_body = include_body;
+    response
+}
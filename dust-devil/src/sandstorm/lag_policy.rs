@@ -0,0 +1,50 @@
+use std::{fmt, str::FromStr};
+
+/// What to do when a Sandstorm manager's event stream falls behind the server's events, and
+/// events that were not yet sent to it are lost.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EventLagPolicy {
+    /// Disconnect the manager.
+    #[default]
+    Disconnect,
+
+    /// Send the manager an [`EventStreamLagged`] event indicating how many events were dropped,
+    /// and continue streaming.
+    ///
+    /// [`EventStreamLagged`]: dust_devil_core::logging::EventData::EventStreamLagged
+    Drop,
+
+    /// Same as [`Self::Drop`], but the event also carries a new metrics snapshot, taken at the
+    /// point from which the stream continues, so the manager can resynchronize its metrics. Any
+    /// events still pending from before the snapshot are skipped too, as the snapshot includes them.
+    Resync,
+}
+
+impl FromStr for EventLagPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            s if s.eq_ignore_ascii_case("disconnect") => Ok(Self::Disconnect),
+            s if s.eq_ignore_ascii_case("drop") => Ok(Self::Drop),
+            s if s.eq_ignore_ascii_case("resync") => Ok(Self::Resync),
+            _ => Err(()),
+        }
+    }
+}
+
+impl EventLagPolicy {
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Disconnect => "disconnect",
+            Self::Drop => "drop",
+            Self::Resync => "resync",
+        }
+    }
+}
+
+impl fmt::Display for EventLagPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_str())
+    }
+}
@@ -1,7 +1,13 @@
 use std::{io::Error, net::SocketAddr};
 
 use dust_devil_core::{
-    sandstorm::{AddUserResponse, DeleteUserResponse, Metrics, RemoveSocketResponse, UpdateUserResponse},
+    ip_filter::{IpFilterKind, IpNetwork},
+    listener_profile::ListenerProfile,
+    log_filter::{EventStreamFilter, LogFilter, LogSink},
+    sandstorm::{
+        ActiveClient, AddUserResponse, DeleteUserResponse, EventReplay, LockoutTarget, Metrics, MetricsBreakdown, RemoveSocketResponse,
+        SandstormCommandType, SandstormPermissions, UpdateUserResponse,
+    },
     socks5::AuthMethod,
     users::UserRole,
 };
@@ -9,8 +15,8 @@ use tokio::sync::oneshot::Receiver;
 
 pub enum ResponseNotification {
     Shutdown(Receiver<()>),
-    LogEventConfig(bool),
-    ListSocks5Sockets(Receiver<Vec<SocketAddr>>),
+    LogEventConfig(bool, Option<EventStreamFilter>, Option<EventReplay>),
+    ListSocks5Sockets(Receiver<Vec<(SocketAddr, ListenerProfile)>>),
     AddSocks5Socket(Receiver<Result<(), Error>>),
     RemoveSocks5Socket(Receiver<RemoveSocketResponse>),
     ListSandstormSockets(Receiver<Vec<SocketAddr>>),
@@ -25,5 +31,22 @@ pub enum ResponseNotification {
     RequestCurrentMetrics(Option<Receiver<Metrics>>),
     GetBufferSize(u32),
     SetBufferSize(bool),
+    GetPermissions(SandstormPermissions),
+    PermissionDenied(SandstormCommandType),
+    UnsupportedCommand(u8),
+    ListLockouts(Vec<(LockoutTarget, u64)>),
+    ClearLockouts(u32),
+    ListIpFilters(Vec<(SocketAddr, IpFilterKind, IpNetwork)>),
+    AddIpFilter(bool),
+    RemoveIpFilter(bool),
+    ListLogFilters(Vec<(LogSink, LogFilter)>),
+    SetLogFilter(bool),
+    MetricsBreakdown(Option<Receiver<MetricsBreakdown>>),
+    ListClients(Vec<ActiveClient>),
+    CloseClients(u32),
     Meow,
 }
+
+/// A response notification, along with the ID of the request it answers if request IDs were
+/// negotiated.
+pub type IdentifiedNotification = (Option<u32>, ResponseNotification);
@@ -1,12 +1,11 @@
 use std::io::Error;
 
 use dust_devil_core::{
-    sandstorm::{ParseHandshakeError, SandstormHandshake, SandstormHandshakeStatus},
+    sandstorm::{ParseHandshakeError, SandstormCapabilities, SandstormHandshake, SandstormHandshakeStatus},
     serialize::ByteWrite,
 };
 use tokio::{
-    io::{AsyncWriteExt, BufReader, BufWriter},
-    net::TcpStream,
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter},
     select,
     sync::mpsc,
     try_join,
@@ -20,10 +19,13 @@ use crate::{
 };
 
 mod error_handling;
+mod lag_policy;
 mod messaging;
 mod request_handler;
 mod response_handler;
 
+pub use lag_policy::EventLagPolicy;
+
 /// The size of the reading buffer for sandstorm clients. Doesn't need to be too much, as sandstorm requests
 /// are typically very small.
 const SANDSTORM_READ_BUFFER_SIZE: usize = 0x400;
@@ -35,7 +37,10 @@ const SANDSTORM_WRITE_BUFFER_SIZE: usize = 0x2000;
 /// The size of the mpsc channel used between the sandstorm request reader task and the response writer task.
 const RESPONSE_NOTIFICATION_CHANNEL_SIZE: usize = 16;
 
-pub async fn handle_sandstorm(stream: TcpStream, mut context: SandstormContext, cancel_token: CancellationToken) {
+pub async fn handle_sandstorm<S>(stream: S, mut context: SandstormContext, cancel_token: CancellationToken)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     select! {
         biased;
         _ = cancel_token.cancelled() => {}
@@ -43,8 +48,11 @@ pub async fn handle_sandstorm(stream: TcpStream, mut context: SandstormContext,
     }
 }
 
-async fn handle_sandstorm_inner(mut stream: TcpStream, context: &mut SandstormContext) -> Result<(), Error> {
-    let (reader, mut writer) = stream.split();
+async fn handle_sandstorm_inner<S>(stream: S, context: &mut SandstormContext) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
     let mut reader = BufReader::with_capacity(SANDSTORM_READ_BUFFER_SIZE, reader);
 
     let handshake = match SandstormHandshake::read_with_version_check(&mut reader).await {
@@ -58,6 +66,7 @@ async fn handle_sandstorm_inner(mut stream: TcpStream, context: &mut SandstormCo
         }
     };
 
+    let negotiation = handshake.negotiate(SandstormCapabilities::all());
     let success = context.try_login(&handshake.username, &handshake.password);
     log_sandstorm_authenticated_as!(context, handshake.username, success == Some(true));
 
@@ -68,6 +77,11 @@ async fn handle_sandstorm_inner(mut stream: TcpStream, context: &mut SandstormCo
     };
     handshake_response.write(&mut writer).await?;
 
+    if let (SandstormHandshakeStatus::Ok, Some(negotiation)) = (handshake_response, negotiation) {
+        negotiation.write(&mut writer).await?;
+        context.negotiation = negotiation;
+    }
+
     let mut writer = BufWriter::with_capacity(SANDSTORM_WRITE_BUFFER_SIZE, writer);
     let (response_tx, response_rx) = mpsc::channel(RESPONSE_NOTIFICATION_CHANNEL_SIZE);
 
@@ -2,58 +2,193 @@ use std::io::{Error, ErrorKind};
 
 use dust_devil_core::{
     sandstorm::{
-        AddSandstormSocketRequest, AddSocks5SocketRequest, AddUserRequest, CurrentMetricsRequest, DeleteUserRequest,
-        EventStreamConfigRequest, GetBufferSizeRequest, ListAuthMethodsRequest, ListSandstormSocketsRequest, ListSocks5SocketsRequest,
-        ListUsersRequest, MeowRequest, RemoveSandstormSocketRequest, RemoveSocks5SocketRequest, SandstormCommandType, SetBufferSizeRequest,
-        ShutdownRequest, ToggleAuthMethodRequest, UpdateUserRequest,
+        AddIpFilterRequest, AddSandstormSocketRequest, AddSocks5SocketRequest, AddUserRequest, ClearLockoutsRequest, CloseClientsRequest,
+        CurrentMetricsRequest, DeleteUserRequest, EventStreamConfigRequest, GetBufferSizeRequest, GetPermissionsRequest,
+        ListAuthMethodsRequest, ListClientsRequest, ListIpFiltersRequest, ListLockoutsRequest, ListLogFiltersRequest,
+        ListSandstormSocketsRequest, ListSocks5SocketsRequest, ListUsersRequest, MeowRequest, MetricsBreakdownRequest,
+        RemoveIpFilterRequest, RemoveSandstormSocketRequest, RemoveSocks5SocketRequest, SandstormCapability, SandstormCommandType,
+        SetBufferSizeRequest, SetLogFilterRequest, ShutdownRequest, ToggleAuthMethodRequest, UpdateUserRequest, Version1,
     },
     serialize::ByteRead,
+    u8_repr_enum::U8ReprEnum,
 };
 
 use crate::context::SandstormContext;
-use tokio::{io::AsyncRead, sync::mpsc::Sender};
+use tokio::{
+    io::{self, AsyncRead, AsyncReadExt},
+    sync::mpsc::Sender,
+};
 
-use super::{error_handling::ToIoResult, messaging::ResponseNotification};
+use super::{
+    error_handling::ToIoResult,
+    messaging::{IdentifiedNotification, ResponseNotification},
+};
 
 pub async fn handle_requests<R>(
     reader: &mut R,
     context: &SandstormContext,
-    response_notifier: Sender<ResponseNotification>,
+    response_notifier: Sender<IdentifiedNotification>,
 ) -> Result<(), Error>
 where
     R: AsyncRead + Unpin + ?Sized,
 {
+    if context.negotiation.frames_requests() {
+        return handle_framed_requests(reader, context, response_notifier).await;
+    }
+
     loop {
         match SandstormCommandType::read(reader).await {
-            Ok(command) => run_command(command, reader, context, &response_notifier).await?,
+            Ok(command) => run_command(command, None, reader, context, &response_notifier).await?,
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(error) => return Err(error),
+        };
+    }
+}
+
+/// Handles requests framed with the length of their payload. Knowing where each request ends
+/// allows answering unknown command types with an unsupported command response, rather than
+/// closing the connection. If request IDs were negotiated, each request's ID comes right after its
+/// command type byte.
+async fn handle_framed_requests<R>(
+    reader: &mut R,
+    context: &SandstormContext,
+    response_notifier: Sender<IdentifiedNotification>,
+) -> Result<(), Error>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    loop {
+        let command_byte = match u8::read(reader).await {
+            Ok(value) => value,
             Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(()),
             Err(error) => return Err(error),
         };
+
+        let request_id = match context.negotiation.capabilities.has(SandstormCapability::RequestIds) {
+            true => Some(u32::read(reader).await?),
+            false => None,
+        };
+
+        let length = u16::read(reader).await?;
+        let mut payload = (&mut *reader).take(length as u64);
+
+        match SandstormCommandType::from_u8(command_byte) {
+            Some(command) if command.is_request() => run_command(command, request_id, &mut payload, context, &response_notifier).await?,
+            _ => response_notifier
+                .send((request_id, ResponseNotification::UnsupportedCommand(command_byte)))
+                .await
+                .map_err_to_io()?,
+        }
+
+        // Skip anything the request had that we didn't read, such as fields added by a later version.
+        io::copy(&mut payload, &mut io::sink()).await?;
+    }
+}
+
+/// Reads and discards the payload of a request, so the stream stays in sync when a request is
+/// denied without being run.
+async fn discard_request<R>(command: SandstormCommandType, context: &SandstormContext, reader: &mut R) -> Result<(), Error>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    match command {
+        SandstormCommandType::Shutdown => read_shutdown_request(context, reader).await.map(|_| ()),
+        SandstormCommandType::EventStreamConfig => EventStreamConfigRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::AddSocks5Socket => read_add_socks5_request(context, reader).await.map(|_| ()),
+        SandstormCommandType::RemoveSocks5Socket => RemoveSocks5SocketRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::AddSandstormSocket => AddSandstormSocketRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::RemoveSandstormSocket => RemoveSandstormSocketRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::AddUser => AddUserRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::UpdateUser => UpdateUserRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::DeleteUser => DeleteUserRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::ToggleAuthMethod => ToggleAuthMethodRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::SetBufferSize => SetBufferSizeRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::ClearLockouts => ClearLockoutsRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::AddIpFilter => AddIpFilterRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::RemoveIpFilter => RemoveIpFilterRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::SetLogFilter => SetLogFilterRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::RequestMetricsBreakdown => MetricsBreakdownRequest::read(reader).await.map(|_| ()),
+        SandstormCommandType::CloseClients => CloseClientsRequest::read(reader).await.map(|_| ()),
+        _ => Ok(()),
+    }
+}
+
+/// Reads a shutdown request, in the format of the client's negotiated version.
+async fn read_shutdown_request<R>(context: &SandstormContext, reader: &mut R) -> Result<ShutdownRequest, Error>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    match context.negotiation.includes_drain_timeout() {
+        true => ShutdownRequest::read(reader).await,
+        false => Version1::<ShutdownRequest>::read(reader).await.map(|request| request.0),
+    }
+}
+
+/// Reads an add socks5 socket request, in the format of the client's negotiated version.
+async fn read_add_socks5_request<R>(context: &SandstormContext, reader: &mut R) -> Result<AddSocks5SocketRequest, Error>
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    match context.negotiation.includes_listener_profiles() {
+        true => AddSocks5SocketRequest::read(reader).await,
+        false => Version1::<AddSocks5SocketRequest>::read(reader).await.map(|request| request.0),
     }
 }
 
 async fn run_command<R>(
     command: SandstormCommandType,
+    request_id: Option<u32>,
     reader: &mut R,
     context: &SandstormContext,
-    response_notifier: &Sender<ResponseNotification>,
+    response_notifier: &Sender<IdentifiedNotification>,
 ) -> Result<(), Error>
 where
     R: AsyncRead + Unpin + ?Sized,
 {
+    if !context.permissions.allows(command) {
+        discard_request(command, context, reader).await?;
+        context.permission_denied(command);
+        response_notifier
+            .send((request_id, ResponseNotification::PermissionDenied(command)))
+            .await
+            .map_err_to_io()?;
+        return Ok(());
+    }
+
     match command {
         SandstormCommandType::Shutdown => {
-            let _ = ShutdownRequest::read(reader).await?;
-            let receiver = context.request_shutdown().await;
+            let request = read_shutdown_request(context, reader).await?;
+            let receiver = context.request_shutdown(request.0).await;
             response_notifier
-                .send(ResponseNotification::Shutdown(receiver))
+                .send((request_id, ResponseNotification::Shutdown(receiver)))
                 .await
                 .map_err_to_io()?;
         }
         SandstormCommandType::EventStreamConfig => {
             let request = EventStreamConfigRequest::read(reader).await?;
+
+            // Filters and replays may only be requested if their capabilities were negotiated, as
+            // otherwise the client wouldn't be able to parse the response.
+            let capabilities = context.negotiation.capabilities;
+            if (request.1.is_some() && !capabilities.has(SandstormCapability::EventStreamFilter))
+                || (request.2.is_some() && !capabilities.has(SandstormCapability::EventReplay))
+            {
+                if !context.negotiation.frames_requests() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "Event stream option requested without its capability",
+                    ));
+                }
+
+                response_notifier
+                    .send((request_id, ResponseNotification::UnsupportedCommand(command.into_u8())))
+                    .await
+                    .map_err_to_io()?;
+                return Ok(());
+            }
+
             response_notifier
-                .send(ResponseNotification::LogEventConfig(request.0))
+                .send((request_id, ResponseNotification::LogEventConfig(request.0, request.1, request.2)))
                 .await
                 .map_err_to_io()?;
         }
@@ -61,15 +196,15 @@ where
             let _ = ListSocks5SocketsRequest::read(reader).await?;
             let receiver = context.list_socks5_sockets().await;
             response_notifier
-                .send(ResponseNotification::ListSocks5Sockets(receiver))
+                .send((request_id, ResponseNotification::ListSocks5Sockets(receiver)))
                 .await
                 .map_err_to_io()?;
         }
         SandstormCommandType::AddSocks5Socket => {
-            let request = AddSocks5SocketRequest::read(reader).await?;
-            let receiver = context.add_socks5_socket(request.0).await;
+            let request = read_add_socks5_request(context, reader).await?;
+            let receiver = context.add_socks5_socket(request.0, request.1).await;
             response_notifier
-                .send(ResponseNotification::AddSocks5Socket(receiver))
+                .send((request_id, ResponseNotification::AddSocks5Socket(receiver)))
                 .await
                 .map_err_to_io()?;
         }
@@ -77,7 +212,7 @@ where
             let request = RemoveSocks5SocketRequest::read(reader).await?;
             let receiver = context.remove_socks5_socket(request.0).await;
             response_notifier
-                .send(ResponseNotification::RemoveSocks5Socket(receiver))
+                .send((request_id, ResponseNotification::RemoveSocks5Socket(receiver)))
                 .await
                 .map_err_to_io()?;
         }
@@ -85,7 +220,7 @@ where
             let _ = ListSandstormSocketsRequest::read(reader).await?;
             let receiver = context.list_sandstorm_sockets().await;
             response_notifier
-                .send(ResponseNotification::ListSandstormSockets(receiver))
+                .send((request_id, ResponseNotification::ListSandstormSockets(receiver)))
                 .await
                 .map_err_to_io()?;
         }
@@ -93,7 +228,7 @@ where
             let request = AddSandstormSocketRequest::read(reader).await?;
             let receiver = context.add_sandstorm_socket(request.0).await;
             response_notifier
-                .send(ResponseNotification::AddSandstormSocket(receiver))
+                .send((request_id, ResponseNotification::AddSandstormSocket(receiver)))
                 .await
                 .map_err_to_io()?;
         }
@@ -101,7 +236,7 @@ where
             let request = RemoveSandstormSocketRequest::read(reader).await?;
             let receiver = context.remove_sandstorm_socket(request.0).await;
             response_notifier
-                .send(ResponseNotification::RemoveSandstormSocket(receiver))
+                .send((request_id, ResponseNotification::RemoveSandstormSocket(receiver)))
                 .await
                 .map_err_to_io()?;
         }
@@ -109,7 +244,7 @@ where
             let _ = ListUsersRequest::read(reader).await?;
             let snapshot = context.get_users_snapshot();
             response_notifier
-                .send(ResponseNotification::ListUsers(snapshot))
+                .send((request_id, ResponseNotification::ListUsers(snapshot)))
                 .await
                 .map_err_to_io()?;
         }
@@ -117,31 +252,31 @@ where
             let request = AddUserRequest::read(reader).await?;
             let result = context.add_user(request.0, request.1, request.2);
             response_notifier
-                .send(ResponseNotification::AddUser(result))
+                .send((request_id, ResponseNotification::AddUser(result)))
                 .await
                 .map_err_to_io()?;
         }
         SandstormCommandType::UpdateUser => {
             let request = UpdateUserRequest::read(reader).await?;
-            let result = context.update_user(request.0, request.1, request.2);
-            response_notifier
-                .send(ResponseNotification::UpdateUser(result))
-                .await
-                .map_err_to_io()?;
+            let notification = match context.update_user(request.0, request.1, request.2) {
+                Some(result) => ResponseNotification::UpdateUser(result),
+                None => ResponseNotification::PermissionDenied(command),
+            };
+            response_notifier.send((request_id, notification)).await.map_err_to_io()?;
         }
         SandstormCommandType::DeleteUser => {
             let request = DeleteUserRequest::read(reader).await?;
-            let result = context.delete_user(request.0);
-            response_notifier
-                .send(ResponseNotification::DeleteUser(result))
-                .await
-                .map_err_to_io()?;
+            let notification = match context.delete_user(request.0) {
+                Some(result) => ResponseNotification::DeleteUser(result),
+                None => ResponseNotification::PermissionDenied(command),
+            };
+            response_notifier.send((request_id, notification)).await.map_err_to_io()?;
         }
         SandstormCommandType::ListAuthMethods => {
             let _ = ListAuthMethodsRequest::read(reader).await?;
             let auth_methods = context.get_auth_methods();
             response_notifier
-                .send(ResponseNotification::ListAuthMethods(auth_methods))
+                .send((request_id, ResponseNotification::ListAuthMethods(auth_methods)))
                 .await
                 .map_err_to_io()?;
         }
@@ -149,7 +284,7 @@ where
             let request = ToggleAuthMethodRequest::read(reader).await?;
             let result = context.toggle_auth_method(request.0, request.1);
             response_notifier
-                .send(ResponseNotification::ToggleAuthMethod(result))
+                .send((request_id, ResponseNotification::ToggleAuthMethod(result)))
                 .await
                 .map_err_to_io()?;
         }
@@ -157,7 +292,7 @@ where
             let _ = CurrentMetricsRequest::read(reader).await?;
             let receiver = context.request_metrics().await;
             response_notifier
-                .send(ResponseNotification::RequestCurrentMetrics(receiver))
+                .send((request_id, ResponseNotification::RequestCurrentMetrics(receiver)))
                 .await
                 .map_err_to_io()?;
         }
@@ -165,7 +300,7 @@ where
             let _ = GetBufferSizeRequest::read(reader).await?;
             let buffer_size = context.get_buffer_size();
             response_notifier
-                .send(ResponseNotification::GetBufferSize(buffer_size))
+                .send((request_id, ResponseNotification::GetBufferSize(buffer_size)))
                 .await
                 .map_err_to_io()?;
         }
@@ -173,13 +308,103 @@ where
             let request = SetBufferSizeRequest::read(reader).await?;
             let result = context.set_buffer_size(request.0);
             response_notifier
-                .send(ResponseNotification::SetBufferSize(result))
+                .send((request_id, ResponseNotification::SetBufferSize(result)))
+                .await
+                .map_err_to_io()?;
+        }
+        SandstormCommandType::GetPermissions => {
+            let _ = GetPermissionsRequest::read(reader).await?;
+            response_notifier
+                .send((request_id, ResponseNotification::GetPermissions(context.permissions)))
+                .await
+                .map_err_to_io()?;
+        }
+        SandstormCommandType::ListLockouts => {
+            let _ = ListLockoutsRequest::read(reader).await?;
+            let lockouts = context.list_lockouts();
+            response_notifier
+                .send((request_id, ResponseNotification::ListLockouts(lockouts)))
+                .await
+                .map_err_to_io()?;
+        }
+        SandstormCommandType::ClearLockouts => {
+            let request = ClearLockoutsRequest::read(reader).await?;
+            let count = context.clear_lockouts(request.0);
+            response_notifier
+                .send((request_id, ResponseNotification::ClearLockouts(count)))
+                .await
+                .map_err_to_io()?;
+        }
+        SandstormCommandType::ListIpFilters => {
+            let _ = ListIpFiltersRequest::read(reader).await?;
+            let filters = context.list_ip_filters();
+            response_notifier
+                .send((request_id, ResponseNotification::ListIpFilters(filters)))
+                .await
+                .map_err_to_io()?;
+        }
+        SandstormCommandType::AddIpFilter => {
+            let request = AddIpFilterRequest::read(reader).await?;
+            let result = context.add_ip_filter(request.0, request.1, request.2);
+            response_notifier
+                .send((request_id, ResponseNotification::AddIpFilter(result)))
+                .await
+                .map_err_to_io()?;
+        }
+        SandstormCommandType::RemoveIpFilter => {
+            let request = RemoveIpFilterRequest::read(reader).await?;
+            let result = context.remove_ip_filter(request.0, request.1, request.2);
+            response_notifier
+                .send((request_id, ResponseNotification::RemoveIpFilter(result)))
+                .await
+                .map_err_to_io()?;
+        }
+        SandstormCommandType::ListLogFilters => {
+            let _ = ListLogFiltersRequest::read(reader).await?;
+            let filters = context.list_log_filters();
+            response_notifier
+                .send((request_id, ResponseNotification::ListLogFilters(filters)))
+                .await
+                .map_err_to_io()?;
+        }
+        SandstormCommandType::SetLogFilter => {
+            let request = SetLogFilterRequest::read(reader).await?;
+            let result = context.set_log_filter(request.0, request.1);
+            response_notifier
+                .send((request_id, ResponseNotification::SetLogFilter(result)))
+                .await
+                .map_err_to_io()?;
+        }
+        SandstormCommandType::RequestMetricsBreakdown => {
+            let request = MetricsBreakdownRequest::read(reader).await?;
+            let receiver = context.request_metrics_breakdown(request.0).await;
+            response_notifier
+                .send((request_id, ResponseNotification::MetricsBreakdown(receiver)))
+                .await
+                .map_err_to_io()?;
+        }
+        SandstormCommandType::ListClients => {
+            let _ = ListClientsRequest::read(reader).await?;
+            let clients = context.list_clients();
+            response_notifier
+                .send((request_id, ResponseNotification::ListClients(clients)))
+                .await
+                .map_err_to_io()?;
+        }
+        SandstormCommandType::CloseClients => {
+            let request = CloseClientsRequest::read(reader).await?;
+            let count = context.close_clients(request.0);
+            response_notifier
+                .send((request_id, ResponseNotification::CloseClients(count)))
                 .await
                 .map_err_to_io()?;
         }
         SandstormCommandType::Meow => {
             let _ = MeowRequest::read(reader).await?;
-            response_notifier.send(ResponseNotification::Meow).await.map_err_to_io()?;
+            response_notifier
+                .send((request_id, ResponseNotification::Meow))
+                .await
+                .map_err_to_io()?;
         }
         c => {
             return Err(Error::new(
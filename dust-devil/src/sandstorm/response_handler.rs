@@ -10,25 +10,34 @@ use std::{
 };
 
 use dust_devil_core::{
-    logging::Event,
+    listener_profile::ListenerProfile,
+    logging::{Event, EventData},
     sandstorm::{
-        AddSandstormSocketResponse, AddSocks5SocketResponse, CurrentMetricsResponse, EventStreamConfigResponse, EventStreamResponseRef,
-        GetBufferSizeResponse, ListAuthMethodsResponse, ListSandstormSocketsResponse, ListSocks5SocketsResponse, ListUsersResponse,
-        MeowResponse, Metrics, RemoveSandstormSocketResponse, RemoveSocketResponse, RemoveSocks5SocketResponse, SetBufferSizeResponse,
-        ShutdownRequest, ToggleAuthMethodResponse,
+        AddIpFilterResponse, AddSandstormSocketResponse, AddSocks5SocketResponse, ClearLockoutsResponse, CloseClientsResponse,
+        CurrentMetricsResponse, EventStreamConfigResponse, EventStreamResponseRef, GetBufferSizeResponse, GetPermissionsResponse,
+        IdentifiedResponse, ListAuthMethodsResponse, ListClientsResponse, ListIpFiltersResponse, ListLockoutsResponse,
+        ListLogFiltersResponse, ListSandstormSocketsResponse, ListSocks5SocketsResponse, ListUsersResponse, MeowResponse, Metrics,
+        MetricsBreakdownResponse, PermissionDeniedResponse, RemoveIpFilterResponse, RemoveSandstormSocketResponse, RemoveSocketResponse,
+        RemoveSocks5SocketResponse, SandstormCapability, SandstormCommandType, SetBufferSizeResponse, SetLogFilterResponse,
+        ShutdownResponse, ToggleAuthMethodResponse, UnsupportedCommandResponse, Version1,
     },
     serialize::ByteWrite,
 };
 
+use time::OffsetDateTime;
 use tokio::{
     io::{AsyncWrite, AsyncWriteExt, BufWriter},
     select,
     sync::{broadcast, mpsc, oneshot},
 };
 
-use crate::context::SandstormContext;
+use crate::{context::SandstormContext, log_filters::StreamFilter};
 
-use super::{error_handling::ToIoResult, messaging::ResponseNotification};
+use super::{
+    error_handling::ToIoResult,
+    lag_policy::EventLagPolicy,
+    messaging::{IdentifiedNotification, ResponseNotification},
+};
 
 /// The maximum amount requests for a specific stream type (e.g. "socks5 sockets requests") that
 /// can pile up before the response handler starts waiting on them to be completed synchronously.
@@ -37,22 +46,24 @@ use super::{error_handling::ToIoResult, messaging::ResponseNotification};
 /// sandstorm sockets over and over again repeatedly without delay? 4 is more than enough.
 const RECEIVER_BUFFER_SIZE: usize = 4;
 
-enum SocketRequestReceiver {
-    List(oneshot::Receiver<Vec<SocketAddr>>),
+enum SocketRequestReceiver<T> {
+    List(oneshot::Receiver<Vec<T>>),
     Add(oneshot::Receiver<Result<(), Error>>),
     Remove(oneshot::Receiver<RemoveSocketResponse>),
 }
 
-enum SocketRequestResult {
-    List(Vec<SocketAddr>),
+type Socks5RequestReceiver = SocketRequestReceiver<(SocketAddr, ListenerProfile)>;
+
+enum SocketRequestResult<T> {
+    List(Vec<T>),
     Add(Result<(), Error>),
     Remove(RemoveSocketResponse),
 }
 
 /// Awaiting a SocketRequestReceiver will return a SocketRequestResult with the result of calling
 /// `.await` on the oneshot::Receiver contaiend within the enum variant of the same name.
-impl Future for SocketRequestReceiver {
-    type Output = Result<SocketRequestResult, oneshot::error::RecvError>;
+impl<T> Future for SocketRequestReceiver<T> {
+    type Output = Result<SocketRequestResult<T>, oneshot::error::RecvError>;
     fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> Poll<Self::Output> {
         match self.deref_mut() {
             Self::List(receiver) => Pin::new(receiver).poll(cx).map(|r| r.map(SocketRequestResult::List)),
@@ -63,10 +74,11 @@ impl Future for SocketRequestReceiver {
 }
 
 /// If the vector is empty, returns a future that stalls indefinitely. Otherwise, waits for the
-/// first future in the vector to complete, removes it from the vector, and returns its result.
-async fn get_first_if<F: Future<Output = R> + Unpin, R>(receivers: &mut VecDeque<F>) -> R {
-    if let Some(receiver) = receivers.front_mut() {
-        let result = receiver.await;
+/// first future in the vector to complete, removes it from the vector, and returns its result
+/// along with the request ID it was paired with.
+async fn get_first_if<F: Future<Output = R> + Unpin, R>(receivers: &mut VecDeque<(Option<u32>, F)>) -> (Option<u32>, R) {
+    if let Some((request_id, receiver)) = receivers.front_mut() {
+        let result = (*request_id, receiver.await);
         receivers.remove(0);
         result
     } else {
@@ -81,26 +93,58 @@ async fn get_first_if<F: Future<Output = R> + Unpin, R>(receivers: &mut VecDeque
 /// vector or a receiver completes, it gets taken out of the vector.
 ///
 /// This is a wrapper function that simplifies processing receivers in a vector, in the order in
-/// which they are in the vector.
-async fn get_first_optional<T>(receivers: &mut VecDeque<Option<oneshot::Receiver<T>>>) -> Result<Option<T>, oneshot::error::RecvError> {
-    let maybe_receiver = match receivers.front_mut() {
+/// which they are in the vector. The request ID each receiver was paired with is returned along
+/// with the result.
+async fn get_first_optional<T>(
+    receivers: &mut VecDeque<(Option<u32>, Option<oneshot::Receiver<T>>)>,
+) -> (Option<u32>, Result<Option<T>, oneshot::error::RecvError>) {
+    let (request_id, maybe_receiver) = match receivers.front_mut() {
         Some(r) => r,
         None => future::pending().await,
     };
 
+    let request_id = *request_id;
     match maybe_receiver {
         Some(receiver) => {
             let result = receiver.await.map(|v| Some(v));
             receivers.remove(0);
-            result
+            (request_id, result)
         }
         None => {
             receivers.remove(0);
-            Ok(None)
+            (request_id, Ok(None))
         }
     }
 }
 
+/// Writes a response, tagged with the ID of the request it answers if request IDs were negotiated.
+async fn write_response<T, W>(request_id: Option<u32>, response: T, writer: &mut W) -> Result<(), Error>
+where
+    T: ByteWrite,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    match request_id {
+        Some(id) => IdentifiedResponse(id, response).write(writer).await,
+        None => response.write(writer).await,
+    }
+}
+
+/// Writes a current metrics response, in the format of the client's negotiated version.
+async fn write_metrics_response<W>(
+    request_id: Option<u32>,
+    metrics: Option<Metrics>,
+    context: &SandstormContext,
+    writer: &mut W,
+) -> Result<(), Error>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    match context.negotiation.includes_metrics_times() {
+        true => write_response(request_id, CurrentMetricsResponse(metrics), writer).await,
+        false => write_response(request_id, Version1(CurrentMetricsResponse(metrics)), writer).await,
+    }
+}
+
 /// If `maybe_receiver` is `Some(receiver)`, then this function returns the same as calling
 /// `receiver.recv()`. Otherwise, if `None`, returns a future that never completes. This function
 /// is intended for easy use within a `select!` block, as adding a
@@ -150,16 +194,17 @@ where
 }
 
 struct ResponseHandlerState {
-    socks_receivers: VecDeque<SocketRequestReceiver>,
-    sandstorm_receivers: VecDeque<SocketRequestReceiver>,
-    metrics_receivers: VecDeque<Option<oneshot::Receiver<Metrics>>>,
+    socks_receivers: VecDeque<(Option<u32>, Socks5RequestReceiver)>,
+    sandstorm_receivers: VecDeque<(Option<u32>, SocketRequestReceiver<SocketAddr>)>,
+    metrics_receivers: VecDeque<(Option<u32>, Option<oneshot::Receiver<Metrics>>)>,
     event_stream_receiver: Option<broadcast::Receiver<Arc<Event>>>,
+    event_stream_filter: Option<StreamFilter>,
 }
 
 pub async fn handle_responses<W>(
     writer: &mut BufWriter<W>,
     context: &SandstormContext,
-    mut response_notifier: mpsc::Receiver<ResponseNotification>,
+    mut response_notifier: mpsc::Receiver<IdentifiedNotification>,
 ) -> Result<(), Error>
 where
     W: AsyncWrite + Unpin,
@@ -169,6 +214,7 @@ where
         sandstorm_receivers: VecDeque::with_capacity(RECEIVER_BUFFER_SIZE),
         metrics_receivers: VecDeque::with_capacity(RECEIVER_BUFFER_SIZE),
         event_stream_receiver: None,
+        event_stream_filter: None,
     };
 
     let mut closed = false;
@@ -183,8 +229,9 @@ where
             biased;
             maybe_notification = response_notifier.recv(), if !closed => {
                 match maybe_notification {
-                    Some(notification) => {
+                    Some((request_id, notification)) => {
                         handle_notification(
+                            request_id,
                             notification,
                             writer,
                             context,
@@ -197,23 +244,32 @@ where
                 };
 
             }
-            result = get_first_if(&mut handler_state.socks_receivers) => {
+            (request_id, result) = get_first_if(&mut handler_state.socks_receivers) => {
                 let socket_result = result.map_err_to_io()?;
-                handle_socks5_response(socket_result, writer).await?;
+                handle_socks5_response(request_id, socket_result, context, writer).await?;
             }
-            result = get_first_if(&mut handler_state.sandstorm_receivers) => {
+            (request_id, result) = get_first_if(&mut handler_state.sandstorm_receivers) => {
                 let socket_result = result.map_err_to_io()?;
-                handle_sandstorm_response(socket_result, writer).await?;
+                handle_sandstorm_response(request_id, socket_result, writer).await?;
             }
-            result = get_first_optional(&mut handler_state.metrics_receivers) => {
+            (request_id, result) = get_first_optional(&mut handler_state.metrics_receivers) => {
                 let result = result.map_err_to_io()?;
-                CurrentMetricsResponse(result).write(writer).await?;
+                write_metrics_response(request_id, result, context, writer).await?;
             }
             maybe_event = recv_if_some(&mut handler_state.event_stream_receiver) => {
                 match maybe_event {
-                    Ok(evt) => EventStreamResponseRef(evt.as_ref()).write(writer).await?,
+                    Ok(evt) => {
+                        let accepted = match &mut handler_state.event_stream_filter {
+                            Some(filter) => filter.accepts(&evt.data, context.state.clients()),
+                            None => true,
+                        };
+
+                        if accepted {
+                            EventStreamResponseRef(evt.as_ref()).write(writer).await?;
+                        }
+                    }
                     Err(broadcast::error::RecvError::Closed) => break,
-                    Err(broadcast::error::RecvError::Lagged(count)) => return Err(Error::new(ErrorKind::Other, format!("Connection too slow to stream events, lagged behind {count} events!"))),
+                    Err(broadcast::error::RecvError::Lagged(count)) => handle_event_stream_lagged(count, writer, context, &mut handler_state).await?,
                 }
             }
             _ = stall_if_empty(writer) => writer.flush().await?,
@@ -223,29 +279,89 @@ where
     Ok(())
 }
 
-async fn handle_socks5_response<W>(socket_result: SocketRequestResult, writer: &mut W) -> Result<(), Error>
+async fn handle_socks5_response<W>(
+    request_id: Option<u32>,
+    socket_result: SocketRequestResult<(SocketAddr, ListenerProfile)>,
+    context: &SandstormContext,
+    writer: &mut W,
+) -> Result<(), Error>
 where
     W: AsyncWrite + Unpin + ?Sized,
 {
     match socket_result {
-        SocketRequestResult::List(result) => ListSocks5SocketsResponse(result).write(writer).await,
-        SocketRequestResult::Add(result) => AddSocks5SocketResponse(result).write(writer).await,
-        SocketRequestResult::Remove(result) => RemoveSocks5SocketResponse(result).write(writer).await,
+        SocketRequestResult::List(result) => match context.negotiation.includes_listener_profiles() {
+            true => write_response(request_id, ListSocks5SocketsResponse(result), writer).await,
+            false => write_response(request_id, Version1(ListSocks5SocketsResponse(result)), writer).await,
+        },
+        SocketRequestResult::Add(result) => write_response(request_id, AddSocks5SocketResponse(result), writer).await,
+        SocketRequestResult::Remove(result) => write_response(request_id, RemoveSocks5SocketResponse(result), writer).await,
     }
 }
 
-async fn handle_sandstorm_response<W>(socket_result: SocketRequestResult, writer: &mut W) -> Result<(), Error>
+async fn handle_sandstorm_response<W>(
+    request_id: Option<u32>,
+    socket_result: SocketRequestResult<SocketAddr>,
+    writer: &mut W,
+) -> Result<(), Error>
 where
     W: AsyncWrite + Unpin + ?Sized,
 {
     match socket_result {
-        SocketRequestResult::List(result) => ListSandstormSocketsResponse(result).write(writer).await,
-        SocketRequestResult::Add(result) => AddSandstormSocketResponse(result).write(writer).await,
-        SocketRequestResult::Remove(result) => RemoveSandstormSocketResponse(result).write(writer).await,
+        SocketRequestResult::List(result) => write_response(request_id, ListSandstormSocketsResponse(result), writer).await,
+        SocketRequestResult::Add(result) => write_response(request_id, AddSandstormSocketResponse(result), writer).await,
+        SocketRequestResult::Remove(result) => write_response(request_id, RemoveSandstormSocketResponse(result), writer).await,
+    }
+}
+
+/// Handles the event stream falling behind by `count` events, as dictated by the context's
+/// [`EventLagPolicy`] (or by disconnecting, if the client didn't negotiate lagged events).
+async fn handle_event_stream_lagged<W>(
+    count: u64,
+    writer: &mut W,
+    context: &SandstormContext,
+    handler_state: &mut ResponseHandlerState,
+) -> Result<(), Error>
+where
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    // A client that doesn't know about lagged events can't be told about the dropped ones.
+    let policy = match context.negotiation.capabilities.has(SandstormCapability::EventStreamLagged) {
+        true => context.event_lag_policy,
+        false => EventLagPolicy::Disconnect,
+    };
+
+    let metrics = match policy {
+        EventLagPolicy::Disconnect => {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Connection too slow to stream events, lagged behind {count} events!"),
+            ))
+        }
+        EventLagPolicy::Drop => None,
+        EventLagPolicy::Resync => match context.request_metrics_and_subscribe(None).await {
+            Some(result_receiver) => {
+                let (metrics, _, event_receiver) = result_receiver.await.map_err_to_io()?;
+                handler_state.event_stream_receiver = Some(event_receiver);
+                Some(metrics)
+            }
+            None => None,
+        },
+    };
+
+    // The events of clients that finished may have been missed, so start over with the registry.
+    if let Some(filter) = &mut handler_state.event_stream_filter {
+        filter.reset();
     }
+
+    let event = Event::new(
+        OffsetDateTime::now_utc().unix_timestamp(),
+        EventData::EventStreamLagged(count, metrics),
+    );
+    EventStreamResponseRef(&event).write(writer).await
 }
 
 async fn handle_notification<W>(
+    request_id: Option<u32>,
     notification: ResponseNotification,
     writer: &mut W,
     context: &SandstormContext,
@@ -257,108 +373,222 @@ where
     match notification {
         ResponseNotification::Shutdown(receiver) => {
             let _ = receiver.await;
-            ShutdownRequest.write(writer).await?;
+            write_response(request_id, ShutdownResponse, writer).await?;
         }
-        ResponseNotification::LogEventConfig(enabled) => {
+        ResponseNotification::LogEventConfig(enabled, filter, replay) => {
+            let mut replayed = Vec::new();
             let result = match (enabled, &handler_state.event_stream_receiver) {
                 (false, _) => {
                     handler_state.event_stream_receiver.take();
+                    handler_state.event_stream_filter.take();
                     EventStreamConfigResponse::Disabled
                 }
-                (true, Some(_)) => EventStreamConfigResponse::WasAlreadyEnabled,
-                (true, None) => match context.request_metrics_and_subscribe().await {
+                (true, Some(_)) => {
+                    // An empty filter accepts every event, so there's no point in keeping it around.
+                    match (filter, &mut handler_state.event_stream_filter) {
+                        (None, _) => {}
+                        (Some(filter), _) if filter.is_empty() => handler_state.event_stream_filter = None,
+                        (Some(filter), Some(stream_filter)) => stream_filter.set(filter),
+                        (Some(filter), None) => handler_state.event_stream_filter = Some(StreamFilter::new(filter)),
+                    }
+                    EventStreamConfigResponse::WasAlreadyEnabled
+                }
+                (true, None) => match context.request_metrics_and_subscribe(replay).await {
                     Some(result_receiver) => {
-                        let (metrics, event_receiver) = result_receiver.await.map_err_to_io()?;
+                        let (metrics, mut history, event_receiver) = result_receiver.await.map_err_to_io()?;
                         handler_state.event_stream_receiver = Some(event_receiver);
-                        EventStreamConfigResponse::Enabled(metrics)
+                        handler_state.event_stream_filter = filter.filter(|f| !f.is_empty()).map(StreamFilter::new);
+
+                        if let Some(stream_filter) = &mut handler_state.event_stream_filter {
+                            history.retain(|evt| stream_filter.accepts(&evt.data, context.state.clients()));
+                        }
+
+                        match replay {
+                            Some(_) => {
+                                replayed = history;
+                                EventStreamConfigResponse::EnabledWithHistory(metrics, replayed.len() as u16)
+                            }
+                            None => EventStreamConfigResponse::Enabled(metrics),
+                        }
                     }
                     None => EventStreamConfigResponse::Disabled,
                 },
             };
 
-            result.write(writer).await?;
+            match context.negotiation.includes_metrics_times() {
+                true => write_response(request_id, result, writer).await?,
+                false => write_response(request_id, Version1(result), writer).await?,
+            }
+
+            for evt in replayed {
+                EventStreamResponseRef(evt.as_ref()).write(writer).await?;
+            }
         }
         ResponseNotification::ListSocks5Sockets(receiver) => {
             if handler_state.socks_receivers.len() == handler_state.socks_receivers.capacity() {
-                let result = handler_state.socks_receivers.pop_front().unwrap().await.map_err_to_io()?;
-                handle_socks5_response(result, writer).await?;
+                let (pending_id, receiver) = handler_state.socks_receivers.pop_front().unwrap();
+                handle_socks5_response(pending_id, receiver.await.map_err_to_io()?, context, writer).await?;
             }
-            handler_state.socks_receivers.push_back(SocketRequestReceiver::List(receiver));
+            handler_state
+                .socks_receivers
+                .push_back((request_id, SocketRequestReceiver::List(receiver)));
         }
         ResponseNotification::AddSocks5Socket(receiver) => {
             if handler_state.socks_receivers.len() == handler_state.socks_receivers.capacity() {
-                let result = handler_state.socks_receivers.pop_front().unwrap().await.map_err_to_io()?;
-                handle_socks5_response(result, writer).await?;
+                let (pending_id, receiver) = handler_state.socks_receivers.pop_front().unwrap();
+                handle_socks5_response(pending_id, receiver.await.map_err_to_io()?, context, writer).await?;
             }
-            handler_state.socks_receivers.push_back(SocketRequestReceiver::Add(receiver));
+            handler_state
+                .socks_receivers
+                .push_back((request_id, SocketRequestReceiver::Add(receiver)));
         }
         ResponseNotification::RemoveSocks5Socket(receiver) => {
             if handler_state.socks_receivers.len() == handler_state.socks_receivers.capacity() {
-                let result = handler_state.socks_receivers.pop_front().unwrap().await.map_err_to_io()?;
-                handle_socks5_response(result, writer).await?;
+                let (pending_id, receiver) = handler_state.socks_receivers.pop_front().unwrap();
+                handle_socks5_response(pending_id, receiver.await.map_err_to_io()?, context, writer).await?;
             }
-            handler_state.socks_receivers.push_back(SocketRequestReceiver::Remove(receiver));
+            handler_state
+                .socks_receivers
+                .push_back((request_id, SocketRequestReceiver::Remove(receiver)));
         }
         ResponseNotification::ListSandstormSockets(receiver) => {
             if handler_state.sandstorm_receivers.len() == handler_state.sandstorm_receivers.capacity() {
-                let result = handler_state.sandstorm_receivers.pop_front().unwrap().await.map_err_to_io()?;
-                handle_sandstorm_response(result, writer).await?;
+                let (pending_id, receiver) = handler_state.sandstorm_receivers.pop_front().unwrap();
+                handle_sandstorm_response(pending_id, receiver.await.map_err_to_io()?, writer).await?;
             }
-            handler_state.sandstorm_receivers.push_back(SocketRequestReceiver::List(receiver));
+            handler_state
+                .sandstorm_receivers
+                .push_back((request_id, SocketRequestReceiver::List(receiver)));
         }
         ResponseNotification::AddSandstormSocket(receiver) => {
             if handler_state.sandstorm_receivers.len() == handler_state.sandstorm_receivers.capacity() {
-                let result = handler_state.sandstorm_receivers.pop_front().unwrap().await.map_err_to_io()?;
-                handle_sandstorm_response(result, writer).await?;
+                let (pending_id, receiver) = handler_state.sandstorm_receivers.pop_front().unwrap();
+                handle_sandstorm_response(pending_id, receiver.await.map_err_to_io()?, writer).await?;
             }
-            handler_state.sandstorm_receivers.push_back(SocketRequestReceiver::Add(receiver));
+            handler_state
+                .sandstorm_receivers
+                .push_back((request_id, SocketRequestReceiver::Add(receiver)));
         }
         ResponseNotification::RemoveSandstormSocket(receiver) => {
             if handler_state.sandstorm_receivers.len() == handler_state.sandstorm_receivers.capacity() {
-                let result = handler_state.sandstorm_receivers.pop_front().unwrap().await.map_err_to_io()?;
-                handle_sandstorm_response(result, writer).await?;
+                let (pending_id, receiver) = handler_state.sandstorm_receivers.pop_front().unwrap();
+                handle_sandstorm_response(pending_id, receiver.await.map_err_to_io()?, writer).await?;
             }
-            handler_state.sandstorm_receivers.push_back(SocketRequestReceiver::Remove(receiver));
+            handler_state
+                .sandstorm_receivers
+                .push_back((request_id, SocketRequestReceiver::Remove(receiver)));
         }
         ResponseNotification::ListUsers(snapshot) => {
-            ListUsersResponse(snapshot).write(writer).await?;
+            write_response(request_id, ListUsersResponse(snapshot), writer).await?;
         }
         ResponseNotification::AddUser(result) => {
-            result.write(writer).await?;
+            write_response(request_id, result, writer).await?;
         }
         ResponseNotification::UpdateUser(result) => {
-            result.write(writer).await?;
+            write_response(request_id, result, writer).await?;
         }
         ResponseNotification::DeleteUser(result) => {
-            result.write(writer).await?;
+            write_response(request_id, result, writer).await?;
         }
         ResponseNotification::ListAuthMethods(auth_methods) => {
-            ListAuthMethodsResponse(auth_methods).write(writer).await?;
+            write_response(request_id, ListAuthMethodsResponse(auth_methods), writer).await?;
         }
         ResponseNotification::ToggleAuthMethod(result) => {
-            ToggleAuthMethodResponse(result).write(writer).await?;
+            write_response(request_id, ToggleAuthMethodResponse(result), writer).await?;
         }
         ResponseNotification::RequestCurrentMetrics(maybe_receiver) => {
             if handler_state.metrics_receivers.len() == handler_state.metrics_receivers.capacity() {
-                let maybe_receiver = handler_state.metrics_receivers.pop_front().unwrap();
-                let result = match maybe_receiver {
+                let (pending_id, pending_receiver) = handler_state.metrics_receivers.pop_front().unwrap();
+                let result = match pending_receiver {
                     Some(receiver) => Some(receiver.await.map_err_to_io()?),
                     None => None,
                 };
-                CurrentMetricsResponse(result).write(writer).await?;
+                write_metrics_response(pending_id, result, context, writer).await?;
             }
 
-            handler_state.metrics_receivers.push_back(maybe_receiver);
+            handler_state.metrics_receivers.push_back((request_id, maybe_receiver));
+        }
+        ResponseNotification::MetricsBreakdown(maybe_receiver) => {
+            let result = match maybe_receiver {
+                Some(receiver) => Some(receiver.await.map_err_to_io()?),
+                None => None,
+            };
+            write_response(request_id, MetricsBreakdownResponse(result), writer).await?;
+        }
+        ResponseNotification::ListClients(clients) => {
+            write_response(request_id, ListClientsResponse(clients), writer).await?;
+        }
+        ResponseNotification::CloseClients(count) => {
+            write_response(request_id, CloseClientsResponse(count), writer).await?;
         }
         ResponseNotification::GetBufferSize(buffer_size) => {
-            GetBufferSizeResponse(buffer_size).write(writer).await?;
+            write_response(request_id, GetBufferSizeResponse(buffer_size), writer).await?;
         }
         ResponseNotification::SetBufferSize(result) => {
-            SetBufferSizeResponse(result).write(writer).await?;
+            write_response(request_id, SetBufferSizeResponse(result), writer).await?;
             writer.flush().await?;
         }
+        ResponseNotification::GetPermissions(permissions) => {
+            write_response(request_id, GetPermissionsResponse(permissions), writer).await?;
+        }
+        ResponseNotification::PermissionDenied(command) => {
+            // Responses of the same synchronization group must come back in order, so any pending
+            // responses of the denied command's group are written out before the denial.
+            match command {
+                SandstormCommandType::ListSocks5Sockets
+                | SandstormCommandType::AddSocks5Socket
+                | SandstormCommandType::RemoveSocks5Socket => {
+                    while let Some((pending_id, receiver)) = handler_state.socks_receivers.pop_front() {
+                        handle_socks5_response(pending_id, receiver.await.map_err_to_io()?, context, writer).await?;
+                    }
+                }
+                SandstormCommandType::ListSandstormSockets
+                | SandstormCommandType::AddSandstormSocket
+                | SandstormCommandType::RemoveSandstormSocket => {
+                    while let Some((pending_id, receiver)) = handler_state.sandstorm_receivers.pop_front() {
+                        handle_sandstorm_response(pending_id, receiver.await.map_err_to_io()?, writer).await?;
+                    }
+                }
+                SandstormCommandType::RequestCurrentMetrics => {
+                    while let Some((pending_id, maybe_receiver)) = handler_state.metrics_receivers.pop_front() {
+                        let result = match maybe_receiver {
+                            Some(receiver) => Some(receiver.await.map_err_to_io()?),
+                            None => None,
+                        };
+                        write_metrics_response(pending_id, result, context, writer).await?;
+                    }
+                }
+                _ => {}
+            }
+
+            write_response(request_id, PermissionDeniedResponse(command), writer).await?;
+        }
+        ResponseNotification::UnsupportedCommand(command) => {
+            write_response(request_id, UnsupportedCommandResponse(command), writer).await?;
+        }
+        ResponseNotification::ListLockouts(lockouts) => {
+            write_response(request_id, ListLockoutsResponse(lockouts), writer).await?;
+        }
+        ResponseNotification::ClearLockouts(count) => {
+            write_response(request_id, ClearLockoutsResponse(count), writer).await?;
+        }
+        ResponseNotification::ListIpFilters(filters) => {
+            write_response(request_id, ListIpFiltersResponse(filters), writer).await?;
+        }
+        ResponseNotification::AddIpFilter(result) => {
+            write_response(request_id, AddIpFilterResponse(result), writer).await?;
+        }
+        ResponseNotification::RemoveIpFilter(result) => {
+            write_response(request_id, RemoveIpFilterResponse(result), writer).await?;
+        }
+        ResponseNotification::ListLogFilters(filters) => {
+            write_response(request_id, ListLogFiltersResponse(filters), writer).await?;
+        }
+        ResponseNotification::SetLogFilter(result) => {
+            write_response(request_id, SetLogFilterResponse(result), writer).await?;
+        }
         ResponseNotification::Meow => {
-            MeowResponse.write(writer).await?;
+            write_response(request_id, MeowResponse, writer).await?;
         }
     }
 
@@ -3,11 +3,13 @@ use std::{
     io::{Error, ErrorKind},
     net::SocketAddr,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use dust_devil_core::{
+    listener_profile::ListenerProfile,
     logging::EventData,
-    sandstorm::RemoveSocketResponse,
+    sandstorm::{LockoutTarget, RemoveSocketResponse, SandstormPermissions},
     users::{UserRole, DEFAULT_USER_PASSWORD, DEFAULT_USER_USERNAME},
 };
 use tokio::{net::TcpListener, select, sync::mpsc};
@@ -15,22 +17,44 @@ use tokio_util::sync::CancellationToken;
 
 use crate::{
     args::StartupArguments,
+    config::{get_metrics_file_string, get_state_string, parse_metrics_file, RestoredState},
     context::{ClientContext, SandstormContext, ServerState},
-    logger::{LogManager, LogSender},
+    handoff::{self, HandedOffSettings, HandoffListener, InheritedListeners},
+    ip_filters::IpFilterManager,
+    logger::{LogManager, LogSender, MetricsRequester},
     messaging::MessageType,
-    printlnif, sandstorm, socks5,
+    metrics_server, printlnif, sandstorm, socks5,
+    systemd::{self, SystemdNotifier},
     users::{UserData, UserManager},
-    utils::accept_from_any::accept_from_any,
+    utils::{
+        accept_from_any::accept_from_any,
+        atomic_file,
+        unix_socket::{self, UnixListener},
+    },
 };
 
 const MESSAGING_CHANNEL_SIZE: usize = 8;
+const LOCKOUT_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+const DRAIN_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_HANDOFF_DRAIN_TIMEOUT: u32 = 300;
+const METRICS_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+const STATE_SAVE_INTERVAL: Duration = Duration::from_secs(60);
 
 pub async fn run_server(startup_args: StartupArguments) {
     let verbose = startup_args.verbose;
     printlnif!(verbose, "Starting up logger");
 
     let logger = if startup_args.events_enabled {
-        let lgr = LogManager::new(verbose, !startup_args.silent, startup_args.log_file.as_deref()).await;
+        let lgr = LogManager::new(
+            verbose,
+            (!startup_args.silent).then(|| startup_args.stdout_log_format.unwrap_or_default()),
+            startup_args.log_file.as_deref(),
+            startup_args.file_log_format.unwrap_or_default(),
+            startup_args.log_rotation.clone(),
+            &startup_args.system_logs(),
+            &startup_args.log_filters,
+        )
+        .await;
         Some(lgr)
     } else {
         None
@@ -56,53 +80,189 @@ macro_rules! sendif {
     };
 }
 
-async fn run_server_inner(startup_args: StartupArguments, logger: Option<&LogManager>) {
+async fn run_server_inner(mut startup_args: StartupArguments, logger: Option<&LogManager>) {
     let log_sender = logger.map(|l| l.new_sender());
 
+    if let (Some(state_file), Some(restored_state)) = (&startup_args.state_file, &startup_args.restored_state) {
+        sendif!(log_sender, EventData::RestoringStateFromFile(state_file.clone()));
+        send_restored_state_events(restored_state, &log_sender);
+    }
+
+    let systemd_notifier = SystemdNotifier::from_env().unwrap_or_else(|err| {
+        sendif!(log_sender, EventData::SystemdNotifyFailed(err));
+        None
+    });
+
+    // The listeners are inherited before loading the users, as the old server saves them before
+    // handing off.
+    let mut inherited = take_systemd_listeners(startup_args.verbose, &log_sender);
+    if let (None, Some(path)) = (&inherited, &startup_args.handoff_socket) {
+        inherited = receive_inherited_listeners(startup_args.verbose, path, &log_sender).await;
+    }
+
     sendif!(log_sender, EventData::LoadingUsersFromFile(startup_args.users_file.clone()));
 
     let users = create_user_manager(&startup_args.users_file, startup_args.users, &log_sender).await;
 
-    let mut socks_listeners = bind_socks_sockets(startup_args.verbose, startup_args.socks5_bind_sockets, &log_sender).await;
+    // Like the users, the metrics are restored after inheriting the listeners, as the old server
+    // saves them before handing off.
+    let metrics_requester = logger.map(|l| l.new_requester());
+    let mut metrics_file = None;
+    if let (Some(requester), Some(path)) = (&metrics_requester, startup_args.metrics_file) {
+        if restore_metrics(&path, requester, &log_sender).await {
+            metrics_file = Some(path);
+        }
+    }
+
+    let (mut socks_listeners, mut socks_profiles, mut sandstorm_listeners, mut sandstorm_unix_listeners) = match inherited {
+        Some(inherited) => {
+            send_inherited_listeners_events(&inherited, &log_sender);
+            // The old server's runtime settings may have been changed since it loaded them, so
+            // they take precedence over the ones loaded at startup.
+            if let Some(settings) = inherited.settings {
+                startup_args.no_auth_enabled = settings.no_auth_enabled;
+                startup_args.userpass_auth_enabled = settings.userpass_auth_enabled;
+                startup_args.buffer_size = settings.buffer_size;
+                startup_args.ip_filters = settings.ip_filters;
+            }
+
+            let (socks_listeners, socks_profiles) = take_inherited_socks_listeners(inherited.socks5);
+            (socks_listeners, socks_profiles, inherited.sandstorm, inherited.sandstorm_unix)
+        }
+        None => {
+            let (socks_listeners, socks_profiles) = bind_socks_sockets(
+                startup_args.verbose,
+                startup_args.socks5_bind_sockets,
+                startup_args.socks5_profiles,
+                &log_sender,
+            )
+            .await;
+            let sandstorm_listeners = bind_sandstorm_sockets(startup_args.verbose, startup_args.sandstorm_bind_sockets, &log_sender).await;
+            let sandstorm_unix_listeners =
+                bind_sandstorm_unix_sockets(startup_args.verbose, startup_args.sandstorm_unix_sockets, &log_sender).await;
+            (socks_listeners, socks_profiles, sandstorm_listeners, sandstorm_unix_listeners)
+        }
+    };
+
     if socks_listeners.is_empty() {
         eprintln!("Failed to bind any socks5 socket! Aborting");
         sendif!(log_sender, EventData::FailedBindAnySocketAborting);
         return;
     }
 
-    let mut sandstorm_listeners = bind_sandstorm_sockets(startup_args.verbose, startup_args.sandstorm_bind_sockets, &log_sender).await;
+    let mut handoff_listener = startup_args
+        .handoff_socket
+        .as_ref()
+        .and_then(|path| match HandoffListener::bind(path) {
+            Ok(listener) => {
+                sendif!(log_sender, EventData::ListeningForHandoff(path.clone()));
+                Some(listener)
+            }
+            Err(err) => {
+                sendif!(log_sender, EventData::FailedBindHandoffSocket(path.clone(), err));
+                None
+            }
+        });
 
     let (message_sender, mut message_receiver) = mpsc::channel(MESSAGING_CHANNEL_SIZE);
 
     printlnif!(startup_args.verbose, "Constructing server state");
+    let ip_filters = IpFilterManager::new();
+    for (listener, kind, network) in startup_args.ip_filters {
+        ip_filters.insert(listener, kind, network);
+    }
+
     let state = Arc::new(ServerState::new(
         users,
+        ip_filters,
         startup_args.no_auth_enabled,
         startup_args.userpass_auth_enabled,
         startup_args.buffer_size,
         message_sender,
-        logger.map(|l| l.new_requester()),
+        logger,
     ));
 
+    let default_profile = Arc::new(ListenerProfile::default());
+
     let mut client_id_counter: u64 = 1;
     let mut manager_id_counter: u64 = 1;
 
     let client_cancel_token = CancellationToken::new();
     let manager_cancel_token = CancellationToken::new();
 
+    let metrics_cancel_token = CancellationToken::new();
+    if let Some(logger) = logger {
+        let metrics_listeners = bind_metrics_sockets(startup_args.verbose, startup_args.metrics_bind_sockets, &log_sender).await;
+        if !metrics_listeners.is_empty() {
+            let requester = logger.new_requester();
+            let cancel_token1 = metrics_cancel_token.clone();
+            tokio::spawn(async move {
+                metrics_server::serve_metrics(startup_args.verbose, metrics_listeners, requester, cancel_token1).await;
+            });
+        }
+    }
+
+    let mut lockout_sweep_interval = tokio::time::interval(LOCKOUT_SWEEP_INTERVAL);
+
+    let default_drain_timeout = startup_args.drain_timeout.unwrap_or(0);
+    let event_lag_policy = startup_args.event_lag_policy.unwrap_or_default();
+    let mut drain_deadline: Option<Instant> = None;
+    let mut drain_report_interval = tokio::time::interval(DRAIN_REPORT_INTERVAL);
+    let mut handed_off = false;
+
+    let mut metrics_save_interval = metrics_file
+        .as_ref()
+        .map(|_| tokio::time::interval_at((Instant::now() + METRICS_SAVE_INTERVAL).into(), METRICS_SAVE_INTERVAL));
+
+    // The state is saved periodically, so changes aren't lost if the server doesn't shut down
+    // cleanly, but only when it changed since it was last saved.
+    let mut state_save_interval = startup_args
+        .state_file
+        .as_ref()
+        .map(|_| tokio::time::interval_at((Instant::now() + STATE_SAVE_INTERVAL).into(), STATE_SAVE_INTERVAL));
+    let mut saved_state_snapshot = None;
+
+    let mut watchdog_interval = systemd_notifier
+        .as_ref()
+        .and_then(|n| n.watchdog_interval())
+        .map(tokio::time::interval);
+
+    let status = format!(
+        "READY=1\nSTATUS=Listening on {} socks5 and {} Sandstorm sockets",
+        socks_listeners.len(),
+        sandstorm_listeners.len() + sandstorm_unix_listeners.len()
+    );
+    notify_systemd(&systemd_notifier, &status, &log_sender).await;
+
     printlnif!(startup_args.verbose, "Entering main loop");
 
     loop {
         select! {
-            accept_result = accept_from_any(&socks_listeners) => {
+            accept_result = accept_from_any(&socks_listeners), if drain_deadline.is_none() => {
                 match accept_result {
-                    Ok((socket, address)) => {
+                    Ok((listener, socket, address)) => {
+                        let listener_address = match check_ip_filters(&state, listener, address) {
+                            Ok(listener_address) => listener_address,
+                            Err(IpFilterRejection::Filtered(listener_address)) => {
+                                sendif!(log_sender, EventData::ConnectionRejectedByIpFilter(listener_address, address));
+                                continue;
+                            }
+                            Err(IpFilterRejection::UnknownListener(err)) => {
+                                sendif!(log_sender, EventData::ClientConnectionAcceptFailed(None, err));
+                                continue;
+                            }
+                        };
+
+                        let profile = socks_profiles.get(&listener_address).unwrap_or(&default_profile);
+                        let client_context = ClientContext::create(client_id_counter, address, Some(listener_address), Arc::clone(profile), &state, logger.map(|l| l.new_sender()));
                         sendif!(log_sender, EventData::NewClientConnectionAccepted(client_id_counter, address));
-                        let client_context = ClientContext::create(client_id_counter, &state, logger.map(|l| l.new_sender()));
                         client_id_counter += 1;
                         let cancel_token1 = client_cancel_token.clone();
+                        let state1 = Arc::clone(&state);
+                        state.client_started();
                         tokio::spawn(async move {
                             socks5::handle_socks5(socket, client_context, cancel_token1).await;
+                            state1.client_finished();
                         });
                     },
                     Err((listener, err)) => {
@@ -112,9 +272,21 @@ async fn run_server_inner(startup_args: StartupArguments, logger: Option<&LogMan
             },
             accept_result = accept_from_any(&sandstorm_listeners) => {
                 match accept_result {
-                    Ok((socket, address)) => {
+                    Ok((listener, socket, address)) => {
+                        match check_ip_filters(&state, listener, address) {
+                            Ok(_) => {}
+                            Err(IpFilterRejection::Filtered(listener_address)) => {
+                                sendif!(log_sender, EventData::ConnectionRejectedByIpFilter(listener_address, address));
+                                continue;
+                            }
+                            Err(IpFilterRejection::UnknownListener(err)) => {
+                                sendif!(log_sender, EventData::ClientConnectionAcceptFailed(None, err));
+                                continue;
+                            }
+                        }
+
                         sendif!(log_sender, EventData::NewSandstormConnectionAccepted(manager_id_counter, address));
-                        let sandstorm_context = SandstormContext::create(manager_id_counter, &state, logger.map(|l| l.new_sender()));
+                        let sandstorm_context = SandstormContext::create(manager_id_counter, LockoutTarget::Address(address.ip()), &state, logger.map(|l| l.new_sender()), event_lag_policy);
                         manager_id_counter += 1;
                         let cancel_token1 = manager_cancel_token.clone();
                         tokio::spawn(async move {
@@ -126,26 +298,70 @@ async fn run_server_inner(startup_args: StartupArguments, logger: Option<&LogMan
                     },
                 }
             }
+            accept_result = accept_from_any(&sandstorm_unix_listeners) => {
+                match accept_result {
+                    Ok((listener, socket, ())) => {
+                        let uid = match unix_socket::peer_uid(&socket) {
+                            Ok(uid) => uid,
+                            Err(err) => {
+                                sendif!(log_sender, EventData::ClientConnectionAcceptFailed(None, err));
+                                continue;
+                            }
+                        };
+
+                        sendif!(log_sender, EventData::NewSandstormUnixConnectionAccepted(manager_id_counter, listener.path().to_string()));
+                        let sandstorm_context = SandstormContext::create(manager_id_counter, LockoutTarget::UnixUser(uid), &state, logger.map(|l| l.new_sender()), event_lag_policy);
+                        manager_id_counter += 1;
+                        let cancel_token1 = manager_cancel_token.clone();
+                        tokio::spawn(async move {
+                            sandstorm::handle_sandstorm(socket, sandstorm_context, cancel_token1).await;
+                        });
+                    },
+                    Err((_, err)) => {
+                        sendif!(log_sender, EventData::ClientConnectionAcceptFailed(None, err));
+                    },
+                }
+            }
             message = message_receiver.recv() => {
                 let message = message.expect("Message channel closed before server loop!");
                 // `message` should never be `None`, as we still have a reference to the ServerState through the `state` variable,
                 // so the sender remains alive.
 
                 match message {
-                    MessageType::ShutdownRequest(result_notifier) => {
+                    MessageType::ShutdownRequest(drain_timeout, result_notifier) => {
                         let _ = result_notifier.send(());
+                        if drain_deadline.is_some() {
+                            eprintln!("Received shutdown request from monitoring connection while draining, shutting down immediately.");
+                            sendif!(log_sender, EventData::DrainAborted(state.active_clients()));
+                            break;
+                        }
+
                         eprintln!("Received shutdown request from monitoring connection, shutting down.");
-                        break;
+                        match start_draining(drain_timeout.unwrap_or(default_drain_timeout), &state, &systemd_notifier, &log_sender).await {
+                            Some(deadline) => {
+                                drain_deadline = Some(deadline);
+                                drain_report_interval.reset();
+                            }
+                            None => break,
+                        }
                     }
                     MessageType::ListSocks5Sockets(result_notifier) => {
-                        let _ = result_notifier.send(socks_listeners.iter().filter_map(|l| l.local_addr().ok()).collect());
+                        let list = socks_listeners
+                            .iter()
+                            .filter_map(|l| l.local_addr().ok())
+                            .map(|a| (a, socks_profiles.get(&a).map(|p| p.as_ref().clone()).unwrap_or_default()))
+                            .collect();
+                        let _ = result_notifier.send(list);
                     }
-                    MessageType::AddSocks5Socket(socket_address, result_notifier) => {
+                    MessageType::AddSocks5Socket(socket_address, profile, result_notifier) => {
                         if socket_address.port() == 0 {
                             let _ = result_notifier.send(Err(Error::new(ErrorKind::InvalidData, "The port may not be zero")));
                         } else {
                             match TcpListener::bind(socket_address).await {
                                 Ok(result) => {
+                                    if !profile.is_default() {
+                                        socks_profiles.insert(result.local_addr().unwrap_or(socket_address), Arc::new(profile));
+                                    }
                                     socks_listeners.push(result);
                                     sendif!(log_sender, EventData::NewSocks5Socket(socket_address));
                                     let _ = result_notifier.send(Ok(()));
@@ -167,6 +383,7 @@ async fn run_server_inner(startup_args: StartupArguments, logger: Option<&LogMan
 
                         let result = if let Some(listener_index) = maybe_listener_index {
                             socks_listeners.swap_remove(listener_index);
+                            socks_profiles.remove(&socket_address);
                             sendif!(log_sender, EventData::RemovedSocks5Socket(socket_address));
                             RemoveSocketResponse::Ok
                         } else {
@@ -215,28 +432,387 @@ async fn run_server_inner(startup_args: StartupArguments, logger: Option<&LogMan
                     }
                 }
             }
+            handoff_result = handoff::accept_handoff(&handoff_listener), if drain_deadline.is_none() => {
+                let stream = match handoff_result {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        sendif!(log_sender, EventData::HandoffSendFailed(err));
+                        continue;
+                    }
+                };
+
+                // Save the users, metrics and state before handing off, so the new server loads them.
+                // From then on the files belong to the new server, so whatever clients still draining
+                // here transfer afterwards is left out of the saved metrics.
+                save_users(&state, &startup_args.users_file, &log_sender).await;
+                if let (Some(requester), Some(path)) = (&metrics_requester, &metrics_file) {
+                    save_metrics(path, requester, &log_sender).await;
+                }
+                if let Some(state_file) = &startup_args.state_file {
+                    let snapshot = take_state_snapshot(&socks_listeners, &socks_profiles, &sandstorm_listeners, &sandstorm_unix_listeners, &state);
+                    save_state(state_file, snapshot, &log_sender).await;
+                }
+
+                let settings = HandedOffSettings {
+                    no_auth_enabled: state.no_auth_enabled(),
+                    userpass_auth_enabled: state.userpass_auth_enabled(),
+                    buffer_size: state.buffer_size(),
+                    ip_filters: state.ip_filters().take_snapshot(),
+                };
+
+                let socket_count = (socks_listeners.len() + sandstorm_listeners.len() + sandstorm_unix_listeners.len()) as u32;
+                let send_result = handoff::send_listeners(
+                    stream,
+                    &socks_listeners,
+                    &socks_profiles,
+                    &sandstorm_listeners,
+                    &sandstorm_unix_listeners,
+                    &settings,
+                ).await;
+
+                if let Err(err) = send_result {
+                    sendif!(log_sender, EventData::HandoffSendFailed(err));
+                    continue;
+                }
+
+                eprintln!("Handed off listening sockets to a new process, shutting down.");
+                sendif!(log_sender, EventData::ListenersHandedOff(socket_count));
+                handed_off = true;
+                handoff_listener = None;
+                metrics_save_interval = None;
+                state_save_interval = None;
+                metrics_cancel_token.cancel();
+                socks_listeners.clear();
+                socks_profiles.clear();
+                sandstorm_listeners.clear();
+                // The new process keeps listening on the Unix sockets, so their files must stay.
+                sandstorm_unix_listeners.drain(..).for_each(UnixListener::close_keeping_file);
+
+                match start_draining(startup_args.drain_timeout.unwrap_or(DEFAULT_HANDOFF_DRAIN_TIMEOUT), &state, &systemd_notifier, &log_sender).await {
+                    Some(deadline) => {
+                        drain_deadline = Some(deadline);
+                        drain_report_interval.reset();
+                    }
+                    None => break,
+                }
+            }
+            _ = lockout_sweep_interval.tick() => {
+                for target in state.lockouts().expire(Instant::now()) {
+                    sendif!(log_sender, EventData::LoginLockoutEnded(target));
+                }
+            }
             _ = tokio::signal::ctrl_c() => {
-                eprintln!("Received shutdown signal, shutting down gracefully. Signal again to shut down ungracefully.");
                 sendif!(log_sender, EventData::ShutdownSignalReceived);
-                break;
+                if drain_deadline.is_some() {
+                    eprintln!("Received shutdown signal while draining, shutting down immediately.");
+                    sendif!(log_sender, EventData::DrainAborted(state.active_clients()));
+                    break;
+                }
+
+                eprintln!("Received shutdown signal, shutting down gracefully. Signal again to shut down ungracefully.");
+                match start_draining(default_drain_timeout, &state, &systemd_notifier, &log_sender).await {
+                    Some(deadline) => {
+                        drain_deadline = Some(deadline);
+                        drain_report_interval.reset();
+                    }
+                    None => break,
+                }
             },
+            _ = tokio::time::sleep_until(drain_deadline.unwrap_or_else(Instant::now).into()), if drain_deadline.is_some() => {
+                sendif!(log_sender, EventData::DrainTimedOut(state.active_clients()));
+                break;
+            }
+            _ = state.wait_clients_finished(), if drain_deadline.is_some() => {
+                sendif!(log_sender, EventData::DrainCompleted);
+                break;
+            }
+            _ = drain_report_interval.tick(), if drain_deadline.is_some() => {
+                let active_clients = state.active_clients();
+                sendif!(log_sender, EventData::DrainProgress(active_clients));
+                notify_systemd(&systemd_notifier, &format!("STATUS=Waiting for {active_clients} clients to finish"), &log_sender).await;
+            }
+            _ = optional_tick(&mut watchdog_interval) => {
+                notify_systemd(&systemd_notifier, "WATCHDOG=1", &log_sender).await;
+            }
+            _ = optional_tick(&mut metrics_save_interval) => {
+                if let (Some(requester), Some(path)) = (&metrics_requester, &metrics_file) {
+                    save_metrics(path, requester, &log_sender).await;
+                }
+            }
+            _ = optional_tick(&mut state_save_interval) => {
+                if let Some(state_file) = &startup_args.state_file {
+                    let snapshot = take_state_snapshot(&socks_listeners, &socks_profiles, &sandstorm_listeners, &sandstorm_unix_listeners, &state);
+                    if saved_state_snapshot.as_ref() != Some(&snapshot) && save_state(state_file, snapshot.clone(), &log_sender).await {
+                        saved_state_snapshot = Some(snapshot);
+                    }
+                }
+            }
         }
     }
 
     printlnif!(startup_args.verbose, "Exited main loop");
 
+    let state_snapshot = startup_args.state_file.as_ref().filter(|_| !handed_off).map(|_| {
+        take_state_snapshot(
+            &socks_listeners,
+            &socks_profiles,
+            &sandstorm_listeners,
+            &sandstorm_unix_listeners,
+            &state,
+        )
+    });
+
     drop(message_receiver);
+    drop(handoff_listener);
     drop(sandstorm_listeners);
+    drop(sandstorm_unix_listeners);
     drop(socks_listeners);
+    metrics_cancel_token.cancel();
     manager_cancel_token.cancel();
     client_cancel_token.cancel();
 
-    sendif!(log_sender, EventData::SavingUsersToFile(startup_args.users_file.clone()));
-    let save_to_file_result = state.users().save_to_file(&startup_args.users_file).await;
-    sendif!(
-        log_sender,
-        EventData::UsersSavedToFile(startup_args.users_file, save_to_file_result)
-    );
+    // Once handed off, the users, metrics and state are owned by the new server and must not be
+    // overwritten.
+    if !handed_off {
+        save_users(&state, &startup_args.users_file, &log_sender).await;
+        if let (Some(requester), Some(path)) = (&metrics_requester, &metrics_file) {
+            save_metrics(path, requester, &log_sender).await;
+        }
+    }
+
+    if let (Some(state_file), Some(state_snapshot)) = (&startup_args.state_file, state_snapshot) {
+        save_state(state_file, state_snapshot, &log_sender).await;
+    }
+}
+
+/// Saves the given state snapshot to the state file. Returns whether it was saved successfully.
+async fn save_state(state_file: &str, snapshot: String, log_sender: &Option<LogSender>) -> bool {
+    sendif!(log_sender, EventData::SavingStateToFile(state_file.to_string()));
+    let save_result = atomic_file::write(state_file, snapshot).await;
+    let saved = save_result.is_ok();
+    sendif!(log_sender, EventData::StateSavedToFile(state_file.to_string(), save_result));
+    saved
+}
+
+async fn save_users(state: &ServerState, users_file: &str, log_sender: &Option<LogSender>) {
+    sendif!(log_sender, EventData::SavingUsersToFile(users_file.to_string()));
+    let save_to_file_result = state.users().save_to_file(users_file).await;
+    sendif!(log_sender, EventData::UsersSavedToFile(users_file.to_string(), save_to_file_result));
+}
+
+/// Loads the metrics file and restores its counters. Returns whether the metrics should be saved
+/// to the file, which is not the case if it exists but couldn't be loaded, so it isn't overwritten.
+async fn restore_metrics(path: &str, requester: &MetricsRequester, log_sender: &Option<LogSender>) -> bool {
+    let source = match tokio::fs::read_to_string(path).await {
+        Ok(source) => source,
+        Err(err) if err.kind() == ErrorKind::NotFound => return true,
+        Err(err) => {
+            sendif!(log_sender, EventData::MetricsRestoredFromFile(path.to_string(), Err(err)));
+            return false;
+        }
+    };
+
+    match parse_metrics_file(&source) {
+        Ok(previous) => {
+            let counter_epoch = previous.counter_epoch;
+            requester.restore(previous).await;
+            sendif!(log_sender, EventData::MetricsRestoredFromFile(path.to_string(), Ok(counter_epoch)));
+            true
+        }
+        Err(err) => {
+            let err = Error::new(ErrorKind::InvalidData, err.to_string());
+            sendif!(log_sender, EventData::MetricsRestoredFromFile(path.to_string(), Err(err)));
+            false
+        }
+    }
+}
+
+/// Saves the metrics' historic counters to the metrics file. These are written to a temporary
+/// file first, which then replaces the metrics file, so it's never left partially written.
+async fn save_metrics(path: &str, requester: &MetricsRequester, log_sender: &Option<LogSender>) {
+    let metrics = match requester.request_snapshot().await {
+        Some(receiver) => match receiver.await {
+            Ok(metrics) => metrics,
+            Err(_) => return,
+        },
+        None => return,
+    };
+
+    let save_result = atomic_file::write(path, get_metrics_file_string(&metrics)).await;
+    sendif!(log_sender, EventData::MetricsSavedToFile(path.to_string(), save_result));
+}
+
+async fn notify_systemd(systemd_notifier: &Option<SystemdNotifier>, state: &str, log_sender: &Option<LogSender>) {
+    if let Some(notifier) = systemd_notifier {
+        if let Err(err) = notifier.notify(state).await {
+            sendif!(log_sender, EventData::SystemdNotifyFailed(err));
+        }
+    }
+}
+
+/// Waits for the next tick of an interval, or forever if there is none, such as when the watchdog
+/// isn't enabled.
+async fn optional_tick(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+fn take_systemd_listeners(verbose: bool, log_sender: &Option<LogSender>) -> Option<InheritedListeners> {
+    printlnif!(verbose, "Checking for listening sockets passed by systemd");
+    match systemd::take_activated_listeners() {
+        Ok(Some(inherited)) => {
+            let socket_count = inherited.count() as u32;
+            sendif!(log_sender, EventData::ListenersInheritedFromSystemd(socket_count));
+            Some(inherited)
+        }
+        Ok(None) => None,
+        Err(err) => {
+            sendif!(log_sender, EventData::SystemdActivationFailed(err));
+            None
+        }
+    }
+}
+
+async fn receive_inherited_listeners(verbose: bool, path: &str, log_sender: &Option<LogSender>) -> Option<InheritedListeners> {
+    printlnif!(verbose, "Checking for a server to take over at handoff socket {path}");
+    match handoff::receive_listeners(path).await {
+        Ok(Some(inherited)) => {
+            sendif!(log_sender, EventData::ListenersInheritedFromHandoff(path.to_string()));
+            Some(inherited)
+        }
+        Ok(None) => None,
+        Err(err) => {
+            sendif!(log_sender, EventData::HandoffReceiveFailed(path.to_string(), err));
+            None
+        }
+    }
+}
+
+fn send_inherited_listeners_events(inherited: &InheritedListeners, log_sender: &Option<LogSender>) {
+    for (listener, _) in &inherited.socks5 {
+        if let Ok(socket_address) = listener.local_addr() {
+            sendif!(log_sender, EventData::Socks5SocketInherited(socket_address));
+        }
+    }
+
+    for listener in &inherited.sandstorm {
+        if let Ok(socket_address) = listener.local_addr() {
+            sendif!(log_sender, EventData::SandstormSocketInherited(socket_address));
+        }
+    }
+
+    for listener in &inherited.sandstorm_unix {
+        sendif!(log_sender, EventData::SandstormUnixSocketInherited(listener.path().to_string()));
+    }
+}
+
+fn take_inherited_socks_listeners(
+    inherited: Vec<(TcpListener, ListenerProfile)>,
+) -> (Vec<TcpListener>, HashMap<SocketAddr, Arc<ListenerProfile>>) {
+    let mut socks_listeners = Vec::new();
+    let mut socks_profiles = HashMap::new();
+    for (listener, profile) in inherited {
+        if let Ok(socket_address) = listener.local_addr() {
+            if !profile.is_default() {
+                socks_profiles.insert(socket_address, Arc::new(profile));
+            }
+        }
+
+        socks_listeners.push(listener);
+    }
+
+    (socks_listeners, socks_profiles)
+}
+
+/// Starts draining the active socks5 clients with the given timeout in seconds, returning the
+/// drain's deadline, or `None` if there is nothing to wait for and the server may stop right away.
+async fn start_draining(
+    drain_timeout: u32,
+    state: &ServerState,
+    systemd_notifier: &Option<SystemdNotifier>,
+    log_sender: &Option<LogSender>,
+) -> Option<Instant> {
+    let active_clients = state.active_clients();
+    if drain_timeout == 0 || active_clients == 0 {
+        notify_systemd(systemd_notifier, "STOPPING=1\nSTATUS=Shutting down", log_sender).await;
+        return None;
+    }
+
+    let status = format!("STOPPING=1\nSTATUS=Waiting for {active_clients} clients to finish");
+    notify_systemd(systemd_notifier, &status, log_sender).await;
+    sendif!(log_sender, EventData::DrainStarted(active_clients, drain_timeout));
+    Some(Instant::now() + Duration::from_secs(drain_timeout as u64))
+}
+
+fn send_restored_state_events(restored_state: &RestoredState, log_sender: &Option<LogSender>) {
+    for socket_address in &restored_state.socks5_sockets {
+        sendif!(log_sender, EventData::Socks5SocketRestoredFromState(*socket_address));
+    }
+
+    for socket_address in &restored_state.sandstorm_sockets {
+        sendif!(log_sender, EventData::SandstormSocketRestoredFromState(*socket_address));
+    }
+
+    for path in &restored_state.sandstorm_unix_sockets {
+        sendif!(log_sender, EventData::SandstormUnixSocketRestoredFromState(path.clone()));
+    }
+
+    for (auth_method, enabled) in &restored_state.auth_methods {
+        sendif!(log_sender, EventData::AuthMethodRestoredFromState(*auth_method, *enabled));
+    }
+
+    if let Some(buffer_size) = restored_state.buffer_size {
+        sendif!(log_sender, EventData::BufferSizeRestoredFromState(buffer_size));
+    }
+
+    for (listener, kind, network) in &restored_state.ip_filters {
+        sendif!(log_sender, EventData::IpFilterRestoredFromState(*listener, *kind, *network));
+    }
+}
+
+/// Writes the server's current runtime settings in the state file format.
+fn take_state_snapshot(
+    socks_listeners: &[TcpListener],
+    socks_profiles: &HashMap<SocketAddr, Arc<ListenerProfile>>,
+    sandstorm_listeners: &[TcpListener],
+    sandstorm_unix_listeners: &[UnixListener],
+    state: &ServerState,
+) -> String {
+    let mut args = StartupArguments::empty();
+    for address in socks_listeners.iter().filter_map(|l| l.local_addr().ok()) {
+        if let Some(profile) = socks_profiles.get(&address) {
+            args.socks5_profiles.insert(address, profile.as_ref().clone());
+        }
+        args.socks5_bind_sockets.push(address);
+    }
+
+    args.sandstorm_bind_sockets = sandstorm_listeners.iter().filter_map(|l| l.local_addr().ok()).collect();
+    args.sandstorm_unix_sockets = sandstorm_unix_listeners.iter().map(|l| l.path().to_string()).collect();
+    args.no_auth_enabled = state.no_auth_enabled();
+    args.userpass_auth_enabled = state.userpass_auth_enabled();
+    args.buffer_size = state.buffer_size();
+    args.ip_filters = state.ip_filters().take_snapshot();
+    get_state_string(&args)
+}
+
+enum IpFilterRejection {
+    Filtered(SocketAddr),
+    UnknownListener(Error),
+}
+
+/// Checks an accepted connection against the IP filters of the listener that accepted it. If the
+/// connection is allowed, returns said listener's address. If the listener's address can't be
+/// determined then neither can its filters, so the connection is rejected.
+fn check_ip_filters(state: &ServerState, listener: &TcpListener, address: SocketAddr) -> Result<SocketAddr, IpFilterRejection> {
+    let listener_address = listener.local_addr().map_err(IpFilterRejection::UnknownListener)?;
+    match state.ip_filters().allows(listener_address, address.ip()) {
+        true => Ok(listener_address),
+        false => Err(IpFilterRejection::Filtered(listener_address)),
+    }
 }
 
 async fn create_user_manager(users_file: &String, mut new_users: HashMap<String, UserData>, log_sender: &Option<LogSender>) -> UserManager {
@@ -255,7 +831,7 @@ async fn create_user_manager(users_file: &String, mut new_users: HashMap<String,
     };
 
     for (username, userdata) in new_users.drain() {
-        if users.insert_or_update(username.clone(), userdata.password, userdata.role) {
+        if users.insert_or_update(username.clone(), userdata.password, userdata.role, userdata.permissions) {
             sendif!(log_sender, EventData::UserReplacedByArgs(username, userdata.role));
         } else {
             sendif!(log_sender, EventData::UserRegisteredByArgs(username, userdata.role));
@@ -271,19 +847,29 @@ async fn create_user_manager(users_file: &String, mut new_users: HashMap<String,
             String::from(DEFAULT_USER_USERNAME),
             String::from(DEFAULT_USER_PASSWORD),
             UserRole::Admin,
+            SandstormPermissions::all(),
         );
     }
 
     users
 }
 
-async fn bind_socks_sockets(verbose: bool, addresses: Vec<SocketAddr>, log_sender: &Option<LogSender>) -> Vec<TcpListener> {
+async fn bind_socks_sockets(
+    verbose: bool,
+    addresses: Vec<SocketAddr>,
+    mut profiles: HashMap<SocketAddr, ListenerProfile>,
+    log_sender: &Option<LogSender>,
+) -> (Vec<TcpListener>, HashMap<SocketAddr, Arc<ListenerProfile>>) {
     let mut socks_listeners = Vec::new();
+    let mut socks_profiles = HashMap::new();
     printlnif!(verbose, "Binding socks listener sockets");
     for bind_address in addresses {
         printlnif!(verbose, "Binding socks listening socket at {bind_address}");
         match TcpListener::bind(bind_address).await {
             Ok(result) => {
+                if let Some(profile) = profiles.remove(&bind_address) {
+                    socks_profiles.insert(result.local_addr().unwrap_or(bind_address), Arc::new(profile));
+                }
                 socks_listeners.push(result);
                 sendif!(log_sender, EventData::NewSocks5Socket(bind_address));
             }
@@ -293,7 +879,7 @@ async fn bind_socks_sockets(verbose: bool, addresses: Vec<SocketAddr>, log_sende
         }
     }
 
-    socks_listeners
+    (socks_listeners, socks_profiles)
 }
 
 async fn bind_sandstorm_sockets(verbose: bool, addresses: Vec<SocketAddr>, log_sender: &Option<LogSender>) -> Vec<TcpListener> {
@@ -314,3 +900,39 @@ async fn bind_sandstorm_sockets(verbose: bool, addresses: Vec<SocketAddr>, log_s
 
     sandstorm_listeners
 }
+
+async fn bind_sandstorm_unix_sockets(verbose: bool, paths: Vec<String>, log_sender: &Option<LogSender>) -> Vec<UnixListener> {
+    let mut sandstorm_unix_listeners = Vec::new();
+    for path in paths {
+        printlnif!(verbose, "Binding sandstorm listening Unix socket at {path}");
+        match UnixListener::bind(&path).await {
+            Ok(result) => {
+                sandstorm_unix_listeners.push(result);
+                sendif!(log_sender, EventData::NewSandstormUnixSocket(path));
+            }
+            Err(err) => {
+                sendif!(log_sender, EventData::FailedBindSandstormUnixSocket(path, err));
+            }
+        }
+    }
+
+    sandstorm_unix_listeners
+}
+
+async fn bind_metrics_sockets(verbose: bool, addresses: Vec<SocketAddr>, log_sender: &Option<LogSender>) -> Vec<TcpListener> {
+    let mut metrics_listeners = Vec::new();
+    for bind_address in addresses {
+        printlnif!(verbose, "Binding metrics listening socket at {bind_address}");
+        match TcpListener::bind(bind_address).await {
+            Ok(result) => {
+                metrics_listeners.push(result);
+                sendif!(log_sender, EventData::NewMetricsSocket(bind_address));
+            }
+            Err(err) => {
+                sendif!(log_sender, EventData::FailedBindMetricsSocket(bind_address, err));
+            }
+        }
+    }
+
+    metrics_listeners
+}
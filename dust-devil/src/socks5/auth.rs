@@ -7,7 +7,7 @@ use crate::{
     utils::chunk_reader::read_chunked_utf8_string,
 };
 
-pub async fn handle_userpass_auth<R, W>(reader: &mut R, writer: &mut W, context: &ClientContext) -> Result<bool, Error>
+pub async fn handle_userpass_auth<R, W>(reader: &mut R, writer: &mut W, context: &mut ClientContext) -> Result<bool, Error>
 where
     R: AsyncRead + Unpin + ?Sized,
     W: AsyncWrite + Unpin + ?Sized,
@@ -23,6 +23,9 @@ where
         let password = read_chunked_utf8_string(reader).await?;
 
         status = context.try_login(&username, &password);
+        if status {
+            context.set_username(username.clone());
+        }
         log_socks_authenticated_with_userpass!(context, username, status);
     }
 
@@ -3,18 +3,29 @@
 // issues, this server needs to collect real time metrics on how many bytes are being sent and
 // received, and that wouldn't have been possible with the tokio util function, as it only gives
 // the transfer metrics after returning.
+//
+// Logging an event for every write is expensive for high-throughput connections, so the byte
+// counts are instead accumulated in the client's context and logged every BYTES_FLUSH_INTERVAL,
+// as well as right before the client's shutdown and finished events.
 
-use tokio::io::{AsyncBufRead, AsyncWrite};
+use tokio::{
+    io::{AsyncBufRead, AsyncWrite},
+    time::{self, Instant, MissedTickBehavior},
+};
 
 use std::{
     future::poll_fn,
     io::Error,
     pin::Pin,
     task::{ready, Context, Poll},
+    time::Duration,
 };
 
 use crate::{context::ClientContext, log_socks_destination_shutdown, log_socks_source_shutdown};
 
+/// How often the bytes transferred by a client are logged while the connection is open.
+pub const BYTES_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
 enum TransferState {
     Running,
     ShuttingDown,
@@ -41,6 +52,7 @@ where
             TransferState::Running => {
                 let buffer = ready!(reader.as_mut().poll_fill_buf(cx))?;
                 if buffer.is_empty() {
+                    context.flush_bytes();
                     if is_src_to_dst {
                         log_socks_source_shutdown!(context);
                     } else {
@@ -53,6 +65,7 @@ where
 
                 let i = ready!(writer.as_mut().poll_write(cx, buffer))?;
                 if i == 0 {
+                    context.flush_bytes();
                     if is_src_to_dst {
                         log_socks_source_shutdown!(context);
                     } else {
@@ -94,8 +107,14 @@ pub async fn copy_bidirectional<
 ) -> Result<(), Error> {
     let mut src_to_dst = TransferState::Running;
     let mut dst_to_src = TransferState::Running;
+    let mut flush_interval = time::interval_at(Instant::now() + BYTES_FLUSH_INTERVAL, BYTES_FLUSH_INTERVAL);
+    flush_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
     poll_fn(|cx| {
+        while flush_interval.poll_tick(cx).is_ready() {
+            context.flush_bytes();
+        }
+
         let src_to_dst = transfer_one_direction(cx, &mut src_to_dst, src_reader, dst_writer, context, true)?;
         let dst_to_src = transfer_one_direction(cx, &mut dst_to_src, dst_reader, src_writer, context, false)?;
 
@@ -1,6 +1,7 @@
 use std::{
     io::{Error, ErrorKind},
     net::{SocketAddr, SocketAddrV4, SocketAddrV6},
+    time::Instant,
 };
 
 use dust_devil_core::{
@@ -18,7 +19,7 @@ use crate::{
     context::ClientContext,
     log_socks_connect_to_destination_failed, log_socks_connected_to_destination, log_socks_connection_attempt,
     log_socks_connection_attempt_bind_failed, log_socks_connection_attempt_connect_failed, log_socks_dns_lookup, log_socks_finished,
-    log_socks_selected_auth, log_socks_unsupported_atyp, log_socks_unsupported_command, log_socks_unsupported_version,
+    log_socks_selected_auth, log_socks_summary, log_socks_unsupported_atyp, log_socks_unsupported_command, log_socks_unsupported_version,
     socks5::{
         parsers::{parse_handshake, parse_request},
         responses::{send_handshake_response, send_request_response},
@@ -30,6 +31,9 @@ mod copy;
 mod parsers;
 mod responses;
 
+#[cfg(test)]
+pub use copy::{copy_bidirectional, BYTES_FLUSH_INTERVAL};
+
 use auth::*;
 use parsers::*;
 use responses::*;
@@ -59,10 +63,20 @@ impl From<ParseRequestError> for SocksStatus {
 }
 
 pub async fn handle_socks5(stream: TcpStream, mut context: ClientContext, cancel_token: CancellationToken) {
+    let close_token = context.close_token();
     select! {
         biased;
         _ = cancel_token.cancelled() => {}
-        result = handle_socks5_inner(stream, &mut context) => log_socks_finished!(context, result),
+        _ = close_token.cancelled() => {
+            context.flush_bytes();
+            log_socks_summary!(context, false);
+            log_socks_finished!(context, Ok(()));
+        }
+        result = handle_socks5_inner(stream, &mut context) => {
+            context.flush_bytes();
+            log_socks_summary!(context, result.is_err());
+            log_socks_finished!(context, result);
+        }
     }
 }
 
@@ -95,12 +109,15 @@ async fn handle_socks5_inner(mut stream: TcpStream, context: &mut ClientContext)
     let request_addresses = match parse_request(&mut reader).await {
         Ok(request) => match request.destination {
             SocksRequestAddress::IPv4(ipv4) => {
+                context.set_destination(ipv4.to_string(), request.port);
                 vec![SocketAddr::V4(SocketAddrV4::new(ipv4, request.port))]
             }
             SocksRequestAddress::IPv6(ipv6) => {
+                context.set_destination(ipv6.to_string(), request.port);
                 vec![SocketAddr::V6(SocketAddrV6::new(ipv6, request.port, 0, 0))]
             }
             SocksRequestAddress::Domainname(mut domainname) => {
+                context.set_destination(domainname.clone(), request.port);
                 log_socks_dns_lookup!(context, domainname.clone());
                 domainname.push_str(":0");
 
@@ -154,8 +171,9 @@ fn select_auth_method(state: &ClientContext, methods: &[u8]) -> Option<AuthMetho
     }
 }
 
-async fn connect_socket(request_addresses: Vec<SocketAddr>, context: &ClientContext) -> Result<TcpStream, SocksStatus> {
+async fn connect_socket(request_addresses: Vec<SocketAddr>, context: &mut ClientContext) -> Result<TcpStream, SocksStatus> {
     let mut last_error = None;
+    let connect_started_at = Instant::now();
 
     for address in request_addresses {
         log_socks_connection_attempt!(context, address);
@@ -182,6 +200,7 @@ async fn connect_socket(request_addresses: Vec<SocketAddr>, context: &ClientCont
             }
         };
 
+        context.connect_latency = Some(connect_started_at.elapsed());
         log_socks_connected_to_destination!(context, address);
         return Ok(destination_stream);
     }
@@ -0,0 +1,312 @@
+//! Sending events to the system's log, either to a syslog daemon or to journald.
+//!
+//! Syslog messages are formatted as per RFC 5424 and sent as datagrams, either to a Unix socket
+//! such as `/dev/log` or to a syslog server over UDP. They use the daemon facility and the
+//! severity given by [`EventData::severity`](dust_devil_core::logging::EventData::severity), so a client sending bytes is logged like this:
+//! `<31>1 2024-03-01T18:30:00Z myhost dust-devil 1234 - - Client 5 sent 1024 bytes`
+//!
+//! Journald messages use its native protocol, and are sent to `/run/systemd/journal/socket`.
+//! Besides the usual `MESSAGE`, `PRIORITY` and `SYSLOG_IDENTIFIER` fields, these include the
+//! event's type in `EVENT_TYPE` and the fields specific to the event's type (as described in
+//! [`crate::log_format`]) with their names in uppercase, such as `CLIENT_ID` or `BYTES`.
+//!
+//! Unix sockets, and therefore journald, are only supported on Unix platforms.
+
+use std::{
+    fmt::{self, Write as FmtWrite},
+    io::{Error, Write},
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use dust_devil_core::logging::{Event, EventSeverity};
+use time::OffsetDateTime;
+use tokio::{
+    net::UdpSocket,
+    sync::broadcast::{error::RecvError, Receiver},
+};
+
+use crate::{
+    log_filters::SinkFilter,
+    log_format::{self, EventFieldWriter},
+    printlnif,
+};
+
+#[cfg(unix)]
+use unix::{get_hostname, UnixDatagramSocket};
+
+#[cfg(not(unix))]
+use unsupported::{get_hostname, UnixDatagramSocket};
+
+/// The default port for sending syslog messages over UDP.
+pub const DEFAULT_SYSLOG_PORT: u16 = 514;
+
+/// The path to journald's socket for its native protocol.
+pub const JOURNALD_SOCKET_PATH: &str = "/run/systemd/journal/socket";
+
+/// The prefix for specifying a syslog target that is a Unix socket.
+pub const UNIX_TARGET_PREFIX: &str = "unix:";
+
+/// The prefix for specifying a syslog target that is a UDP address.
+pub const UDP_TARGET_PREFIX: &str = "udp:";
+
+/// The name events are logged under.
+pub const APP_NAME: &str = "dust-devil";
+
+const SYSLOG_FACILITY_DAEMON: u8 = 3;
+const MESSAGE_BUFFER_SIZE: usize = 0x200;
+
+/// Where syslog messages are sent to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyslogTarget {
+    Unix(String),
+    Udp(SocketAddr),
+}
+
+impl fmt::Display for SyslogTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unix(path) => write!(f, "{UNIX_TARGET_PREFIX}{path}"),
+            Self::Udp(address) => write!(f, "{UDP_TARGET_PREFIX}{address}"),
+        }
+    }
+}
+
+/// A system log sink events may be sent to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SystemLog {
+    Syslog(SyslogTarget),
+    Journald,
+}
+
+impl fmt::Display for SystemLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syslog(target) => write!(f, "syslog at {target}"),
+            Self::Journald => write!(f, "journald"),
+        }
+    }
+}
+
+/// Writes an event as an RFC 5424 syslog message, without a trailing newline.
+pub fn write_syslog_message(out: &mut Vec<u8>, event: &Event, hostname: &str, pid: u32) {
+    write_syslog_header(out, event.data.severity(), event.timestamp, hostname, pid);
+    let _ = write!(out, "{}", event.data);
+}
+
+fn write_syslog_header(out: &mut Vec<u8>, severity: EventSeverity, timestamp: i64, hostname: &str, pid: u32) {
+    let t = OffsetDateTime::from_unix_timestamp(timestamp).unwrap_or(OffsetDateTime::UNIX_EPOCH);
+    let _ = write!(
+        out,
+        "<{}>1 {:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z {hostname} {APP_NAME} {pid} - - ",
+        SYSLOG_FACILITY_DAEMON * 8 + severity as u8,
+        t.year(),
+        t.month() as u8,
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second(),
+    );
+}
+
+/// Writes an event as a journald native protocol message.
+pub fn write_journald_message(out: &mut Vec<u8>, event: &Event) {
+    let mut message = JournaldMessage { out };
+    message.number("PRIORITY", event.data.severity() as u8);
+    message.string("SYSLOG_IDENTIFIER", APP_NAME);
+    message.string("MESSAGE", &event.data);
+    message.string("EVENT_TYPE", event.data.type_name());
+    log_format::write_event_fields(&mut message, &event.data);
+}
+
+fn write_lost_events(out: &mut Vec<u8>, target: &SystemLog, lost_count: u64, hostname: &str, pid: u32) {
+    let message = format!("ERROR!! {lost_count} events lost due to slowdown!");
+    match target {
+        SystemLog::Syslog(_) => {
+            let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+            write_syslog_header(out, EventSeverity::Warning, timestamp, hostname, pid);
+            out.extend_from_slice(message.as_bytes());
+        }
+        SystemLog::Journald => {
+            let mut journald_message = JournaldMessage { out };
+            journald_message.number("PRIORITY", EventSeverity::Warning as u8);
+            journald_message.string("SYSLOG_IDENTIFIER", APP_NAME);
+            journald_message.string("MESSAGE", message);
+            journald_message.string("EVENT_TYPE", "events_lost");
+            journald_message.number("count", lost_count);
+        }
+    }
+}
+
+/// Writes fields into a journald native protocol message.
+struct JournaldMessage<'a> {
+    out: &'a mut Vec<u8>,
+}
+
+impl EventFieldWriter for JournaldMessage<'_> {
+    fn string(&mut self, name: &str, value: impl fmt::Display) {
+        let mut s = String::new();
+        let _ = write!(s, "{value}");
+
+        self.out.extend(name.bytes().map(|b| b.to_ascii_uppercase()));
+
+        // Values with newlines must be written as a newline followed by their little-endian length.
+        if s.contains('\n') {
+            self.out.push(b'\n');
+            self.out.extend_from_slice(&(s.len() as u64).to_le_bytes());
+        } else {
+            self.out.push(b'=');
+        }
+
+        self.out.extend_from_slice(s.as_bytes());
+        self.out.push(b'\n');
+    }
+
+    fn null(&mut self, _name: &str) {}
+
+    fn number(&mut self, name: &str, value: impl Into<i128>) {
+        self.string(name, value.into());
+    }
+
+    fn boolean(&mut self, name: &str, value: bool) {
+        self.string(name, value);
+    }
+}
+
+/// A socket that sends messages to a system log sink.
+pub enum SystemLogSocket {
+    Udp(UdpSocket),
+    Unix(UnixDatagramSocket),
+}
+
+impl SystemLogSocket {
+    /// Creates a socket that sends messages to the given system log sink.
+    pub async fn connect(target: &SystemLog) -> Result<Self, Error> {
+        match target {
+            SystemLog::Syslog(SyslogTarget::Udp(address)) => {
+                let bind_address: SocketAddr = match address {
+                    SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+                    SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+                };
+
+                let socket = UdpSocket::bind(bind_address).await?;
+                socket.connect(address).await?;
+                Ok(Self::Udp(socket))
+            }
+            SystemLog::Syslog(SyslogTarget::Unix(path)) => Ok(Self::Unix(UnixDatagramSocket::connect(path)?)),
+            SystemLog::Journald => Ok(Self::Unix(UnixDatagramSocket::connect(JOURNALD_SOCKET_PATH)?)),
+        }
+    }
+
+    async fn send(&self, message: &[u8]) -> Result<(), Error> {
+        match self {
+            Self::Udp(socket) => socket.send(message).await.map(|_| ()),
+            Self::Unix(socket) => socket.send(message).await,
+        }
+    }
+}
+
+/// Sends every received event accepted by the filter to a system log sink, as a datagram per event.
+///
+/// Failing to send a message doesn't stop the task, as the system log daemon may come back later.
+/// These failures are reported once, and then again only after a message is successfully sent.
+pub async fn system_log_task(
+    verbose: bool,
+    mut log_receiver: Receiver<Arc<Event>>,
+    target: SystemLog,
+    socket: SystemLogSocket,
+    mut filter: SinkFilter,
+) {
+    printlnif!(verbose, "Logger task for {target} started");
+
+    let hostname = get_hostname().unwrap_or_else(|| "-".to_string());
+    let pid = std::process::id();
+    let mut message = Vec::with_capacity(MESSAGE_BUFFER_SIZE);
+    let mut failing = false;
+
+    loop {
+        match log_receiver.recv().await {
+            Ok(event) if !filter.accepts(&event.data) => continue,
+            Ok(event) => match target {
+                SystemLog::Syslog(_) => write_syslog_message(&mut message, &event, &hostname, pid),
+                SystemLog::Journald => write_journald_message(&mut message, &event),
+            },
+            Err(RecvError::Lagged(lost_count)) => write_lost_events(&mut message, &target, lost_count, &hostname, pid),
+            Err(RecvError::Closed) => break,
+        }
+
+        match socket.send(&message).await {
+            Ok(()) => failing = false,
+            Err(error) if !failing => {
+                eprintln!("Logger task for {target} failed to send an event: {error}");
+                failing = true;
+            }
+            Err(_) => {}
+        }
+
+        message.clear();
+    }
+
+    printlnif!(verbose, "Logger task for {target} finished");
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{ffi::CStr, io::Error};
+
+    use tokio::net::UnixDatagram;
+
+    /// A datagram socket connected to a Unix socket.
+    pub struct UnixDatagramSocket(UnixDatagram);
+
+    impl UnixDatagramSocket {
+        pub fn connect(path: &str) -> Result<Self, Error> {
+            let socket = UnixDatagram::unbound()?;
+            socket.connect(path)?;
+            Ok(UnixDatagramSocket(socket))
+        }
+
+        pub async fn send(&self, message: &[u8]) -> Result<(), Error> {
+            self.0.send(message).await.map(|_| ())
+        }
+    }
+
+    /// Gets this machine's hostname, or `None` if it can't be determined or isn't valid in a
+    /// syslog message.
+    pub fn get_hostname() -> Option<String> {
+        let mut buf = [0u8; 256];
+        if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+            return None;
+        }
+
+        let hostname = CStr::from_bytes_until_nul(&buf).ok()?.to_str().ok()?;
+        match !hostname.is_empty() && hostname.bytes().all(|b| b.is_ascii_graphic()) {
+            true => Some(hostname.to_string()),
+            false => None,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod unsupported {
+    use std::io::{Error, ErrorKind};
+
+    pub enum UnixDatagramSocket {}
+
+    impl UnixDatagramSocket {
+        pub fn connect(_path: &str) -> Result<Self, Error> {
+            Err(Error::new(
+                ErrorKind::Unsupported,
+                "Unix sockets are not supported on this platform",
+            ))
+        }
+
+        pub async fn send(&self, _message: &[u8]) -> Result<(), Error> {
+            match *self {}
+        }
+    }
+
+    pub fn get_hostname() -> Option<String> {
+        None
+    }
+}
@@ -0,0 +1,226 @@
+//! Integration with systemd's socket activation and service notification protocols.
+//!
+//! When started through socket activation, systemd passes the server its listening sockets as file
+//! descriptors starting at 3, indicated through the `LISTEN_PID`, `LISTEN_FDS` and
+//! `LISTEN_FDNAMES` environment variables. These are used instead of binding any sockets. Sockets
+//! named `sandstorm` (with `FileDescriptorName=sandstorm` on the socket unit) are used for
+//! Sandstorm connections, and may be either TCP or Unix sockets, while all others are used for
+//! SOCKS5 clients.
+//!
+//! If the `NOTIFY_SOCKET` environment variable is set, the server notifies systemd with `READY=1`
+//! once it's accepting connections and `STOPPING=1` once it starts shutting down, alongside
+//! `STATUS=` messages describing what it's doing. If `WATCHDOG_USEC` is set, the server also pings
+//! systemd with `WATCHDOG=1` at half the specified interval.
+//!
+//! These are only supported on Unix platforms.
+
+use std::{
+    io::{Error, ErrorKind},
+    time::Duration,
+};
+
+/// The file descriptor name that indicates a socket-activated listener is for Sandstorm.
+pub const SANDSTORM_FD_NAME: &str = "sandstorm";
+
+#[cfg(unix)]
+pub use unix::{take_activated_listeners, SystemdNotifier};
+
+#[cfg(all(unix, test))]
+pub use unix::take_listeners;
+
+#[cfg(not(unix))]
+pub use unsupported::{take_activated_listeners, SystemdNotifier};
+
+/// Parses the socket activation environment variables, returning whether each of the passed file
+/// descriptors is a Sandstorm socket, or `None` if no sockets were passed to this process.
+pub fn parse_listen_fds(
+    listen_pid: Option<&str>,
+    listen_fds: Option<&str>,
+    listen_fdnames: Option<&str>,
+    pid: u32,
+) -> Result<Option<Vec<bool>>, Error> {
+    let (listen_pid, listen_fds) = match (listen_pid, listen_fds) {
+        (Some(listen_pid), Some(listen_fds)) => (listen_pid, listen_fds),
+        _ => return Ok(None),
+    };
+
+    if listen_pid.trim().parse::<u32>().ok() != Some(pid) {
+        return Ok(None);
+    }
+
+    let fd_count = match listen_fds.trim().parse::<usize>() {
+        Ok(count) => count,
+        Err(_) => return Err(Error::new(ErrorKind::InvalidData, "Invalid LISTEN_FDS value")),
+    };
+
+    let mut names = listen_fdnames.unwrap_or_default().split(':');
+    let kinds = (0..fd_count).map(|_| names.next() == Some(SANDSTORM_FD_NAME)).collect();
+    Ok(Some(kinds))
+}
+
+/// Parses the watchdog environment variables, returning the interval at which to ping systemd.
+pub fn parse_watchdog(watchdog_usec: Option<&str>, watchdog_pid: Option<&str>, pid: u32) -> Option<Duration> {
+    if watchdog_pid.is_some_and(|p| p.trim().parse::<u32>().ok() != Some(pid)) {
+        return None;
+    }
+
+    match watchdog_usec?.trim().parse::<u64>() {
+        Ok(usec) if usec != 0 => Some(Duration::from_micros(usec / 2)),
+        _ => None,
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        env,
+        io::{Error, ErrorKind},
+        mem,
+        os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        path::PathBuf,
+        time::Duration,
+    };
+
+    use tokio::net::{TcpListener, UnixDatagram};
+
+    use crate::{handoff::InheritedListeners, utils::unix_socket::UnixListener};
+
+    use super::{parse_listen_fds, parse_watchdog};
+
+    const LISTEN_FDS_START: RawFd = 3;
+
+    /// Takes the listening sockets passed to this process through socket activation, or returns
+    /// `Ok(None)` if there are none.
+    pub fn take_activated_listeners() -> Result<Option<InheritedListeners>, Error> {
+        let listen_pid = env::var("LISTEN_PID").ok();
+        let listen_fds = env::var("LISTEN_FDS").ok();
+        let listen_fdnames = env::var("LISTEN_FDNAMES").ok();
+
+        let kinds = match parse_listen_fds(
+            listen_pid.as_deref(),
+            listen_fds.as_deref(),
+            listen_fdnames.as_deref(),
+            std::process::id(),
+        )? {
+            Some(kinds) => kinds,
+            None => return Ok(None),
+        };
+
+        // Take ownership of all the file descriptors first, so they get closed on error.
+        let fds: Vec<OwnedFd> = (0..kinds.len())
+            .map(|i| unsafe { OwnedFd::from_raw_fd(LISTEN_FDS_START + i as RawFd) })
+            .collect();
+
+        take_listeners(kinds, fds).map(Some)
+    }
+
+    /// Turns socket-activated file descriptors into listeners, given whether each one is for
+    /// Sandstorm. The files of Unix sockets belong to systemd, so they're never removed.
+    pub fn take_listeners(kinds: Vec<bool>, fds: Vec<OwnedFd>) -> Result<InheritedListeners, Error> {
+        let mut inherited = InheritedListeners::default();
+        for (is_sandstorm, fd) in kinds.into_iter().zip(fds) {
+            if is_sandstorm && is_unix_socket(&fd)? {
+                match UnixListener::from_fd(fd, false)? {
+                    Some(listener) => inherited.sandstorm_unix.push(listener),
+                    None => return Err(Error::new(ErrorKind::InvalidInput, "Unix sockets must be bound to a path")),
+                }
+
+                continue;
+            }
+
+            let listener = std::net::TcpListener::from(fd);
+            listener.local_addr()?;
+            listener.set_nonblocking(true)?;
+            let listener = TcpListener::from_std(listener)?;
+
+            match is_sandstorm {
+                true => inherited.sandstorm.push(listener),
+                false => inherited.socks5.push((listener, Default::default())),
+            }
+        }
+
+        Ok(inherited)
+    }
+
+    fn is_unix_socket(fd: &OwnedFd) -> Result<bool, Error> {
+        let mut address: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let mut length = mem::size_of_val(&address) as libc::socklen_t;
+        match unsafe { libc::getsockname(fd.as_raw_fd(), (&mut address as *mut libc::sockaddr_storage).cast(), &mut length) } {
+            -1 => Err(Error::last_os_error()),
+            _ => Ok(address.ss_family as libc::c_int == libc::AF_UNIX),
+        }
+    }
+
+    /// Sends notifications to systemd's notification socket.
+    pub struct SystemdNotifier {
+        socket: UnixDatagram,
+        path: PathBuf,
+        watchdog_interval: Option<Duration>,
+    }
+
+    impl SystemdNotifier {
+        /// Creates a notifier for the socket at the given path. Paths starting with `@` refer to
+        /// abstract sockets.
+        pub fn new(path: &str, watchdog_interval: Option<Duration>) -> Result<Self, Error> {
+            let path = match path.strip_prefix('@') {
+                Some(name) => PathBuf::from(format!("\0{name}")),
+                None => PathBuf::from(path),
+            };
+
+            Ok(SystemdNotifier {
+                socket: UnixDatagram::unbound()?,
+                path,
+                watchdog_interval,
+            })
+        }
+
+        /// Creates a notifier from the `NOTIFY_SOCKET` environment variable, or returns `Ok(None)`
+        /// if it isn't set.
+        pub fn from_env() -> Result<Option<Self>, Error> {
+            let path = match env::var("NOTIFY_SOCKET") {
+                Ok(path) if !path.is_empty() => path,
+                _ => return Ok(None),
+            };
+
+            let watchdog_usec = env::var("WATCHDOG_USEC").ok();
+            let watchdog_pid = env::var("WATCHDOG_PID").ok();
+            let watchdog_interval = parse_watchdog(watchdog_usec.as_deref(), watchdog_pid.as_deref(), std::process::id());
+            Self::new(&path, watchdog_interval).map(Some)
+        }
+
+        pub fn watchdog_interval(&self) -> Option<Duration> {
+            self.watchdog_interval
+        }
+
+        pub async fn notify(&self, state: &str) -> Result<(), Error> {
+            self.socket.send_to(state.as_bytes(), &self.path).await.map(|_| ())
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod unsupported {
+    use std::{io::Error, time::Duration};
+
+    use crate::handoff::InheritedListeners;
+
+    pub fn take_activated_listeners() -> Result<Option<InheritedListeners>, Error> {
+        Ok(None)
+    }
+
+    pub enum SystemdNotifier {}
+
+    impl SystemdNotifier {
+        pub fn from_env() -> Result<Option<Self>, Error> {
+            Ok(None)
+        }
+
+        pub fn watchdog_interval(&self) -> Option<Duration> {
+            match *self {}
+        }
+
+        pub async fn notify(&self, _state: &str) -> Result<(), Error> {
+            match *self {}
+        }
+    }
+}
@@ -1,12 +1,27 @@
 use std::{
     collections::HashMap,
+    io::ErrorKind,
     net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
 };
 
-use dust_devil_core::users::UserRole;
+use dust_devil_core::{
+    ip_filter::{IpFilterKind, IpFilterParseError, IpNetwork, IpNetworkParseError},
+    listener_profile::{ListenerProfile, ListenerProfileParseError},
+    log_filter::{LogFilterParseError, LogSink},
+    sandstorm::SandstormPermissions,
+    users::UserRole,
+};
 
 use crate::{
-    args::{BufferSizeErrorType, DEFAULT_SANDSTORM_PORT},
+    args::{
+        BufferSizeErrorType, DrainTimeoutErrorType, EventLagPolicyErrorType, IpFilterErrorType, LogFilterErrorType, LogFormatErrorType,
+        LogRotationErrorType, SyslogErrorType, DEFAULT_METRICS_PORT, DEFAULT_SANDSTORM_PORT,
+    },
+    config::ConfigErrorType,
+    log_file::LogRotation,
+    log_format::LogFormat,
+    sandstorm::EventLagPolicy,
+    system_log::SyslogTarget,
     users::UserData,
 };
 
@@ -34,7 +49,14 @@ fn usermap(s: &[(&str, &str, UserRole)]) -> HashMap<String, UserData> {
     for (username, password, role) in s {
         let username = String::from(*username);
         let password = String::from(*password);
-        h.insert(username, UserData { password, role: *role });
+        h.insert(
+            username,
+            UserData {
+                password,
+                role: *role,
+                permissions: SandstormPermissions::all(),
+            },
+        );
     }
 
     h
@@ -253,6 +275,116 @@ fn test_listen_multiple() {
     );
 }
 
+#[test]
+fn test_listen_profile() {
+    let result = args("-l 127.0.0.1=noauth,-userpass -l 1.2.3.4:5678=-noauth,buffer:16K,role:admin,user:pedro,user:juan");
+    let local = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, DEFAULT_SOCKS5_PORT));
+    let public = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 5678));
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            socks5_bind_sockets: vec![local, public],
+            socks5_profiles: HashMap::from([
+                (
+                    local,
+                    ListenerProfile {
+                        no_auth_enabled: Some(true),
+                        userpass_auth_enabled: Some(false),
+                        ..Default::default()
+                    }
+                ),
+                (
+                    public,
+                    ListenerProfile {
+                        no_auth_enabled: Some(false),
+                        buffer_size: Some(16384),
+                        allowed_roles: vec![UserRole::Admin],
+                        allowed_users: vec!["pedro".to_string(), "juan".to_string()],
+                        ..Default::default()
+                    }
+                ),
+            ]),
+            ..Default::default()
+        }))
+    );
+
+    // An empty profile is the same as no profile.
+    let result = args("-l 1.2.3.4:5678=");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            socks5_bind_sockets: vec![public],
+            ..Default::default()
+        }))
+    );
+}
+
+#[test]
+fn test_listen_profile_invalid() {
+    let result = args("-l 1.2.3.4=noauth,cats");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::Socks5ListenError(SocketErrorType::InvalidProfile(
+            "-l".to_string(),
+            "1.2.3.4=noauth,cats".to_string(),
+            ListenerProfileParseError::UnknownOption("cats".to_string())
+        )))
+    );
+
+    let result = args("-l 1.2.3.4=buffer:0");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::Socks5ListenError(SocketErrorType::InvalidProfile(
+            "-l".to_string(),
+            "1.2.3.4=buffer:0".to_string(),
+            ListenerProfileParseError::InvalidBufferSize("0".to_string())
+        )))
+    );
+
+    let result = args("-l 1.2.3.4=role:superuser");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::Socks5ListenError(SocketErrorType::InvalidProfile(
+            "-l".to_string(),
+            "1.2.3.4=role:superuser".to_string(),
+            ListenerProfileParseError::InvalidRole("superuser".to_string())
+        )))
+    );
+
+    let result = args("-l 1.2.3.4.5=noauth");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::Socks5ListenError(SocketErrorType::InvalidSocketAddress(
+            "-l".to_string(),
+            "1.2.3.4.5=noauth".to_string()
+        )))
+    );
+}
+
+#[test]
+fn test_listener_profile_display_roundtrip() {
+    let profile: ListenerProfile = "-noauth, userpass ,buffer:4K,role:regular,user:pedro".parse().unwrap();
+    assert_eq!(profile.to_string(), "-noauth,userpass,buffer:4096,role:regular,user:pedro");
+    assert_eq!(profile.to_string().parse::<ListenerProfile>(), Ok(profile.clone()));
+
+    assert!(profile.allows_user("pedro", UserRole::Regular));
+    assert!(!profile.allows_user("pedro", UserRole::Admin));
+    assert!(!profile.allows_user("juan", UserRole::Regular));
+    assert!(ListenerProfile::default().allows_user("juan", UserRole::Admin));
+}
+
+#[test]
+fn test_listener_profile_roles_and_users() {
+    let profile: ListenerProfile = "role:admin,user:pedro,user:juan".parse().unwrap();
+
+    // A user must be allowed by both the roles and the users.
+    assert!(profile.allows_user("pedro", UserRole::Admin));
+    assert!(profile.allows_user("juan", UserRole::Admin));
+    assert!(!profile.allows_user("pedro", UserRole::Regular));
+    assert!(!profile.allows_user("carlos", UserRole::Admin));
+    assert!(!profile.allows_user("carlos", UserRole::Regular));
+}
+
 #[test]
 fn test_listen_unexpected_end() {
     let result = args("-l");
@@ -361,6 +493,38 @@ fn test_listen_sandstorm_multiple() {
     );
 }
 
+#[test]
+fn test_listen_sandstorm_unix() {
+    let result = args("-m unix:/run/dust-devil/sandstorm.sock");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            sandstorm_bind_sockets: vec![],
+            sandstorm_unix_sockets: vec!["/run/dust-devil/sandstorm.sock".to_string()],
+            ..Default::default()
+        }))
+    );
+
+    let result = args("-m unix:sandstorm.sock -m 1.2.3.4 --management unix:sandstorm.sock");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            sandstorm_bind_sockets: vec![SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), DEFAULT_SANDSTORM_PORT))],
+            sandstorm_unix_sockets: vec!["sandstorm.sock".to_string()],
+            ..Default::default()
+        }))
+    );
+
+    let result = args("-m unix:");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::SandstormListenError(SocketErrorType::InvalidSocketAddress(
+            "-m".to_string(),
+            "unix:".to_string()
+        )))
+    );
+}
+
 #[test]
 fn test_listen_sandstorm_unexpected_end() {
     let result = args("-m");
@@ -1031,6 +1195,535 @@ fn test_buffer_size_too_big() {
     );
 }
 
+#[test]
+fn test_ip_filters() {
+    let result = args("-f [::]:2222=10.5.1.2/16 --deny 0.0.0.0:1080=192.168.1.10 -F 0.0.0.0:1080=192.168.1.10");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            ip_filters: vec![
+                (
+                    SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 2222, 0, 0)),
+                    IpFilterKind::Allow,
+                    "10.5.0.0/16".parse().unwrap(),
+                ),
+                (
+                    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 1080)),
+                    IpFilterKind::Deny,
+                    IpNetwork::new(Ipv4Addr::new(192, 168, 1, 10).into(), 32).unwrap(),
+                ),
+            ],
+            ..Default::default()
+        }))
+    );
+}
+
+#[test]
+fn test_ip_filter_errors() {
+    let result = args("--allow");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::IpFilterError(IpFilterErrorType::UnexpectedEnd(
+            "--allow".to_string()
+        )))
+    );
+
+    let result = args("-f 10.0.0.0/8");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::IpFilterError(IpFilterErrorType::InvalidFilter(
+            "-f".to_string(),
+            "10.0.0.0/8".to_string(),
+            IpFilterParseError::MissingSeparator
+        )))
+    );
+
+    let result = args("-F 0.0.0.0=10.0.0.0/8");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::IpFilterError(IpFilterErrorType::InvalidFilter(
+            "-F".to_string(),
+            "0.0.0.0=10.0.0.0/8".to_string(),
+            IpFilterParseError::InvalidListenerAddress
+        )))
+    );
+
+    let result = args("-F 0.0.0.0:1080=10.0.0.0/33");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::IpFilterError(IpFilterErrorType::InvalidFilter(
+            "-F".to_string(),
+            "0.0.0.0:1080=10.0.0.0/33".to_string(),
+            IpFilterParseError::InvalidNetwork(IpNetworkParseError::InvalidPrefixLength)
+        )))
+    );
+}
+
+#[test]
+fn test_config_file_errors() {
+    let result = args("--config");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::ConfigFileError(FileErrorType::UnexpectedEnd(
+            "--config".to_string()
+        )))
+    );
+
+    let result = args("-c ./some/dir/missing-config.toml");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::InvalidConfig(
+            "./some/dir/missing-config.toml".to_string(),
+            ConfigErrorType::IO(ErrorKind::NotFound)
+        ))
+    );
+
+    let result = args("-c a.toml --config b.toml");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::ConfigFileError(FileErrorType::AlreadySpecified(
+            "--config".to_string()
+        )))
+    );
+}
+
+#[test]
+fn test_check_config() {
+    let result = args("--check-config -v");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::CheckConfig(StartupArguments {
+            verbose: true,
+            ..Default::default()
+        }))
+    );
+}
+
+#[test]
+fn test_state_file() {
+    let result = args("-S ./some/dir/missing-state.toml");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            state_file: Some("./some/dir/missing-state.toml".to_string()),
+            ..Default::default()
+        }))
+    );
+
+    let result = args("--state-file");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::StateFileError(FileErrorType::UnexpectedEnd(
+            "--state-file".to_string()
+        )))
+    );
+
+    let result = args("-S a.toml -S b.toml");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::StateFileError(FileErrorType::AlreadySpecified("-S".to_string())))
+    );
+}
+
+#[test]
+fn test_drain_timeout() {
+    let result = args("-D 30");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            drain_timeout: Some(30),
+            ..Default::default()
+        }))
+    );
+
+    let result = args("--drain-timeout 0");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            drain_timeout: Some(0),
+            ..Default::default()
+        }))
+    );
+
+    let result = args("--drain-timeout");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::DrainTimeoutError(DrainTimeoutErrorType::UnexpectedEnd(
+            "--drain-timeout".to_string()
+        )))
+    );
+
+    let result = args("-D -5");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::DrainTimeoutError(DrainTimeoutErrorType::InvalidValue(
+            "-D".to_string(),
+            "-5".to_string()
+        )))
+    );
+
+    let result = args("-D 5 -D 10");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::DrainTimeoutError(DrainTimeoutErrorType::AlreadySpecified(
+            "-D".to_string()
+        )))
+    );
+}
+
+#[test]
+fn test_event_lag_policy() {
+    for (s, policy) in [
+        ("disconnect", EventLagPolicy::Disconnect),
+        ("Drop", EventLagPolicy::Drop),
+        ("resync", EventLagPolicy::Resync),
+    ] {
+        let result = args(&format!("--event-lag-policy {s}"));
+        assert_eq!(
+            result,
+            Ok(ArgumentsRequest::Run(StartupArguments {
+                event_lag_policy: Some(policy),
+                ..Default::default()
+            }))
+        );
+    }
+
+    let result = args("--event-lag-policy");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::EventLagPolicyError(EventLagPolicyErrorType::UnexpectedEnd(
+            "--event-lag-policy".to_string()
+        )))
+    );
+
+    let result = args("--event-lag-policy ignore");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::EventLagPolicyError(EventLagPolicyErrorType::InvalidValue(
+            "--event-lag-policy".to_string(),
+            "ignore".to_string()
+        )))
+    );
+
+    let result = args("--event-lag-policy drop --event-lag-policy resync");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::EventLagPolicyError(EventLagPolicyErrorType::AlreadySpecified(
+            "--event-lag-policy".to_string()
+        )))
+    );
+}
+
+#[test]
+fn test_log_rotation() {
+    let result = args("-o logs.txt --log-rotate-size 10M --log-rotate-interval 1d --log-keep 7 --log-compress");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            log_file: Some("logs.txt".to_string()),
+            log_rotation: LogRotation {
+                max_size: Some(10 * 1024 * 1024),
+                interval: Some(24 * 60 * 60),
+                keep: Some(7),
+                compress: true,
+            },
+            ..Default::default()
+        }))
+    );
+
+    let result = args("--log-rotate-interval 90 --log-file logs.txt --log-keep 0");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            log_file: Some("logs.txt".to_string()),
+            log_rotation: LogRotation {
+                interval: Some(90),
+                keep: Some(0),
+                ..Default::default()
+            },
+            ..Default::default()
+        }))
+    );
+
+    let result = args("-o logs.txt --log-rotate-interval 30m --log-rotate-interval 1h");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::LogRotationError(LogRotationErrorType::AlreadySpecified(
+            "--log-rotate-interval".to_string()
+        )))
+    );
+
+    let result = args("-o logs.txt --log-rotate-interval 0s");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::LogRotationError(LogRotationErrorType::InvalidInterval(
+            "--log-rotate-interval".to_string(),
+            "0s".to_string()
+        )))
+    );
+
+    let result = args("-o logs.txt --log-rotate-size 1T");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::LogRotationError(LogRotationErrorType::InvalidSize(
+            "--log-rotate-size".to_string(),
+            "1T".to_string()
+        )))
+    );
+
+    let result = args("-o logs.txt --log-keep -1");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::LogRotationError(LogRotationErrorType::InvalidCount(
+            "--log-keep".to_string(),
+            "-1".to_string()
+        )))
+    );
+
+    let result = args("-o logs.txt --log-keep");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::LogRotationError(LogRotationErrorType::UnexpectedEnd(
+            "--log-keep".to_string()
+        )))
+    );
+
+    let result = args("--log-compress");
+    assert_eq!(result, Err(ArgumentsError::LogRotationWithoutLogFile));
+}
+
+#[test]
+fn test_log_format() {
+    let result = args("--log-format json");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            stdout_log_format: Some(LogFormat::Json),
+            file_log_format: Some(LogFormat::Json),
+            ..Default::default()
+        }))
+    );
+
+    let result = args("--log-format stdout=text,file=JSON");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            stdout_log_format: Some(LogFormat::Text),
+            file_log_format: Some(LogFormat::Json),
+            ..Default::default()
+        }))
+    );
+
+    let result = args("--log-format json --log-format stdout=text");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            stdout_log_format: Some(LogFormat::Text),
+            file_log_format: Some(LogFormat::Json),
+            ..Default::default()
+        }))
+    );
+
+    let result = args("--log-format xml");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::LogFormatError(LogFormatErrorType::InvalidFormat(
+            "--log-format".to_string(),
+            "xml".to_string()
+        )))
+    );
+
+    let result = args("--log-format syslog=json");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::LogFormatError(LogFormatErrorType::InvalidFormat(
+            "--log-format".to_string(),
+            "syslog=json".to_string()
+        )))
+    );
+
+    let result = args("--log-format");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::LogFormatError(LogFormatErrorType::UnexpectedEnd(
+            "--log-format".to_string()
+        )))
+    );
+}
+
+#[test]
+fn test_system_logs() {
+    let result = args("--syslog unix:/dev/log --syslog /var/run/syslog --syslog udp:127.0.0.1 --syslog 127.0.0.1:1514 --journald");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            syslog_targets: vec![
+                SyslogTarget::Unix("/dev/log".to_string()),
+                SyslogTarget::Unix("/var/run/syslog".to_string()),
+                SyslogTarget::Udp(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 514))),
+                SyslogTarget::Udp(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1514))),
+            ],
+            journald: true,
+            ..Default::default()
+        }))
+    );
+
+    let result = args("--syslog unix:");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::SyslogError(SyslogErrorType::InvalidTarget(
+            "--syslog".to_string(),
+            "unix:".to_string()
+        )))
+    );
+
+    let result = args("--syslog");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::SyslogError(SyslogErrorType::UnexpectedEnd("--syslog".to_string())))
+    );
+
+    let result = args("-d --journald");
+    assert_eq!(result, Err(ArgumentsError::CannotLogWithEventsDisabled));
+
+    let result = args("--syslog /dev/log -d");
+    assert_eq!(result, Err(ArgumentsError::CannotLogWithEventsDisabled));
+}
+
+#[test]
+fn test_log_filters() {
+    let result = args("--log-filter severity=info --log-filter file:exclude=client_bytes_* --log-filter syslog:all");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            log_filters: vec![
+                (None, "severity=info".parse().unwrap()),
+                (Some(LogSink::File), "exclude=client_bytes_*".parse().unwrap()),
+                (Some(LogSink::Syslog), Default::default()),
+            ],
+            ..Default::default()
+        }))
+    );
+
+    let result = args("--log-filter stdout:severity=loud");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::LogFilterError(LogFilterErrorType::InvalidFilter(
+            "--log-filter".to_string(),
+            "stdout:severity=loud".to_string(),
+            LogFilterParseError::InvalidSeverity("loud".to_string())
+        )))
+    );
+
+    let result = args("--log-filter");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::LogFilterError(LogFilterErrorType::UnexpectedEnd(
+            "--log-filter".to_string()
+        )))
+    );
+}
+
+#[test]
+fn test_metrics_listen() {
+    let result = args("--metrics-listen 127.0.0.1 --metrics-listen [::1]:9100");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            metrics_bind_sockets: vec![
+                SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, DEFAULT_METRICS_PORT)),
+                SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::LOCALHOST, 9100, 0, 0)),
+            ],
+            ..Default::default()
+        }))
+    );
+
+    let result = args("--metrics-listen 127.0.0.1:lol");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::MetricsListenError(SocketErrorType::InvalidSocketAddress(
+            "--metrics-listen".to_string(),
+            "127.0.0.1:lol".to_string()
+        )))
+    );
+
+    let result = args("--metrics-listen");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::MetricsListenError(SocketErrorType::UnexpectedEnd(
+            "--metrics-listen".to_string()
+        )))
+    );
+
+    let result = args("-d --metrics-listen 127.0.0.1");
+    assert_eq!(result, Err(ArgumentsError::CannotServeMetricsWithEventsDisabled));
+
+    let result = args("--metrics-listen 127.0.0.1 -d");
+    assert_eq!(result, Err(ArgumentsError::CannotServeMetricsWithEventsDisabled));
+}
+
+#[test]
+fn test_metrics_file() {
+    let result = args("--metrics-file metrics.toml");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            metrics_file: Some("metrics.toml".to_string()),
+            ..Default::default()
+        }))
+    );
+
+    let result = args("--metrics-file a.toml --metrics-file b.toml");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::MetricsFileError(FileErrorType::AlreadySpecified(
+            "--metrics-file".to_string()
+        )))
+    );
+
+    let result = args("--metrics-file");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::MetricsFileError(FileErrorType::UnexpectedEnd(
+            "--metrics-file".to_string()
+        )))
+    );
+
+    let result = args("-d --metrics-file metrics.toml");
+    assert_eq!(result, Err(ArgumentsError::CannotSaveMetricsWithEventsDisabled));
+
+    let result = args("--metrics-file metrics.toml -d");
+    assert_eq!(result, Err(ArgumentsError::CannotSaveMetricsWithEventsDisabled));
+}
+
+#[test]
+fn test_handoff_socket() {
+    let result = args("-H ./some/dir/missing-handoff.sock");
+    assert_eq!(
+        result,
+        Ok(ArgumentsRequest::Run(StartupArguments {
+            handoff_socket: Some("./some/dir/missing-handoff.sock".to_string()),
+            ..Default::default()
+        }))
+    );
+
+    let result = args("--handoff-socket");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::HandoffSocketError(FileErrorType::UnexpectedEnd(
+            "--handoff-socket".to_string()
+        )))
+    );
+
+    let result = args("-H a.sock -H b.sock");
+    assert_eq!(
+        result,
+        Err(ArgumentsError::HandoffSocketError(FileErrorType::AlreadySpecified(
+            "-H".to_string()
+        )))
+    );
+}
+
 #[test]
 fn test_unknown_argument() {
     let result = args("-q");
@@ -0,0 +1,91 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use dust_devil_core::sandstorm::CloseClientsTarget;
+
+use crate::clients::ClientRegistry;
+
+fn address(port: u16) -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port))
+}
+
+#[test]
+fn test_registry() {
+    let registry = ClientRegistry::new();
+    let third = registry.register(3, address(5003), None);
+    let first = registry.register(1, address(5001), None);
+    registry.register(2, address(5002), None);
+
+    first.set_username("pedro".to_string());
+    first.set_destination("example.com:443".to_string());
+    first.add_bytes_sent(100);
+    first.add_bytes_sent(20);
+    first.add_bytes_received(300);
+    third.set_destination("[::1]:80".to_string());
+
+    let list = registry.list();
+    assert_eq!(list.iter().map(|c| c.client_id).collect::<Vec<_>>(), vec![1, 2, 3]);
+    assert_eq!(list[0].address, address(5001));
+    assert_eq!(list[0].username.as_deref(), Some("pedro"));
+    assert_eq!(list[0].destination.as_deref(), Some("example.com:443"));
+    assert_eq!((list[0].bytes_sent, list[0].bytes_received), (120, 300));
+    assert_eq!(list[1].username, None);
+    assert_eq!(list[1].destination, None);
+    assert_eq!(list[2].destination.as_deref(), Some("[::1]:80"));
+
+    registry.unregister(2);
+    registry.unregister(2);
+    assert_eq!(registry.list().iter().map(|c| c.client_id).collect::<Vec<_>>(), vec![1, 3]);
+}
+
+#[test]
+fn test_close() {
+    let registry = ClientRegistry::new();
+    let first = registry.register(1, address(5001), None);
+    let second = registry.register(2, address(5002), None);
+    let third = registry.register(3, SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 5003)), None);
+    first.set_username("pedro".to_string());
+    third.set_username("pedro".to_string());
+
+    let mut closed = Vec::new();
+    assert_eq!(
+        registry.close(&CloseClientsTarget::Username("pedro".to_string()), |id| closed.push(id)),
+        2
+    );
+    assert_eq!(closed, vec![1, 3]);
+    assert!(first.close_token().is_cancelled());
+    assert!(!second.close_token().is_cancelled());
+    assert!(third.close_token().is_cancelled());
+
+    // Clients already being closed are not closed again.
+    closed.clear();
+    let localhost = CloseClientsTarget::Address(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    assert_eq!(registry.close(&localhost, |id| closed.push(id)), 1);
+    assert_eq!(closed, vec![2]);
+    assert!(second.close_token().is_cancelled());
+
+    let fourth = registry.register(4, address(5004), None);
+    assert_eq!(registry.close(&CloseClientsTarget::Client(5), |_| {}), 0);
+    assert_eq!(registry.close(&CloseClientsTarget::Client(4), |_| {}), 1);
+    assert!(fourth.close_token().is_cancelled());
+}
+
+#[test]
+fn test_close_ipv4_mapped() {
+    let registry = ClientRegistry::new();
+    let mapped = Ipv4Addr::new(10, 0, 0, 1).to_ipv6_mapped();
+    let first = registry.register(1, SocketAddr::V6(SocketAddrV6::new(mapped, 5001, 0, 0)), None);
+    let second = registry.register(2, SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 5002)), None);
+    let third = registry.register(3, SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::LOCALHOST, 5003, 0, 0)), None);
+
+    let target = CloseClientsTarget::Address(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+    assert_eq!(registry.close(&target, |_| {}), 2);
+    assert!(first.close_token().is_cancelled());
+    assert!(second.close_token().is_cancelled());
+    assert!(!third.close_token().is_cancelled());
+
+    let fourth = registry.register(4, address(5004), None);
+    let target = CloseClientsTarget::Address(IpAddr::V6(Ipv4Addr::LOCALHOST.to_ipv6_mapped()));
+    assert_eq!(registry.close(&target, |_| {}), 1);
+    assert!(fourth.close_token().is_cancelled());
+    assert!(!third.close_token().is_cancelled());
+}
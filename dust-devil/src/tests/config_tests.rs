@@ -0,0 +1,549 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+use dust_devil_core::{
+    ip_filter::{IpFilterKind, IpNetwork},
+    listener_profile::ListenerProfile,
+    log_filter::{LogFilterParseError, LogSink},
+    socks5::AuthMethod,
+    users::UserRole,
+};
+
+use crate::{
+    args::{
+        ArgumentsError, BufferSizeErrorType, EventLagPolicyErrorType, LogFilterErrorType, LogRotationErrorType, NewUserErrorType,
+        SocketErrorType, StartupArguments, SyslogErrorType,
+    },
+    config::{
+        apply_config, apply_state, get_effective_config_string, get_metrics_file_string, get_state_string, parse_metrics_file,
+        ConfigErrorType, RestoredState,
+    },
+    log_file::LogRotation,
+    metrics::{AuthFailure, ConnectionFailure},
+    sandstorm::EventLagPolicy,
+    system_log::SyslogTarget,
+};
+
+fn config(cli: StartupArguments, cli_auth_toggles: &[AuthMethod], s: &str) -> Result<StartupArguments, ConfigErrorType> {
+    let mut result = cli;
+    apply_config(&mut result, cli_auth_toggles, s)?;
+    result.fill_empty_fields_with_defaults();
+    Ok(result)
+}
+
+fn invalid_value(line_number: u32, char_at: u32, error: impl Into<ArgumentsError>) -> ConfigErrorType {
+    ConfigErrorType::InvalidValue(line_number, char_at, Box::new(error.into()))
+}
+
+#[test]
+fn test_empty() {
+    let result = config(StartupArguments::empty(), &[], "");
+    assert_eq!(result, Ok(StartupArguments::default()));
+
+    let result = config(StartupArguments::empty(), &[], "# Nothing to see here\n\n");
+    assert_eq!(result, Ok(StartupArguments::default()));
+}
+
+#[test]
+fn test_all_fields() {
+    let source = concat!(
+        "verbose = true\n",
+        "silent = true\n",
+        "log-file = \"logs.txt\"\n",
+        "log-rotate-size = \"10M\"\n",
+        "log-rotate-interval = \"12h\"\n",
+        "log-keep = 3\n",
+        "log-compress = true\n",
+        "users-file = \"users2.txt\"\n",
+        "buffer-size = \"16K\"\n",
+        "drain-timeout = 30\n",
+        "event-lag-policy = \"resync\"\n",
+        "noauth = false\n",
+        "userpass = true\n",
+        "listen = [\"0.0.0.0:1080\", \"127.0.0.1:1081=noauth,buffer:1K\"]\n",
+        "management = [\"[::1]\"]\n",
+        "users = [\"@pedro:1234\", \"juan:carlos\"]\n",
+        "allow = [\"[::1]:2222=::1\"]\n",
+        "deny = [\"0.0.0.0:1080=10.0.0.0/8\"]\n",
+    );
+
+    let result = config(StartupArguments::empty(), &[], source).unwrap();
+
+    let local_listener = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1081));
+    assert!(result.verbose);
+    assert!(result.silent);
+    assert!(result.events_enabled);
+    assert_eq!(result.log_file, Some("logs.txt".to_string()));
+    assert_eq!(
+        result.log_rotation,
+        LogRotation {
+            max_size: Some(10 * 1024 * 1024),
+            interval: Some(12 * 60 * 60),
+            keep: Some(3),
+            compress: true,
+        }
+    );
+    assert_eq!(result.users_file, "users2.txt");
+    assert_eq!(result.buffer_size, 16 * 1024);
+    assert_eq!(result.drain_timeout, Some(30));
+    assert_eq!(result.event_lag_policy, Some(EventLagPolicy::Resync));
+    assert!(!result.no_auth_enabled);
+    assert!(result.userpass_auth_enabled);
+    assert_eq!(
+        result.socks5_bind_sockets,
+        vec![SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 1080)), local_listener]
+    );
+    assert_eq!(
+        result.socks5_profiles.get(&local_listener),
+        Some(&ListenerProfile {
+            no_auth_enabled: Some(true),
+            buffer_size: Some(1024),
+            ..Default::default()
+        })
+    );
+    assert_eq!(
+        result.sandstorm_bind_sockets,
+        vec![SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::LOCALHOST, 2222, 0, 0))]
+    );
+    assert_eq!(result.users.len(), 2);
+    assert_eq!(
+        result.users.get("pedro").map(|u| (u.password.as_str(), u.role)),
+        Some(("1234", UserRole::Admin))
+    );
+    assert_eq!(
+        result.users.get("juan").map(|u| (u.password.as_str(), u.role)),
+        Some(("carlos", UserRole::Regular))
+    );
+    assert_eq!(
+        result.ip_filters,
+        vec![
+            (
+                SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::LOCALHOST, 2222, 0, 0)),
+                IpFilterKind::Allow,
+                IpNetwork::new(Ipv6Addr::LOCALHOST.into(), 128).unwrap(),
+            ),
+            (
+                SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 1080)),
+                IpFilterKind::Deny,
+                "10.0.0.0/8".parse().unwrap(),
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_buffer_size_number() {
+    let result = config(StartupArguments::empty(), &[], "buffer-size = 4096");
+    assert_eq!(
+        result,
+        Ok(StartupArguments {
+            buffer_size: 4096,
+            ..Default::default()
+        })
+    );
+}
+
+#[test]
+fn test_disable_events() {
+    let result = config(StartupArguments::empty(), &[], "disable-events = true");
+    assert_eq!(
+        result,
+        Ok(StartupArguments {
+            events_enabled: false,
+            silent: true,
+            ..Default::default()
+        })
+    );
+
+    let result = config(StartupArguments::empty(), &[], "disable-events = true\nlog-file = \"logs.txt\"");
+    assert_eq!(result, Err(invalid_value(2, 12, ArgumentsError::CannotLogWithEventsDisabled)));
+
+    let result = config(StartupArguments::empty(), &[], "disable-events = true\njournald = true");
+    assert_eq!(result, Err(invalid_value(2, 12, ArgumentsError::CannotLogWithEventsDisabled)));
+}
+
+#[test]
+fn test_system_logs() {
+    let source = "syslog = [\"unix:/dev/log\", \"127.0.0.1:1514\"]\njournald = true\n";
+    let result = config(StartupArguments::empty(), &[], source).unwrap();
+    assert_eq!(
+        result.syslog_targets,
+        vec![
+            SyslogTarget::Unix("/dev/log".to_string()),
+            SyslogTarget::Udp(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1514))),
+        ]
+    );
+    assert!(result.journald);
+
+    let mut cli = StartupArguments::empty();
+    cli.syslog_targets.push(SyslogTarget::Unix("/var/run/syslog".to_string()));
+    let result = config(cli, &[], source).unwrap();
+    assert_eq!(result.syslog_targets, vec![SyslogTarget::Unix("/var/run/syslog".to_string())]);
+
+    let result = config(StartupArguments::empty(), &[], "syslog = [\"unix:\"]");
+    assert_eq!(
+        result,
+        Err(invalid_value(
+            1,
+            11,
+            SyslogErrorType::InvalidTarget("syslog".to_string(), "unix:".to_string())
+        ))
+    );
+}
+
+#[test]
+fn test_log_filters() {
+    let source = "log-filter = [\"severity=info\", \"file:users=pedro\"]\n";
+    let mut cli = StartupArguments::empty();
+    cli.log_filters.push((Some(LogSink::File), "severity=notice".parse().unwrap()));
+    let result = config(cli, &[], source).unwrap();
+    assert_eq!(
+        result.log_filters,
+        vec![
+            (None, "severity=info".parse().unwrap()),
+            (Some(LogSink::File), "users=pedro".parse().unwrap()),
+            (Some(LogSink::File), "severity=notice".parse().unwrap()),
+        ]
+    );
+
+    let result = config(StartupArguments::empty(), &[], "log-filter = [\"include=Client\"]");
+    assert_eq!(
+        result,
+        Err(invalid_value(
+            1,
+            15,
+            LogFilterErrorType::InvalidFilter(
+                "log-filter".to_string(),
+                "include=Client".to_string(),
+                LogFilterParseError::InvalidEventType("Client".to_string())
+            )
+        ))
+    );
+}
+
+#[test]
+fn test_metrics_listen() {
+    let source = "metrics-listen = [\"127.0.0.1\", \"[::1]:9100\"]\n";
+    let result = config(StartupArguments::empty(), &[], source).unwrap();
+    assert_eq!(
+        result.metrics_bind_sockets,
+        vec![
+            SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9180)),
+            SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::LOCALHOST, 9100, 0, 0)),
+        ]
+    );
+
+    let mut cli = StartupArguments::empty();
+    cli.metrics_bind_sockets
+        .push(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9999)));
+    let result = config(cli, &[], source).unwrap();
+    assert_eq!(
+        result.metrics_bind_sockets,
+        vec![SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9999))]
+    );
+
+    let result = config(StartupArguments::empty(), &[], "metrics-listen = [\"nope:nope\"]");
+    assert_eq!(
+        result,
+        Err(invalid_value(
+            1,
+            19,
+            ArgumentsError::MetricsListenError(SocketErrorType::InvalidSocketAddress(
+                "metrics-listen".to_string(),
+                "nope:nope".to_string()
+            ))
+        ))
+    );
+
+    let result = config(
+        StartupArguments::empty(),
+        &[],
+        "disable-events = true\nmetrics-listen = [\"127.0.0.1\"]",
+    );
+    assert_eq!(
+        result,
+        Err(invalid_value(2, 19, ArgumentsError::CannotServeMetricsWithEventsDisabled))
+    );
+}
+
+#[test]
+fn test_metrics_file() {
+    let source = "metrics-file = \"metrics.toml\"\n";
+    let result = config(StartupArguments::empty(), &[], source).unwrap();
+    assert_eq!(result.metrics_file, Some("metrics.toml".to_string()));
+
+    let mut cli = StartupArguments::empty();
+    cli.metrics_file = Some("cli.toml".to_string());
+    let result = config(cli, &[], source).unwrap();
+    assert_eq!(result.metrics_file, Some("cli.toml".to_string()));
+
+    let result = config(
+        StartupArguments::empty(),
+        &[],
+        "disable-events = true\nmetrics-file = \"metrics.toml\"",
+    );
+    assert_eq!(
+        result,
+        Err(invalid_value(2, 16, ArgumentsError::CannotSaveMetricsWithEventsDisabled))
+    );
+}
+
+#[test]
+fn test_metrics_file_roundtrip() {
+    let source = concat!(
+        "counter-epoch = 1700000000\n",
+        "historic-client-connections = 12\n",
+        "client-bytes-sent = 1024\n",
+        "dns-lookups = 3\n",
+        "auth-failures = { socks5_invalid_credentials = 2 }\n",
+        "connection-failures = { destination_unreachable = 1 }\n",
+    );
+
+    let metrics = parse_metrics_file(source).unwrap();
+    assert_eq!(metrics.counter_epoch, 1700000000);
+    assert_eq!(metrics.historic_client_connections, 12);
+    assert_eq!(metrics.client_bytes_sent, 1024);
+    assert_eq!(metrics.client_bytes_received, 0);
+    assert_eq!(metrics.dns_lookups, 3);
+    assert_eq!(metrics.auth_failures(AuthFailure::Socks5InvalidCredentials), 2);
+    assert_eq!(metrics.auth_failures(AuthFailure::SandstormInvalidCredentials), 0);
+    assert_eq!(metrics.connection_failures(ConnectionFailure::DestinationUnreachable), 1);
+
+    let written = get_metrics_file_string(&metrics);
+    assert_eq!(parse_metrics_file(&written), Ok(metrics));
+
+    assert!(parse_metrics_file("historic-client-connections = 12\n").is_err());
+}
+
+#[test]
+fn test_cli_overrides() {
+    let mut cli = StartupArguments::empty();
+    cli.buffer_size = 1024;
+    cli.users_file = "cli.txt".to_string();
+    cli.no_auth_enabled = true;
+    cli.userpass_auth_enabled = false;
+    cli.socks5_bind_sockets
+        .push(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1080)));
+    cli.users = config(StartupArguments::empty(), &[], "users = [\"pedro:cli\"]").unwrap().users;
+
+    let source = concat!(
+        "buffer-size = 2048\n",
+        "users-file = \"file.txt\"\n",
+        "noauth = false\n",
+        "userpass = true\n",
+        "listen = [\"0.0.0.0:1080\"]\n",
+        "management = [\"127.0.0.1:2222\"]\n",
+        "users = [\"pedro:file\", \"juan:file\"]\n",
+    );
+
+    let result = config(cli, &[AuthMethod::NoAuth], source).unwrap();
+    assert_eq!(result.buffer_size, 1024);
+    assert_eq!(result.users_file, "cli.txt");
+    assert!(result.no_auth_enabled);
+    assert!(result.userpass_auth_enabled);
+    assert_eq!(
+        result.socks5_bind_sockets,
+        vec![SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1080))]
+    );
+    assert_eq!(
+        result.sandstorm_bind_sockets,
+        vec![SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 2222))]
+    );
+    assert_eq!(result.users.get("pedro").map(|u| u.password.as_str()), Some("cli"));
+    assert_eq!(result.users.get("juan").map(|u| u.password.as_str()), Some("file"));
+}
+
+#[test]
+fn test_management_unix() {
+    let source = "management = [\"unix:/run/dust-devil/sandstorm.sock\"]\n";
+    let result = config(StartupArguments::empty(), &[], source).unwrap();
+    assert!(result.sandstorm_bind_sockets.is_empty());
+    assert_eq!(result.sandstorm_unix_sockets, vec!["/run/dust-devil/sandstorm.sock".to_string()]);
+
+    // Unix sockets on the command line override the file's Sandstorm sockets too.
+    let mut cli = StartupArguments::empty();
+    cli.sandstorm_unix_sockets.push("sandstorm.sock".to_string());
+    let result = config(cli, &[], "management = [\"127.0.0.1:2222\"]\n").unwrap();
+    assert!(result.sandstorm_bind_sockets.is_empty());
+    assert_eq!(result.sandstorm_unix_sockets, vec!["sandstorm.sock".to_string()]);
+}
+
+#[test]
+fn test_syntax_errors() {
+    let result = config(StartupArguments::empty(), &[], "verbose = true\nsilent = \n");
+    assert!(matches!(result, Err(ConfigErrorType::Syntax(2, 10, _))));
+
+    let result = config(StartupArguments::empty(), &[], "verbose = true\n  croquetas = 5\n");
+    assert!(matches!(result, Err(ConfigErrorType::Syntax(2, 3, _))));
+
+    let result = config(StartupArguments::empty(), &[], "listen = \"0.0.0.0\"");
+    assert!(matches!(result, Err(ConfigErrorType::Syntax(1, 10, _))));
+}
+
+#[test]
+fn test_invalid_values() {
+    let result = config(StartupArguments::empty(), &[], "listen = [\"0.0.0.0\", \"pedro\"]");
+    assert_eq!(
+        result,
+        Err(invalid_value(
+            1,
+            22,
+            ArgumentsError::Socks5ListenError(SocketErrorType::InvalidSocketAddress("listen".to_string(), "pedro".to_string()))
+        ))
+    );
+
+    let result = config(StartupArguments::empty(), &[], "\n\nbuffer-size = \"5Q\"");
+    assert_eq!(
+        result,
+        Err(invalid_value(
+            3,
+            15,
+            BufferSizeErrorType::InvalidCharacters("buffer-size".to_string(), "5Q".to_string())
+        ))
+    );
+
+    let result = config(StartupArguments::empty(), &[], "users = [\n    \"pedro:1\",\n    \"pedro:2\",\n]");
+    assert_eq!(
+        result,
+        Err(invalid_value(
+            3,
+            5,
+            NewUserErrorType::DuplicateUsername("users".to_string(), "pedro:2".to_string())
+        ))
+    );
+
+    let result = config(StartupArguments::empty(), &[], "log-rotate-interval = \"2y\"");
+    assert_eq!(
+        result,
+        Err(invalid_value(
+            1,
+            23,
+            LogRotationErrorType::InvalidInterval("log-rotate-interval".to_string(), "2y".to_string())
+        ))
+    );
+
+    let result = config(StartupArguments::empty(), &[], "event-lag-policy = \"ignore\"");
+    assert_eq!(
+        result,
+        Err(invalid_value(
+            1,
+            20,
+            EventLagPolicyErrorType::InvalidValue("event-lag-policy".to_string(), "ignore".to_string())
+        ))
+    );
+}
+
+#[test]
+fn test_effective_config_roundtrip() {
+    let source = concat!(
+        "verbose = true\n",
+        "log-file = \"logs.txt\"\n",
+        "log-rotate-size = \"1M\"\n",
+        "log-rotate-interval = \"1w\"\n",
+        "log-compress = true\n",
+        "log-format = \"file=json\"\n",
+        "syslog = [\"/dev/log\", \"udp:127.0.0.1\"]\n",
+        "journald = true\n",
+        "log-filter = [\"severity=info\", \"syslog:exclude=client_*,users=a\\\\,b\"]\n",
+        "buffer-size = \"16K\"\n",
+        "event-lag-policy = \"drop\"\n",
+        "noauth = false\n",
+        "listen = [\"0.0.0.0:1080\", \"127.0.0.1:1081=noauth,buffer:1K\"]\n",
+        "management = [\"[::1]\"]\n",
+        "metrics-listen = [\"127.0.0.1\"]\n",
+        "metrics-file = \"metrics.toml\"\n",
+        "allow = [\"[::1]:2222=::1\"]\n",
+        "deny = [\"0.0.0.0:1080=10.0.0.0/8\"]\n",
+    );
+
+    let result = config(StartupArguments::empty(), &[], source).unwrap();
+    let effective = get_effective_config_string(&result);
+    assert_eq!(config(StartupArguments::empty(), &[], &effective), Ok(result));
+}
+
+#[test]
+fn test_state_restore() {
+    let mut cli = StartupArguments::empty();
+    cli.sandstorm_bind_sockets
+        .push(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 2222)));
+    cli.no_auth_enabled = true;
+
+    let source = concat!(
+        "buffer-size = 2048\n",
+        "noauth = false\n",
+        "userpass = false\n",
+        "listen = [\"0.0.0.0:1080=-noauth\"]\n",
+        "management = [\"0.0.0.0:2222\"]\n",
+        "deny = [\"0.0.0.0:1080=10.0.0.0/8\"]\n",
+    );
+
+    let mut auth_toggles = vec![AuthMethod::NoAuth];
+    let restored = apply_state(&mut cli, &mut auth_toggles, source).unwrap();
+
+    let listener = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 1080));
+    let filter = (listener, IpFilterKind::Deny, "10.0.0.0/8".parse().unwrap());
+    assert_eq!(
+        restored,
+        RestoredState {
+            socks5_sockets: vec![listener],
+            sandstorm_sockets: vec![],
+            sandstorm_unix_sockets: vec![],
+            auth_methods: vec![(AuthMethod::UsernameAndPassword, false)],
+            buffer_size: Some(2048),
+            ip_filters: vec![filter],
+        }
+    );
+    assert_eq!(auth_toggles, vec![AuthMethod::NoAuth, AuthMethod::UsernameAndPassword]);
+
+    assert_eq!(cli.socks5_bind_sockets, vec![listener]);
+    assert!(cli.socks5_profiles.contains_key(&listener));
+    assert_eq!(
+        cli.sandstorm_bind_sockets,
+        vec![SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 2222))]
+    );
+    assert!(cli.no_auth_enabled);
+    assert!(!cli.userpass_auth_enabled);
+    assert_eq!(cli.buffer_size, 2048);
+    assert_eq!(cli.ip_filters, vec![filter]);
+
+    // The configuration file may not override what was restored from the state.
+    apply_config(&mut cli, &auth_toggles, "userpass = true\nbuffer-size = 1024").unwrap();
+    assert!(!cli.userpass_auth_enabled);
+    assert_eq!(cli.buffer_size, 2048);
+}
+
+#[test]
+fn test_state_invalid() {
+    let result = apply_state(&mut StartupArguments::empty(), &mut Vec::new(), "users = [\"pedro:1234\"]");
+    assert!(matches!(result, Err(ConfigErrorType::Syntax(1, 1, _))));
+
+    let result = apply_state(
+        &mut StartupArguments::empty(),
+        &mut Vec::new(),
+        "noauth = true\nmanagement = [\"pedro\"]",
+    );
+    assert_eq!(
+        result,
+        Err(invalid_value(
+            2,
+            15,
+            ArgumentsError::SandstormListenError(SocketErrorType::InvalidSocketAddress("management".to_string(), "pedro".to_string()))
+        ))
+    );
+}
+
+#[test]
+fn test_state_roundtrip() {
+    let source = concat!(
+        "buffer-size = 4096\n",
+        "noauth = false\n",
+        "userpass = true\n",
+        "listen = [\"0.0.0.0:1080\", \"127.0.0.1:1081=noauth,buffer:1024\"]\n",
+        "management = [\"[::1]:2222\", \"unix:/run/dust-devil/sandstorm.sock\"]\n",
+        "allow = [\"[::1]:2222=::1/128\"]\n",
+        "deny = []\n",
+    );
+
+    let mut state = StartupArguments::empty();
+    apply_state(&mut state, &mut Vec::new(), source).unwrap();
+    assert_eq!(get_state_string(&state), source);
+}
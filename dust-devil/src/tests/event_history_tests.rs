@@ -0,0 +1,54 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::Arc,
+};
+
+use dust_devil_core::{
+    logging::{Event, EventData},
+    sandstorm::EventReplay,
+};
+
+use crate::event_history::EventHistory;
+
+fn event(timestamp: i64) -> Arc<Event> {
+    let address = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1080));
+    Arc::new(Event::new(timestamp, EventData::NewSocks5Socket(address)))
+}
+
+fn timestamps(events: Vec<Arc<Event>>) -> Vec<i64> {
+    events.iter().map(|e| e.timestamp).collect()
+}
+
+#[test]
+fn test_replay_last() {
+    let mut history = EventHistory::with_capacity(4);
+    assert!(history.replay(EventReplay::Last(10)).is_empty());
+
+    for timestamp in 1..=6 {
+        history.push(event(timestamp));
+    }
+
+    assert_eq!(timestamps(history.replay(EventReplay::Last(0))), vec![]);
+    assert_eq!(timestamps(history.replay(EventReplay::Last(2))), vec![5, 6]);
+    assert_eq!(timestamps(history.replay(EventReplay::Last(10))), vec![3, 4, 5, 6]);
+}
+
+#[test]
+fn test_replay_since() {
+    let mut history = EventHistory::with_capacity(8);
+    for timestamp in [10, 10, 11, 13, 13, 14] {
+        history.push(event(timestamp));
+    }
+
+    assert_eq!(timestamps(history.replay(EventReplay::Since(0))), vec![10, 10, 11, 13, 13, 14]);
+    assert_eq!(timestamps(history.replay(EventReplay::Since(12))), vec![13, 13, 14]);
+    assert_eq!(timestamps(history.replay(EventReplay::Since(13))), vec![13, 13, 14]);
+    assert_eq!(timestamps(history.replay(EventReplay::Since(15))), vec![]);
+}
+
+#[test]
+fn test_zero_capacity() {
+    let mut history = EventHistory::with_capacity(0);
+    history.push(event(1));
+    assert!(history.replay(EventReplay::Since(0)).is_empty());
+}
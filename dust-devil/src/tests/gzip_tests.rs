@@ -0,0 +1,49 @@
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+use crate::utils::gzip::compress;
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::new();
+    compress(data, &mut result).unwrap();
+    result
+}
+
+fn gunzip(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::new();
+    GzDecoder::new(data).read_to_end(&mut result).unwrap();
+    result
+}
+
+#[test]
+fn test_empty() {
+    let result = gzip(b"");
+
+    assert_eq!(result[..3], [0x1f, 0x8b, 0x08]);
+    assert_eq!(gunzip(&result), b"");
+}
+
+#[test]
+fn test_roundtrip() {
+    let data = b"[2024-01-01 00:00:00] Client 1 connected\n".repeat(1000);
+    let result = gzip(&data);
+
+    assert!(result.len() < data.len() / 10);
+    assert_eq!(gunzip(&result), data);
+}
+
+#[test]
+fn test_roundtrip_incompressible() {
+    let mut state = 0x12345678u32;
+    let data: Vec<u8> = (0..200000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect();
+
+    assert_eq!(gunzip(&gzip(&data)), data);
+}
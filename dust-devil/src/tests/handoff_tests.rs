@@ -0,0 +1,99 @@
+use std::{collections::HashMap, net::Ipv4Addr, sync::Arc};
+
+use dust_devil_core::{
+    ip_filter::{IpFilterKind, IpNetwork},
+    listener_profile::ListenerProfile,
+};
+use tokio::net::TcpListener;
+
+use crate::{
+    handoff::{receive_listeners, send_listeners, HandedOffSettings, HandoffListener},
+    utils::{accept_from_any::accept_from_any, unix_socket::UnixListener},
+};
+
+fn socket_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("dust-devil-{name}-{}.sock", std::process::id()));
+    path.to_string_lossy().into_owned()
+}
+
+#[tokio::test]
+async fn test_no_server() {
+    let path = socket_path("handoff-missing");
+    let result = receive_listeners(&path).await;
+    assert!(result.is_ok_and(|inherited| inherited.is_none()));
+}
+
+#[tokio::test]
+async fn test_handoff() {
+    let path = socket_path("handoff");
+    let handoff_listener = HandoffListener::bind(&path).unwrap();
+
+    let socks5 = vec![
+        TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        TcpListener::bind("127.0.0.1:0").await.unwrap(),
+    ];
+    let sandstorm = vec![TcpListener::bind("127.0.0.1:0").await.unwrap()];
+    let sandstorm_unix_path = socket_path("handoff-sandstorm");
+    let sandstorm_unix = vec![UnixListener::bind(&sandstorm_unix_path).await.unwrap()];
+
+    let profile = ListenerProfile {
+        no_auth_enabled: Some(false),
+        buffer_size: Some(1024),
+        ..Default::default()
+    };
+    let mut profiles = HashMap::new();
+    profiles.insert(socks5[1].local_addr().unwrap(), Arc::new(profile.clone()));
+
+    let socks5_addresses: Vec<_> = socks5.iter().map(|l| l.local_addr().unwrap()).collect();
+    let sandstorm_address = sandstorm[0].local_addr().unwrap();
+
+    let settings = HandedOffSettings {
+        no_auth_enabled: false,
+        userpass_auth_enabled: true,
+        buffer_size: 4096,
+        ip_filters: vec![(
+            socks5_addresses[0],
+            IpFilterKind::Deny,
+            IpNetwork::new(Ipv4Addr::new(10, 0, 0, 0).into(), 8).unwrap(),
+        )],
+    };
+    let sent_settings = settings.clone();
+
+    let sender = tokio::spawn(async move {
+        let stream = handoff_listener.accept().await.unwrap();
+        let result = send_listeners(stream, &socks5, &profiles, &sandstorm, &sandstorm_unix, &sent_settings).await;
+        sandstorm_unix.into_iter().for_each(UnixListener::close_keeping_file);
+        result
+    });
+
+    let inherited = receive_listeners(&path).await.unwrap().unwrap();
+    assert!(sender.await.unwrap().is_ok());
+    let _ = std::fs::remove_file(&path);
+
+    let inherited_socks5: Vec<_> = inherited.socks5.iter().map(|(l, p)| (l.local_addr().unwrap(), p.clone())).collect();
+    assert_eq!(
+        inherited_socks5,
+        vec![(socks5_addresses[0], ListenerProfile::default()), (socks5_addresses[1], profile)]
+    );
+    assert_eq!(inherited.settings, Some(settings));
+    assert_eq!(inherited.sandstorm.len(), 1);
+    assert_eq!(inherited.sandstorm[0].local_addr().unwrap(), sandstorm_address);
+
+    // The inherited sockets must keep accepting connections after the original ones are closed.
+    let connector = tokio::net::TcpStream::connect(sandstorm_address);
+    let (connect_result, accept_result) = tokio::join!(connector, inherited.sandstorm[0].accept());
+    assert!(connect_result.is_ok());
+    assert!(accept_result.is_ok());
+
+    assert_eq!(inherited.sandstorm_unix.len(), 1);
+    assert_eq!(inherited.sandstorm_unix[0].path(), sandstorm_unix_path);
+
+    let connector = tokio::net::UnixStream::connect(&sandstorm_unix_path);
+    let (connect_result, accept_result) = tokio::join!(connector, accept_from_any(&inherited.sandstorm_unix));
+    assert!(connect_result.is_ok());
+    assert!(accept_result.is_ok());
+
+    // The new owner of a Unix socket removes its file once it stops listening on it.
+    drop(inherited);
+    assert!(!std::path::Path::new(&sandstorm_unix_path).exists());
+}
@@ -0,0 +1,91 @@
+use dust_devil_core::{
+    sandstorm::{
+        FramedRequest, IdentifiedRequest, IdentifiedResponse, MeowRequest, MeowResponse, ParseHandshakeError, SandstormCapabilities,
+        SandstormCapability, SandstormHandshake, SandstormHandshakeRef, SandstormNegotiation, SetBufferSizeRequest, SetBufferSizeResponse,
+        SANDSTORM_VERSION,
+    },
+    serialize::{ByteRead, ByteWrite},
+};
+
+async fn read_handshake(bytes: &[u8]) -> SandstormHandshake {
+    let mut reader = bytes;
+    match SandstormHandshake::read_with_version_check(&mut reader).await {
+        Ok(handshake) => {
+            assert!(reader.is_empty());
+            handshake
+        }
+        Err(_) => panic!("Failed to read handshake"),
+    }
+}
+
+#[tokio::test]
+async fn test_version1_handshake() {
+    let handshake = read_handshake(&[1, 5, b'p', b'e', b'd', b'r', b'o', 4, b'1', b'2', b'3', b'4']).await;
+    assert_eq!(handshake.version, 1);
+    assert_eq!(handshake.username, "pedro");
+    assert_eq!(handshake.password, "1234");
+    assert_eq!(handshake.capabilities, SandstormCapabilities::none());
+    assert_eq!(handshake.negotiate(SandstormCapabilities::all()), None);
+
+    let mut reader: &[u8] = &[0, 5, b'p', b'e', b'd', b'r', b'o'];
+    let result = SandstormHandshake::read_with_version_check(&mut reader).await;
+    assert!(matches!(result, Err(ParseHandshakeError::InvalidVersion(0))));
+}
+
+#[tokio::test]
+async fn test_negotiation() {
+    let mut bytes = Vec::new();
+    SandstormHandshakeRef::new("pedro", "1234").write(&mut bytes).await.unwrap();
+
+    let handshake = read_handshake(&bytes).await;
+    assert_eq!(handshake.version, SANDSTORM_VERSION);
+    assert_eq!(handshake.capabilities, SandstormCapabilities::all());
+
+    let negotiation = handshake.negotiate(SandstormCapabilities::all()).unwrap();
+    assert_eq!(negotiation.version, SANDSTORM_VERSION);
+    assert_eq!(negotiation.capabilities, SandstormCapabilities::all());
+    assert!(negotiation.frames_requests());
+
+    // A newer client negotiates down to our version, and unknown capabilities are ignored.
+    let handshake = read_handshake(&[7, 1, b'a', 1, b'b', 0xFF, 0xFF, 0xFF, 0xF2]).await;
+    assert_eq!(handshake.version, 7);
+    let negotiation = handshake.negotiate(SandstormCapabilities::all()).unwrap();
+    assert_eq!(negotiation.version, SANDSTORM_VERSION);
+    let capabilities: Vec<_> = negotiation.capabilities.iter().collect();
+    assert_eq!(capabilities, vec![SandstormCapability::EventReplay]);
+
+    let mut bytes = Vec::new();
+    negotiation.write(&mut bytes).await.unwrap();
+    assert_eq!(bytes, vec![SANDSTORM_VERSION, 0, 0, 0, 0b10]);
+    assert_eq!(SandstormNegotiation::read(&mut bytes.as_slice()).await.unwrap(), negotiation);
+}
+
+#[tokio::test]
+async fn test_framed_request() {
+    let mut bytes = Vec::new();
+    FramedRequest(SetBufferSizeRequest(0x1000)).write(&mut bytes).await.unwrap();
+    FramedRequest(MeowRequest).write(&mut bytes).await.unwrap();
+
+    assert_eq!(bytes, vec![0x11, 0, 4, 0, 0, 0x10, 0, 0xFF, 0, 0]);
+}
+
+#[tokio::test]
+async fn test_identified_messages() {
+    let mut bytes = Vec::new();
+    IdentifiedRequest(0x0102, SetBufferSizeRequest(0x1000))
+        .write(&mut bytes)
+        .await
+        .unwrap();
+    IdentifiedRequest(7, MeowRequest).write(&mut bytes).await.unwrap();
+
+    assert_eq!(bytes, vec![0x11, 0, 0, 1, 2, 0, 4, 0, 0, 0x10, 0, 0xFF, 0, 0, 0, 7, 0, 0]);
+
+    let mut bytes = Vec::new();
+    IdentifiedResponse(0x0102, SetBufferSizeResponse(true))
+        .write(&mut bytes)
+        .await
+        .unwrap();
+    IdentifiedResponse(7, MeowResponse).write(&mut bytes).await.unwrap();
+
+    assert_eq!(bytes, vec![0x11, 0, 0, 1, 2, 1, 0xFF, 0, 0, 0, 7, b'M', b'E', b'O', b'W']);
+}
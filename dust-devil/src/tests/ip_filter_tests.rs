@@ -0,0 +1,90 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use dust_devil_core::ip_filter::{IpFilterKind, IpNetwork};
+
+use crate::ip_filters::IpFilterManager;
+
+fn listener() -> SocketAddr {
+    "0.0.0.0:1080".parse().unwrap()
+}
+
+fn other_listener() -> SocketAddr {
+    "[::]:2222".parse().unwrap()
+}
+
+fn network(s: &str) -> IpNetwork {
+    s.parse().unwrap()
+}
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn test_network_contains() {
+    let net = network("10.5.3.4/16");
+    assert_eq!(net.address(), IpAddr::V4(Ipv4Addr::new(10, 5, 0, 0)));
+    assert_eq!(net.prefix_len(), 16);
+    assert!(net.contains(ip("10.5.255.1")));
+    assert!(!net.contains(ip("10.6.0.1")));
+    assert!(net.contains(ip("::ffff:10.5.0.1")));
+
+    let net = network("fd00::/8");
+    assert!(net.contains(ip("fd12:3456::1")));
+    assert!(!net.contains(ip("fe80::1")));
+    assert!(!net.contains(ip("10.5.0.1")));
+
+    assert!(network("0.0.0.0/0").contains(ip("1.2.3.4")));
+    assert!(network("::/0").contains(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+    assert!(network("1.2.3.4").contains(ip("1.2.3.4")));
+    assert!(!network("1.2.3.4").contains(ip("1.2.3.5")));
+}
+
+#[test]
+fn test_no_filters_allows_all() {
+    let mgr = IpFilterManager::new();
+    assert!(mgr.allows(listener(), ip("1.2.3.4")));
+    assert!(mgr.take_snapshot().is_empty());
+}
+
+#[test]
+fn test_allow_and_deny() {
+    let mgr = IpFilterManager::new();
+    assert!(mgr.insert(listener(), IpFilterKind::Allow, network("10.0.0.0/8")));
+    assert!(mgr.insert(listener(), IpFilterKind::Deny, network("10.5.0.0/16")));
+
+    assert!(mgr.allows(listener(), ip("10.1.2.3")));
+    assert!(!mgr.allows(listener(), ip("10.5.2.3")));
+    assert!(!mgr.allows(listener(), ip("192.168.1.1")));
+
+    // Filters only apply to the listener they were assigned to.
+    assert!(mgr.allows(other_listener(), ip("192.168.1.1")));
+}
+
+#[test]
+fn test_insert_and_remove() {
+    let mgr = IpFilterManager::new();
+    assert!(mgr.insert(other_listener(), IpFilterKind::Deny, network("fd00::/8")));
+    assert!(mgr.insert(listener(), IpFilterKind::Deny, network("1.2.3.4")));
+    assert!(mgr.insert(listener(), IpFilterKind::Allow, network("1.2.0.0/16")));
+    assert!(!mgr.insert(listener(), IpFilterKind::Allow, network("1.2.3.4/16")));
+
+    assert_eq!(
+        mgr.take_snapshot(),
+        vec![
+            (listener(), IpFilterKind::Allow, network("1.2.0.0/16")),
+            (listener(), IpFilterKind::Deny, network("1.2.3.4")),
+            (other_listener(), IpFilterKind::Deny, network("fd00::/8")),
+        ]
+    );
+
+    assert!(!mgr.remove(listener(), IpFilterKind::Deny, network("1.2.0.0/16")));
+    assert!(mgr.remove(listener(), IpFilterKind::Allow, network("1.2.0.0/16")));
+    assert!(!mgr.remove(listener(), IpFilterKind::Allow, network("1.2.0.0/16")));
+    assert!(mgr.allows(listener(), ip("5.6.7.8")));
+    assert!(!mgr.allows(listener(), ip("1.2.3.4")));
+
+    assert!(mgr.remove(listener(), IpFilterKind::Deny, network("1.2.3.4")));
+    assert!(mgr.remove(other_listener(), IpFilterKind::Deny, network("fd00::/8")));
+    assert!(mgr.take_snapshot().is_empty());
+}
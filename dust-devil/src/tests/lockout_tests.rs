@@ -0,0 +1,207 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::{Duration, Instant},
+};
+
+use dust_devil_core::{
+    sandstorm::LockoutTarget,
+    serialize::{ByteRead, ByteWrite},
+};
+
+use crate::lockout::{
+    LockoutManager, BASE_LOCKOUT_DURATION, FAILURES_BEFORE_LOCKOUT, FORGET_AFTER, MAX_LOCKOUT_DURATION, MAX_RECORDS,
+    USERNAME_FAILURES_BEFORE_LOCKOUT,
+};
+
+fn address() -> LockoutTarget {
+    LockoutTarget::Address(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10)))
+}
+
+fn username() -> LockoutTarget {
+    LockoutTarget::UserFrom(Box::new(address()), String::from("pedro"))
+}
+
+fn username_from(source: LockoutTarget) -> LockoutTarget {
+    LockoutTarget::UserFrom(Box::new(source), String::from("pedro"))
+}
+
+fn fail_until_locked(mgr: &LockoutManager, target: &LockoutTarget, now: Instant) -> Duration {
+    for _ in 1..FAILURES_BEFORE_LOCKOUT {
+        assert_eq!(mgr.register_failure(target.clone(), now), None);
+        assert!(!mgr.is_locked_out(target, now));
+    }
+
+    let (failures, duration) = mgr.register_failure(target.clone(), now).unwrap();
+    assert_eq!(failures, FAILURES_BEFORE_LOCKOUT);
+    assert!(mgr.is_locked_out(target, now));
+    duration
+}
+
+#[test]
+fn test_lockout_after_failures() {
+    let mgr = LockoutManager::new();
+    let now = Instant::now();
+
+    assert_eq!(fail_until_locked(&mgr, &address(), now), BASE_LOCKOUT_DURATION);
+    assert!(!mgr.is_locked_out(&username(), now));
+    assert_eq!(mgr.snapshot(now), vec![(address(), BASE_LOCKOUT_DURATION.as_secs())]);
+
+    // Failures during an active lockout are not counted.
+    assert_eq!(mgr.register_failure(address(), now), None);
+
+    let later = now + BASE_LOCKOUT_DURATION;
+    assert!(!mgr.is_locked_out(&address(), later));
+    assert_eq!(mgr.expire(later), vec![address()]);
+    assert_eq!(mgr.expire(later), vec![]);
+    assert!(mgr.snapshot(later).is_empty());
+}
+
+#[test]
+fn test_username_from_any_source() {
+    let mgr = LockoutManager::new();
+    let now = Instant::now();
+    let any_source = LockoutTarget::Username(String::from("pedro"));
+
+    // A username from any source takes more failures to be locked out than from a single source.
+    for _ in 1..USERNAME_FAILURES_BEFORE_LOCKOUT {
+        assert_eq!(mgr.register_failure(any_source.clone(), now), None);
+    }
+    assert!(!mgr.is_locked_out(&any_source, now));
+
+    let (failures, duration) = mgr.register_failure(any_source.clone(), now).unwrap();
+    assert_eq!(failures, USERNAME_FAILURES_BEFORE_LOCKOUT);
+    assert_eq!(duration, BASE_LOCKOUT_DURATION);
+    assert!(mgr.is_locked_out(&any_source, now));
+    assert!(!mgr.is_locked_out(&username(), now));
+
+    fail_until_locked(&mgr, &username(), now);
+    assert_eq!(mgr.clear(Some(&any_source), now), 2);
+    assert!(mgr.snapshot(now).is_empty());
+}
+
+#[test]
+fn test_success_resets_failures() {
+    let mgr = LockoutManager::new();
+    let now = Instant::now();
+
+    for _ in 1..FAILURES_BEFORE_LOCKOUT {
+        mgr.register_failure(username(), now);
+    }
+
+    mgr.register_success(&username());
+    assert_eq!(mgr.register_failure(username(), now), None);
+    assert!(!mgr.is_locked_out(&username(), now));
+}
+
+#[test]
+fn test_lockout_escalation() {
+    let mgr = LockoutManager::new();
+    let mut now = Instant::now();
+
+    let mut expected = BASE_LOCKOUT_DURATION;
+    for _ in 0..12 {
+        let duration = fail_until_locked(&mgr, &username(), now);
+        assert_eq!(duration, expected);
+        now += duration;
+        assert_eq!(mgr.expire(now), vec![username()]);
+        expected = (expected * 2).min(MAX_LOCKOUT_DURATION);
+    }
+
+    assert_eq!(expected, MAX_LOCKOUT_DURATION);
+
+    // Once forgotten, the escalation starts over.
+    now += FORGET_AFTER;
+    mgr.expire(now);
+    assert_eq!(fail_until_locked(&mgr, &username(), now), BASE_LOCKOUT_DURATION);
+}
+
+#[test]
+fn test_clear_lockouts() {
+    let mgr = LockoutManager::new();
+    let now = Instant::now();
+
+    fail_until_locked(&mgr, &address(), now);
+    fail_until_locked(&mgr, &username(), now);
+
+    assert_eq!(mgr.clear(Some(&LockoutTarget::Username(String::from("juan"))), now), 0);
+    assert_eq!(mgr.clear(Some(&username()), now), 1);
+    assert!(!mgr.is_locked_out(&username(), now));
+    assert!(mgr.is_locked_out(&address(), now));
+
+    fail_until_locked(&mgr, &username(), now);
+    assert_eq!(mgr.clear(None, now), 2);
+    assert!(mgr.snapshot(now).is_empty());
+
+    // Clearing a username clears its lockouts from every source.
+    let other_source = LockoutTarget::UnixUser(1000);
+    fail_until_locked(&mgr, &username(), now);
+    fail_until_locked(&mgr, &username_from(other_source.clone()), now);
+    fail_until_locked(&mgr, &other_source, now);
+    assert_eq!(mgr.clear(Some(&LockoutTarget::Username(String::from("pedro"))), now), 2);
+    assert_eq!(mgr.snapshot(now), vec![(other_source, BASE_LOCKOUT_DURATION.as_secs())]);
+}
+
+#[test]
+fn test_max_records() {
+    let mgr = LockoutManager::new();
+    let now = Instant::now();
+
+    fail_until_locked(&mgr, &address(), now);
+    for i in 1..MAX_RECORDS as u32 {
+        let later = now + Duration::from_micros(i as u64);
+        assert_eq!(mgr.register_failure(LockoutTarget::UnixUser(i), later), None);
+    }
+    assert_eq!(mgr.len(), MAX_RECORDS);
+
+    // The least recently active record not under a lockout is forgotten to make room.
+    let later = now + Duration::from_secs(1);
+    fail_until_locked(&mgr, &username(), later);
+    assert_eq!(mgr.len(), MAX_RECORDS);
+    assert!(mgr.is_locked_out(&address(), later));
+
+    // The forgotten record's failures start over, so these don't add up to a lockout.
+    for _ in 1..FAILURES_BEFORE_LOCKOUT {
+        assert_eq!(mgr.register_failure(LockoutTarget::UnixUser(1), later), None);
+    }
+    assert_eq!(mgr.len(), MAX_RECORDS);
+}
+
+#[test]
+fn test_max_records_all_locked_out() {
+    let mgr = LockoutManager::new();
+    let now = Instant::now();
+
+    for i in 0..MAX_RECORDS as u32 {
+        for _ in 0..FAILURES_BEFORE_LOCKOUT {
+            mgr.register_failure(LockoutTarget::UnixUser(i), now);
+        }
+    }
+    assert_eq!(mgr.snapshot(now).len(), MAX_RECORDS);
+
+    // With every record under a lockout, a new target's failures aren't recorded.
+    for _ in 0..FAILURES_BEFORE_LOCKOUT {
+        assert_eq!(mgr.register_failure(address(), now), None);
+    }
+    assert!(!mgr.is_locked_out(&address(), now));
+    assert_eq!(mgr.len(), MAX_RECORDS);
+
+    // Once the lockouts end, the least recently active record makes room for the new target.
+    let later = now + BASE_LOCKOUT_DURATION;
+    fail_until_locked(&mgr, &address(), later);
+    assert_eq!(mgr.len(), MAX_RECORDS);
+}
+
+#[tokio::test]
+async fn test_unix_user_target() {
+    let mgr = LockoutManager::new();
+    let now = Instant::now();
+
+    let unix_user = LockoutTarget::UnixUser(1000);
+    fail_until_locked(&mgr, &unix_user, now);
+    assert!(!mgr.is_locked_out(&LockoutTarget::Address(IpAddr::V4(Ipv4Addr::LOCALHOST)), now));
+
+    let mut bytes = Vec::new();
+    unix_user.write(&mut bytes).await.unwrap();
+    assert_eq!(bytes, vec![0x02, 0x00, 0x00, 0x03, 0xE8]);
+    assert_eq!(LockoutTarget::read(&mut &bytes[..]).await.unwrap(), unix_user);
+}
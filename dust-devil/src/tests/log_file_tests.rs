@@ -0,0 +1,92 @@
+use std::{fs, path::PathBuf};
+
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    log_file::{rotated_path, LogFile, LogRotation},
+    logger::{LogMaintenance, LogWriter},
+};
+
+fn log_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dust-devil-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn file_len(path: &str) -> Option<u64> {
+    fs::metadata(path).ok().map(|m| m.len())
+}
+
+#[tokio::test]
+async fn test_rotate_by_size() {
+    let dir = log_dir("rotate-size");
+    let path = dir.join("logs.txt").to_string_lossy().into_owned();
+    let rotation = LogRotation {
+        max_size: Some(100),
+        keep: Some(2),
+        ..Default::default()
+    };
+
+    let mut log_file = LogFile::open(false, &path, rotation).await.unwrap();
+    for i in 0..3u64 {
+        log_file.write_all(&vec![b'a'; 150 + i as usize]).await.unwrap();
+        let maintenance = log_file.maintenance_needed().await;
+        assert_eq!(maintenance, LogMaintenance::Rotate);
+        log_file.maintain(maintenance).await.unwrap();
+    }
+
+    log_file.write_all(b"hello").await.unwrap();
+    log_file.close().await.unwrap();
+
+    assert_eq!(file_len(&path), Some(5));
+    assert_eq!(file_len(&rotated_path(&path, 1, false)), Some(152));
+    assert_eq!(file_len(&rotated_path(&path, 2, false)), Some(151));
+    assert_eq!(file_len(&rotated_path(&path, 3, false)), None);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_rotate_compressed() {
+    let dir = log_dir("rotate-compressed");
+    let path = dir.join("logs.txt").to_string_lossy().into_owned();
+    let rotation = LogRotation {
+        max_size: Some(1000),
+        compress: true,
+        ..Default::default()
+    };
+
+    let mut log_file = LogFile::open(false, &path, rotation).await.unwrap();
+    log_file.write_all(&b"Some event happened\n".repeat(100)).await.unwrap();
+    log_file.maintain(LogMaintenance::Rotate).await.unwrap();
+    log_file.close().await.unwrap();
+
+    assert_eq!(file_len(&path), Some(0));
+    assert_eq!(file_len(&rotated_path(&path, 1, false)), None);
+    let compressed = fs::read(rotated_path(&path, 1, true)).unwrap();
+    assert_eq!(compressed[..2], [0x1f, 0x8b]);
+    assert!(compressed.len() < 2000);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_reopen() {
+    let dir = log_dir("reopen");
+    let path = dir.join("logs.txt").to_string_lossy().into_owned();
+    let moved_path = dir.join("logs.old").to_string_lossy().into_owned();
+
+    let mut log_file = LogFile::open(false, &path, LogRotation::default()).await.unwrap();
+    log_file.write_all(b"before\n").await.unwrap();
+    log_file.flush().await.unwrap();
+
+    fs::rename(&path, &moved_path).unwrap();
+    log_file.maintain(LogMaintenance::Reopen).await.unwrap();
+    log_file.write_all(b"after\n").await.unwrap();
+    log_file.close().await.unwrap();
+
+    assert_eq!(fs::read_to_string(&moved_path).unwrap(), "before\n");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "after\n");
+
+    let _ = fs::remove_dir_all(&dir);
+}
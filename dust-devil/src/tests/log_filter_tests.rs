@@ -0,0 +1,226 @@
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+use dust_devil_core::{
+    log_filter::{parse_sink_filter, EventStreamFilter, LogFilter, LogFilterParseError, LogSink},
+    logging::{EventData, EventSeverity},
+};
+
+use crate::{
+    clients::ClientRegistry,
+    log_filters::{LogFilterManager, StreamFilter},
+};
+
+fn filter(s: &str) -> LogFilter {
+    s.parse().unwrap()
+}
+
+fn address() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1080))
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(filter("all"), LogFilter::default());
+    assert_eq!(filter(""), LogFilter::default());
+
+    assert_eq!(
+        filter("severity=notice, exclude=client_bytes_*+client_source_shutdown,clients=3+7+3,users=pedro+ju\\+an"),
+        LogFilter {
+            min_severity: EventSeverity::Notice,
+            include: vec![],
+            exclude: vec!["client_bytes_*".to_string(), "client_source_shutdown".to_string()],
+            client_ids: vec![3, 7],
+            usernames: vec!["pedro".to_string(), "ju+an".to_string()],
+        }
+    );
+
+    assert_eq!(
+        "severity=loud".parse::<LogFilter>(),
+        Err(LogFilterParseError::InvalidSeverity("loud".to_string()))
+    );
+    assert_eq!(
+        "include=Client*".parse::<LogFilter>(),
+        Err(LogFilterParseError::InvalidEventType("Client*".to_string()))
+    );
+    assert_eq!(
+        "clients=3+x".parse::<LogFilter>(),
+        Err(LogFilterParseError::InvalidClientId("x".to_string()))
+    );
+    assert_eq!(
+        "users=".parse::<LogFilter>(),
+        Err(LogFilterParseError::InvalidUsername(String::new()))
+    );
+    assert_eq!(
+        "verbosity=info".parse::<LogFilter>(),
+        Err(LogFilterParseError::InvalidSetting("verbosity=info".to_string()))
+    );
+}
+
+#[test]
+fn test_display_roundtrip() {
+    for s in [
+        "all",
+        "severity=warning",
+        "include=client_*,exclude=client_bytes_sent",
+        "severity=info,clients=1+2,users=a\\,b+c\\\\d",
+    ] {
+        assert_eq!(filter(s).to_string(), s);
+        assert_eq!(filter(&filter(s).to_string()), filter(s));
+    }
+}
+
+#[test]
+fn test_parse_sink_filter() {
+    assert_eq!(parse_sink_filter("severity=info"), Ok((None, filter("severity=info"))));
+    assert_eq!(
+        parse_sink_filter("File:severity=info"),
+        Ok((Some(LogSink::File), filter("severity=info")))
+    );
+    assert_eq!(
+        parse_sink_filter("journald:all"),
+        Ok((Some(LogSink::Journald), LogFilter::default()))
+    );
+    assert_eq!(parse_sink_filter("users=a:b"), Ok((None, filter("users=a:b"))));
+    assert!(parse_sink_filter("printer:all").is_err());
+}
+
+#[test]
+fn test_matches() {
+    let bytes_sent = EventData::ClientBytesSent(5, 1024);
+    let connected = EventData::NewClientConnectionAccepted(5, address());
+    let shutdown = EventData::SandstormRequestedShutdown(1);
+
+    let f = filter("severity=info");
+    assert!(!f.matches(&bytes_sent, None));
+    assert!(f.matches(&connected, None));
+    assert!(f.matches(&shutdown, None));
+
+    let f = filter("exclude=client_bytes_*");
+    assert!(!f.matches(&bytes_sent, None));
+    assert!(f.matches(&connected, None));
+
+    let f = filter("include=new_client_connection_accepted");
+    assert!(!f.matches(&bytes_sent, None));
+    assert!(f.matches(&connected, None));
+    assert!(!f.matches(&shutdown, None));
+
+    let f = filter("clients=6");
+    assert!(!f.matches(&connected, None));
+    assert!(f.matches(&EventData::ClientBytesSent(6, 1), None));
+    assert!(f.matches(&shutdown, None));
+
+    let f = filter("users=pedro");
+    assert!(!f.matches(&bytes_sent, None));
+    assert!(!f.matches(&bytes_sent, Some("juan")));
+    assert!(f.matches(&bytes_sent, Some("pedro")));
+    assert!(f.matches(&shutdown, None));
+}
+
+#[test]
+fn test_sink_filters() {
+    let initial = vec![
+        (None, filter("severity=info")),
+        (Some(LogSink::File), filter("users=pedro")),
+        (Some(LogSink::Syslog), filter("severity=error")),
+        (None, filter("severity=notice")),
+    ];
+
+    let mut manager = LogFilterManager::new();
+    let mut stdout = manager.register(LogSink::Stdout, &initial);
+    let mut file = manager.register(LogSink::File, &initial);
+    let mut syslog1 = manager.register(LogSink::Syslog, &initial);
+    let mut syslog2 = manager.register(LogSink::Syslog, &initial);
+
+    assert_eq!(
+        manager.list(),
+        vec![
+            (LogSink::Stdout, filter("severity=notice")),
+            (LogSink::File, filter("severity=notice")),
+            (LogSink::Syslog, filter("severity=notice")),
+        ]
+    );
+
+    assert!(!manager.set(LogSink::Journald, LogFilter::default()));
+    assert!(manager.set(LogSink::File, filter("users=pedro")));
+    assert!(manager.set(LogSink::Syslog, filter("severity=error")));
+
+    let events = [
+        EventData::ClientAuthenticatedWithUserpass(5, "pedro".to_string(), true),
+        EventData::ClientBytesSent(5, 1024),
+        EventData::ClientBytesSent(6, 1024),
+        EventData::ClientConnectionFinished(5, 1024, 0, Ok(())),
+        EventData::ClientBytesSent(5, 1024),
+    ];
+
+    let results: Vec<_> = events.iter().map(|event| file.accepts(event)).collect();
+    assert_eq!(results, vec![true, true, false, true, false]);
+
+    for event in &events {
+        assert!(!stdout.accepts(event));
+        assert!(!syslog1.accepts(event));
+        assert!(!syslog2.accepts(event));
+    }
+}
+
+#[test]
+fn test_parse_stream_filter() {
+    let listener = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 1080));
+
+    assert_eq!("all".parse(), Ok(EventStreamFilter::default()));
+    assert_eq!(
+        "severity=info, listeners=0.0.0.0:1080+127.0.0.1:1080+0.0.0.0:1080".parse(),
+        Ok(EventStreamFilter {
+            filter: filter("severity=info"),
+            listeners: vec![listener, address()],
+        })
+    );
+    assert_eq!(
+        "listeners=0.0.0.0".parse::<EventStreamFilter>(),
+        Err(LogFilterParseError::InvalidListener("0.0.0.0".to_string()))
+    );
+    assert_eq!(
+        "users=pedro,verbosity=info".parse::<EventStreamFilter>(),
+        Err(LogFilterParseError::InvalidSetting("verbosity=info".to_string()))
+    );
+
+    for s in [
+        "all",
+        "listeners=127.0.0.1:1080",
+        "severity=info,users=a\\,b,listeners=0.0.0.0:1080+127.0.0.1:1080",
+    ] {
+        let parsed: EventStreamFilter = s.parse().unwrap();
+        assert_eq!(parsed.to_string(), s);
+    }
+}
+
+#[test]
+fn test_stream_filter() {
+    let listener = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 1080));
+    let registry = ClientRegistry::new();
+    registry.register(5, address(), Some(listener)).set_username("pedro".to_string());
+    registry.register(6, address(), Some(address()));
+
+    let mut stream_filter = StreamFilter::new("users=pedro,listeners=0.0.0.0:1080".parse().unwrap());
+    let events = [
+        EventData::ClientBytesSent(5, 1024),
+        EventData::ClientBytesSent(6, 1024),
+        EventData::ClientAuthenticatedWithUserpass(6, "pedro".to_string(), true),
+        EventData::ClientBytesSent(6, 1024),
+        EventData::ClientBytesSent(7, 1024),
+        EventData::SandstormRequestedShutdown(1),
+        EventData::ClientConnectionFinished(5, 1024, 0, Ok(())),
+    ];
+
+    let results: Vec<_> = events.iter().map(|event| stream_filter.accepts(event, &registry)).collect();
+    assert_eq!(results, vec![true, false, false, false, false, true, true]);
+
+    stream_filter.set("listeners=127.0.0.1:1080".parse().unwrap());
+    assert!(stream_filter.accepts(&EventData::ClientBytesSent(6, 1024), &registry));
+    assert!(!stream_filter.accepts(&EventData::ClientBytesSent(5, 1024), &registry));
+
+    // A client first seen at its connection hadn't authenticated yet, regardless of the registry.
+    registry.register(8, address(), Some(listener)).set_username("pedro".to_string());
+    stream_filter.set("users=pedro".parse().unwrap());
+    assert!(!stream_filter.accepts(&EventData::NewClientConnectionAccepted(8, address()), &registry));
+    assert!(stream_filter.accepts(&EventData::ClientAuthenticatedWithUserpass(8, "pedro".to_string(), true), &registry));
+}
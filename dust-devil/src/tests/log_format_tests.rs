@@ -0,0 +1,114 @@
+use std::{
+    io::{Error, ErrorKind},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+};
+
+use dust_devil_core::{
+    logging::{ClientSummary, Event, EventData},
+    socks5::{SocksRequest, SocksRequestAddress},
+};
+use time::UtcOffset;
+
+use crate::log_format::{write_event, write_lost_events, LogFormat};
+
+const TIMESTAMP: i64 = 1709317800;
+
+fn format(format: LogFormat, data: EventData, utc_offset: UtcOffset) -> String {
+    let mut out = Vec::new();
+    write_event(&mut out, format, &Event::new(TIMESTAMP, data), utc_offset);
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_text() {
+    let result = format(LogFormat::Text, EventData::ClientBytesSent(5, 1024), UtcOffset::UTC);
+    assert_eq!(result, "[2024-03-01 18:30:00] Client 5 sent 1024 bytes\n");
+}
+
+#[test]
+fn test_json() {
+    let result = format(LogFormat::Json, EventData::ClientBytesSent(5, 1024), UtcOffset::UTC);
+    assert_eq!(
+        result,
+        concat!(
+            "{\"timestamp\":1709317800,\"time\":\"2024-03-01T18:30:00+00:00\",\"type\":\"client_bytes_sent\",",
+            "\"message\":\"Client 5 sent 1024 bytes\",\"client_id\":5,\"bytes\":1024}\n"
+        )
+    );
+
+    let address = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 7), 5000));
+    let offset = UtcOffset::from_hms(-3, -30, 0).unwrap();
+    let result = format(LogFormat::Json, EventData::NewClientConnectionAccepted(8, address), offset);
+    assert_eq!(
+        result,
+        concat!(
+            "{\"timestamp\":1709317800,\"time\":\"2024-03-01T15:00:00-03:30\",\"type\":\"new_client_connection_accepted\",",
+            "\"message\":\"New client connection from 10.0.0.7:5000 assigned ID 8\",\"client_id\":8,\"address\":\"10.0.0.7:5000\"}\n"
+        )
+    );
+}
+
+#[test]
+fn test_json_fields() {
+    let error = Error::new(ErrorKind::ConnectionReset, "reset \"by\" peer\n");
+    let result = format(
+        LogFormat::Json,
+        EventData::ClientConnectionFinished(3, 10, 20, Err(error)),
+        UtcOffset::UTC,
+    );
+    assert!(result.ends_with(",\"client_id\":3,\"bytes_sent\":10,\"bytes_received\":20,\"error\":\"reset \\\"by\\\" peer\\n\"}\n"));
+
+    let result = format(
+        LogFormat::Json,
+        EventData::ClientConnectionFinished(3, 10, 20, Ok(())),
+        UtcOffset::UTC,
+    );
+    assert!(result.ends_with(",\"client_id\":3,\"bytes_sent\":10,\"bytes_received\":20}\n"));
+
+    let request = SocksRequest::new(SocksRequestAddress::Domainname("example.com".to_string()), 443);
+    let result = format(LogFormat::Json, EventData::ClientSocksRequest(4, request), UtcOffset::UTC);
+    assert!(result.ends_with(",\"client_id\":4,\"destination_type\":\"domainname\",\"destination\":\"example.com\",\"port\":443}\n"));
+
+    let result = format(
+        LogFormat::Json,
+        EventData::ClientAuthenticatedWithUserpass(2, "pe\u{1}dro".to_string(), false),
+        UtcOffset::UTC,
+    );
+    assert!(result.ends_with(",\"client_id\":2,\"username\":\"pe\\u0001dro\",\"success\":false}\n"));
+
+    let result = format(
+        LogFormat::Json,
+        EventData::ClientConnectionAcceptFailed(None, Error::from(ErrorKind::Other)),
+        UtcOffset::UTC,
+    );
+    assert!(result.ends_with(",\"listener\":null,\"error\":\"other error\"}\n"));
+
+    let summary = ClientSummary {
+        username: None,
+        destination: Some("example.com".to_string()),
+        connect_latency_micros: None,
+        duration_micros: 1_500_000,
+        bytes_sent: 0,
+        bytes_received: 0,
+        failed: true,
+    };
+    let result = format(LogFormat::Json, EventData::ClientConnectionSummary(6, summary), UtcOffset::UTC);
+    assert!(result.contains("\"message\":\"Client 6 summary: no user, destination example.com, lasted 1.5s, failed\""));
+    assert!(result.ends_with(concat!(
+        ",\"client_id\":6,\"username\":null,\"destination\":\"example.com\",\"connect_latency_micros\":null,",
+        "\"duration_micros\":1500000,\"bytes_sent\":0,\"bytes_received\":0,\"failed\":true}\n"
+    )));
+}
+
+#[test]
+fn test_lost_events() {
+    let mut out = Vec::new();
+    write_lost_events(&mut out, LogFormat::Text, 7, UtcOffset::UTC);
+    assert_eq!(String::from_utf8(out).unwrap(), "ERROR!! 7 events lost due to slowdown!\n");
+
+    let mut out = Vec::new();
+    write_lost_events(&mut out, LogFormat::Json, 7, UtcOffset::UTC);
+    let result = String::from_utf8(out).unwrap();
+    assert!(result.starts_with("{\"timestamp\":"));
+    assert!(result.ends_with(",\"type\":\"events_lost\",\"count\":7}\n"));
+}
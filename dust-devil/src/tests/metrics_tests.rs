@@ -0,0 +1,248 @@
+use std::{
+    io::{Error, ErrorKind},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+};
+
+use dust_devil_core::{
+    logging::{ClientSummary, EventData},
+    sandstorm::{BreakdownEntry, Histogram, Metrics},
+};
+
+use crate::metrics::{
+    write_openmetrics, AuthFailure, ConnectionFailure, ListenerKind, ServerBreakdown, ServerMetrics, MAX_TRACKED_DESTINATIONS,
+    OTHER_DESTINATIONS,
+};
+
+fn address(port: u16) -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port))
+}
+
+fn recorded(events: Vec<EventData>) -> ServerMetrics {
+    let mut metrics = ServerMetrics::default();
+    for event in &events {
+        metrics.record(event);
+    }
+
+    metrics
+}
+
+#[test]
+fn test_record() {
+    let metrics = recorded(vec![
+        EventData::NewSocks5Socket(address(1080)),
+        EventData::NewSocks5Socket(address(1081)),
+        EventData::RemovedSocks5Socket(address(1081)),
+        EventData::NewSandstormSocket(address(2222)),
+        EventData::NewSandstormUnixSocket("/run/sandstorm.sock".to_string()),
+        EventData::NewMetricsSocket(address(9180)),
+        EventData::NewClientConnectionAccepted(1, address(5000)),
+        EventData::NewClientConnectionAccepted(2, address(5001)),
+        EventData::ClientNoAcceptableAuthMethod(1),
+        EventData::ClientConnectionFinished(1, 0, 0, Ok(())),
+        EventData::ClientAuthenticatedWithUserpass(2, "pedro".to_string(), false),
+        EventData::ClientAuthenticatedWithUserpass(2, "pedro".to_string(), true),
+        EventData::ClientDnsLookup(2, "example.com".to_string()),
+        EventData::ClientFailedToConnectToDestination(2),
+        EventData::ClientBytesSent(2, 100),
+        EventData::ClientBytesReceived(2, 300),
+        EventData::ClientConnectionFinished(2, 100, 300, Err(Error::from(ErrorKind::ConnectionReset))),
+        EventData::ConnectionRejectedByIpFilter(address(1080), address(5002)),
+        EventData::NewSandstormConnectionAccepted(1, address(6000)),
+        EventData::SandstormAuthenticatedAs(1, "admin".to_string(), false),
+    ]);
+
+    assert_eq!(metrics.current_client_connections, 0);
+    assert_eq!(metrics.historic_client_connections, 2);
+    assert_eq!(metrics.dns_lookups, 1);
+    assert_eq!(metrics.auth_failures(AuthFailure::Socks5InvalidCredentials), 1);
+    assert_eq!(metrics.auth_failures(AuthFailure::Socks5NoAcceptableMethod), 1);
+    assert_eq!(metrics.auth_failures(AuthFailure::SandstormInvalidCredentials), 1);
+    assert_eq!(metrics.connection_failures(ConnectionFailure::DestinationUnreachable), 1);
+    assert_eq!(metrics.connection_failures(ConnectionFailure::IoError), 1);
+    assert_eq!(metrics.connection_failures(ConnectionFailure::RejectedByIpFilter), 1);
+    assert_eq!(metrics.connection_failures(ConnectionFailure::AcceptFailed), 0);
+    assert_eq!(metrics.listeners(ListenerKind::Socks5), 1);
+    assert_eq!(metrics.listeners(ListenerKind::Sandstorm), 2);
+    assert_eq!(metrics.listeners(ListenerKind::Metrics), 1);
+
+    assert_eq!(
+        metrics.sandstorm_metrics(),
+        Metrics {
+            current_client_connections: 0,
+            historic_client_connections: 2,
+            client_bytes_sent: 100,
+            client_bytes_received: 300,
+            current_sandstorm_connections: 1,
+            historic_sandstorm_connections: 1,
+            start_time: 0,
+            counter_epoch: 0,
+        }
+    );
+
+    let metrics = recorded(vec![
+        EventData::Socks5SocketInherited(address(1080)),
+        EventData::SandstormSocketInherited(address(2222)),
+        EventData::ListenersHandedOff(2),
+    ]);
+    assert_eq!(metrics.listeners, [0; 3]);
+}
+
+#[test]
+fn test_openmetrics() {
+    let metrics = recorded(vec![
+        EventData::NewSocks5Socket(address(1080)),
+        EventData::NewClientConnectionAccepted(1, address(5000)),
+        EventData::ClientBytesSent(1, 1024),
+        EventData::ClientRequestedUnsupportedCommand(1, 2),
+    ]);
+
+    let mut out = String::new();
+    write_openmetrics(&mut out, &metrics).unwrap();
+
+    assert!(out.starts_with(concat!(
+        "# TYPE dust_devil_client_connections gauge\n",
+        "# HELP dust_devil_client_connections Socks5 clients currently connected.\n",
+        "dust_devil_client_connections 1\n",
+    )));
+    assert!(out.contains(concat!(
+        "# TYPE dust_devil_client_sent_bytes counter\n",
+        "# UNIT dust_devil_client_sent_bytes bytes\n",
+        "# HELP dust_devil_client_sent_bytes Bytes sent by socks5 clients.\n",
+        "dust_devil_client_sent_bytes_total 1024\n",
+        "dust_devil_client_sent_bytes_created 0\n",
+    )));
+    assert!(out.contains("dust_devil_auth_failures_total{protocol=\"sandstorm\",reason=\"invalid_credentials\"} 0\n"));
+    assert!(out.contains("dust_devil_connection_failures_total{reason=\"unsupported_command\"} 1\n"));
+    assert!(out.contains("dust_devil_listeners{protocol=\"socks5\"} 1\n"));
+    assert!(out.contains("dust_devil_start_time_seconds 0\n"));
+    assert!(out.ends_with("\n# EOF\n"));
+
+    // Every sample must belong to the family declared right before it.
+    let mut family = "";
+    for line in out.lines() {
+        if let Some(rest) = line.strip_prefix("# TYPE ") {
+            family = rest.split(' ').next().unwrap();
+        } else if !line.starts_with('#') {
+            assert!(line.starts_with(family), "{line} is not in family {family}");
+        }
+    }
+}
+
+#[test]
+fn test_restore() {
+    let mut previous = recorded(vec![
+        EventData::NewClientConnectionAccepted(1, address(5000)),
+        EventData::ClientBytesSent(1, 100),
+        EventData::ClientDnsLookup(1, "example.com".to_string()),
+        EventData::ClientFailedToConnectToDestination(1),
+        EventData::NewSocks5Socket(address(1080)),
+    ]);
+    previous.counter_epoch = 1000;
+
+    let mut metrics = ServerMetrics::starting_at(2000);
+    metrics.record(&EventData::NewClientConnectionAccepted(1, address(5000)));
+    metrics.restore(&previous);
+
+    assert_eq!(metrics.start_time, 2000);
+    assert_eq!(metrics.counter_epoch, 1000);
+    assert_eq!(metrics.current_client_connections, 1);
+    assert_eq!(metrics.historic_client_connections, 2);
+    assert_eq!(metrics.client_bytes_sent, 100);
+    assert_eq!(metrics.dns_lookups, 1);
+    assert_eq!(metrics.connection_failures(ConnectionFailure::DestinationUnreachable), 1);
+    assert_eq!(metrics.listeners(ListenerKind::Socks5), 0);
+}
+
+fn summary(username: Option<&str>, destination: &str, latency: Option<u64>, bytes_sent: u64, bytes_received: u64) -> EventData {
+    EventData::ClientConnectionSummary(
+        0,
+        ClientSummary {
+            username: username.map(|u| u.to_string()),
+            destination: Some(destination.to_string()),
+            connect_latency_micros: latency,
+            duration_micros: 2_000_000,
+            bytes_sent,
+            bytes_received,
+            failed: latency.is_none(),
+        },
+    )
+}
+
+#[test]
+fn test_histogram() {
+    let mut histogram = Histogram::new(&[10, 100]);
+    for value in [0, 10, 11, 100, 101, 5000] {
+        histogram.record(value);
+    }
+
+    assert_eq!(histogram.bounds(), &[10, 100]);
+    assert_eq!(histogram.counts(), &[2, 2, 2]);
+    assert_eq!(histogram.count(), 6);
+    assert_eq!(histogram.sum(), 5222);
+}
+
+#[test]
+fn test_breakdown() {
+    let mut breakdown = ServerBreakdown::default();
+    for event in [
+        summary(Some("pedro"), "example.com", Some(2_000), 100, 1000),
+        summary(Some("pedro"), "example.com", Some(30_000), 100, 1000),
+        summary(Some("carlos"), "10.0.0.1", None, 0, 0),
+        summary(None, "example.org", Some(2_000), 5000, 0),
+        EventData::ClientBytesSent(0, 12345),
+    ] {
+        breakdown.record(&event);
+    }
+
+    let result = breakdown.top(0);
+    assert_eq!(
+        result.users,
+        vec![
+            BreakdownEntry {
+                name: "pedro".to_string(),
+                connections: 2,
+                failures: 0,
+                bytes_sent: 200,
+                bytes_received: 2000,
+            },
+            BreakdownEntry {
+                name: "carlos".to_string(),
+                connections: 1,
+                failures: 1,
+                bytes_sent: 0,
+                bytes_received: 0,
+            },
+        ]
+    );
+
+    let names: Vec<&str> = result.destinations.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, vec!["example.org", "example.com", "10.0.0.1"]);
+    assert_eq!(result.connect_latency.count(), 3);
+    assert_eq!(result.connect_latency.counts()[1], 2);
+    assert_eq!(result.connection_duration.count(), 4);
+
+    let result = breakdown.top(1);
+    assert_eq!(result.users.len(), 1);
+    assert_eq!(result.users[0].name, "pedro");
+    assert_eq!(result.destinations.len(), 1);
+    assert_eq!(result.destinations[0].name, "example.org");
+}
+
+#[test]
+fn test_breakdown_destination_limit() {
+    let mut breakdown = ServerBreakdown::default();
+    for i in 0..MAX_TRACKED_DESTINATIONS {
+        breakdown.record(&summary(None, &format!("host{i}"), Some(1), 1, 0));
+    }
+
+    breakdown.record(&summary(None, "host0", Some(1), 1, 0));
+    breakdown.record(&summary(None, "overflow1", Some(1), 10, 0));
+    breakdown.record(&summary(None, "overflow2", Some(1), 10, 0));
+
+    let result = breakdown.top(0);
+    assert_eq!(result.destinations.len(), MAX_TRACKED_DESTINATIONS + 1);
+    assert_eq!(result.destinations[0].name, OTHER_DESTINATIONS);
+    assert_eq!(result.destinations[0].connections, 2);
+    assert_eq!(result.destinations[1].name, "host0");
+    assert_eq!(result.destinations[1].connections, 2);
+}
@@ -1,2 +1,19 @@
 mod args_tests;
+mod clients_tests;
+mod config_tests;
+mod event_history_tests;
+mod gzip_tests;
+#[cfg(unix)]
+mod handoff_tests;
+mod handshake_tests;
+mod ip_filter_tests;
+mod lockout_tests;
+mod log_file_tests;
+mod log_filter_tests;
+mod log_format_tests;
+mod metrics_tests;
+mod sandstorm_tests;
+mod socks5_tests;
+mod system_log_tests;
+mod systemd_tests;
 mod users_tests;
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use dust_devil_core::{
+    listener_profile::ListenerProfile,
+    log_filter::EventStreamFilter,
+    sandstorm::{
+        AddSocks5SocketRequest, CurrentMetricsResponse, EventReplay, EventStreamConfigRequest, EventStreamConfigResponse, FramedRequest,
+        ListSocks5SocketsResponse, LockoutTarget, MeowRequest, Metrics, SandstormCapabilities, SandstormCommandType, SandstormHandshakeRef,
+        SandstormPermissions, ShutdownRequest, Version1,
+    },
+    serialize::{ByteRead, ByteWrite},
+    users::UserRole,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::mpsc,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    context::{SandstormContext, ServerState},
+    ip_filters::IpFilterManager,
+    sandstorm::{handle_sandstorm, EventLagPolicy},
+    users::UserManager,
+};
+
+/// Runs a Sandstorm session for an admin with the given permissions and protocol version and
+/// capabilities, sending it the given requests after the handshake, and returns everything the
+/// server answered.
+async fn run_session(version: u8, capabilities: SandstormCapabilities, permissions: SandstormPermissions, requests: &[u8]) -> Vec<u8> {
+    let users = UserManager::new();
+    users.insert_or_update(String::from("pedro"), String::from("1234"), UserRole::Admin, permissions);
+
+    let (message_tx, _message_rx) = mpsc::channel(4);
+    let state = Arc::new(ServerState::new(
+        users,
+        IpFilterManager::new(),
+        true,
+        true,
+        0x2000,
+        message_tx,
+        None,
+    ));
+    let source = LockoutTarget::Address("127.0.0.1".parse().unwrap());
+    let context = SandstormContext::create(1, source, &state, None, EventLagPolicy::Disconnect);
+
+    let (mut client, server) = tokio::io::duplex(0x1000);
+    let server_task = tokio::spawn(handle_sandstorm(server, context, CancellationToken::new()));
+
+    let mut handshake = SandstormHandshakeRef::new("pedro", "1234");
+    handshake.version = version;
+    handshake.capabilities = capabilities;
+    let mut bytes = Vec::new();
+    handshake.write(&mut bytes).await.unwrap();
+    bytes.extend_from_slice(requests);
+
+    client.write_all(&bytes).await.unwrap();
+    client.shutdown().await.unwrap();
+
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).await.unwrap();
+    server_task.await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn test_denied_request_payload_is_skipped() {
+    let mut requests = Vec::new();
+    Version1(ShutdownRequest(None)).write(&mut requests).await.unwrap();
+    MeowRequest.write(&mut requests).await.unwrap();
+
+    let response = run_session(1, SandstormCapabilities::none(), SandstormPermissions::none(), &requests).await;
+    assert_eq!(response, vec![0x00, 0x13, 0x00, 0xFF, b'M', b'E', b'O', b'W']);
+
+    let mut requests = Vec::new();
+    FramedRequest(ShutdownRequest(Some(0x01FF))).write(&mut requests).await.unwrap();
+    FramedRequest(MeowRequest).write(&mut requests).await.unwrap();
+
+    let response = run_session(2, SandstormCapabilities::none(), SandstormPermissions::none(), &requests).await;
+    assert_eq!(response[6..], [0x13, 0x00, 0xFF, b'M', b'E', b'O', b'W']);
+}
+
+#[tokio::test]
+async fn test_version1_shutdown() {
+    let mut bytes = Vec::new();
+    Version1(ShutdownRequest(Some(30))).write(&mut bytes).await.unwrap();
+    assert_eq!(bytes, vec![0x00]);
+
+    let mut reader = &bytes[1..];
+    let result = Version1::<ShutdownRequest>::read(&mut reader).await.unwrap().0;
+    assert_eq!(result.0, None);
+
+    let mut bytes = Vec::new();
+    ShutdownRequest(Some(30)).write(&mut bytes).await.unwrap();
+    assert_eq!(bytes, vec![0x00, 0x01, 0x00, 0x00, 0x00, 30]);
+}
+
+#[tokio::test]
+async fn test_version1_metrics() {
+    let metrics = Metrics {
+        current_client_connections: 1,
+        historic_client_connections: 2,
+        client_bytes_sent: 3,
+        client_bytes_received: 4,
+        current_sandstorm_connections: 5,
+        historic_sandstorm_connections: 6,
+        start_time: 7,
+        counter_epoch: 8,
+    };
+    let version1_metrics = Metrics {
+        start_time: 0,
+        counter_epoch: 0,
+        ..metrics
+    };
+
+    let mut bytes = Vec::new();
+    Version1(CurrentMetricsResponse(Some(metrics))).write(&mut bytes).await.unwrap();
+    assert_eq!(bytes.len(), 2 + 4 + 8 + 8 + 8 + 4 + 8);
+
+    let mut reader = &bytes[..];
+    assert_eq!(
+        SandstormCommandType::read(&mut reader).await.unwrap(),
+        SandstormCommandType::RequestCurrentMetrics
+    );
+    let result = Version1::<CurrentMetricsResponse>::read(&mut reader).await.unwrap().0;
+    assert_eq!(result.0, Some(version1_metrics));
+
+    let mut bytes = Vec::new();
+    Version1(EventStreamConfigResponse::Enabled(metrics))
+        .write(&mut bytes)
+        .await
+        .unwrap();
+    let mut reader = &bytes[1..];
+    let result = Version1::<EventStreamConfigResponse>::read(&mut reader).await.unwrap().0;
+    assert_eq!(result, EventStreamConfigResponse::Enabled(version1_metrics));
+    assert!(reader.is_empty());
+
+    let mut bytes = Vec::new();
+    let result = Version1(EventStreamConfigResponse::EnabledWithHistory(metrics, 1))
+        .write(&mut bytes)
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_event_stream_options_require_capabilities() {
+    let mut requests = Vec::new();
+    let filter = EventStreamConfigRequest(true, Some(EventStreamFilter::default()), None);
+    FramedRequest(filter).write(&mut requests).await.unwrap();
+    let replay = EventStreamConfigRequest(true, None, Some(EventReplay::Last(5)));
+    FramedRequest(replay).write(&mut requests).await.unwrap();
+    FramedRequest(MeowRequest).write(&mut requests).await.unwrap();
+
+    let response = run_session(2, SandstormCapabilities::none(), SandstormPermissions::all(), &requests).await;
+    assert_eq!(response[..6], [0x00, 0x02, 0x00, 0x00, 0x00, 0x00]);
+    assert_eq!(response[6..], [0x1E, 0x01, 0x1E, 0x01, 0xFF, b'M', b'E', b'O', b'W']);
+
+    // Version 1 has no unsupported command response, so the connection is closed instead.
+    let mut requests = Vec::new();
+    EventStreamConfigRequest(true, Some(EventStreamFilter::default()), None)
+        .write(&mut requests)
+        .await
+        .unwrap();
+    MeowRequest.write(&mut requests).await.unwrap();
+
+    let response = run_session(1, SandstormCapabilities::none(), SandstormPermissions::all(), &requests).await;
+    assert_eq!(response, vec![0x00]);
+}
+
+#[tokio::test]
+async fn test_version1_socks5_sockets() {
+    let profile: ListenerProfile = "-noauth,role:admin".parse().unwrap();
+    let sockets = vec![
+        ("127.0.0.1:1080".parse().unwrap(), profile.clone()),
+        ("[::1]:1081".parse().unwrap(), ListenerProfile::default()),
+    ];
+
+    let mut bytes = Vec::new();
+    Version1(ListSocks5SocketsResponse(sockets.clone()))
+        .write(&mut bytes)
+        .await
+        .unwrap();
+    let mut expected = vec![SandstormCommandType::ListSocks5Sockets as u8];
+    let addresses: Vec<_> = sockets.iter().map(|(address, _)| *address).collect();
+    addresses.as_slice().write(&mut expected).await.unwrap();
+    assert_eq!(bytes, expected);
+
+    let mut reader = &bytes[1..];
+    let result = Version1::<ListSocks5SocketsResponse>::read(&mut reader).await.unwrap().0;
+    assert_eq!(result.0, vec![(sockets[0].0, ListenerProfile::default()), sockets[1].clone()]);
+    assert!(reader.is_empty());
+
+    let mut bytes = Vec::new();
+    Version1(AddSocks5SocketRequest(sockets[0].0, profile))
+        .write(&mut bytes)
+        .await
+        .unwrap();
+    let mut reader = &bytes[1..];
+    let result = Version1::<AddSocks5SocketRequest>::read(&mut reader).await.unwrap().0;
+    assert_eq!((result.0, result.1), (sockets[0].0, ListenerProfile::default()));
+    assert!(reader.is_empty());
+}
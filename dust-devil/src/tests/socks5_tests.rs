@@ -0,0 +1,297 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use dust_devil_core::{
+    listener_profile::ListenerProfile,
+    logging::{Event, EventData},
+    sandstorm::CloseClientsTarget,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::{broadcast::Receiver, mpsc},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    context::{ClientContext, ServerState},
+    ip_filters::IpFilterManager,
+    logger::LogSender,
+    socks5::{copy_bidirectional, handle_socks5, BYTES_FLUSH_INTERVAL},
+    users::UserManager,
+};
+
+const CLIENT_ID: u64 = 1;
+const SENT_LEN: usize = 100000;
+const RECEIVED_LEN: usize = 50000;
+
+fn create_state() -> Arc<ServerState> {
+    let (message_tx, _message_rx) = mpsc::channel(4);
+    Arc::new(ServerState::new(
+        UserManager::new(),
+        IpFilterManager::new(),
+        true,
+        false,
+        0x2000,
+        message_tx,
+        None,
+    ))
+}
+
+fn create_context(state: &Arc<ServerState>, log_sender: LogSender) -> ClientContext {
+    let address = "127.0.0.1:12345".parse().unwrap();
+    ClientContext::create(
+        CLIENT_ID,
+        address,
+        None,
+        Arc::new(ListenerProfile::default()),
+        state,
+        Some(log_sender),
+    )
+}
+
+fn take_events(receiver: &mut Receiver<Arc<Event>>) -> Vec<Arc<Event>> {
+    let mut events = Vec::new();
+    while let Ok(event) = receiver.try_recv() {
+        events.push(event);
+    }
+    events
+}
+
+/// Sums the bytes sent and received reported by the given events.
+fn byte_totals(events: &[Arc<Event>]) -> (usize, usize) {
+    events.iter().fold((0, 0), |(sent, received), event| match event.data {
+        EventData::ClientBytesSent(client_id, count) => {
+            assert_eq!(client_id, CLIENT_ID);
+            (sent + count as usize, received)
+        }
+        EventData::ClientBytesReceived(client_id, count) => {
+            assert_eq!(client_id, CLIENT_ID);
+            (sent, received + count as usize)
+        }
+        _ => (sent, received),
+    })
+}
+
+fn position(events: &[Arc<Event>], predicate: fn(&EventData) -> bool) -> usize {
+    events.iter().position(|event| predicate(&event.data)).unwrap()
+}
+
+/// Writes `len` bytes to the stream and then shuts it down, while reading from it until it's
+/// closed. Returns the amount of bytes read.
+async fn exchange<S: AsyncRead + AsyncWrite>(stream: S, len: usize) -> usize {
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let write = async {
+        writer.write_all(&vec![0x55; len]).await.unwrap();
+        writer.shutdown().await.unwrap();
+    };
+
+    let read = async {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).await.unwrap();
+        buf.len()
+    };
+
+    tokio::join!(write, read).1
+}
+
+/// Starts handling a SOCKS5 client that connects to a destination through the proxy, and returns
+/// the client's and the destination's streams once the connection is established.
+async fn start_proxied_connection(
+    state: &Arc<ServerState>,
+    log_sender: LogSender,
+    cancel_token: CancellationToken,
+) -> (TcpStream, TcpStream, JoinHandle<()>) {
+    let proxy_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let destination_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let destination_address = destination_listener.local_addr().unwrap();
+
+    let (client, proxied) = tokio::join!(TcpStream::connect(proxy_listener.local_addr().unwrap()), proxy_listener.accept());
+    let mut client = client.unwrap();
+    let (proxied, _) = proxied.unwrap();
+    let context = create_context(state, log_sender);
+    let handle = tokio::spawn(handle_socks5(proxied, context, cancel_token));
+
+    client.write_all(&[5, 1, 0]).await.unwrap();
+    let mut handshake_response = [0u8; 2];
+    client.read_exact(&mut handshake_response).await.unwrap();
+    assert_eq!(handshake_response, [5, 0]);
+
+    let ip = match destination_address {
+        SocketAddr::V4(address) => address.ip().octets(),
+        SocketAddr::V6(_) => unreachable!(),
+    };
+    let port = destination_address.port().to_be_bytes();
+    client
+        .write_all(&[5, 1, 0, 1, ip[0], ip[1], ip[2], ip[3], port[0], port[1]])
+        .await
+        .unwrap();
+
+    let (destination, _) = destination_listener.accept().await.unwrap();
+    let mut request_response = [0u8; 10];
+    client.read_exact(&mut request_response).await.unwrap();
+    assert_eq!(request_response[..2], [5, 0]);
+
+    (client, destination, handle)
+}
+
+#[test]
+fn test_flush_bytes() {
+    let state = create_state();
+    let (log_sender, mut receiver) = LogSender::channel();
+    let mut context = create_context(&state, log_sender);
+
+    context.register_bytes_sent(10);
+    context.register_bytes_sent(5);
+    context.register_bytes_received(7);
+    assert!(take_events(&mut receiver).is_empty());
+
+    context.flush_bytes();
+    let events = take_events(&mut receiver);
+    assert_eq!(events.len(), 2);
+    assert_eq!(byte_totals(&events), (15, 7));
+
+    context.flush_bytes();
+    assert!(take_events(&mut receiver).is_empty());
+
+    // Bytes still pending when the client is dropped are flushed.
+    context.register_bytes_received(3);
+    drop(context);
+    let events = take_events(&mut receiver);
+    assert_eq!(events.len(), 1);
+    assert_eq!(byte_totals(&events), (0, 3));
+    assert!(state.clients().lookup(CLIENT_ID).is_none());
+}
+
+#[tokio::test]
+async fn test_copy_logs_all_bytes() {
+    let state = create_state();
+    let (log_sender, mut receiver) = LogSender::channel();
+    let mut context = create_context(&state, log_sender);
+
+    // Small buffers make the bytes be copied over many writes.
+    let (client, proxy_source) = tokio::io::duplex(64);
+    let (proxy_destination, destination) = tokio::io::duplex(64);
+    let (src_reader, mut src_writer) = tokio::io::split(proxy_source);
+    let (dst_reader, mut dst_writer) = tokio::io::split(proxy_destination);
+    let mut src_reader = BufReader::with_capacity(16, src_reader);
+    let mut dst_reader = BufReader::with_capacity(16, dst_reader);
+
+    let client_task = tokio::spawn(exchange(client, SENT_LEN));
+    let destination_task = tokio::spawn(exchange(destination, RECEIVED_LEN));
+
+    copy_bidirectional(&mut src_reader, &mut src_writer, &mut dst_reader, &mut dst_writer, &mut context)
+        .await
+        .unwrap();
+
+    assert_eq!(client_task.await.unwrap(), RECEIVED_LEN);
+    assert_eq!(destination_task.await.unwrap(), SENT_LEN);
+    assert_eq!((context.bytes_sent, context.bytes_received), (SENT_LEN as u64, RECEIVED_LEN as u64));
+
+    let events = take_events(&mut receiver);
+    assert_eq!(byte_totals(&events), (SENT_LEN, RECEIVED_LEN));
+
+    // Each direction's bytes are logged before its shutdown.
+    let last_sent = events
+        .iter()
+        .rposition(|e| matches!(e.data, EventData::ClientBytesSent(..)))
+        .unwrap();
+    let last_received = events
+        .iter()
+        .rposition(|e| matches!(e.data, EventData::ClientBytesReceived(..)))
+        .unwrap();
+    assert!(last_sent < position(&events, |data| matches!(data, EventData::ClientSourceShutdown(_))));
+    assert!(last_received < position(&events, |data| matches!(data, EventData::ClientDestinationShutdown(_))));
+
+    // Nothing is left pending once the copy is done.
+    context.flush_bytes();
+    assert!(take_events(&mut receiver).is_empty());
+}
+
+#[tokio::test]
+async fn test_copy_logs_bytes_periodically() {
+    let state = create_state();
+    let (log_sender, mut receiver) = LogSender::channel();
+    let mut context = create_context(&state, log_sender);
+
+    let (mut client, proxy_source) = tokio::io::duplex(0x1000);
+    let (proxy_destination, mut destination) = tokio::io::duplex(0x1000);
+    let (src_reader, mut src_writer) = tokio::io::split(proxy_source);
+    let (dst_reader, mut dst_writer) = tokio::io::split(proxy_destination);
+    let mut src_reader = BufReader::new(src_reader);
+    let mut dst_reader = BufReader::new(dst_reader);
+
+    let copy = copy_bidirectional(&mut src_reader, &mut src_writer, &mut dst_reader, &mut dst_writer, &mut context);
+    let driver = async {
+        client.write_all(&[0x55; 1000]).await.unwrap();
+        destination.read_exact(&mut [0u8; 1000]).await.unwrap();
+
+        // The bytes are logged while the connection is still open.
+        tokio::time::sleep(BYTES_FLUSH_INTERVAL + Duration::from_millis(500)).await;
+        assert_eq!(byte_totals(&take_events(&mut receiver)), (1000, 0));
+
+        client.shutdown().await.unwrap();
+        destination.shutdown().await.unwrap();
+    };
+
+    let (result, ()) = tokio::join!(copy, driver);
+    result.unwrap();
+}
+
+#[tokio::test]
+async fn test_bytes_logged_before_finished() {
+    let state = create_state();
+    let (log_sender, mut receiver) = LogSender::channel();
+    let (client, destination, handle) = start_proxied_connection(&state, log_sender, CancellationToken::new()).await;
+
+    let (received, sent) = tokio::join!(exchange(client, SENT_LEN), exchange(destination, RECEIVED_LEN));
+    assert_eq!((received, sent), (RECEIVED_LEN, SENT_LEN));
+    handle.await.unwrap();
+
+    // The client's context is dropped by now, so any bytes it left pending would be logged after
+    // the connection finished.
+    let events = take_events(&mut receiver);
+    assert_eq!(byte_totals(&events), (SENT_LEN, RECEIVED_LEN));
+    let finished = position(&events, |data| matches!(data, EventData::ClientConnectionFinished(..)));
+    assert_eq!(finished, events.len() - 1);
+}
+
+#[tokio::test]
+async fn test_bytes_logged_when_closed() {
+    let state = create_state();
+    let (log_sender, mut receiver) = LogSender::channel();
+    let (mut client, mut destination, handle) = start_proxied_connection(&state, log_sender, CancellationToken::new()).await;
+
+    client.write_all(&[0x55; 1000]).await.unwrap();
+    destination.read_exact(&mut [0u8; 1000]).await.unwrap();
+    destination.write_all(&[0x55; 500]).await.unwrap();
+    client.read_exact(&mut [0u8; 500]).await.unwrap();
+
+    assert_eq!(state.clients().close(&CloseClientsTarget::Client(CLIENT_ID), |_| {}), 1);
+    handle.await.unwrap();
+
+    let events = take_events(&mut receiver);
+    assert_eq!(byte_totals(&events), (1000, 500));
+    let finished = position(&events, |data| matches!(data, EventData::ClientConnectionFinished(..)));
+    assert_eq!(finished, events.len() - 1);
+}
+
+#[tokio::test]
+async fn test_bytes_logged_on_shutdown() {
+    let state = create_state();
+    let (log_sender, mut receiver) = LogSender::channel();
+    let cancel_token = CancellationToken::new();
+    let (mut client, mut destination, handle) = start_proxied_connection(&state, log_sender, cancel_token.clone()).await;
+
+    client.write_all(&[0x55; 1000]).await.unwrap();
+    destination.read_exact(&mut [0u8; 1000]).await.unwrap();
+
+    // When the server shuts down, clients are dropped without logging that they finished, but
+    // their bytes are still logged.
+    cancel_token.cancel();
+    handle.await.unwrap();
+
+    let events = take_events(&mut receiver);
+    assert_eq!(byte_totals(&events), (1000, 0));
+    assert!(!events.iter().any(|e| matches!(e.data, EventData::ClientConnectionFinished(..))));
+}
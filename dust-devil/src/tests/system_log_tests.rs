@@ -0,0 +1,143 @@
+use std::{
+    io::{Error, ErrorKind},
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+
+use dust_devil_core::{
+    log_filter::LogSink,
+    logging::{Event, EventData, EventSeverity},
+};
+use tokio::{net::UdpSocket, sync::broadcast};
+
+use crate::{
+    log_filters::LogFilterManager,
+    system_log::{self, write_journald_message, write_syslog_message, SyslogTarget, SystemLog, SystemLogSocket},
+};
+
+const TIMESTAMP: i64 = 1709317800;
+
+#[test]
+fn test_severity() {
+    assert_eq!(EventData::ClientBytesSent(5, 1024).severity(), EventSeverity::Debug);
+    assert_eq!(
+        EventData::ClientAuthenticatedWithUserpass(5, "pedro".to_string(), true).severity(),
+        EventSeverity::Info
+    );
+    assert_eq!(
+        EventData::ClientAuthenticatedWithUserpass(5, "pedro".to_string(), false).severity(),
+        EventSeverity::Warning
+    );
+    assert_eq!(EventData::ClientConnectionFinished(5, 1, 2, Ok(())).severity(), EventSeverity::Info);
+    assert_eq!(
+        EventData::ClientConnectionFinished(5, 1, 2, Err(Error::from(ErrorKind::ConnectionReset))).severity(),
+        EventSeverity::Warning
+    );
+    assert_eq!(EventData::BufferSizeChangedByManager(1, 4096).severity(), EventSeverity::Notice);
+    assert_eq!(EventData::FailedBindAnySocketAborting.severity(), EventSeverity::Error);
+}
+
+#[test]
+fn test_syslog_message() {
+    let mut out = Vec::new();
+    write_syslog_message(
+        &mut out,
+        &Event::new(TIMESTAMP, EventData::ClientBytesSent(5, 1024)),
+        "myhost",
+        1234,
+    );
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "<31>1 2024-03-01T18:30:00Z myhost dust-devil 1234 - - Client 5 sent 1024 bytes"
+    );
+
+    let mut out = Vec::new();
+    write_syslog_message(&mut out, &Event::new(TIMESTAMP, EventData::FailedBindAnySocketAborting), "-", 1);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "<27>1 2024-03-01T18:30:00Z - dust-devil 1 - - Failed to bind any socks5 socket! Aborting"
+    );
+}
+
+#[test]
+fn test_journald_message() {
+    let mut out = Vec::new();
+    write_journald_message(&mut out, &Event::new(TIMESTAMP, EventData::ClientBytesSent(5, 1024)));
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        concat!(
+            "PRIORITY=7\n",
+            "SYSLOG_IDENTIFIER=dust-devil\n",
+            "MESSAGE=Client 5 sent 1024 bytes\n",
+            "EVENT_TYPE=client_bytes_sent\n",
+            "CLIENT_ID=5\n",
+            "BYTES=1024\n",
+        )
+    );
+
+    let error = Error::new(ErrorKind::ConnectionReset, "reset\nby peer");
+    let mut out = Vec::new();
+    write_journald_message(
+        &mut out,
+        &Event::new(TIMESTAMP, EventData::SandstormConnectionFinished(2, Err(error))),
+    );
+    let expected_error = [b"ERROR\n".as_slice(), &13u64.to_le_bytes(), b"reset\nby peer\n"].concat();
+    assert!(out.ends_with(&expected_error));
+}
+
+#[tokio::test]
+async fn test_udp_task() {
+    let stand_in = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+    let target = SystemLog::Syslog(SyslogTarget::Udp(stand_in.local_addr().unwrap()));
+    let socket = SystemLogSocket::connect(&target).await.unwrap();
+
+    let filter = LogFilterManager::new().register(LogSink::Syslog, &[]);
+    let (sender, receiver) = broadcast::channel(16);
+    let task = tokio::spawn(system_log::system_log_task(false, receiver, target, socket, filter));
+
+    let event = Arc::new(Event::new(TIMESTAMP, EventData::ClientBytesSent(5, 1024)));
+    assert!(sender.send(event).is_ok());
+    let mut buf = [0u8; 512];
+    let (len, _) = stand_in.recv_from(&mut buf).await.unwrap();
+    let message = std::str::from_utf8(&buf[..len]).unwrap();
+    assert!(message.starts_with("<31>1 2024-03-01T18:30:00Z "));
+    assert!(message.ends_with(&format!(" dust-devil {} - - Client 5 sent 1024 bytes", std::process::id())));
+
+    drop(sender);
+    task.await.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_unix_task() {
+    use tokio::net::UnixDatagram;
+
+    let path = std::env::temp_dir().join(format!("dust-devil-syslog-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let stand_in = UnixDatagram::bind(&path).unwrap();
+
+    let target = SystemLog::Syslog(SyslogTarget::Unix(path.to_string_lossy().into_owned()));
+    let socket = SystemLogSocket::connect(&target).await.unwrap();
+
+    let filters = [(None, "severity=info".parse().unwrap())];
+    let filter = LogFilterManager::new().register(LogSink::Syslog, &filters);
+    let (sender, receiver) = broadcast::channel(16);
+    let task = tokio::spawn(system_log::system_log_task(false, receiver, target, socket, filter));
+
+    // Byte counts are debug events, so the filter discards this one.
+    let event = Arc::new(Event::new(TIMESTAMP, EventData::ClientBytesSent(8, 1024)));
+    assert!(sender.send(event).is_ok());
+
+    let address = SocketAddr::from((Ipv4Addr::new(10, 0, 0, 7), 5000));
+    let event = Arc::new(Event::new(TIMESTAMP, EventData::NewClientConnectionAccepted(8, address)));
+    assert!(sender.send(event).is_ok());
+    let mut buf = [0u8; 512];
+    let len = stand_in.recv(&mut buf).await.unwrap();
+    let message = std::str::from_utf8(&buf[..len]).unwrap();
+    assert!(message.starts_with("<30>1 2024-03-01T18:30:00Z "));
+    assert!(message.ends_with("New client connection from 10.0.0.7:5000 assigned ID 8"));
+
+    drop(sender);
+    task.await.unwrap();
+    let _ = std::fs::remove_file(&path);
+}
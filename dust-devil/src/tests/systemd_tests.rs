@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use crate::systemd::{parse_listen_fds, parse_watchdog};
+
+#[test]
+fn test_listen_fds() {
+    assert!(parse_listen_fds(None, None, None, 1234).is_ok_and(|r| r.is_none()));
+    assert!(parse_listen_fds(Some("1234"), None, None, 1234).is_ok_and(|r| r.is_none()));
+    assert!(parse_listen_fds(Some("4321"), Some("2"), None, 1234).is_ok_and(|r| r.is_none()));
+    assert!(parse_listen_fds(Some("1234"), Some("two"), None, 1234).is_err());
+
+    let result = parse_listen_fds(Some("1234"), Some("2"), None, 1234).unwrap();
+    assert_eq!(result, Some(vec![false, false]));
+
+    let result = parse_listen_fds(Some("1234"), Some("3"), Some("socks5:sandstorm:unknown"), 1234).unwrap();
+    assert_eq!(result, Some(vec![false, true, false]));
+
+    let result = parse_listen_fds(Some("1234"), Some("2"), Some("sandstorm"), 1234).unwrap();
+    assert_eq!(result, Some(vec![true, false]));
+
+    let result = parse_listen_fds(Some("1234"), Some("0"), Some(""), 1234).unwrap();
+    assert_eq!(result, Some(vec![]));
+}
+
+#[test]
+fn test_watchdog() {
+    assert_eq!(parse_watchdog(None, None, 1234), None);
+    assert_eq!(parse_watchdog(Some("0"), None, 1234), None);
+    assert_eq!(parse_watchdog(Some("pedro"), None, 1234), None);
+    assert_eq!(parse_watchdog(Some("10000000"), None, 1234), Some(Duration::from_secs(5)));
+    assert_eq!(parse_watchdog(Some("10000000"), Some("1234"), 1234), Some(Duration::from_secs(5)));
+    assert_eq!(parse_watchdog(Some("10000000"), Some("4321"), 1234), None);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_notifier() {
+    use tokio::net::UnixDatagram;
+
+    use crate::systemd::SystemdNotifier;
+
+    let path = std::env::temp_dir().join(format!("dust-devil-notify-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let stand_in = UnixDatagram::bind(&path).unwrap();
+
+    let notifier = SystemdNotifier::new(&path.to_string_lossy(), Some(Duration::from_secs(1))).unwrap();
+    assert_eq!(notifier.watchdog_interval(), Some(Duration::from_secs(1)));
+
+    let mut buf = [0u8; 64];
+    for state in ["READY=1\nSTATUS=Listening", "WATCHDOG=1", "STOPPING=1"] {
+        notifier.notify(state).await.unwrap();
+        let len = stand_in.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], state.as_bytes());
+    }
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_activated_unix_socket_file_is_kept() {
+    use std::os::fd::OwnedFd;
+
+    use crate::systemd::take_listeners;
+
+    let path = std::env::temp_dir().join(format!("dust-devil-activated-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+
+    let inherited = take_listeners(vec![true], vec![OwnedFd::from(listener)]).unwrap();
+    assert_eq!(inherited.sandstorm_unix.len(), 1);
+    assert_eq!(inherited.sandstorm_unix[0].path(), path.to_string_lossy());
+
+    drop(inherited);
+    assert!(path.exists());
+
+    let _ = std::fs::remove_file(&path);
+}
@@ -1,10 +1,13 @@
 use dashmap::DashMap;
-use dust_devil_core::users::{UserRole, UsersLoadingError};
+use dust_devil_core::{
+    sandstorm::{SandstormCommandType, SandstormPermissions},
+    users::{UserRole, UsersLoadingError},
+};
 use tokio::io::BufReader;
 
 use crate::utils::process_lines;
 
-use crate::users::{UserData, UserManager};
+use crate::users::{UserChangeError, UserData, UserManager};
 
 async fn from(s: &str) -> Result<UserManager, UsersLoadingError> {
     UserManager::from(&mut BufReader::new(s.as_bytes())).await
@@ -19,7 +22,14 @@ fn usermap(s: &[(&str, &str, UserRole)]) -> DashMap<String, UserData> {
     for (username, password, role) in s {
         let username = String::from(*username);
         let password = String::from(*password);
-        h.insert(username, UserData { password, role: *role });
+        h.insert(
+            username,
+            UserData {
+                password,
+                role: *role,
+                permissions: SandstormPermissions::all(),
+            },
+        );
     }
 
     h
@@ -197,7 +207,7 @@ async fn test_integration1() {
     assert_eq!(result.admin_count(), 1);
 
     assert_eq!(
-        result.update("chi:chí".to_string(), None, Some(UserRole::Admin)),
+        result.update("chi:chí".to_string(), None, Some(UserRole::Admin), SandstormPermissions::all()),
         Ok(Some(UserRole::Admin))
     );
     assert_has(
@@ -212,7 +222,7 @@ async fn test_integration1() {
     assert_eq!(result.admin_count(), 2);
 
     assert_eq!(
-        result.update("chi:chí".to_string(), None, Some(UserRole::Admin)),
+        result.update("chi:chí".to_string(), None, Some(UserRole::Admin), SandstormPermissions::all()),
         Ok(Some(UserRole::Admin))
     );
     assert_has(
@@ -227,7 +237,12 @@ async fn test_integration1() {
     assert_eq!(result.admin_count(), 2);
 
     assert_eq!(
-        result.update("pedro".to_string(), Some("CROCANTE".to_string()), Some(UserRole::Regular)),
+        result.update(
+            "pedro".to_string(),
+            Some("CROCANTE".to_string()),
+            Some(UserRole::Regular),
+            SandstormPermissions::all()
+        ),
         Ok(Some(UserRole::Regular))
     );
     assert_has(
@@ -241,10 +256,18 @@ async fn test_integration1() {
     );
     assert_eq!(result.admin_count(), 1);
 
-    assert!(!result.insert("pedro".to_string(), "password".to_string(), UserRole::Regular));
+    assert!(!result.insert(
+        "pedro".to_string(),
+        "password".to_string(),
+        UserRole::Regular,
+        SandstormPermissions::all()
+    ));
     assert_eq!(result.admin_count(), 1);
 
-    assert_eq!(result.update("chi:chí".to_string(), None, Some(UserRole::Regular)), Ok(None));
+    assert_eq!(
+        result.update("chi:chí".to_string(), None, Some(UserRole::Regular), SandstormPermissions::all()),
+        Ok(None)
+    );
     assert_has(
         &result,
         &[
@@ -256,7 +279,7 @@ async fn test_integration1() {
     );
     assert_eq!(result.admin_count(), 1);
 
-    assert_eq!(result.delete("chi:chí".to_string()), Ok(None));
+    assert_eq!(result.delete("chi:chí".to_string(), SandstormPermissions::all()), Ok(None));
     assert_has(
         &result,
         &[
@@ -269,7 +292,7 @@ async fn test_integration1() {
     assert_eq!(result.admin_count(), 1);
 
     assert_eq!(
-        result.delete("pedro".to_string()),
+        result.delete("pedro".to_string(), SandstormPermissions::all()),
         Ok(Some(("pedro".to_string(), UserRole::Regular)))
     );
     assert_has(
@@ -282,7 +305,12 @@ async fn test_integration1() {
     );
     assert_eq!(result.admin_count(), 1);
 
-    assert!(result.insert("peluca".to_string(), "pelu💇💇".to_string(), UserRole::Admin));
+    assert!(result.insert(
+        "peluca".to_string(),
+        "pelu💇💇".to_string(),
+        UserRole::Admin,
+        SandstormPermissions::all()
+    ));
     assert_has(
         &result,
         &[
@@ -295,7 +323,7 @@ async fn test_integration1() {
     assert_eq!(result.admin_count(), 2);
 
     assert_eq!(
-        result.delete("chi:chí".to_string()),
+        result.delete("chi:chí".to_string(), SandstormPermissions::all()),
         Ok(Some(("chi:chí".to_string(), UserRole::Admin)))
     );
     assert_has(
@@ -308,3 +336,169 @@ async fn test_integration1() {
     );
     assert_eq!(result.admin_count(), 1);
 }
+
+#[tokio::test]
+async fn test_permissions() {
+    let result = from("@[all,-shutdown]pedro:pedrito4321\n@[list_users, add_user]juan:juancito\n@[]nadie:nada\n@\\[x:y").await;
+    let mgr = result.unwrap();
+
+    let pedro = mgr.users().get("pedro").unwrap().permissions;
+    assert!(!pedro.allows(SandstormCommandType::Shutdown));
+    assert!(pedro.allows(SandstormCommandType::AddUser));
+    assert!(pedro.allows(SandstormCommandType::Meow));
+
+    let mut expected = SandstormPermissions::none();
+    expected.grant(SandstormCommandType::ListUsers);
+    expected.grant(SandstormCommandType::AddUser);
+    assert_eq!(mgr.users().get("juan").unwrap().permissions, expected);
+
+    let nadie = mgr.users().get("nadie").unwrap().permissions;
+    assert_eq!(nadie, SandstormPermissions::none());
+    assert!(!nadie.allows(SandstormCommandType::ListSocks5Sockets));
+    assert!(nadie.allows(SandstormCommandType::GetPermissions));
+
+    assert_eq!(mgr.users().get("[x").unwrap().permissions, SandstormPermissions::all());
+
+    let mut saved = Vec::new();
+    mgr.save_to(&mut saved).await.unwrap();
+    let reloaded = from_bytes(&saved).await.unwrap();
+    for ele in mgr.users().iter() {
+        assert_eq!(reloaded.users().get(ele.key()).as_deref(), Some(ele.value()));
+    }
+}
+
+#[tokio::test]
+async fn test_new_admin_permissions() {
+    let result = from_bytes(b"@pedro:pedrito4321\n#carlos:carlitox@33").await.unwrap();
+
+    let mut permissions = SandstormPermissions::none();
+    permissions.grant(SandstormCommandType::AddUser);
+
+    assert!(result.insert("juan".to_string(), "juancito123".to_string(), UserRole::Admin, permissions));
+    assert_eq!(result.users().get("juan").unwrap().permissions, permissions);
+
+    assert_eq!(
+        result.update("carlos".to_string(), None, Some(UserRole::Admin), permissions),
+        Ok(Some(UserRole::Admin))
+    );
+    assert_eq!(result.users().get("carlos").unwrap().permissions, permissions);
+
+    // Updating an existing admin's role keeps its permissions.
+    assert_eq!(
+        result.update("carlos".to_string(), None, Some(UserRole::Admin), SandstormPermissions::all()),
+        Ok(Some(UserRole::Admin))
+    );
+    assert_eq!(result.users().get("carlos").unwrap().permissions, permissions);
+}
+
+#[tokio::test]
+async fn test_update_admin_with_more_permissions() {
+    let result = from_bytes(
+        b"@pedro:pedrito4321
+#carlos:carlitox@33",
+    )
+    .await
+    .unwrap();
+
+    let mut permissions = SandstormPermissions::none();
+    permissions.grant(SandstormCommandType::UpdateUser);
+    permissions.grant(SandstormCommandType::DeleteUser);
+    assert!(result.insert("juan".to_string(), "juancito123".to_string(), UserRole::Admin, permissions));
+
+    assert_eq!(
+        result.update("pedro".to_string(), Some("hacked".to_string()), None, permissions),
+        Err(UserChangeError::PermissionDenied)
+    );
+    assert_eq!(
+        result.update("pedro".to_string(), None, Some(UserRole::Regular), permissions),
+        Err(UserChangeError::PermissionDenied)
+    );
+    assert_eq!(
+        result.try_login("pedro", "pedrito4321"),
+        Some((UserRole::Admin, SandstormPermissions::all()))
+    );
+
+    // Regular users and admins with a subset of the requester's permissions can be changed.
+    assert_eq!(
+        result.update("carlos".to_string(), Some("CROCANTE".to_string()), None, permissions),
+        Ok(Some(UserRole::Regular))
+    );
+    assert_eq!(
+        result.update("juan".to_string(), Some("juan123".to_string()), None, permissions),
+        Ok(Some(UserRole::Admin))
+    );
+    assert_eq!(
+        result.update("nobody".to_string(), None, Some(UserRole::Admin), permissions),
+        Err(UserChangeError::NotFound)
+    );
+    assert_eq!(
+        result.update("pedro".to_string(), Some("pedro123".to_string()), None, SandstormPermissions::all()),
+        Ok(Some(UserRole::Admin))
+    );
+}
+
+#[tokio::test]
+async fn test_delete_admin_with_more_permissions() {
+    let result = from_bytes(
+        b"@pedro:pedrito4321
+#carlos:carlitox@33",
+    )
+    .await
+    .unwrap();
+
+    let mut permissions = SandstormPermissions::none();
+    permissions.grant(SandstormCommandType::DeleteUser);
+    assert!(result.insert("juan".to_string(), "juancito123".to_string(), UserRole::Admin, permissions));
+
+    assert_eq!(
+        result.delete("pedro".to_string(), permissions),
+        Err(UserChangeError::PermissionDenied)
+    );
+    assert!(result.contains("pedro"));
+    assert_eq!(result.admin_count(), 2);
+
+    assert_eq!(
+        result.delete("carlos".to_string(), permissions),
+        Ok(Some(("carlos".to_string(), UserRole::Regular)))
+    );
+    assert_eq!(result.delete("nobody".to_string(), permissions), Err(UserChangeError::NotFound));
+    assert_eq!(
+        result.delete("juan".to_string(), permissions),
+        Ok(Some(("juan".to_string(), UserRole::Admin)))
+    );
+    assert_eq!(result.delete("pedro".to_string(), SandstormPermissions::all()), Ok(None));
+}
+
+#[tokio::test]
+async fn test_invalid_permissions() {
+    let result = from("@[all,-shutdown,make_coffee]pedro:pedrito4321").await;
+    assert_err_with(&result, UsersLoadingError::InvalidPermission(1, 17, "make_coffee".to_string()));
+
+    let result = from("\n  @[all,-meow]pedro:pedrito4321").await;
+    assert_err_with(&result, UsersLoadingError::InvalidPermission(2, 9, "meow".to_string()));
+
+    let result = from("@[all,-shutdown").await;
+    assert_err_with(&result, UsersLoadingError::ExpectedPermissionsEndGotEOF(1, 15));
+}
+
+#[tokio::test]
+async fn test_save_to_file() {
+    let path = std::env::temp_dir().join(format!("dust-devil-users-{}.txt", std::process::id()));
+    std::fs::write(&path, "#old:contents").unwrap();
+
+    let mgr = from_bytes(b"@pedro:pedrito4321\n#carlos:carlitox@33").await.unwrap();
+    assert_eq!(mgr.save_to_file(&path).await.unwrap(), 2);
+
+    let temp_path = crate::utils::atomic_file::temp_path(&path);
+    assert!(!temp_path.exists());
+
+    let reloaded = UserManager::from_file(&path).await;
+    let _ = std::fs::remove_file(&path);
+    assert_ok_with(
+        &reloaded,
+        &[
+            ("pedro", "pedrito4321", UserRole::Admin),
+            ("carlos", "carlitox@33", UserRole::Regular),
+        ],
+    );
+}
@@ -13,6 +13,13 @@
 //! followed by the username, followed by a colon ':', followed by the password until the end of
 //! the line (or file).
 //!
+//! Admin users may optionally restrict which Sandstorm commands they're allowed to issue by placing
+//! a comma-separated list of permissions between '[' and ']' right after the role char. Each entry
+//! is either a command name (such as "add_user" or "list_socks5_sockets"), which grants that
+//! command, or "all", which grants all commands. Entries prefixed with '-' revoke instead of grant,
+//! and are applied in order starting from no permissions. Users without this list are granted all
+//! permissions.
+//!
 //! Characters in both the username and password may be escaped with a '\', this allows a username
 //! to contain the ':' character. Any character can be escaped. A line may also be a comment by
 //! starting with '!'. All lines have whitespaces trimmed at the start and empty lines are ignored.
@@ -27,6 +34,9 @@
 //! ! Our admin Pedro, everybody loves him
 //! @pedro:pedrito4321
 //!
+//! ! Our second admin Juan, who can do everything except shutting down the server
+//! @[all,-shutdown]juan:juancito123
+//!
 //! ! Our first user Carlos and his brother Felipe, fucken assholes
 //! #carlos:carlitox@33
 //! #felipe:mi_hermano_es_un_boludo
@@ -43,13 +53,19 @@ use std::{
 };
 
 use dashmap::{mapref::entry::Entry, DashMap};
-use dust_devil_core::users::{UserRole, UsersLoadingError, ADMIN_PREFIX_CHAR, COMMENT_PREFIX_CHAR, ESCAPE_CHAR, REGULAR_PREFIX_CHAR};
+use dust_devil_core::{
+    sandstorm::{SandstormPermissions, RESTRICTABLE_COMMANDS},
+    users::{
+        UserRole, UsersLoadingError, ADMIN_PREFIX_CHAR, ALL_PERMISSIONS_NAME, COMMENT_PREFIX_CHAR, ESCAPE_CHAR, PERMISSIONS_END_CHAR,
+        PERMISSIONS_SEPARATOR_CHAR, PERMISSIONS_START_CHAR, PERMISSION_REVOKE_CHAR, REGULAR_PREFIX_CHAR,
+    },
+};
 use tokio::{
     fs::File,
     io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufWriter},
 };
 
-use crate::utils::{self, process_lines::ProcessFileLinesError};
+use crate::utils::{self, atomic_file, process_lines::ProcessFileLinesError};
 
 #[derive(Debug)]
 pub struct UserManager {
@@ -61,6 +77,60 @@ pub struct UserManager {
 pub struct UserData {
     pub password: String,
     pub role: UserRole,
+    pub permissions: SandstormPermissions,
+}
+
+impl UserData {
+    /// Returns whether a manager with the given permissions may update or delete this user. An
+    /// admin may only be changed by a manager that has all of the admin's permissions, as otherwise
+    /// the manager could take over the admin's account to gain them.
+    fn can_be_changed_by(&self, requester_permissions: SandstormPermissions) -> bool {
+        self.role != UserRole::Admin || self.permissions.is_subset_of(requester_permissions)
+    }
+}
+
+/// The reasons for which [`UserManager::update`] or [`UserManager::delete`] may fail.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UserChangeError {
+    /// There is no user with the given username.
+    NotFound,
+
+    /// The user is an admin with permissions the requester doesn't have.
+    PermissionDenied,
+}
+
+fn apply_permission_entry(
+    permissions: &mut SandstormPermissions,
+    entry: &str,
+    line_number: u32,
+    char_at: u32,
+) -> Result<(), UsersLoadingError> {
+    let entry = entry.trim();
+    if entry.is_empty() {
+        return Ok(());
+    }
+
+    let (revoke, name) = match entry.strip_prefix(PERMISSION_REVOKE_CHAR) {
+        Some(name) => (true, name),
+        None => (false, entry),
+    };
+
+    if name == ALL_PERMISSIONS_NAME {
+        *permissions = if revoke {
+            SandstormPermissions::none()
+        } else {
+            SandstormPermissions::all()
+        };
+        return Ok(());
+    }
+
+    match RESTRICTABLE_COMMANDS.iter().find(|c| c.to_str() == name) {
+        Some(&command) if revoke => permissions.revoke(command),
+        Some(&command) => permissions.grant(command),
+        None => return Err(UsersLoadingError::InvalidPermission(line_number, char_at, String::from(name))),
+    }
+
+    Ok(())
 }
 
 pub fn parse_line_into_user(s: &str, line_number: u32, mut char_at: u32) -> Result<Option<(String, UserData)>, UsersLoadingError> {
@@ -76,6 +146,34 @@ pub fn parse_line_into_user(s: &str, line_number: u32, mut char_at: u32) -> Resu
         _ => return Err(UsersLoadingError::InvalidRoleChar(line_number, char_at, role_char)),
     };
 
+    let mut permissions = SandstormPermissions::all();
+    if chars.clone().next() == Some(PERMISSIONS_START_CHAR) {
+        chars.next();
+        char_at += 1;
+        permissions = SandstormPermissions::none();
+
+        let mut entry = String::new();
+        let mut entry_start = char_at + 1;
+        loop {
+            let next_char = chars
+                .next()
+                .ok_or(UsersLoadingError::ExpectedPermissionsEndGotEOF(line_number, char_at))?;
+            char_at += 1;
+
+            if next_char == PERMISSIONS_SEPARATOR_CHAR || next_char == PERMISSIONS_END_CHAR {
+                apply_permission_entry(&mut permissions, &entry, line_number, entry_start)?;
+                entry.clear();
+                entry_start = char_at + 1;
+
+                if next_char == PERMISSIONS_END_CHAR {
+                    break;
+                }
+            } else {
+                entry.push(next_char);
+            }
+        }
+    }
+
     let mut username = String::with_capacity(255);
     let mut escape_next = false;
     loop {
@@ -125,7 +223,14 @@ pub fn parse_line_into_user(s: &str, line_number: u32, mut char_at: u32) -> Resu
         return Err(UsersLoadingError::EmptyPassword(line_number, char_at));
     }
 
-    Ok(Some((username, UserData { password, role })))
+    Ok(Some((
+        username,
+        UserData {
+            password,
+            role,
+            permissions,
+        },
+    )))
 }
 
 impl UserManager {
@@ -221,8 +326,19 @@ impl UserManager {
 
             writer.write_u8(ele.role.into_role_char() as u8).await?;
 
-            for &c in ele.key().as_bytes() {
-                if c == b'\\' || c == b':' {
+            if ele.permissions != SandstormPermissions::all() {
+                writer.write_u8(PERMISSIONS_START_CHAR as u8).await?;
+                for (i, command) in ele.permissions.granted().enumerate() {
+                    if i != 0 {
+                        writer.write_u8(PERMISSIONS_SEPARATOR_CHAR as u8).await?;
+                    }
+                    writer.write_all(command.to_str().as_bytes()).await?;
+                }
+                writer.write_u8(PERMISSIONS_END_CHAR as u8).await?;
+            }
+
+            for (i, &c) in ele.key().as_bytes().iter().enumerate() {
+                if c == b'\\' || c == b':' || (i == 0 && c == PERMISSIONS_START_CHAR as u8) {
                     writer.write_u8(b'\\').await?;
                 }
                 writer.write_u8(c).await?;
@@ -235,16 +351,22 @@ impl UserManager {
         Ok(count)
     }
 
+    /// Saves the users to the given file. These are written to a temporary file first, which then
+    /// replaces the given file, so it's never left partially written.
     pub async fn save_to_file<F: AsRef<Path>>(&self, filename: F) -> Result<u64, Error> {
-        let file = File::create(filename).await?;
+        let filename = filename.as_ref();
+        let temp_filename = atomic_file::temp_path(filename);
+
+        let file = File::create(&temp_filename).await?;
         let mut writer = BufWriter::new(file);
         let count = self.save_to(&mut writer).await?;
         writer.flush().await?;
+        atomic_file::commit(writer.into_inner(), &temp_filename, filename).await?;
 
         Ok(count)
     }
 
-    pub fn insert(&self, username: String, password: String, role: UserRole) -> bool {
+    pub fn insert(&self, username: String, password: String, role: UserRole, permissions: SandstormPermissions) -> bool {
         // Note: This code might look like it has a race condition, as two threads could simultaneously
         // see the entry as vacant and then both try to insert the key. However, upon further examination,
         // the Entry<...> type actually holds a lock underneath, which lasts until the variable is
@@ -256,7 +378,11 @@ impl UserManager {
             return false;
         }
 
-        entry.insert(UserData { password, role });
+        entry.insert(UserData {
+            password,
+            role,
+            permissions,
+        });
         if role == UserRole::Admin {
             self.admin_count.fetch_add(1, Ordering::Relaxed);
         }
@@ -264,8 +390,15 @@ impl UserManager {
         true
     }
 
-    pub fn insert_or_update(&self, username: String, password: String, role: UserRole) -> bool {
-        let insert_result = self.users.insert(username, UserData { password, role });
+    pub fn insert_or_update(&self, username: String, password: String, role: UserRole, permissions: SandstormPermissions) -> bool {
+        let insert_result = self.users.insert(
+            username,
+            UserData {
+                password,
+                role,
+                permissions,
+            },
+        );
 
         if insert_result.as_ref().is_some_and(|old| old.role == UserRole::Admin) {
             if role != UserRole::Admin {
@@ -278,11 +411,23 @@ impl UserManager {
         insert_result.is_some()
     }
 
-    pub fn update(&self, username: String, password: Option<String>, role: Option<UserRole>) -> Result<Option<UserRole>, ()> {
+    /// Updates the password and/or role of a user on behalf of a manager with the given
+    /// permissions. If a non-admin user is promoted to admin, their permissions are set to
+    /// `requester_permissions`. Admins with permissions the requester doesn't have can't be updated.
+    pub fn update(
+        &self,
+        username: String,
+        password: Option<String>,
+        role: Option<UserRole>,
+        requester_permissions: SandstormPermissions,
+    ) -> Result<Option<UserRole>, UserChangeError> {
         let entry = self.users.entry(username);
 
         if let Entry::Occupied(mut occupied_entry) = entry {
             let user = occupied_entry.get_mut();
+            if !user.can_be_changed_by(requester_permissions) {
+                return Err(UserChangeError::PermissionDenied);
+            }
 
             if let Some(new_role) = role {
                 if user.role == UserRole::Admin && new_role != UserRole::Admin {
@@ -306,6 +451,7 @@ impl UserManager {
 
                 if user.role != UserRole::Admin && new_role == UserRole::Admin {
                     self.admin_count.fetch_add(1, Ordering::Relaxed);
+                    user.permissions = requester_permissions;
                 }
 
                 user.role = new_role;
@@ -317,14 +463,24 @@ impl UserManager {
 
             Ok(Some(user.role))
         } else {
-            Err(())
+            Err(UserChangeError::NotFound)
         }
     }
 
-    pub fn delete(&self, username: String) -> Result<Option<(String, UserRole)>, ()> {
+    /// Deletes a user on behalf of a manager with the given permissions. Admins with permissions
+    /// the requester doesn't have can't be deleted.
+    pub fn delete(
+        &self,
+        username: String,
+        requester_permissions: SandstormPermissions,
+    ) -> Result<Option<(String, UserRole)>, UserChangeError> {
         let entry = self.users.entry(username);
 
         if let Entry::Occupied(occupied_entry) = entry {
+            if !occupied_entry.get().can_be_changed_by(requester_permissions) {
+                return Err(UserChangeError::PermissionDenied);
+            }
+
             if occupied_entry.get().role == UserRole::Admin {
                 let update_result =
                     self.admin_count.fetch_update(
@@ -347,7 +503,7 @@ impl UserManager {
             let entry = occupied_entry.remove_entry();
             Ok(Some((entry.0, entry.1.role)))
         } else {
-            Err(())
+            Err(UserChangeError::NotFound)
         }
     }
 
@@ -359,8 +515,15 @@ impl UserManager {
         self.admin_count.load(Ordering::Relaxed)
     }
 
-    pub fn try_login(&self, username: &str, password: &str) -> Option<UserRole> {
-        self.users.get(username).filter(|u| u.password == password).map(|u| u.role)
+    pub fn contains(&self, username: &str) -> bool {
+        self.users.contains_key(username)
+    }
+
+    pub fn try_login(&self, username: &str, password: &str) -> Option<(UserRole, SandstormPermissions)> {
+        self.users
+            .get(username)
+            .filter(|u| u.password == password)
+            .map(|u| (u.role, u.permissions))
     }
 
     pub fn take_snapshot(&self) -> Vec<(String, UserRole)> {
@@ -1,14 +1,47 @@
-use std::{future::poll_fn, io::Error, net::SocketAddr, task::Poll};
+use std::{
+    future::poll_fn,
+    io::{self, Error},
+    net::SocketAddr,
+    task::{Context, Poll},
+};
 
 use tokio::net::{TcpListener, TcpStream};
 
-pub async fn accept_from_any(listeners: &[TcpListener]) -> Result<(TcpStream, SocketAddr), (&TcpListener, Error)> {
+use super::unix_socket::{UnixListener, UnixStream};
+
+/// A listening socket that connections may be accepted from.
+pub trait Listener {
+    type Stream;
+    type Address;
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<(Self::Stream, Self::Address)>>;
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+    type Address = SocketAddr;
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<(TcpStream, SocketAddr)>> {
+        TcpListener::poll_accept(self, cx)
+    }
+}
+
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+    type Address = ();
+
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<(UnixStream, ())>> {
+        UnixListener::poll_accept(self, cx)
+    }
+}
+
+pub async fn accept_from_any<L: Listener>(listeners: &[L]) -> Result<(&L, L::Stream, L::Address), (&L, Error)> {
     poll_fn(|cx| {
         for l in listeners {
             let poll_status = l.poll_accept(cx);
             if let Poll::Ready(result) = poll_status {
                 return Poll::Ready(match result {
-                    Ok(result_ok) => Ok(result_ok),
+                    Ok((stream, address)) => Ok((l, stream, address)),
                     Err(result_err) => Err((l, result_err)),
                 });
             }
@@ -0,0 +1,35 @@
+//! Writing files such that they're never left partially written, by writing to a temporary file
+//! next to the destination first and then renaming it over the destination.
+
+use std::{
+    ffi::OsString,
+    io::Error,
+    path::{Path, PathBuf},
+};
+
+use tokio::{fs::File, io::AsyncWriteExt};
+
+/// Gets the path of the temporary file used while writing the file at the given path.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let mut temp_path = OsString::from(path);
+    temp_path.push(".tmp");
+    PathBuf::from(temp_path)
+}
+
+/// Replaces the file at the given path with a temporary file that has been fully written and
+/// synced to disk.
+pub async fn commit(temp_file: File, temp_path: &Path, path: &Path) -> Result<(), Error> {
+    temp_file.sync_all().await?;
+    tokio::fs::rename(temp_path, path).await
+}
+
+/// Writes the given contents to the file at the given path, replacing it only once the contents
+/// were fully written.
+pub async fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<(), Error> {
+    let path = path.as_ref();
+    let temp_path = temp_path(path);
+
+    let mut file = File::create(&temp_path).await?;
+    file.write_all(contents.as_ref()).await?;
+    commit(file, &temp_path, path).await
+}
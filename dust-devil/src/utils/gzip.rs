@@ -0,0 +1,12 @@
+//! Gzip compression, used for compressing rotated log files.
+
+use std::io::{self, Read, Write};
+
+use flate2::{write::GzEncoder, Compression};
+
+/// Compresses all the data from `reader` with gzip and writes it to `writer`.
+pub fn compress<R: Read, W: Write>(mut reader: R, writer: W) -> io::Result<()> {
+    let mut encoder = GzEncoder::new(writer, Compression::default());
+    io::copy(&mut reader, &mut encoder)?;
+    encoder.finish()?.flush()
+}
@@ -1,4 +1,7 @@
 pub mod accept_from_any;
+pub mod atomic_file;
 pub mod chunk_reader;
+pub mod gzip;
 pub mod printlnif;
 pub mod process_lines;
+pub mod unix_socket;
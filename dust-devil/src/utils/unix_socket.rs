@@ -0,0 +1,167 @@
+//! Listening for connections on Unix domain sockets, which are bound to a filesystem path and may
+//! be protected with file permissions. These are only supported on Unix platforms.
+//!
+//! Peers connecting through a Unix domain socket don't have an address of their own, so they're
+//! instead identified by the user ID of their process.
+
+#[cfg(unix)]
+pub use unix::{peer_uid, UnixListener, UnixStream};
+
+#[cfg(not(unix))]
+pub use unsupported::{peer_uid, UnixListener, UnixStream};
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        fs,
+        io::{Error, ErrorKind},
+        os::fd::{AsRawFd, OwnedFd, RawFd},
+        task::{Context, Poll},
+    };
+
+    pub use tokio::net::UnixStream;
+
+    /// Gets the user ID of the process at the other end of a Unix domain socket.
+    pub fn peer_uid(stream: &UnixStream) -> Result<u32, Error> {
+        stream.peer_cred().map(|cred| cred.uid())
+    }
+
+    /// A listening Unix domain socket, alongside the path it's bound to. If this process owns the
+    /// socket's file, it is removed when the listener is dropped, unless it is closed with
+    /// `close_keeping_file`.
+    pub struct UnixListener {
+        listener: tokio::net::UnixListener,
+        path: String,
+        remove_file: bool,
+    }
+
+    impl UnixListener {
+        /// Binds a Unix domain socket at the given path. If a file already exists at said path but
+        /// no one is listening on it (for example, a socket left behind by a server that crashed),
+        /// then it is replaced.
+        pub async fn bind(path: &str) -> Result<Self, Error> {
+            let listener = match tokio::net::UnixListener::bind(path) {
+                Err(error) if error.kind() == ErrorKind::AddrInUse => match UnixStream::connect(path).await {
+                    Err(connect_error) if connect_error.kind() == ErrorKind::ConnectionRefused => {
+                        fs::remove_file(path)?;
+                        tokio::net::UnixListener::bind(path)?
+                    }
+                    _ => return Err(error),
+                },
+                result => result?,
+            };
+
+            Ok(UnixListener {
+                listener,
+                path: path.to_string(),
+                remove_file: true,
+            })
+        }
+
+        /// Takes a listening Unix domain socket from a file descriptor. Returns `Ok(None)` if the
+        /// file descriptor is a socket that isn't bound to a filesystem path. If `owns_file` is
+        /// false, the socket's file belongs to someone else (such as systemd) and is never removed.
+        pub fn from_fd(fd: OwnedFd, owns_file: bool) -> Result<Option<Self>, Error> {
+            let listener = std::os::unix::net::UnixListener::from(fd);
+            let path = match listener.local_addr()?.as_pathname() {
+                Some(path) => path.to_string_lossy().into_owned(),
+                None => return Ok(None),
+            };
+
+            listener.set_nonblocking(true)?;
+            Ok(Some(UnixListener {
+                listener: tokio::net::UnixListener::from_std(listener)?,
+                path,
+                remove_file: owns_file,
+            }))
+        }
+
+        pub fn path(&self) -> &str {
+            &self.path
+        }
+
+        pub fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<Result<(UnixStream, ()), Error>> {
+            self.listener.poll_accept(cx).map_ok(|(stream, _)| (stream, ()))
+        }
+
+        /// Closes this listener without removing its socket's file, such as after handing it off
+        /// to another process which is still listening on it.
+        pub fn close_keeping_file(mut self) {
+            self.remove_file = false;
+        }
+    }
+
+    impl AsRawFd for UnixListener {
+        fn as_raw_fd(&self) -> RawFd {
+            self.listener.as_raw_fd()
+        }
+    }
+
+    impl Drop for UnixListener {
+        fn drop(&mut self) {
+            if self.remove_file {
+                let _ = fs::remove_file(&self.path);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod unsupported {
+    use std::{
+        io::{Error, ErrorKind},
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    pub enum UnixStream {}
+
+    pub enum UnixListener {}
+
+    pub fn peer_uid(stream: &UnixStream) -> Result<u32, Error> {
+        match *stream {}
+    }
+
+    impl UnixListener {
+        pub async fn bind(_path: &str) -> Result<Self, Error> {
+            Err(Error::new(
+                ErrorKind::Unsupported,
+                "Unix sockets are only supported on Unix platforms",
+            ))
+        }
+
+        pub fn path(&self) -> &str {
+            match *self {}
+        }
+
+        pub fn poll_accept(&self, _cx: &mut Context<'_>) -> Poll<Result<(UnixStream, ()), Error>> {
+            match *self {}
+        }
+
+        pub fn close_keeping_file(self) {
+            match self {}
+        }
+    }
+
+    impl AsyncRead for UnixStream {
+        fn poll_read(self: Pin<&mut Self>, _cx: &mut Context<'_>, _buf: &mut ReadBuf<'_>) -> Poll<Result<(), Error>> {
+            match *self {}
+        }
+    }
+
+    impl AsyncWrite for UnixStream {
+        fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, _buf: &[u8]) -> Poll<Result<usize, Error>> {
+            match *self {}
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            match *self {}
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            match *self {}
+        }
+    }
+}
@@ -1,11 +1,15 @@
 use std::{
     env, fmt,
     io::ErrorKind,
-    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs},
 };
 
 use dust_devil_core::{
     buffer_size::{parse_pretty_buffer_size, PrettyBufferSizeParseError},
+    ip_filter::{parse_ip_filter, IpFilterKind, IpFilterParseError, IpNetwork},
+    listener_profile::{ListenerProfile, ListenerProfileParseError, PROFILE_START_CHAR},
+    log_filter::{parse_sink_filter, EventStreamFilter, LogFilter, LogFilterParseError, LogSink},
+    sandstorm::{CloseClientsTarget, EventReplay, LockoutTarget, UNIX_SOCKET_PREFIX},
     socks5::AuthMethod,
     users::{self, UserRole},
 };
@@ -32,7 +36,9 @@ pub fn get_help_string() -> &'static str {
         "  -s, --silent                    Do not print to stdout\n",
         "  -x, --host <address>            Specify the server to connect to\n",
         "  -c, --credentials <creds>       Specify the user to log in as, in user:password format\n",
+        "  -T, --timeout <seconds>         Give up on requests that aren't answered within this time\n",
         "  -S, --shutdown                  Requests the server to shut down\n",
+        "  -D, --shutdown-drain <seconds>  Requests the server to shut down, waiting this long for clients to finish\n",
         "  -l, --list-socks5               Requests the server sends a list of socks5 sockets\n",
         "  -k, --add-socks5 <address>      Requests the server opens a new socks5 socket\n",
         "  -r, --remove-socks5 <address>   Requests the server removes an existing socks5 socket\n",
@@ -47,15 +53,36 @@ pub fn get_help_string() -> &'static str {
         "  -A, --auth-enable <auth_type>   Requests the server enables an authentication method\n",
         "  -a, --auth-disable <auth_type>  Requests the server disables an authentication method\n",
         "  -m, --get-metrics               Requests the server sends the current metrics\n",
+        "  -M, --metrics-breakdown <top>   Requests the server sends its metrics by user and destination\n",
+        "  -C, --list-clients              Requests the server sends a list of connected socks5 clients\n",
+        "  -X, --close-client <target>     Requests the server closes the connections of some socks5 clients\n",
         "  -B, --get-buffer-size           Requests the server sends the current buffer size\n",
         "  -b, --set-buffer-size <size>    Requests the server changes its buffer size\n",
+        "  -P, --get-permissions           Requests the server sends this session's permissions\n",
+        "  -j, --list-lockouts             Requests the server sends a list of active login lockouts\n",
+        "  -J, --clear-lockout <target>    Requests the server clears an active login lockout\n",
+        "  -n, --list-filters              Requests the server sends a list of IP filters\n",
+        "  -f, --add-filter <filter>       Requests the server adds a new IP filter\n",
+        "  -F, --remove-filter <filter>    Requests the server removes an existing IP filter\n",
+        "  -g, --list-log-filters          Requests the server sends the event filters of its log sinks\n",
+        "  -G, --set-log-filter <filter>   Requests the server replaces the event filter of a log sink\n",
         "  -w, --meow                      Requests a meow ping to the server\n",
         "  -o, --output-logs               Remain open and print the server's logs to stdout\n",
+        "  -e, --event-filter <filter>     Only print the logs that pass this filter (requires -o)\n",
+        "  -H, --history <replay>          Print some past logs before the live ones (requires -o)\n",
         "  -i, --interactive               Remains open with an advanced terminal UI interface\n",
         "\n",
         "Socket addresses may be specified as an IPv4 or IPv6 address, or a domainname, and may include a port number. If ",
         "no port is specified, then the appropriate default will be used (1080 for Socks5 and 2222 for Sandstorm). If no ",
-        "-x/--host parameter is specified, then localhost:2222 will be used.\n",
+        "-x/--host parameter is specified, then localhost:2222 will be used. To connect through a Unix socket instead, ",
+        "specify its path prefixed by \"unix:\", for example -x unix:/run/dust-devil/sandstorm.sock.\n",
+        "\n",
+        "When adding a socks5 socket, the address may be followed by an equals sign and a profile, which overrides the ",
+        "server-wide settings for clients connecting through that socket. A profile is a comma-separated list of options: ",
+        "\"noauth\" or \"-noauth\" and \"userpass\" or \"-userpass\" enable or disable an authentication method, ",
+        "\"buffer:<size>\" sets the buffer size, and \"role:<role>\" and \"user:<username>\" only allow users with one of ",
+        "the given roles and one of the given usernames to log in (so if both are given, a user must match both). For ",
+        "example, -k \"0.0.0.0:1081=-noauth,role:admin\".\n",
         "\n",
         "Credentials may be specified with the -c/--credentials argument, in username:password format. If no credentials ",
         "argument is specified, then the credentials will be taken from the SANDSTORM_USER environment variable, which must ",
@@ -74,11 +101,45 @@ pub fn get_help_string() -> &'static str {
         "Buffer sizes may be specified in bytes ('-b 8192'), kilobytes ('-b 8K'), megabytes ('-b 1M') or gigabytes ('-b 1G' ",
         "if you respect your computer, please don't) but may not be equal to nor larger than 4GBs.\n",
         "\n",
+        "When clearing a login lockout, the target may be an IP address, a username, \"uid:\" followed by the user ID of ",
+        "managers connecting through Unix sockets, or \"all\" to clear all the active lockouts.\n",
+        "\n",
+        "IP filters are specified as \"allow\" or \"deny\", a colon, the listening socket's address, an equals sign, and ",
+        "a network. For example, \"allow:[::]:2222=10.5.0.0/16\" only allows connections to the Sandstorm socket at ",
+        "[::]:2222 from the 10.5.0.0/16 network, and \"deny:0.0.0.0:1080=192.168.1.10\" rejects connections from ",
+        "192.168.1.10 to the SOCKS5 socket at 0.0.0.0:1080.\n",
+        "\n",
+        "When closing clients, the target may be a client ID, an IP address to close all the clients connecting from ",
+        "it, or \"user:<username>\" to close all the clients authenticated as that user. For example, -X 42 or -X ",
+        "user:pedro.\n",
+        "\n",
+        "The metrics breakdown lists the users and destination hosts with the most bytes transferred, up to the given ",
+        "amount of each (or all of them if 0), alongside histograms of how long connecting to destinations took and how ",
+        "long clients stayed connected. Only clients that have already disconnected are counted.\n",
+        "\n",
+        "Log filters are specified as the log sink (\"stdout\", \"file\", \"syslog\" or \"journald\"), a colon, and ",
+        "a comma-separated list of settings: \"severity=<level>\" only logs events at least as severe as the given level ",
+        "(\"error\", \"warning\", \"notice\", \"info\" or \"debug\"), \"include=<types>\" and \"exclude=<types>\" ",
+        "only log or don't log the given event types (which may end with '*' to match by prefix), and \"clients=<ids>\" ",
+        "and \"users=<usernames>\" only log the client events of the given clients or users. Lists are separated by '+'. ",
+        "For example, \"file:severity=notice,exclude=client_*\", or \"syslog:all\" to remove the syslog sink's filter.\n",
+        "\n",
+        "The -e/--event-filter setting filters the logs printed by -o/--output-logs on the server's side, and is specified ",
+        "as the same list of settings as a log filter without the sink, plus \"listeners=<addresses>\" to only print the ",
+        "client events of the clients that connected through the given socks5 sockets. For example, -o -e ",
+        "\"severity=info,listeners=0.0.0.0:1080\".\n",
+        "\n",
+        "The -H/--history setting has the server replay some of its most recently logged events before printing the ",
+        "live ones, and is specified as either an amount of events, or \"since:<timestamp>\" with a UNIX timestamp in ",
+        "seconds. For example, -o -H 100 or -o -H since:1700000000. The server only keeps a limited amount of past events, ",
+        "so fewer than requested may be printed. If an event filter is specified, it also applies to the replayed events.\n",
+        "\n",
         "The requests are done in the order in which they're specified and their results printed to stdout (unless ",
         "-s/--silent is specified). Pipelining will be used, so the requests are not guaranteed to come back in the same ",
         "order. The only ordering guarantees are those defined in the Sandstorm protocol (so, for example, list/add/remove ",
         "socks5 sockets operations are guaranteed to be handled in order and answered in order, but an add user request in ",
-        "the middle of all that may not come back in the same order).\n",
+        "the middle of all that may not come back in the same order). If -T/--timeout is specified, requests that aren't ",
+        "answered in time are printed as timed out, and their responses are ignored if they arrive later.\n",
         "\n",
         "The -o/--output-logs and -i/--interactive modes are mutually exclusive, only one may be enabled.\n",
         "\n",
@@ -113,6 +174,7 @@ pub fn get_help_string() -> &'static str {
 }
 
 #[derive(Debug, PartialEq, Eq)]
+#[allow(clippy::large_enum_variant)] // Only ever constructed once, at startup.
 pub enum ArgumentsRequest {
     Help,
     Version,
@@ -121,9 +183,9 @@ pub enum ArgumentsRequest {
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum CommandRequest {
-    Shutdown,
+    Shutdown(Option<u32>),
     ListSocks5Sockets,
-    AddSocks5Socket(SocketAddr),
+    AddSocks5Socket(SocketAddr, ListenerProfile),
     RemoveSocks5Socket(SocketAddr),
     ListSandstormSockets,
     AddSandstormSocket(SocketAddr),
@@ -135,8 +197,19 @@ pub enum CommandRequest {
     ListAuthMethods,
     ToggleAuthMethod(AuthMethod, bool),
     GetMetrics,
+    GetMetricsBreakdown(u16),
+    ListClients,
+    CloseClients(CloseClientsTarget),
     GetBufferSize,
     SetBufferSize(u32),
+    GetPermissions,
+    ListLockouts,
+    ClearLockouts(Option<LockoutTarget>),
+    ListIpFilters,
+    AddIpFilter(SocketAddr, IpFilterKind, IpNetwork),
+    RemoveIpFilter(SocketAddr, IpFilterKind, IpNetwork),
+    ListLogFilters,
+    SetLogFilter(LogSink, LogFilter),
     Meow,
 }
 
@@ -145,9 +218,13 @@ pub struct StartupArguments {
     pub verbose: bool,
     pub silent: bool,
     pub server_address: Vec<SocketAddr>,
+    pub server_unix_socket: Option<String>,
     pub login_credentials: (String, String),
+    pub request_timeout: Option<u32>,
     pub requests: Vec<CommandRequest>,
     pub output_logs: bool,
+    pub event_filter: Option<EventStreamFilter>,
+    pub event_replay: Option<EventReplay>,
     pub interactive: bool,
 }
 
@@ -157,15 +234,19 @@ impl StartupArguments {
             verbose: false,
             silent: false,
             server_address: Vec::new(),
+            server_unix_socket: None,
             login_credentials: (String::new(), String::new()),
+            request_timeout: None,
             requests: Vec::new(),
             output_logs: false,
+            event_filter: None,
+            event_replay: None,
             interactive: false,
         }
     }
 
     pub fn fill_empty_fields_with_defaults(&mut self) {
-        if self.server_address.is_empty() {
+        if self.server_address.is_empty() && self.server_unix_socket.is_none() {
             self.server_address
                 .push(SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::LOCALHOST, DEFAULT_SANDSTORM_PORT, 0, 0)));
             self.server_address
@@ -198,6 +279,18 @@ pub enum ArgumentsError {
     DeleteUserError(DeleteUserErrorType),
     AuthToggleError(AuthToggleErrorType),
     BufferSizeError(BufferSizeErrorType),
+    ClearLockoutError(ClearLockoutErrorType),
+    AddIpFilterError(IpFilterErrorType),
+    RemoveIpFilterError(IpFilterErrorType),
+    SetLogFilterError(LogFilterErrorType),
+    ShutdownDrainError(ShutdownDrainErrorType),
+    RequestTimeoutError(RequestTimeoutErrorType),
+    MetricsBreakdownError(MetricsBreakdownErrorType),
+    CloseClientError(CloseClientErrorType),
+    EventFilterError(EventFilterErrorType),
+    EventFilterWithoutOutput,
+    HistoryError(HistoryErrorType),
+    HistoryWithoutOutput,
     CantMixOutputAndInteractive,
 }
 
@@ -218,6 +311,18 @@ impl fmt::Display for ArgumentsError {
             Self::DeleteUserError(delete_user_error) => delete_user_error.fmt(f),
             Self::AuthToggleError(auth_toggle_error) => auth_toggle_error.fmt(f),
             Self::BufferSizeError(buffer_size_error) => buffer_size_error.fmt(f),
+            Self::ClearLockoutError(clear_lockout_error) => clear_lockout_error.fmt(f),
+            Self::AddIpFilterError(add_ip_filter_error) => add_ip_filter_error.fmt(f),
+            Self::RemoveIpFilterError(remove_ip_filter_error) => remove_ip_filter_error.fmt(f),
+            Self::SetLogFilterError(set_log_filter_error) => set_log_filter_error.fmt(f),
+            Self::ShutdownDrainError(shutdown_drain_error) => shutdown_drain_error.fmt(f),
+            Self::RequestTimeoutError(request_timeout_error) => request_timeout_error.fmt(f),
+            Self::MetricsBreakdownError(metrics_breakdown_error) => metrics_breakdown_error.fmt(f),
+            Self::CloseClientError(close_client_error) => close_client_error.fmt(f),
+            Self::EventFilterError(event_filter_error) => event_filter_error.fmt(f),
+            Self::EventFilterWithoutOutput => write!(f, "Cannot specify -e/--event-filter without -o/--output-logs"),
+            Self::HistoryError(history_error) => history_error.fmt(f),
+            Self::HistoryWithoutOutput => write!(f, "Cannot specify -H/--history without -o/--output-logs"),
             Self::CantMixOutputAndInteractive => write!(f, "Cannot specify both -o/--output-logs and -i/--interactive together"),
         }
     }
@@ -227,6 +332,7 @@ impl fmt::Display for ArgumentsError {
 pub enum SocketErrorType {
     UnexpectedEnd(String),
     InvalidSocketAddress(String, String),
+    InvalidProfile(String, String, ListenerProfileParseError),
 }
 
 impl fmt::Display for SocketErrorType {
@@ -234,6 +340,7 @@ impl fmt::Display for SocketErrorType {
         match self {
             Self::UnexpectedEnd(arg) => write!(f, "Expected socket address after {arg}"),
             Self::InvalidSocketAddress(arg, addr) => write!(f, "Invalid socket address after {arg}: {addr}"),
+            Self::InvalidProfile(arg, addr, error) => write!(f, "Invalid socket profile after {arg}: {addr}: {error}"),
         }
     }
 }
@@ -267,6 +374,37 @@ fn parse_socket_arg(
     Ok(())
 }
 
+fn parse_host_arg(result: &mut StartupArguments, arg: String, maybe_arg2: Option<String>) -> Result<(), SocketErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(value) => value,
+        None => return Err(SocketErrorType::UnexpectedEnd(arg)),
+    };
+
+    match arg2.strip_prefix(UNIX_SOCKET_PREFIX) {
+        Some("") => Err(SocketErrorType::InvalidSocketAddress(arg, arg2)),
+        Some(path) => {
+            result.server_unix_socket = Some(path.to_string());
+            Ok(())
+        }
+        None => parse_socket_arg(&mut result.server_address, arg, Some(arg2), DEFAULT_SANDSTORM_PORT),
+    }
+}
+
+fn parse_socket_profile_arg(arg: &str, maybe_arg2: Option<String>) -> Result<(Option<String>, ListenerProfile), SocketErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(value) => value,
+        None => return Ok((None, ListenerProfile::default())),
+    };
+
+    match arg2.split_once(PROFILE_START_CHAR) {
+        Some((address, profile)) => match profile.parse::<ListenerProfile>() {
+            Ok(profile) => Ok((Some(address.to_string()), profile)),
+            Err(error) => Err(SocketErrorType::InvalidProfile(arg.to_string(), arg2, error)),
+        },
+        None => Ok((Some(arg2), ListenerProfile::default())),
+    }
+}
+
 pub enum ParseIntoUserError {
     Empty,
     InvalidRoleChar(char),
@@ -606,6 +744,339 @@ fn parse_delete_user(arg: String, maybe_arg2: Option<String>) -> Result<String,
     }
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShutdownDrainErrorType {
+    UnexpectedEnd(String),
+    InvalidValue(String, String),
+}
+
+impl fmt::Display for ShutdownDrainErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected drain timeout in seconds after {arg}"),
+            Self::InvalidValue(arg, arg2) => write!(f, "Invalid drain timeout at {arg} {arg2}"),
+        }
+    }
+}
+
+impl From<ShutdownDrainErrorType> for ArgumentsError {
+    fn from(value: ShutdownDrainErrorType) -> Self {
+        Self::ShutdownDrainError(value)
+    }
+}
+
+fn parse_shutdown_drain(arg: String, maybe_arg2: Option<String>) -> Result<u32, ShutdownDrainErrorType> {
+    match maybe_arg2 {
+        None => Err(ShutdownDrainErrorType::UnexpectedEnd(arg)),
+        Some(arg2) => arg2.trim().parse().map_err(|_| ShutdownDrainErrorType::InvalidValue(arg, arg2)),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RequestTimeoutErrorType {
+    UnexpectedEnd(String),
+    InvalidValue(String, String),
+}
+
+impl fmt::Display for RequestTimeoutErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected request timeout in seconds after {arg}"),
+            Self::InvalidValue(arg, arg2) => write!(f, "Invalid request timeout at {arg} {arg2}, expected a positive amount of seconds"),
+        }
+    }
+}
+
+impl From<RequestTimeoutErrorType> for ArgumentsError {
+    fn from(value: RequestTimeoutErrorType) -> Self {
+        Self::RequestTimeoutError(value)
+    }
+}
+
+fn parse_request_timeout(arg: String, maybe_arg2: Option<String>) -> Result<u32, RequestTimeoutErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(value) => value,
+        None => return Err(RequestTimeoutErrorType::UnexpectedEnd(arg)),
+    };
+
+    match arg2.trim().parse() {
+        Ok(timeout) if timeout != 0 => Ok(timeout),
+        _ => Err(RequestTimeoutErrorType::InvalidValue(arg, arg2)),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MetricsBreakdownErrorType {
+    UnexpectedEnd(String),
+    InvalidValue(String, String),
+}
+
+impl fmt::Display for MetricsBreakdownErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected amount of top users and destinations after {arg}"),
+            Self::InvalidValue(arg, arg2) => write!(f, "Invalid amount of top users and destinations at {arg} {arg2}"),
+        }
+    }
+}
+
+impl From<MetricsBreakdownErrorType> for ArgumentsError {
+    fn from(value: MetricsBreakdownErrorType) -> Self {
+        Self::MetricsBreakdownError(value)
+    }
+}
+
+fn parse_metrics_breakdown(arg: String, maybe_arg2: Option<String>) -> Result<u16, MetricsBreakdownErrorType> {
+    match maybe_arg2 {
+        None => Err(MetricsBreakdownErrorType::UnexpectedEnd(arg)),
+        Some(arg2) => arg2.trim().parse().map_err(|_| MetricsBreakdownErrorType::InvalidValue(arg, arg2)),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ClearLockoutErrorType {
+    UnexpectedEnd(String),
+    Empty(String),
+    UsernameTooLong(String),
+}
+
+impl fmt::Display for ClearLockoutErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected lockout target after {arg}"),
+            Self::Empty(arg) => write!(f, "Empty lockout target after {arg}"),
+            Self::UsernameTooLong(arg) => write!(f, "Username after {arg} too long"),
+        }
+    }
+}
+
+impl From<ClearLockoutErrorType> for ArgumentsError {
+    fn from(value: ClearLockoutErrorType) -> Self {
+        Self::ClearLockoutError(value)
+    }
+}
+
+/// The prefix for specifying a lockout on the managers connecting through Unix sockets as a
+/// given user ID.
+const UNIX_USER_LOCKOUT_PREFIX: &str = "uid:";
+
+fn parse_clear_lockout(arg: String, maybe_arg2: Option<String>) -> Result<Option<LockoutTarget>, ClearLockoutErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(value) => value,
+        None => return Err(ClearLockoutErrorType::UnexpectedEnd(arg)),
+    };
+
+    if arg2.is_empty() {
+        return Err(ClearLockoutErrorType::Empty(arg));
+    }
+
+    if arg2.eq_ignore_ascii_case("all") {
+        return Ok(None);
+    }
+
+    if let Some(uid) = arg2.strip_prefix(UNIX_USER_LOCKOUT_PREFIX).and_then(|uid| uid.parse().ok()) {
+        return Ok(Some(LockoutTarget::UnixUser(uid)));
+    }
+
+    match arg2.parse::<IpAddr>() {
+        Ok(address) => Ok(Some(LockoutTarget::Address(address))),
+        Err(_) if arg2.len() > 255 => Err(ClearLockoutErrorType::UsernameTooLong(arg)),
+        Err(_) => Ok(Some(LockoutTarget::Username(arg2))),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CloseClientErrorType {
+    UnexpectedEnd(String),
+    InvalidTarget(String, String),
+    UsernameTooLong(String),
+}
+
+impl fmt::Display for CloseClientErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected client target after {arg}"),
+            Self::InvalidTarget(arg, arg2) => write!(f, "Invalid client target after {arg}: {arg2}"),
+            Self::UsernameTooLong(arg) => write!(f, "Username after {arg} too long"),
+        }
+    }
+}
+
+impl From<CloseClientErrorType> for ArgumentsError {
+    fn from(value: CloseClientErrorType) -> Self {
+        Self::CloseClientError(value)
+    }
+}
+
+fn parse_close_client(arg: String, maybe_arg2: Option<String>) -> Result<CloseClientsTarget, CloseClientErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(value) => value,
+        None => return Err(CloseClientErrorType::UnexpectedEnd(arg)),
+    };
+
+    if let Some(username) = arg2.strip_prefix("user:") {
+        return match username.len() {
+            0 => Err(CloseClientErrorType::InvalidTarget(arg, arg2)),
+            1..=255 => Ok(CloseClientsTarget::Username(username.to_string())),
+            _ => Err(CloseClientErrorType::UsernameTooLong(arg)),
+        };
+    }
+
+    if let Ok(client_id) = arg2.parse::<u64>() {
+        return Ok(CloseClientsTarget::Client(client_id));
+    }
+
+    match arg2.parse::<IpAddr>() {
+        Ok(address) => Ok(CloseClientsTarget::Address(address)),
+        Err(_) => Err(CloseClientErrorType::InvalidTarget(arg, arg2)),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IpFilterErrorType {
+    UnexpectedEnd(String),
+    InvalidKind(String, String),
+    InvalidFilter(String, String, IpFilterParseError),
+}
+
+impl fmt::Display for IpFilterErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected IP filter after {arg}"),
+            Self::InvalidKind(arg, arg2) => write!(f, "IP filter must start with allow: or deny: at {arg} {arg2}"),
+            Self::InvalidFilter(arg, arg2, error) => write!(f, "Invalid IP filter at {arg} {arg2}: {error}"),
+        }
+    }
+}
+
+fn parse_ip_filter_arg(arg: String, maybe_arg2: Option<String>) -> Result<(SocketAddr, IpFilterKind, IpNetwork), IpFilterErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(value) => value,
+        None => return Err(IpFilterErrorType::UnexpectedEnd(arg)),
+    };
+
+    let (kind, filter) = match arg2.split_once(':') {
+        Some((kind, filter)) if kind.trim().eq_ignore_ascii_case("allow") => (IpFilterKind::Allow, filter),
+        Some((kind, filter)) if kind.trim().eq_ignore_ascii_case("deny") => (IpFilterKind::Deny, filter),
+        _ => return Err(IpFilterErrorType::InvalidKind(arg, arg2)),
+    };
+
+    match parse_ip_filter(filter) {
+        Ok((listener, network)) => Ok((listener, kind, network)),
+        Err(error) => Err(IpFilterErrorType::InvalidFilter(arg, arg2, error)),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum LogFilterErrorType {
+    UnexpectedEnd(String),
+    MissingSink(String, String),
+    InvalidFilter(String, String, LogFilterParseError),
+}
+
+impl fmt::Display for LogFilterErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected log filter after {arg}"),
+            Self::MissingSink(arg, arg2) => write!(f, "Log filter must start with a log sink and a colon at {arg} {arg2}"),
+            Self::InvalidFilter(arg, arg2, error) => write!(f, "Invalid log filter at {arg} {arg2}: {error}"),
+        }
+    }
+}
+
+impl From<LogFilterErrorType> for ArgumentsError {
+    fn from(value: LogFilterErrorType) -> Self {
+        ArgumentsError::SetLogFilterError(value)
+    }
+}
+
+fn parse_log_filter_arg(arg: String, maybe_arg2: Option<String>) -> Result<(LogSink, LogFilter), LogFilterErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(value) => value,
+        None => return Err(LogFilterErrorType::UnexpectedEnd(arg)),
+    };
+
+    match parse_sink_filter(&arg2) {
+        Ok((Some(sink), filter)) => Ok((sink, filter)),
+        Ok((None, _)) => Err(LogFilterErrorType::MissingSink(arg, arg2)),
+        Err(error) => Err(LogFilterErrorType::InvalidFilter(arg, arg2, error)),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EventFilterErrorType {
+    UnexpectedEnd(String),
+    InvalidFilter(String, String, LogFilterParseError),
+}
+
+impl fmt::Display for EventFilterErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected event filter after {arg}"),
+            Self::InvalidFilter(arg, arg2, error) => write!(f, "Invalid event filter at {arg} {arg2}: {error}"),
+        }
+    }
+}
+
+impl From<EventFilterErrorType> for ArgumentsError {
+    fn from(value: EventFilterErrorType) -> Self {
+        ArgumentsError::EventFilterError(value)
+    }
+}
+
+fn parse_event_filter_arg(arg: String, maybe_arg2: Option<String>) -> Result<EventStreamFilter, EventFilterErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(value) => value,
+        None => return Err(EventFilterErrorType::UnexpectedEnd(arg)),
+    };
+
+    match arg2.parse() {
+        Ok(filter) => Ok(filter),
+        Err(error) => Err(EventFilterErrorType::InvalidFilter(arg, arg2, error)),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HistoryErrorType {
+    UnexpectedEnd(String),
+    InvalidValue(String, String),
+}
+
+impl fmt::Display for HistoryErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd(arg) => write!(f, "Expected history replay after {arg}"),
+            Self::InvalidValue(arg, arg2) => write!(
+                f,
+                "Invalid history replay at {arg} {arg2}, expected an amount of events or since:<timestamp>"
+            ),
+        }
+    }
+}
+
+impl From<HistoryErrorType> for ArgumentsError {
+    fn from(value: HistoryErrorType) -> Self {
+        ArgumentsError::HistoryError(value)
+    }
+}
+
+fn parse_history_arg(arg: String, maybe_arg2: Option<String>) -> Result<EventReplay, HistoryErrorType> {
+    let arg2 = match maybe_arg2 {
+        Some(value) => value,
+        None => return Err(HistoryErrorType::UnexpectedEnd(arg)),
+    };
+
+    let result = match arg2.strip_prefix("since:") {
+        Some(timestamp) => timestamp.parse().map(EventReplay::Since).ok(),
+        None => arg2.parse().map(EventReplay::Last).ok(),
+    };
+
+    match result {
+        Some(replay) => Ok(replay),
+        None => Err(HistoryErrorType::InvalidValue(arg, arg2)),
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum AuthToggleErrorType {
     UnexpectedEnd(String),
@@ -715,17 +1186,23 @@ where
         } else if arg.eq("-s") || arg.eq_ignore_ascii_case("--silent") {
             result.silent = true;
         } else if arg.eq("-x") || arg.eq_ignore_ascii_case("--host") {
-            parse_socket_arg(&mut result.server_address, arg, args.next(), DEFAULT_SANDSTORM_PORT).map_err(ArgumentsError::HostError)?;
+            parse_host_arg(&mut result, arg, args.next()).map_err(ArgumentsError::HostError)?;
         } else if arg.eq("-S") || arg.eq_ignore_ascii_case("--shutdown") {
-            result.requests.push(CommandRequest::Shutdown);
+            result.requests.push(CommandRequest::Shutdown(None));
+        } else if arg.eq("-D") || arg.eq_ignore_ascii_case("--shutdown-drain") {
+            let drain_timeout = parse_shutdown_drain(arg, args.next())?;
+            result.requests.push(CommandRequest::Shutdown(Some(drain_timeout)));
         } else if arg.eq("-c") || arg.eq_ignore_ascii_case("--credentials") {
             parse_credentials(&mut result.login_credentials, arg, args.next())?;
+        } else if arg.eq("-T") || arg.eq_ignore_ascii_case("--timeout") {
+            result.request_timeout = Some(parse_request_timeout(arg, args.next())?);
         } else if arg.eq("-l") || arg.eq_ignore_ascii_case("--list-socks5") {
             result.requests.push(CommandRequest::ListSocks5Sockets);
         } else if arg.eq("-k") || arg.eq_ignore_ascii_case("--add-socks5") {
-            parse_socket_arg(&mut tmp_sockets_vec, arg, args.next(), DEFAULT_SOCKS5_PORT).map_err(ArgumentsError::AddSocks5Error)?;
+            let (address, profile) = parse_socket_profile_arg(&arg, args.next()).map_err(ArgumentsError::AddSocks5Error)?;
+            parse_socket_arg(&mut tmp_sockets_vec, arg, address, DEFAULT_SOCKS5_PORT).map_err(ArgumentsError::AddSocks5Error)?;
             for socket in &tmp_sockets_vec {
-                result.requests.push(CommandRequest::AddSocks5Socket(*socket));
+                result.requests.push(CommandRequest::AddSocks5Socket(*socket, profile.clone()));
             }
             tmp_sockets_vec.clear();
         } else if arg.eq("-r") || arg.eq_ignore_ascii_case("--remove-socks5") {
@@ -772,11 +1249,39 @@ where
             result.requests.push(CommandRequest::ToggleAuthMethod(auth_method, false));
         } else if arg.eq("-m") || arg.eq_ignore_ascii_case("--get-metrics") {
             result.requests.push(CommandRequest::GetMetrics);
+        } else if arg.eq("-M") || arg.eq_ignore_ascii_case("--metrics-breakdown") {
+            let top = parse_metrics_breakdown(arg, args.next())?;
+            result.requests.push(CommandRequest::GetMetricsBreakdown(top));
+        } else if arg.eq("-C") || arg.eq_ignore_ascii_case("--list-clients") {
+            result.requests.push(CommandRequest::ListClients);
+        } else if arg.eq("-X") || arg.eq_ignore_ascii_case("--close-client") {
+            let target = parse_close_client(arg, args.next())?;
+            result.requests.push(CommandRequest::CloseClients(target));
         } else if arg.eq("-B") || arg.eq_ignore_ascii_case("--get-buffer-size") {
             result.requests.push(CommandRequest::GetBufferSize);
         } else if arg.eq("-b") || arg.eq_ignore_ascii_case("--set-buffer-size") {
             let buffer_size = parse_buffer_size_arg(arg, args.next())?;
             result.requests.push(CommandRequest::SetBufferSize(buffer_size));
+        } else if arg.eq("-P") || arg.eq_ignore_ascii_case("--get-permissions") {
+            result.requests.push(CommandRequest::GetPermissions);
+        } else if arg.eq("-j") || arg.eq_ignore_ascii_case("--list-lockouts") {
+            result.requests.push(CommandRequest::ListLockouts);
+        } else if arg.eq("-J") || arg.eq_ignore_ascii_case("--clear-lockout") {
+            let target = parse_clear_lockout(arg, args.next())?;
+            result.requests.push(CommandRequest::ClearLockouts(target));
+        } else if arg.eq("-n") || arg.eq_ignore_ascii_case("--list-filters") {
+            result.requests.push(CommandRequest::ListIpFilters);
+        } else if arg.eq("-f") || arg.eq_ignore_ascii_case("--add-filter") {
+            let (listener, kind, network) = parse_ip_filter_arg(arg, args.next()).map_err(ArgumentsError::AddIpFilterError)?;
+            result.requests.push(CommandRequest::AddIpFilter(listener, kind, network));
+        } else if arg.eq("-F") || arg.eq_ignore_ascii_case("--remove-filter") {
+            let (listener, kind, network) = parse_ip_filter_arg(arg, args.next()).map_err(ArgumentsError::RemoveIpFilterError)?;
+            result.requests.push(CommandRequest::RemoveIpFilter(listener, kind, network));
+        } else if arg.eq("-g") || arg.eq_ignore_ascii_case("--list-log-filters") {
+            result.requests.push(CommandRequest::ListLogFilters);
+        } else if arg.eq("-G") || arg.eq_ignore_ascii_case("--set-log-filter") {
+            let (sink, filter) = parse_log_filter_arg(arg, args.next())?;
+            result.requests.push(CommandRequest::SetLogFilter(sink, filter));
         } else if arg.eq("-w") || arg.eq_ignore_ascii_case("--meow") {
             result.requests.push(CommandRequest::Meow);
         } else if arg.eq("-o") || arg.eq_ignore_ascii_case("--output-logs") {
@@ -784,6 +1289,10 @@ where
                 return Err(ArgumentsError::CantMixOutputAndInteractive);
             }
             result.output_logs = true;
+        } else if arg.eq("-e") || arg.eq_ignore_ascii_case("--event-filter") {
+            result.event_filter = Some(parse_event_filter_arg(arg, args.next())?);
+        } else if arg.eq("-H") || arg.eq_ignore_ascii_case("--history") {
+            result.event_replay = Some(parse_history_arg(arg, args.next())?);
         } else if arg.eq("-i") || arg.eq_ignore_ascii_case("--interactive") {
             if result.output_logs {
                 return Err(ArgumentsError::CantMixOutputAndInteractive);
@@ -794,6 +1303,14 @@ where
         }
     }
 
+    if result.event_filter.is_some() && !result.output_logs {
+        return Err(ArgumentsError::EventFilterWithoutOutput);
+    }
+
+    if result.event_replay.is_some() && !result.output_logs {
+        return Err(ArgumentsError::HistoryWithoutOutput);
+    }
+
     if result.login_credentials.0.is_empty() {
         result.login_credentials = match parse_env_credentials()? {
             Some(creds) => creds,
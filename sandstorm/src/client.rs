@@ -1,10 +1,12 @@
 use std::{
     io::{Error, ErrorKind},
     net::SocketAddr,
+    time::Duration,
 };
 
 use dust_devil_core::{
-    sandstorm::{SandstormHandshakeRef, SandstormHandshakeStatus},
+    log_filter::EventStreamFilter,
+    sandstorm::{EventReplay, SandstormHandshakeRef, SandstormHandshakeStatus, SandstormNegotiation, SANDSTORM_VERSION},
     serialize::{ByteRead, ByteWrite},
 };
 use tokio::{
@@ -53,7 +55,23 @@ async fn run_client_inner(startup_args: StartupArguments) -> Result<(), Error> {
         "Will use read buffer size of {read_buffer_size} and write buffer size of {write_buffer_size}"
     );
 
-    let socket = match connect(startup_args.verbose, startup_args.server_address).await {
+    if let Some(path) = &startup_args.server_unix_socket {
+        let socket = match connect_unix(startup_args.verbose, path).await {
+            Ok(sock) => {
+                printlnif!(!startup_args.silent, "Connected to {path}");
+                sock
+            }
+            Err(error) => {
+                eprintln!("Failed to connect to server: {error}");
+                return Ok(());
+            }
+        };
+
+        let (read_half, write_half) = socket.into_split();
+        return run_session(startup_args, read_buffer_size, write_buffer_size, read_half, write_half).await;
+    }
+
+    let socket = match connect(startup_args.verbose, startup_args.server_address.clone()).await {
         Ok((sock, addr)) => {
             printlnif!(!startup_args.silent, "Connected to {addr}");
             sock
@@ -64,10 +82,24 @@ async fn run_client_inner(startup_args: StartupArguments) -> Result<(), Error> {
         }
     };
 
-    let (mut read_half, write_half) = socket.into_split();
+    let (read_half, write_half) = socket.into_split();
+    run_session(startup_args, read_buffer_size, write_buffer_size, read_half, write_half).await
+}
+
+async fn run_session<R, W>(
+    startup_args: StartupArguments,
+    read_buffer_size: usize,
+    write_buffer_size: usize,
+    mut read_half: R,
+    write_half: W,
+) -> Result<(), Error>
+where
+    R: AsyncRead + Unpin + 'static,
+    W: AsyncWrite + Unpin + 'static,
+{
     let mut writer_buf = BufWriter::with_capacity(write_buffer_size, write_half);
 
-    let handshake_status = handshake(
+    let negotiation = handshake(
         startup_args.verbose,
         startup_args.silent,
         &startup_args.login_credentials.0,
@@ -77,13 +109,15 @@ async fn run_client_inner(startup_args: StartupArguments) -> Result<(), Error> {
     )
     .await?;
 
-    if !handshake_status {
-        return Ok(());
-    }
+    let negotiation = match negotiation {
+        Some(value) => value,
+        None => return Ok(()),
+    };
 
     let reader_buf = BufReader::with_capacity(read_buffer_size, read_half);
 
-    let (manager, read_error_recevier) = SandstormRequestManager::new(reader_buf, writer_buf);
+    let (mut manager, read_error_recevier) = SandstormRequestManager::new(reader_buf, writer_buf, negotiation);
+    manager.set_request_timeout(startup_args.request_timeout.map(|secs| Duration::from_secs(secs as u64)));
 
     let mut terminal_reset_required = false;
     let result = select! {
@@ -105,6 +139,8 @@ async fn run_client_inner(startup_args: StartupArguments) -> Result<(), Error> {
             startup_args.silent,
             &startup_args.requests,
             startup_args.output_logs,
+            startup_args.event_filter.clone(),
+            startup_args.event_replay,
             startup_args.interactive,
             &mut terminal_reset_required,
             manager
@@ -152,12 +188,35 @@ async fn connect(verbose: bool, addresses: Vec<SocketAddr>) -> Result<(TcpStream
     Err(last_error.unwrap_or_else(|| Error::new(ErrorKind::InvalidInput, "No addresses specified!")))
 }
 
-async fn handshake<R, W>(verbose: bool, silent: bool, username: &str, password: &str, writer: &mut W, reader: &mut R) -> Result<bool, Error>
+#[cfg(unix)]
+async fn connect_unix(verbose: bool, path: &str) -> Result<tokio::net::UnixStream, Error> {
+    printlnif!(verbose, "Attempting to connect to {path}");
+    tokio::net::UnixStream::connect(path).await
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(_verbose: bool, _path: &str) -> Result<TcpStream, Error> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        "Unix sockets are only supported on Unix platforms",
+    ))
+}
+
+/// Performs the handshake, returning the version and capabilities negotiated with the server if it
+/// succeeded.
+async fn handshake<R, W>(
+    verbose: bool,
+    silent: bool,
+    username: &str,
+    password: &str,
+    writer: &mut W,
+    reader: &mut R,
+) -> Result<Option<SandstormNegotiation>, Error>
 where
     R: AsyncRead + Unpin + ?Sized,
     W: AsyncWrite + Unpin + ?Sized,
 {
-    printlnif!(verbose, "Sending handshake");
+    printlnif!(verbose, "Sending handshake for version {SANDSTORM_VERSION}");
     SandstormHandshakeRef::new(username, password).write(writer).await?;
     writer.flush().await?;
 
@@ -172,14 +231,31 @@ where
         SandstormHandshakeStatus::UnspecifiedError => eprintln!("Handshake failed with unspecified error"),
     }
 
-    Ok(result == SandstormHandshakeStatus::Ok)
+    if result != SandstormHandshakeStatus::Ok {
+        return Ok(None);
+    }
+
+    let negotiation = SandstormNegotiation::read(reader).await?;
+    if verbose {
+        let capabilities: Vec<_> = negotiation.capabilities.iter().map(|c| c.to_str()).collect();
+        println!(
+            "Negotiated version {} with capabilities: {}",
+            negotiation.version,
+            capabilities.join(", ")
+        );
+    }
+
+    Ok(Some(negotiation))
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_connection<W>(
     verbose: bool,
     silent: bool,
     requests: &Vec<CommandRequest>,
     output_logs: bool,
+    event_filter: Option<EventStreamFilter>,
+    event_replay: Option<EventReplay>,
     interactive: bool,
     terminal_reset_required: &mut bool,
     mut manager: SandstormRequestManager<W>,
@@ -194,7 +270,7 @@ where
         manager.flush_and_wait().await?;
 
         if output_logs {
-            handle_output(verbose, manager).await
+            handle_output(verbose, event_filter, event_replay, manager).await
         } else {
             handle_interactive(verbose, manager, terminal_reset_required).await
         }
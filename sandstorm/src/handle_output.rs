@@ -1,6 +1,9 @@
 use std::io::{Error, ErrorKind};
 
-use dust_devil_core::sandstorm::{EventStreamConfigResponse, EventStreamResponse};
+use dust_devil_core::{
+    log_filter::EventStreamFilter,
+    sandstorm::{EventReplay, EventStreamConfigResponse, EventStreamResponse, Metrics, SandstormCapability},
+};
 use time::{OffsetDateTime, UtcOffset};
 use tokio::{io::AsyncWrite, sync::oneshot};
 
@@ -9,7 +12,9 @@ use crate::{
     sandstorm::{EventStreamReceiver, SandstormRequestManager},
 };
 
-fn get_event_handler_fn() -> impl FnMut(EventStreamResponse) {
+/// Returns the function that prints the streamed events, after the given amount of replayed past
+/// events prints a line marking the start of the live events.
+fn get_event_handler_fn(mut replayed_remaining: u16) -> impl FnMut(EventStreamResponse) {
     let utc_offset = match UtcOffset::current_local_offset() {
         Ok(offset) => offset,
         Err(_) => {
@@ -34,17 +39,52 @@ fn get_event_handler_fn() -> impl FnMut(EventStreamResponse) {
             t.second(),
             event.data
         );
+
+        if replayed_remaining != 0 {
+            replayed_remaining -= 1;
+            if replayed_remaining == 0 {
+                println!("End of replayed events, live events follow");
+            }
+        }
     }
 }
 
-pub async fn handle_output<W>(verbose: bool, mut manager: SandstormRequestManager<W>) -> Result<(), Error>
+fn print_metrics(metrics: &Metrics) {
+    println!("current client connections: {}", metrics.current_client_connections);
+    println!("historic client connections: {}", metrics.historic_client_connections);
+    println!("client bytes sent: {}", metrics.client_bytes_sent);
+    println!("client bytes received: {}", metrics.client_bytes_received);
+    println!("current sandstorm connections: {}", metrics.current_sandstorm_connections);
+    println!("historic sandstorm connections: {}", metrics.historic_sandstorm_connections);
+    println!("start time: {}", metrics.start_time);
+    println!("counter epoch: {}", metrics.counter_epoch);
+}
+
+pub async fn handle_output<W>(
+    verbose: bool,
+    event_filter: Option<EventStreamFilter>,
+    event_replay: Option<EventReplay>,
+    mut manager: SandstormRequestManager<W>,
+) -> Result<(), Error>
 where
     W: AsyncWrite + Unpin,
 {
+    let capabilities = manager.capabilities();
+    if event_filter.is_some() && !capabilities.has(SandstormCapability::EventStreamFilter) {
+        return Err(Error::new(ErrorKind::Unsupported, "The server doesn't support event filters"));
+    }
+
+    if event_replay.is_some() && !capabilities.has(SandstormCapability::EventReplay) {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "The server doesn't support replaying past events",
+        ));
+    }
+
     printlnif!(verbose, "Starting log output, enabling event stream");
     let (tx, rx) = oneshot::channel();
     manager
-        .event_stream_config_fn(true, |result| match result {
+        .event_stream_config_fn(true, event_filter, event_replay, |result| match result {
             EventStreamConfigResponse::Disabled => {
                 eprintln!("Server refused to enable event streaming");
                 let _ = tx.send(false);
@@ -52,14 +92,19 @@ where
             }
             EventStreamConfigResponse::Enabled(metrics) => {
                 println!("Event streaming enabled! Current metrics are:");
-                println!("current client connections: {}", metrics.current_client_connections);
-                println!("historic client connections: {}", metrics.historic_client_connections);
-                println!("client bytes sent: {}", metrics.client_bytes_sent);
-                println!("client bytes received: {}", metrics.client_bytes_received);
-                println!("current sandstorm connections: {}", metrics.current_sandstorm_connections);
-                println!("historic sandstorm connections: {}", metrics.historic_sandstorm_connections);
+                print_metrics(&metrics);
+                let _ = tx.send(true);
+                Some(EventStreamReceiver::Function(Box::new(get_event_handler_fn(0))))
+            }
+            EventStreamConfigResponse::EnabledWithHistory(metrics, replayed_count) => {
+                println!("Event streaming enabled! Current metrics are:");
+                print_metrics(&metrics);
+                match replayed_count {
+                    0 => println!("No past events to replay, live events follow"),
+                    count => println!("Replaying {count} past events"),
+                }
                 let _ = tx.send(true);
-                Some(EventStreamReceiver::Function(Box::new(get_event_handler_fn())))
+                Some(EventStreamReceiver::Function(Box::new(get_event_handler_fn(replayed_count))))
             }
             EventStreamConfigResponse::WasAlreadyEnabled => {
                 eprintln!("Couldn't enable event streaming: Server responded with WasAlreadyEnabled.");
@@ -3,6 +3,10 @@ use std::{
     io::Error,
 };
 
+use dust_devil_core::{
+    logging::FormatMicros,
+    sandstorm::{BreakdownEntry, Histogram},
+};
 use tokio::io::AsyncWrite;
 
 use crate::{args::CommandRequest, sandstorm::SandstormRequestManager};
@@ -23,15 +27,59 @@ impl<'a> fmt::Display for UserDisplayer<'a> {
     }
 }
 
+fn print_breakdown_entries(title: &str, entries: &[BreakdownEntry]) {
+    println!("{title} ({}):", entries.len());
+    for entry in entries {
+        println!(
+            "{} connections={} failures={} bytes_sent={} bytes_received={}",
+            entry.name, entry.connections, entry.failures, entry.bytes_sent, entry.bytes_received
+        );
+    }
+}
+
+fn print_histogram(title: &str, histogram: &Histogram) {
+    println!("{title} (count={}, sum={}):", histogram.count(), FormatMicros(histogram.sum()));
+    for (bound, count) in histogram.bounds().iter().zip(histogram.counts()) {
+        println!("<= {} {count}", FormatMicros(*bound));
+    }
+    if let Some(count) = histogram.counts().last() {
+        println!(
+            "> {} {count}",
+            histogram.bounds().last().map_or(FormatMicros(0), |bound| FormatMicros(*bound))
+        );
+    }
+}
+
 pub async fn handle_requests<W>(silent: bool, requests: &Vec<CommandRequest>, manager: &mut SandstormRequestManager<W>) -> Result<(), Error>
 where
     W: AsyncWrite + Unpin,
 {
+    manager.set_permission_denied_handler(move |command| {
+        if !silent {
+            println!("{command:?} PermissionDenied");
+            println!("{RESULT_SEPARATOR}");
+        }
+    });
+
+    manager.set_unsupported_command_handler(move |command| {
+        if !silent {
+            println!("{command:?} UnsupportedCommand");
+            println!("{RESULT_SEPARATOR}");
+        }
+    });
+
+    manager.set_timeout_handler(move |command| {
+        if !silent {
+            println!("{command:?} TimedOut");
+            println!("{RESULT_SEPARATOR}");
+        }
+    });
+
     for request in requests {
         match request {
-            CommandRequest::Shutdown => {
+            CommandRequest::Shutdown(drain_timeout) => {
                 manager
-                    .shutdown_fn(move |_result| {
+                    .shutdown_fn(*drain_timeout, move |_result| {
                         if !silent {
                             println!("Shutdown Ok");
                             println!("{RESULT_SEPARATOR}");
@@ -45,18 +93,21 @@ where
                         let list = result.0;
                         if !silent {
                             println!("ListSocks5Sockets ({})", list.len());
-                            for addr in list {
-                                println!("{addr}");
+                            for (addr, profile) in list {
+                                match profile.is_default() {
+                                    true => println!("{addr}"),
+                                    false => println!("{addr}={profile}"),
+                                }
                             }
                             println!("{RESULT_SEPARATOR}");
                         }
                     })
                     .await?;
             }
-            CommandRequest::AddSocks5Socket(address) => {
+            CommandRequest::AddSocks5Socket(address, profile) => {
                 let address = *address;
                 manager
-                    .add_socks5_socket_fn(address, move |result| {
+                    .add_socks5_socket_fn(address, profile.clone(), move |result| {
                         if !silent {
                             match result.0 {
                                 Ok(()) => println!("AddSocks5Socket {address} Ok"),
@@ -212,6 +263,8 @@ where
                                     println!("client bytes received: {}", metrics.client_bytes_received);
                                     println!("current sandstorm connections: {}", metrics.current_sandstorm_connections);
                                     println!("historic sandstorm connections: {}", metrics.historic_sandstorm_connections);
+                                    println!("start time: {}", metrics.start_time);
+                                    println!("counter epoch: {}", metrics.counter_epoch);
                                 }
                                 None => println!("RequestCurrentMetrics None"),
                             }
@@ -220,6 +273,57 @@ where
                     })
                     .await?;
             }
+            CommandRequest::GetMetricsBreakdown(top) => {
+                manager
+                    .get_metrics_breakdown_fn(*top, move |result| {
+                        if !silent {
+                            match result.0 {
+                                Some(breakdown) => {
+                                    println!("RequestMetricsBreakdown Some");
+                                    print_breakdown_entries("users", &breakdown.users);
+                                    print_breakdown_entries("destinations", &breakdown.destinations);
+                                    print_histogram("connect latency", &breakdown.connect_latency);
+                                    print_histogram("connection duration", &breakdown.connection_duration);
+                                }
+                                None => println!("RequestMetricsBreakdown None"),
+                            }
+                            println!("{RESULT_SEPARATOR}");
+                        }
+                    })
+                    .await?;
+            }
+            CommandRequest::ListClients => {
+                manager
+                    .list_clients_fn(move |result| {
+                        if !silent {
+                            println!("ListClients ({})", result.0.len());
+                            for client in result.0 {
+                                println!(
+                                    "{} {} user={} destination={} connected_at={} bytes_sent={} bytes_received={}",
+                                    client.client_id,
+                                    client.address,
+                                    client.username.as_deref().unwrap_or("-"),
+                                    client.destination.as_deref().unwrap_or("-"),
+                                    client.connected_at,
+                                    client.bytes_sent,
+                                    client.bytes_received
+                                );
+                            }
+                            println!("{RESULT_SEPARATOR}");
+                        }
+                    })
+                    .await?;
+            }
+            CommandRequest::CloseClients(target) => {
+                manager
+                    .close_clients_fn(target.clone(), move |result| {
+                        if !silent {
+                            println!("CloseClients {}", result.0);
+                            println!("{RESULT_SEPARATOR}");
+                        }
+                    })
+                    .await?;
+            }
             CommandRequest::GetBufferSize => {
                 manager
                     .get_buffer_size_fn(move |result| {
@@ -246,6 +350,121 @@ where
                     })
                     .await?;
             }
+            CommandRequest::GetPermissions => {
+                manager
+                    .get_permissions_fn(move |result| {
+                        if !silent {
+                            let granted: Vec<_> = result.0.granted().collect();
+                            println!("GetPermissions ({})", granted.len());
+                            for command in granted {
+                                println!("{command}");
+                            }
+                            println!("{RESULT_SEPARATOR}");
+                        }
+                    })
+                    .await?;
+            }
+            CommandRequest::ListLockouts => {
+                manager
+                    .list_lockouts_fn(move |result| {
+                        if !silent {
+                            println!("ListLockouts ({})", result.0.len());
+                            for (target, seconds) in result.0 {
+                                println!("{target} ({seconds}s remaining)");
+                            }
+                            println!("{RESULT_SEPARATOR}");
+                        }
+                    })
+                    .await?;
+            }
+            CommandRequest::ClearLockouts(target) => {
+                manager
+                    .clear_lockouts_fn(target.clone(), move |result| {
+                        if !silent {
+                            println!("ClearLockouts {}", result.0);
+                            println!("{RESULT_SEPARATOR}");
+                        }
+                    })
+                    .await?;
+            }
+            CommandRequest::ListIpFilters => {
+                manager
+                    .list_ip_filters_fn(move |result| {
+                        if !silent {
+                            println!("ListIpFilters ({})", result.0.len());
+                            for (listener, kind, network) in result.0 {
+                                println!("{listener} {kind} {network}");
+                            }
+                            println!("{RESULT_SEPARATOR}");
+                        }
+                    })
+                    .await?;
+            }
+            CommandRequest::AddIpFilter(listener, kind, network) => {
+                let (listener, kind, network) = (*listener, *kind, *network);
+                manager
+                    .add_ip_filter_fn(listener, kind, network, move |result| {
+                        if !silent {
+                            println!(
+                                "AddIpFilter {listener} {kind} {network} {}",
+                                match result.0 {
+                                    true => "Ok",
+                                    false => "AlreadyExists",
+                                }
+                            );
+                            println!("{RESULT_SEPARATOR}");
+                        }
+                    })
+                    .await?;
+            }
+            CommandRequest::RemoveIpFilter(listener, kind, network) => {
+                let (listener, kind, network) = (*listener, *kind, *network);
+                manager
+                    .remove_ip_filter_fn(listener, kind, network, move |result| {
+                        if !silent {
+                            println!(
+                                "RemoveIpFilter {listener} {kind} {network} {}",
+                                match result.0 {
+                                    true => "Ok",
+                                    false => "NotFound",
+                                }
+                            );
+                            println!("{RESULT_SEPARATOR}");
+                        }
+                    })
+                    .await?;
+            }
+            CommandRequest::ListLogFilters => {
+                manager
+                    .list_log_filters_fn(move |result| {
+                        if !silent {
+                            println!("ListLogFilters ({})", result.0.len());
+                            for (sink, filter) in result.0 {
+                                println!("{sink} {filter}");
+                            }
+                            println!("{RESULT_SEPARATOR}");
+                        }
+                    })
+                    .await?;
+            }
+            CommandRequest::SetLogFilter(sink, filter) => {
+                let sink = *sink;
+                let filter_string = filter.to_string();
+                manager
+                    .set_log_filter_fn(sink, filter.clone(), move |result| {
+                        if !silent {
+                            println!(
+                                "SetLogFilter {sink} {filter_string} {}",
+                                match result.0 {
+                                    true => "Ok",
+                                    false => "NoSuchSink",
+                                }
+                            );
+                            println!("{RESULT_SEPARATOR}");
+                        }
+                    })
+                    .await?;
+            }
             CommandRequest::Meow => {
                 manager
                     .meow_fn(move |_result| {
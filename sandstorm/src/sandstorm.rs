@@ -5,27 +5,37 @@ use std::{
     net::SocketAddr,
     ops::Deref,
     rc::Rc,
+    time::Duration,
 };
 
 use dust_devil_core::{
+    ip_filter::{IpFilterKind, IpNetwork},
+    listener_profile::ListenerProfile,
+    log_filter::{EventStreamFilter, LogFilter, LogSink},
     sandstorm::{
-        AddSandstormSocketRequest, AddSandstormSocketResponse, AddSocks5SocketRequest, AddSocks5SocketResponse, AddUserRequestRef,
-        AddUserResponse, CurrentMetricsRequest, CurrentMetricsResponse, DeleteUserRequestRef, DeleteUserResponse, EventStreamConfigRequest,
-        EventStreamConfigResponse, EventStreamResponse, GetBufferSizeRequest, GetBufferSizeResponse, ListAuthMethodsRequest,
-        ListAuthMethodsResponse, ListSandstormSocketsRequest, ListSandstormSocketsResponse, ListSocks5SocketsRequest,
-        ListSocks5SocketsResponse, ListUsersRequest, ListUsersResponse, MeowRequest, MeowResponse, RemoveSandstormSocketRequest,
-        RemoveSandstormSocketResponse, RemoveSocks5SocketRequest, RemoveSocks5SocketResponse, SandstormCommandType, SetBufferSizeRequest,
-        SetBufferSizeResponse, ShutdownRequest, ShutdownResponse, ToggleAuthMethodRequest, ToggleAuthMethodResponse, UpdateUserRequestRef,
-        UpdateUserResponse,
+        AddIpFilterRequest, AddIpFilterResponse, AddSandstormSocketRequest, AddSandstormSocketResponse, AddSocks5SocketRequest,
+        AddSocks5SocketResponse, AddUserRequestRef, AddUserResponse, ClearLockoutsRequest, ClearLockoutsResponse, CloseClientsRequest,
+        CloseClientsResponse, CloseClientsTarget, CurrentMetricsRequest, CurrentMetricsResponse, DeleteUserRequestRef, DeleteUserResponse,
+        EventReplay, EventStreamConfigRequest, EventStreamConfigResponse, EventStreamResponse, FramedRequest, GetBufferSizeRequest,
+        GetBufferSizeResponse, GetPermissionsRequest, GetPermissionsResponse, IdentifiedRequest, ListAuthMethodsRequest,
+        ListAuthMethodsResponse, ListClientsRequest, ListClientsResponse, ListIpFiltersRequest, ListIpFiltersResponse, ListLockoutsRequest,
+        ListLockoutsResponse, ListLogFiltersRequest, ListLogFiltersResponse, ListSandstormSocketsRequest, ListSandstormSocketsResponse,
+        ListSocks5SocketsRequest, ListSocks5SocketsResponse, ListUsersRequest, ListUsersResponse, LockoutTarget, MeowRequest, MeowResponse,
+        MetricsBreakdownRequest, MetricsBreakdownResponse, PermissionDeniedResponse, RemoveIpFilterRequest, RemoveIpFilterResponse,
+        RemoveSandstormSocketRequest, RemoveSandstormSocketResponse, RemoveSocks5SocketRequest, RemoveSocks5SocketResponse,
+        SandstormCapabilities, SandstormCapability, SandstormCommandType, SandstormNegotiation, SetBufferSizeRequest,
+        SetBufferSizeResponse, SetLogFilterRequest, SetLogFilterResponse, ShutdownRequest, ShutdownResponse, ToggleAuthMethodRequest,
+        ToggleAuthMethodResponse, UnsupportedCommandResponse, UpdateUserRequestRef, UpdateUserResponse, Version1,
     },
     serialize::{ByteRead, ByteWrite},
     socks5::AuthMethod,
+    u8_repr_enum::U8ReprEnum,
     users::UserRole,
 };
 use tokio::{
     io::{AsyncRead, AsyncWrite, AsyncWriteExt},
     sync::{mpsc, oneshot, Mutex},
-    task::JoinHandle,
+    task::{AbortHandle, JoinHandle},
 };
 
 pub enum EventStreamReceiver {
@@ -37,24 +47,189 @@ struct ResponseHandlers {
     remaining: usize,
     was_shutdown: bool,
     flush_notifier: Option<oneshot::Sender<()>>,
-    shutdown_handlers: VecDeque<Box<dyn FnOnce(ShutdownResponse)>>,
-    event_stream_config_handlers: VecDeque<Box<dyn FnOnce(EventStreamConfigResponse) -> Option<EventStreamReceiver>>>,
-    list_socks5_handlers: VecDeque<Box<dyn FnOnce(ListSocks5SocketsResponse)>>,
-    add_socks5_handlers: VecDeque<Box<dyn FnOnce(AddSocks5SocketResponse)>>,
-    remove_socks5_handlers: VecDeque<Box<dyn FnOnce(RemoveSocks5SocketResponse)>>,
-    list_sandstorm_handlers: VecDeque<Box<dyn FnOnce(ListSandstormSocketsResponse)>>,
-    add_sandstorm_handlers: VecDeque<Box<dyn FnOnce(AddSandstormSocketResponse)>>,
-    remove_sandstorm_handlers: VecDeque<Box<dyn FnOnce(RemoveSandstormSocketResponse)>>,
-    list_users_handlers: VecDeque<Box<dyn FnOnce(ListUsersResponse)>>,
-    add_user_handlers: VecDeque<Box<dyn FnOnce(AddUserResponse)>>,
-    update_user_handlers: VecDeque<Box<dyn FnOnce(UpdateUserResponse)>>,
-    delete_user_handlers: VecDeque<Box<dyn FnOnce(DeleteUserResponse)>>,
-    list_auth_methods_handlers: VecDeque<Box<dyn FnOnce(ListAuthMethodsResponse)>>,
-    toggle_auth_method_handlers: VecDeque<Box<dyn FnOnce(ToggleAuthMethodResponse)>>,
-    get_metrics_handlers: VecDeque<Box<dyn FnOnce(CurrentMetricsResponse)>>,
-    get_buffer_size_handlers: VecDeque<Box<dyn FnOnce(GetBufferSizeResponse)>>,
-    set_buffer_size_handlers: VecDeque<Box<dyn FnOnce(SetBufferSizeResponse)>>,
-    meow_handlers: VecDeque<Box<dyn FnOnce(MeowResponse)>>,
+    shutdown_handlers: VecDeque<PendingHandler<ShutdownResponse>>,
+    event_stream_config_handlers: VecDeque<PendingHandler<EventStreamConfigResponse, Option<EventStreamReceiver>>>,
+    list_socks5_handlers: VecDeque<PendingHandler<ListSocks5SocketsResponse>>,
+    add_socks5_handlers: VecDeque<PendingHandler<AddSocks5SocketResponse>>,
+    remove_socks5_handlers: VecDeque<PendingHandler<RemoveSocks5SocketResponse>>,
+    list_sandstorm_handlers: VecDeque<PendingHandler<ListSandstormSocketsResponse>>,
+    add_sandstorm_handlers: VecDeque<PendingHandler<AddSandstormSocketResponse>>,
+    remove_sandstorm_handlers: VecDeque<PendingHandler<RemoveSandstormSocketResponse>>,
+    list_users_handlers: VecDeque<PendingHandler<ListUsersResponse>>,
+    add_user_handlers: VecDeque<PendingHandler<AddUserResponse>>,
+    update_user_handlers: VecDeque<PendingHandler<UpdateUserResponse>>,
+    delete_user_handlers: VecDeque<PendingHandler<DeleteUserResponse>>,
+    list_auth_methods_handlers: VecDeque<PendingHandler<ListAuthMethodsResponse>>,
+    toggle_auth_method_handlers: VecDeque<PendingHandler<ToggleAuthMethodResponse>>,
+    get_metrics_handlers: VecDeque<PendingHandler<CurrentMetricsResponse>>,
+    get_buffer_size_handlers: VecDeque<PendingHandler<GetBufferSizeResponse>>,
+    set_buffer_size_handlers: VecDeque<PendingHandler<SetBufferSizeResponse>>,
+    get_permissions_handlers: VecDeque<PendingHandler<GetPermissionsResponse>>,
+    list_lockouts_handlers: VecDeque<PendingHandler<ListLockoutsResponse>>,
+    clear_lockouts_handlers: VecDeque<PendingHandler<ClearLockoutsResponse>>,
+    list_ip_filters_handlers: VecDeque<PendingHandler<ListIpFiltersResponse>>,
+    add_ip_filter_handlers: VecDeque<PendingHandler<AddIpFilterResponse>>,
+    remove_ip_filter_handlers: VecDeque<PendingHandler<RemoveIpFilterResponse>>,
+    list_log_filters_handlers: VecDeque<PendingHandler<ListLogFiltersResponse>>,
+    set_log_filter_handlers: VecDeque<PendingHandler<SetLogFilterResponse>>,
+    metrics_breakdown_handlers: VecDeque<PendingHandler<MetricsBreakdownResponse>>,
+    list_clients_handlers: VecDeque<PendingHandler<ListClientsResponse>>,
+    close_clients_handlers: VecDeque<PendingHandler<CloseClientsResponse>>,
+    meow_handlers: VecDeque<PendingHandler<MeowResponse>>,
+    permission_denied_handler: Option<Box<dyn FnMut(SandstormCommandType)>>,
+    unsupported_command_handler: Option<Box<dyn FnMut(SandstormCommandType)>>,
+    timeout_handler: Option<Box<dyn FnMut(SandstormCommandType)>>,
+}
+
+type HandlerFn<T, R> = Box<dyn FnOnce(T) -> R>;
+
+/// A pending response handler, along with the ID of the request it's for. The handler is taken out
+/// if the request times out, in which case its response is ignored if it eventually arrives.
+struct PendingHandler<T, R = ()> {
+    request_id: u32,
+    handler: Option<HandlerFn<T, R>>,
+    /// The task counting down the request's timeout, which is aborted once the entry is removed.
+    timeout: Option<AbortHandle>,
+}
+
+impl<T, R> PendingHandler<T, R> {
+    fn new(request_id: u32, handler: HandlerFn<T, R>) -> Self {
+        Self {
+            request_id,
+            handler: Some(handler),
+            timeout: None,
+        }
+    }
+}
+
+impl<T, R> Drop for PendingHandler<T, R> {
+    fn drop(&mut self) {
+        if let Some(timeout) = self.timeout.take() {
+            timeout.abort();
+        }
+    }
+}
+
+/// Removes the pending handler for the request with the given ID, or the oldest one if responses
+/// don't carry IDs. Returns `None` if there's no such request, or `Some(None)` if it timed out.
+fn take_pending<T, R>(queue: &mut VecDeque<PendingHandler<T, R>>, request_id: Option<u32>) -> Option<Option<HandlerFn<T, R>>> {
+    let index = match request_id {
+        Some(id) => queue.iter().position(|pending| pending.request_id == id)?,
+        None => 0,
+    };
+
+    queue.remove(index).map(|mut pending| pending.handler.take())
+}
+
+/// The operations on a queue of pending handlers that don't depend on the type of its handlers.
+trait PendingQueue {
+    /// Removes a pending handler without calling it, as [`take_pending`] does. Returns `None` if
+    /// there's no such request, or whether it was still waiting on its response otherwise.
+    fn discard(&mut self, request_id: Option<u32>) -> Option<bool>;
+
+    /// Drops the handler of the request with the given ID, if it's still waiting on its response.
+    /// Returns whether it was.
+    fn expire(&mut self, request_id: u32) -> bool;
+
+    /// Sets the task counting down the timeout of the request with the given ID, so it's aborted
+    /// once the request is answered. The task is aborted right away if there's no such request.
+    fn set_timeout(&mut self, request_id: u32, timeout: AbortHandle);
+}
+
+impl<T, R> PendingQueue for VecDeque<PendingHandler<T, R>> {
+    fn discard(&mut self, request_id: Option<u32>) -> Option<bool> {
+        take_pending(self, request_id).map(|handler| handler.is_some())
+    }
+
+    fn expire(&mut self, request_id: u32) -> bool {
+        self.iter_mut()
+            .find(|pending| pending.request_id == request_id)
+            .and_then(|pending| pending.handler.take())
+            .is_some()
+    }
+
+    fn set_timeout(&mut self, request_id: u32, timeout: AbortHandle) {
+        match self.iter_mut().find(|pending| pending.request_id == request_id) {
+            Some(pending) => pending.timeout = Some(timeout),
+            None => timeout.abort(),
+        }
+    }
+}
+
+impl ResponseHandlers {
+    fn queue_for(&mut self, command: SandstormCommandType) -> Option<&mut dyn PendingQueue> {
+        let queue: &mut dyn PendingQueue = match command {
+            SandstormCommandType::Shutdown => &mut self.shutdown_handlers,
+            SandstormCommandType::EventStreamConfig => &mut self.event_stream_config_handlers,
+            SandstormCommandType::ListSocks5Sockets => &mut self.list_socks5_handlers,
+            SandstormCommandType::AddSocks5Socket => &mut self.add_socks5_handlers,
+            SandstormCommandType::RemoveSocks5Socket => &mut self.remove_socks5_handlers,
+            SandstormCommandType::ListSandstormSockets => &mut self.list_sandstorm_handlers,
+            SandstormCommandType::AddSandstormSocket => &mut self.add_sandstorm_handlers,
+            SandstormCommandType::RemoveSandstormSocket => &mut self.remove_sandstorm_handlers,
+            SandstormCommandType::ListUsers => &mut self.list_users_handlers,
+            SandstormCommandType::AddUser => &mut self.add_user_handlers,
+            SandstormCommandType::UpdateUser => &mut self.update_user_handlers,
+            SandstormCommandType::DeleteUser => &mut self.delete_user_handlers,
+            SandstormCommandType::ListAuthMethods => &mut self.list_auth_methods_handlers,
+            SandstormCommandType::ToggleAuthMethod => &mut self.toggle_auth_method_handlers,
+            SandstormCommandType::RequestCurrentMetrics => &mut self.get_metrics_handlers,
+            SandstormCommandType::GetBufferSize => &mut self.get_buffer_size_handlers,
+            SandstormCommandType::SetBufferSize => &mut self.set_buffer_size_handlers,
+            SandstormCommandType::GetPermissions => &mut self.get_permissions_handlers,
+            SandstormCommandType::ListLockouts => &mut self.list_lockouts_handlers,
+            SandstormCommandType::ClearLockouts => &mut self.clear_lockouts_handlers,
+            SandstormCommandType::ListIpFilters => &mut self.list_ip_filters_handlers,
+            SandstormCommandType::AddIpFilter => &mut self.add_ip_filter_handlers,
+            SandstormCommandType::RemoveIpFilter => &mut self.remove_ip_filter_handlers,
+            SandstormCommandType::ListLogFilters => &mut self.list_log_filters_handlers,
+            SandstormCommandType::SetLogFilter => &mut self.set_log_filter_handlers,
+            SandstormCommandType::RequestMetricsBreakdown => &mut self.metrics_breakdown_handlers,
+            SandstormCommandType::ListClients => &mut self.list_clients_handlers,
+            SandstormCommandType::CloseClients => &mut self.close_clients_handlers,
+            SandstormCommandType::Meow => &mut self.meow_handlers,
+            SandstormCommandType::EventStream | SandstormCommandType::PermissionDenied | SandstormCommandType::UnsupportedCommand => {
+                return None
+            }
+        };
+
+        Some(queue)
+    }
+
+    /// Takes the handler for a response out of the given queue, as [`take_pending`] does.
+    fn take_handler<T, R, S>(&mut self, select: S, request_id: Option<u32>) -> Option<Option<HandlerFn<T, R>>>
+    where
+        S: FnOnce(&mut Self) -> &mut VecDeque<PendingHandler<T, R>>,
+    {
+        let taken = take_pending(select(self), request_id);
+        if let Some(Some(_)) = taken {
+            self.remaining -= 1;
+        }
+
+        taken
+    }
+
+    /// Removes the pending handler for the given command type and request without calling it.
+    /// Returns `None` if there was no such request, or whether it hadn't timed out otherwise.
+    fn discard_handler(&mut self, command: SandstormCommandType, request_id: Option<u32>) -> Option<bool> {
+        let was_pending = self.queue_for(command)?.discard(request_id)?;
+        if was_pending {
+            self.remaining -= 1;
+        }
+
+        Some(was_pending)
+    }
+
+    /// Drops the handler of a request that wasn't answered in time. Returns whether the request
+    /// was still waiting on its response.
+    fn expire_handler(&mut self, command: SandstormCommandType, request_id: u32) -> bool {
+        let expired = self.queue_for(command).is_some_and(|queue| queue.expire(request_id));
+        if expired {
+            self.remaining -= 1;
+        }
+
+        expired
+    }
 }
 
 pub struct SandstormRequestManager<W>
@@ -62,22 +237,35 @@ where
     W: AsyncWrite + Unpin,
 {
     writer: W,
+    negotiation: SandstormNegotiation,
     reader_task_handle: JoinHandle<()>,
     handlers: Rc<RefCell<ResponseHandlers>>,
+    request_id_counter: u32,
+    request_timeout: Option<Duration>,
 }
 
-async fn reader_task<R>(mut reader: R, read_error_sender: oneshot::Sender<Result<(), Error>>, handlers: Rc<RefCell<ResponseHandlers>>) -> R
+async fn reader_task<R>(
+    mut reader: R,
+    negotiation: SandstormNegotiation,
+    read_error_sender: oneshot::Sender<Result<(), Error>>,
+    handlers: Rc<RefCell<ResponseHandlers>>,
+) -> R
 where
     R: AsyncRead + Unpin,
 {
-    let _ = read_error_sender.send(reader_task_inner(&mut reader, handlers).await);
+    let _ = read_error_sender.send(reader_task_inner(&mut reader, negotiation, handlers).await);
     reader
 }
 
-async fn reader_task_inner<R>(reader: &mut R, handlers: Rc<RefCell<ResponseHandlers>>) -> Result<(), Error>
+async fn reader_task_inner<R>(
+    reader: &mut R,
+    negotiation: SandstormNegotiation,
+    handlers: Rc<RefCell<ResponseHandlers>>,
+) -> Result<(), Error>
 where
     R: AsyncRead + Unpin + ?Sized,
 {
+    let uses_request_ids = negotiation.capabilities.has(SandstormCapability::RequestIds);
     let mut event_stream_sender = None;
 
     loop {
@@ -87,31 +275,41 @@ where
             Err(error) => return Err(error),
         };
 
+        // Event stream messages don't answer a request, so they never carry a request ID.
+        let request_id = match uses_request_ids && command != SandstormCommandType::EventStream {
+            true => Some(u32::read(reader).await?),
+            false => None,
+        };
+
         match command {
             SandstormCommandType::Shutdown => {
                 let result = ShutdownResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.shutdown_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.shutdown_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected Shutdown response")),
                 }
             }
             SandstormCommandType::EventStreamConfig => {
-                let result = EventStreamConfigResponse::read(reader).await?;
+                let result = match negotiation.includes_metrics_times() {
+                    true => EventStreamConfigResponse::read(reader).await?,
+                    false => Version1::<EventStreamConfigResponse>::read(reader).await?.0,
+                };
 
                 let mut handlers = handlers.deref().borrow_mut();
-                let handler = match handlers.event_stream_config_handlers.pop_front() {
-                    Some(f) => f,
+                let maybe_handler = match handlers.take_handler(|h| &mut h.event_stream_config_handlers, request_id) {
+                    Some(maybe_handler) => maybe_handler,
                     None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected EventStreamConfig response")),
                 };
-                handlers.remaining -= 1;
                 drop(handlers);
 
-                event_stream_sender = handler(result);
+                if let Some(handler) = maybe_handler {
+                    event_stream_sender = handler(result);
+                }
             }
             SandstormCommandType::EventStream => {
                 let event = EventStreamResponse::read(reader).await?;
@@ -127,38 +325,41 @@ where
                 }
             }
             SandstormCommandType::ListSocks5Sockets => {
-                let result = ListSocks5SocketsResponse::read(reader).await?;
+                let result = match negotiation.includes_listener_profiles() {
+                    true => ListSocks5SocketsResponse::read(reader).await?,
+                    false => Version1::<ListSocks5SocketsResponse>::read(reader).await?.0,
+                };
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.list_socks5_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.list_socks5_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected ListSocks5Sockets response")),
                 }
             }
             SandstormCommandType::AddSocks5Socket => {
                 let result = AddSocks5SocketResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.add_socks5_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.add_socks5_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected AddSocks5Socket response")),
                 }
             }
             SandstormCommandType::RemoveSocks5Socket => {
                 let result = RemoveSocks5SocketResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.remove_socks5_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.remove_socks5_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => {
                         return Err(Error::new(
                             ErrorKind::InvalidData,
@@ -170,12 +371,12 @@ where
             SandstormCommandType::ListSandstormSockets => {
                 let result = ListSandstormSocketsResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.list_sandstorm_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.list_sandstorm_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => {
                         return Err(Error::new(
                             ErrorKind::InvalidData,
@@ -187,12 +388,12 @@ where
             SandstormCommandType::AddSandstormSocket => {
                 let result = AddSandstormSocketResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.add_sandstorm_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.add_sandstorm_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => {
                         return Err(Error::new(
                             ErrorKind::InvalidData,
@@ -204,12 +405,12 @@ where
             SandstormCommandType::RemoveSandstormSocket => {
                 let result = RemoveSandstormSocketResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.remove_sandstorm_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.remove_sandstorm_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => {
                         return Err(Error::new(
                             ErrorKind::InvalidData,
@@ -221,84 +422,87 @@ where
             SandstormCommandType::ListUsers => {
                 let result = ListUsersResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.list_users_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.list_users_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected ListUsers response")),
                 }
             }
             SandstormCommandType::AddUser => {
                 let result = AddUserResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.add_user_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.add_user_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected AddUser response")),
                 }
             }
             SandstormCommandType::UpdateUser => {
                 let result = UpdateUserResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.update_user_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.update_user_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected UpdateUser response")),
                 }
             }
             SandstormCommandType::DeleteUser => {
                 let result = DeleteUserResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.delete_user_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.delete_user_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected DeleteUser response")),
                 }
             }
             SandstormCommandType::ListAuthMethods => {
                 let result = ListAuthMethodsResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.list_auth_methods_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.list_auth_methods_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected ListAuthMethods response")),
                 }
             }
             SandstormCommandType::ToggleAuthMethod => {
                 let result = ToggleAuthMethodResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.toggle_auth_method_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.toggle_auth_method_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected ToggleAuthMethod response")),
                 }
             }
             SandstormCommandType::RequestCurrentMetrics => {
-                let result = CurrentMetricsResponse::read(reader).await?;
+                let result = match negotiation.includes_metrics_times() {
+                    true => CurrentMetricsResponse::read(reader).await?,
+                    false => Version1::<CurrentMetricsResponse>::read(reader).await?.0,
+                };
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.get_metrics_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.get_metrics_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => {
                         return Err(Error::new(
                             ErrorKind::InvalidData,
@@ -310,36 +514,219 @@ where
             SandstormCommandType::GetBufferSize => {
                 let result = GetBufferSizeResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.get_buffer_size_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.get_buffer_size_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected GetBufferSize response")),
                 }
             }
             SandstormCommandType::SetBufferSize => {
                 let result = SetBufferSizeResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.set_buffer_size_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.set_buffer_size_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected SetBufferSize response")),
                 }
             }
+            SandstormCommandType::GetPermissions => {
+                let result = GetPermissionsResponse::read(reader).await?;
+                let mut handlers = handlers.deref().borrow_mut();
+                match handlers.take_handler(|h| &mut h.get_permissions_handlers, request_id) {
+                    Some(Some(f)) => {
+                        drop(handlers);
+                        f(result);
+                    }
+                    Some(None) => {}
+                    None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected GetPermissions response")),
+                }
+            }
+            SandstormCommandType::ListLockouts => {
+                let result = ListLockoutsResponse::read(reader).await?;
+                let mut handlers = handlers.deref().borrow_mut();
+                match handlers.take_handler(|h| &mut h.list_lockouts_handlers, request_id) {
+                    Some(Some(f)) => {
+                        drop(handlers);
+                        f(result);
+                    }
+                    Some(None) => {}
+                    None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected ListLockouts response")),
+                }
+            }
+            SandstormCommandType::ClearLockouts => {
+                let result = ClearLockoutsResponse::read(reader).await?;
+                let mut handlers = handlers.deref().borrow_mut();
+                match handlers.take_handler(|h| &mut h.clear_lockouts_handlers, request_id) {
+                    Some(Some(f)) => {
+                        drop(handlers);
+                        f(result);
+                    }
+                    Some(None) => {}
+                    None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected ClearLockouts response")),
+                }
+            }
+            SandstormCommandType::ListIpFilters => {
+                let result = ListIpFiltersResponse::read(reader).await?;
+                let mut handlers = handlers.deref().borrow_mut();
+                match handlers.take_handler(|h| &mut h.list_ip_filters_handlers, request_id) {
+                    Some(Some(f)) => {
+                        drop(handlers);
+                        f(result);
+                    }
+                    Some(None) => {}
+                    None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected ListIpFilters response")),
+                }
+            }
+            SandstormCommandType::AddIpFilter => {
+                let result = AddIpFilterResponse::read(reader).await?;
+                let mut handlers = handlers.deref().borrow_mut();
+                match handlers.take_handler(|h| &mut h.add_ip_filter_handlers, request_id) {
+                    Some(Some(f)) => {
+                        drop(handlers);
+                        f(result);
+                    }
+                    Some(None) => {}
+                    None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected AddIpFilter response")),
+                }
+            }
+            SandstormCommandType::RemoveIpFilter => {
+                let result = RemoveIpFilterResponse::read(reader).await?;
+                let mut handlers = handlers.deref().borrow_mut();
+                match handlers.take_handler(|h| &mut h.remove_ip_filter_handlers, request_id) {
+                    Some(Some(f)) => {
+                        drop(handlers);
+                        f(result);
+                    }
+                    Some(None) => {}
+                    None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected RemoveIpFilter response")),
+                }
+            }
+            SandstormCommandType::ListLogFilters => {
+                let result = ListLogFiltersResponse::read(reader).await?;
+                let mut handlers = handlers.deref().borrow_mut();
+                match handlers.take_handler(|h| &mut h.list_log_filters_handlers, request_id) {
+                    Some(Some(f)) => {
+                        drop(handlers);
+                        f(result);
+                    }
+                    Some(None) => {}
+                    None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected ListLogFilters response")),
+                }
+            }
+            SandstormCommandType::SetLogFilter => {
+                let result = SetLogFilterResponse::read(reader).await?;
+                let mut handlers = handlers.deref().borrow_mut();
+                match handlers.take_handler(|h| &mut h.set_log_filter_handlers, request_id) {
+                    Some(Some(f)) => {
+                        drop(handlers);
+                        f(result);
+                    }
+                    Some(None) => {}
+                    None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected SetLogFilter response")),
+                }
+            }
+            SandstormCommandType::RequestMetricsBreakdown => {
+                let result = MetricsBreakdownResponse::read(reader).await?;
+                let mut handlers = handlers.deref().borrow_mut();
+                match handlers.take_handler(|h| &mut h.metrics_breakdown_handlers, request_id) {
+                    Some(Some(f)) => {
+                        drop(handlers);
+                        f(result);
+                    }
+                    Some(None) => {}
+                    None => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            "Received unexpected RequestMetricsBreakdown response",
+                        ))
+                    }
+                }
+            }
+            SandstormCommandType::ListClients => {
+                let result = ListClientsResponse::read(reader).await?;
+                let mut handlers = handlers.deref().borrow_mut();
+                match handlers.take_handler(|h| &mut h.list_clients_handlers, request_id) {
+                    Some(Some(f)) => {
+                        drop(handlers);
+                        f(result);
+                    }
+                    Some(None) => {}
+                    None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected ListClients response")),
+                }
+            }
+            SandstormCommandType::CloseClients => {
+                let result = CloseClientsResponse::read(reader).await?;
+                let mut handlers = handlers.deref().borrow_mut();
+                match handlers.take_handler(|h| &mut h.close_clients_handlers, request_id) {
+                    Some(Some(f)) => {
+                        drop(handlers);
+                        f(result);
+                    }
+                    Some(None) => {}
+                    None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected CloseClients response")),
+                }
+            }
+            SandstormCommandType::PermissionDenied => {
+                let result = PermissionDeniedResponse::read(reader).await?;
+                let mut handlers_guard = handlers.deref().borrow_mut();
+                let was_pending = match handlers_guard.discard_handler(result.0, request_id) {
+                    Some(was_pending) => was_pending,
+                    None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected PermissionDenied response")),
+                };
+
+                // The handler is taken out while it runs, so it may freely use the request manager.
+                let maybe_handler = handlers_guard.permission_denied_handler.take().filter(|_| was_pending);
+                drop(handlers_guard);
+                if let Some(mut f) = maybe_handler {
+                    f(result.0);
+                    handlers.deref().borrow_mut().permission_denied_handler.get_or_insert(f);
+                }
+            }
+            SandstormCommandType::UnsupportedCommand => {
+                let result = UnsupportedCommandResponse::read(reader).await?;
+                let command = match SandstormCommandType::from_u8(result.0) {
+                    Some(command) => command,
+                    None => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            "Received UnsupportedCommand for unknown command",
+                        ))
+                    }
+                };
+
+                let mut handlers_guard = handlers.deref().borrow_mut();
+                let was_pending = match handlers_guard.discard_handler(command, request_id) {
+                    Some(was_pending) => was_pending,
+                    None => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            "Received unexpected UnsupportedCommand response",
+                        ))
+                    }
+                };
+
+                let maybe_handler = handlers_guard.unsupported_command_handler.take().filter(|_| was_pending);
+                drop(handlers_guard);
+                if let Some(mut f) = maybe_handler {
+                    f(command);
+                    handlers.deref().borrow_mut().unsupported_command_handler.get_or_insert(f);
+                }
+            }
             SandstormCommandType::Meow => {
                 let result = MeowResponse::read(reader).await?;
                 let mut handlers = handlers.deref().borrow_mut();
-                match handlers.meow_handlers.pop_front() {
-                    Some(f) => {
-                        handlers.remaining -= 1;
+                match handlers.take_handler(|h| &mut h.meow_handlers, request_id) {
+                    Some(Some(f)) => {
                         drop(handlers);
                         f(result);
                     }
+                    Some(None) => {}
                     None => return Err(Error::new(ErrorKind::InvalidData, "Received unexpected Meow response")),
                 }
             }
@@ -357,7 +744,7 @@ where
     if handlers.was_shutdown {
         match handlers.remaining {
             0 => Ok(()),
-            rem if rem == handlers.shutdown_handlers.len() => Ok(()),
+            rem if rem == handlers.shutdown_handlers.iter().filter(|p| p.handler.is_some()).count() => Ok(()),
             _ => Err(Error::new(
                 ErrorKind::UnexpectedEof,
                 "The server closed the connection before answering all the requests",
@@ -368,13 +755,37 @@ where
     }
 }
 
+/// Gives up on a request that wasn't answered in time, calling the timeout handler if the request
+/// was still waiting on its response.
+fn expire_request(handlers: &Rc<RefCell<ResponseHandlers>>, command: SandstormCommandType, request_id: u32) {
+    let mut handlers_guard = handlers.deref().borrow_mut();
+    if !handlers_guard.expire_handler(command, request_id) {
+        return;
+    }
+
+    if handlers_guard.remaining == 0 {
+        if let Some(sender) = handlers_guard.flush_notifier.take() {
+            let _ = sender.send(());
+        }
+    }
+
+    let maybe_handler = handlers_guard.timeout_handler.take();
+    drop(handlers_guard);
+    if let Some(mut f) = maybe_handler {
+        f(command);
+        handlers.deref().borrow_mut().timeout_handler.get_or_insert(f);
+    }
+}
+
 #[allow(clippy::await_holding_refcell_ref)] // TODO: Remove once clippy false positive is fixed (https://github.com/rust-lang/rust-clippy/issues/6353)
 
 impl<W> SandstormRequestManager<W>
 where
     W: AsyncWrite + Unpin,
 {
-    pub fn new<R>(reader: R, writer: W) -> (Self, oneshot::Receiver<Result<(), Error>>)
+    /// Creates a manager for a session in which the handshake already succeeded, with the
+    /// version and capabilities negotiated in it.
+    pub fn new<R>(reader: R, writer: W, negotiation: SandstormNegotiation) -> (Self, oneshot::Receiver<Result<(), Error>>)
     where
         R: AsyncRead + Unpin + 'static,
     {
@@ -401,23 +812,84 @@ where
             get_metrics_handlers: VecDeque::new(),
             get_buffer_size_handlers: VecDeque::new(),
             set_buffer_size_handlers: VecDeque::new(),
+            get_permissions_handlers: VecDeque::new(),
+            list_lockouts_handlers: VecDeque::new(),
+            clear_lockouts_handlers: VecDeque::new(),
+            list_ip_filters_handlers: VecDeque::new(),
+            add_ip_filter_handlers: VecDeque::new(),
+            remove_ip_filter_handlers: VecDeque::new(),
+            list_log_filters_handlers: VecDeque::new(),
+            set_log_filter_handlers: VecDeque::new(),
+            metrics_breakdown_handlers: VecDeque::new(),
+            list_clients_handlers: VecDeque::new(),
+            close_clients_handlers: VecDeque::new(),
             meow_handlers: VecDeque::new(),
+            permission_denied_handler: None,
+            unsupported_command_handler: None,
+            timeout_handler: None,
         }));
 
         let handlers1 = handlers.clone();
         let reader_task_handle = tokio::task::spawn_local(async move {
-            reader_task(reader, read_error_sender, handlers1).await;
+            reader_task(reader, negotiation, read_error_sender, handlers1).await;
         });
 
         let value = Self {
             writer,
+            negotiation,
             reader_task_handle,
             handlers,
+            request_id_counter: 0,
+            request_timeout: None,
         };
 
         (value, read_error_rx)
     }
 
+    /// Gets the capabilities supported by both this client and the server.
+    pub fn capabilities(&self) -> SandstormCapabilities {
+        self.negotiation.capabilities
+    }
+
+    /// Sets how long to wait for the response to each request made from now on before giving up
+    /// on it, or `None` to wait indefinitely. A response that arrives after its request timed out
+    /// is ignored.
+    pub fn set_request_timeout(&mut self, timeout: Option<Duration>) {
+        self.request_timeout = timeout;
+    }
+
+    fn next_request_id(&mut self) -> u32 {
+        let request_id = self.request_id_counter;
+        self.request_id_counter = self.request_id_counter.wrapping_add(1);
+        request_id
+    }
+
+    /// Writes a request, framed with its length and tagged with its ID as the negotiated version
+    /// and capabilities require, and starts counting down its timeout if there is one.
+    async fn send_request<T: ByteWrite>(&mut self, command: SandstormCommandType, request_id: u32, request: T) -> Result<(), Error> {
+        if let Some(timeout) = self.request_timeout {
+            let handlers = Rc::clone(&self.handlers);
+            let timeout_task = tokio::task::spawn_local(async move {
+                tokio::time::sleep(timeout).await;
+                expire_request(&handlers, command, request_id);
+            });
+
+            let timeout_task = timeout_task.abort_handle();
+            match self.handlers.deref().borrow_mut().queue_for(command) {
+                Some(queue) => queue.set_timeout(request_id, timeout_task),
+                None => timeout_task.abort(),
+            }
+        }
+
+        if self.negotiation.capabilities.has(SandstormCapability::RequestIds) {
+            IdentifiedRequest(request_id, request).write(&mut self.writer).await
+        } else if self.negotiation.frames_requests() {
+            FramedRequest(request).write(&mut self.writer).await
+        } else {
+            request.write(&mut self.writer).await
+        }
+    }
+
     pub async fn flush_writer(&mut self) -> Result<(), Error> {
         self.writer.flush().await
     }
@@ -450,45 +922,92 @@ where
         Ok(())
     }
 
-    pub async fn shutdown_fn<F: FnOnce(ShutdownResponse) + 'static>(&mut self, f: F) -> Result<(), Error> {
+    /// Requests the server shuts down. Fails without sending anything if a drain timeout is given
+    /// but the negotiated version doesn't support it.
+    pub async fn shutdown_fn<F: FnOnce(ShutdownResponse) + 'static>(&mut self, drain_timeout: Option<u32>, f: F) -> Result<(), Error> {
+        let includes_drain_timeout = self.negotiation.includes_drain_timeout();
+        if drain_timeout.is_some() && !includes_drain_timeout {
+            return Err(Error::new(ErrorKind::Unsupported, "The server doesn't support drain timeouts"));
+        }
+
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.shutdown_handlers.push_back(Box::new(f));
+        handlers.shutdown_handlers.push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         handlers.was_shutdown = true;
         drop(handlers);
-        ShutdownRequest.write(&mut self.writer).await
+
+        let request = ShutdownRequest(drain_timeout);
+        match includes_drain_timeout {
+            true => self.send_request(SandstormCommandType::Shutdown, request_id, request).await,
+            false => {
+                self.send_request(SandstormCommandType::Shutdown, request_id, Version1(request))
+                    .await
+            }
+        }
     }
 
     pub async fn event_stream_config_fn<F: FnOnce(EventStreamConfigResponse) -> Option<EventStreamReceiver> + 'static>(
         &mut self,
         status: bool,
+        filter: Option<EventStreamFilter>,
+        replay: Option<EventReplay>,
         f: F,
     ) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.event_stream_config_handlers.push_back(Box::new(f));
+        handlers
+            .event_stream_config_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        EventStreamConfigRequest(status).write(&mut self.writer).await
+        self.send_request(
+            SandstormCommandType::EventStreamConfig,
+            request_id,
+            EventStreamConfigRequest(status, filter, replay),
+        )
+        .await
     }
 
     pub async fn list_socks5_sockets_fn<F: FnOnce(ListSocks5SocketsResponse) + 'static>(&mut self, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.list_socks5_handlers.push_back(Box::new(f));
+        handlers
+            .list_socks5_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        ListSocks5SocketsRequest.write(&mut self.writer).await
+        self.send_request(SandstormCommandType::ListSocks5Sockets, request_id, ListSocks5SocketsRequest)
+            .await
     }
 
+    /// Requests the server opens a socks5 socket. Fails without sending anything if the profile
+    /// isn't the default but the negotiated version doesn't support listener profiles.
     pub async fn add_socks5_socket_fn<F: FnOnce(AddSocks5SocketResponse) + 'static>(
         &mut self,
         address: SocketAddr,
+        profile: ListenerProfile,
         f: F,
     ) -> Result<(), Error> {
+        let includes_listener_profiles = self.negotiation.includes_listener_profiles();
+        if !profile.is_default() && !includes_listener_profiles {
+            return Err(Error::new(ErrorKind::Unsupported, "The server doesn't support listener profiles"));
+        }
+
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.add_socks5_handlers.push_back(Box::new(f));
+        handlers.add_socks5_handlers.push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        AddSocks5SocketRequest(address).write(&mut self.writer).await
+
+        let request = AddSocks5SocketRequest(address, profile);
+        match includes_listener_profiles {
+            true => self.send_request(SandstormCommandType::AddSocks5Socket, request_id, request).await,
+            false => {
+                self.send_request(SandstormCommandType::AddSocks5Socket, request_id, Version1(request))
+                    .await
+            }
+        }
     }
 
     pub async fn remove_socks5_socket_fn<F: FnOnce(RemoveSocks5SocketResponse) + 'static>(
@@ -496,19 +1015,31 @@ where
         address: SocketAddr,
         f: F,
     ) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.remove_socks5_handlers.push_back(Box::new(f));
+        handlers
+            .remove_socks5_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        RemoveSocks5SocketRequest(address).write(&mut self.writer).await
+        self.send_request(
+            SandstormCommandType::RemoveSocks5Socket,
+            request_id,
+            RemoveSocks5SocketRequest(address),
+        )
+        .await
     }
 
     pub async fn list_sandstorm_sockets_fn<F: FnOnce(ListSandstormSocketsResponse) + 'static>(&mut self, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.list_sandstorm_handlers.push_back(Box::new(f));
+        handlers
+            .list_sandstorm_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        ListSandstormSocketsRequest.write(&mut self.writer).await
+        self.send_request(SandstormCommandType::ListSandstormSockets, request_id, ListSandstormSocketsRequest)
+            .await
     }
 
     pub async fn add_sandstorm_socket_fn<F: FnOnce(AddSandstormSocketResponse) + 'static>(
@@ -516,11 +1047,19 @@ where
         address: SocketAddr,
         f: F,
     ) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.add_sandstorm_handlers.push_back(Box::new(f));
+        handlers
+            .add_sandstorm_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        AddSandstormSocketRequest(address).write(&mut self.writer).await
+        self.send_request(
+            SandstormCommandType::AddSandstormSocket,
+            request_id,
+            AddSandstormSocketRequest(address),
+        )
+        .await
     }
 
     pub async fn remove_sandstorm_socket_fn<F: FnOnce(RemoveSandstormSocketResponse) + 'static>(
@@ -528,19 +1067,29 @@ where
         address: SocketAddr,
         f: F,
     ) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.remove_sandstorm_handlers.push_back(Box::new(f));
+        handlers
+            .remove_sandstorm_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        RemoveSandstormSocketRequest(address).write(&mut self.writer).await
+        self.send_request(
+            SandstormCommandType::RemoveSandstormSocket,
+            request_id,
+            RemoveSandstormSocketRequest(address),
+        )
+        .await
     }
 
     pub async fn list_users_fn<F: FnOnce(ListUsersResponse) + 'static>(&mut self, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.list_users_handlers.push_back(Box::new(f));
+        handlers.list_users_handlers.push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        ListUsersRequest.write(&mut self.writer).await
+        self.send_request(SandstormCommandType::ListUsers, request_id, ListUsersRequest)
+            .await
     }
 
     pub async fn add_user_fn<F: FnOnce(AddUserResponse) + 'static>(
@@ -550,11 +1099,17 @@ where
         role: UserRole,
         f: F,
     ) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.add_user_handlers.push_back(Box::new(f));
+        handlers.add_user_handlers.push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        AddUserRequestRef(username, password, role).write(&mut self.writer).await
+        self.send_request(
+            SandstormCommandType::AddUser,
+            request_id,
+            AddUserRequestRef(username, password, role),
+        )
+        .await
     }
 
     pub async fn update_user_fn<F: FnOnce(UpdateUserResponse) + 'static>(
@@ -564,27 +1119,43 @@ where
         role: Option<UserRole>,
         f: F,
     ) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.update_user_handlers.push_back(Box::new(f));
+        handlers
+            .update_user_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        UpdateUserRequestRef(username, password, role).write(&mut self.writer).await
+        self.send_request(
+            SandstormCommandType::UpdateUser,
+            request_id,
+            UpdateUserRequestRef(username, password, role),
+        )
+        .await
     }
 
     pub async fn delete_user_fn<F: FnOnce(DeleteUserResponse) + 'static>(&mut self, username: &str, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.delete_user_handlers.push_back(Box::new(f));
+        handlers
+            .delete_user_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        DeleteUserRequestRef(username).write(&mut self.writer).await
+        self.send_request(SandstormCommandType::DeleteUser, request_id, DeleteUserRequestRef(username))
+            .await
     }
 
     pub async fn list_auth_methods_fn<F: FnOnce(ListAuthMethodsResponse) + 'static>(&mut self, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.list_auth_methods_handlers.push_back(Box::new(f));
+        handlers
+            .list_auth_methods_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        ListAuthMethodsRequest.write(&mut self.writer).await
+        self.send_request(SandstormCommandType::ListAuthMethods, request_id, ListAuthMethodsRequest)
+            .await
     }
 
     pub async fn toggle_auth_method_fn<F: FnOnce(ToggleAuthMethodResponse) + 'static>(
@@ -593,43 +1164,248 @@ where
         status: bool,
         f: F,
     ) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.toggle_auth_method_handlers.push_back(Box::new(f));
+        handlers
+            .toggle_auth_method_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        ToggleAuthMethodRequest(auth_method, status).write(&mut self.writer).await
+        self.send_request(
+            SandstormCommandType::ToggleAuthMethod,
+            request_id,
+            ToggleAuthMethodRequest(auth_method, status),
+        )
+        .await
     }
 
     pub async fn get_metrics_fn<F: FnOnce(CurrentMetricsResponse) + 'static>(&mut self, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.get_metrics_handlers.push_back(Box::new(f));
+        handlers
+            .get_metrics_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        CurrentMetricsRequest.write(&mut self.writer).await
+        self.send_request(SandstormCommandType::RequestCurrentMetrics, request_id, CurrentMetricsRequest)
+            .await
     }
 
     pub async fn get_buffer_size_fn<F: FnOnce(GetBufferSizeResponse) + 'static>(&mut self, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.get_buffer_size_handlers.push_back(Box::new(f));
+        handlers
+            .get_buffer_size_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        GetBufferSizeRequest.write(&mut self.writer).await
+        self.send_request(SandstormCommandType::GetBufferSize, request_id, GetBufferSizeRequest)
+            .await
     }
 
     pub async fn set_buffer_size_fn<F: FnOnce(SetBufferSizeResponse) + 'static>(&mut self, buffer_size: u32, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
+        let mut handlers = self.handlers.deref().borrow_mut();
+        handlers
+            .set_buffer_size_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
+        handlers.remaining += 1;
+        drop(handlers);
+        self.send_request(SandstormCommandType::SetBufferSize, request_id, SetBufferSizeRequest(buffer_size))
+            .await
+    }
+
+    pub async fn get_permissions_fn<F: FnOnce(GetPermissionsResponse) + 'static>(&mut self, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
+        let mut handlers = self.handlers.deref().borrow_mut();
+        handlers
+            .get_permissions_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
+        handlers.remaining += 1;
+        drop(handlers);
+        self.send_request(SandstormCommandType::GetPermissions, request_id, GetPermissionsRequest)
+            .await
+    }
+
+    pub async fn list_lockouts_fn<F: FnOnce(ListLockoutsResponse) + 'static>(&mut self, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
+        let mut handlers = self.handlers.deref().borrow_mut();
+        handlers
+            .list_lockouts_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
+        handlers.remaining += 1;
+        drop(handlers);
+        self.send_request(SandstormCommandType::ListLockouts, request_id, ListLockoutsRequest)
+            .await
+    }
+
+    pub async fn clear_lockouts_fn<F: FnOnce(ClearLockoutsResponse) + 'static>(
+        &mut self,
+        target: Option<LockoutTarget>,
+        f: F,
+    ) -> Result<(), Error> {
+        let request_id = self.next_request_id();
+        let mut handlers = self.handlers.deref().borrow_mut();
+        handlers
+            .clear_lockouts_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
+        handlers.remaining += 1;
+        drop(handlers);
+        self.send_request(SandstormCommandType::ClearLockouts, request_id, ClearLockoutsRequest(target))
+            .await
+    }
+
+    pub async fn list_ip_filters_fn<F: FnOnce(ListIpFiltersResponse) + 'static>(&mut self, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
+        let mut handlers = self.handlers.deref().borrow_mut();
+        handlers
+            .list_ip_filters_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
+        handlers.remaining += 1;
+        drop(handlers);
+        self.send_request(SandstormCommandType::ListIpFilters, request_id, ListIpFiltersRequest)
+            .await
+    }
+
+    pub async fn add_ip_filter_fn<F: FnOnce(AddIpFilterResponse) + 'static>(
+        &mut self,
+        listener: SocketAddr,
+        kind: IpFilterKind,
+        network: IpNetwork,
+        f: F,
+    ) -> Result<(), Error> {
+        let request_id = self.next_request_id();
+        let mut handlers = self.handlers.deref().borrow_mut();
+        handlers
+            .add_ip_filter_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
+        handlers.remaining += 1;
+        drop(handlers);
+        self.send_request(
+            SandstormCommandType::AddIpFilter,
+            request_id,
+            AddIpFilterRequest(listener, kind, network),
+        )
+        .await
+    }
+
+    pub async fn remove_ip_filter_fn<F: FnOnce(RemoveIpFilterResponse) + 'static>(
+        &mut self,
+        listener: SocketAddr,
+        kind: IpFilterKind,
+        network: IpNetwork,
+        f: F,
+    ) -> Result<(), Error> {
+        let request_id = self.next_request_id();
+        let mut handlers = self.handlers.deref().borrow_mut();
+        handlers
+            .remove_ip_filter_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
+        handlers.remaining += 1;
+        drop(handlers);
+        self.send_request(
+            SandstormCommandType::RemoveIpFilter,
+            request_id,
+            RemoveIpFilterRequest(listener, kind, network),
+        )
+        .await
+    }
+
+    pub async fn list_log_filters_fn<F: FnOnce(ListLogFiltersResponse) + 'static>(&mut self, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
+        let mut handlers = self.handlers.deref().borrow_mut();
+        handlers
+            .list_log_filters_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
+        handlers.remaining += 1;
+        drop(handlers);
+        self.send_request(SandstormCommandType::ListLogFilters, request_id, ListLogFiltersRequest)
+            .await
+    }
+
+    pub async fn set_log_filter_fn<F: FnOnce(SetLogFilterResponse) + 'static>(
+        &mut self,
+        sink: LogSink,
+        filter: LogFilter,
+        f: F,
+    ) -> Result<(), Error> {
+        let request_id = self.next_request_id();
+        let mut handlers = self.handlers.deref().borrow_mut();
+        handlers
+            .set_log_filter_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
+        handlers.remaining += 1;
+        drop(handlers);
+        self.send_request(SandstormCommandType::SetLogFilter, request_id, SetLogFilterRequest(sink, filter))
+            .await
+    }
+
+    pub async fn get_metrics_breakdown_fn<F: FnOnce(MetricsBreakdownResponse) + 'static>(&mut self, top: u16, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
+        let mut handlers = self.handlers.deref().borrow_mut();
+        handlers
+            .metrics_breakdown_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
+        handlers.remaining += 1;
+        drop(handlers);
+        self.send_request(
+            SandstormCommandType::RequestMetricsBreakdown,
+            request_id,
+            MetricsBreakdownRequest(top),
+        )
+        .await
+    }
+
+    pub async fn list_clients_fn<F: FnOnce(ListClientsResponse) + 'static>(&mut self, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
+        let mut handlers = self.handlers.deref().borrow_mut();
+        handlers
+            .list_clients_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
+        handlers.remaining += 1;
+        drop(handlers);
+        self.send_request(SandstormCommandType::ListClients, request_id, ListClientsRequest)
+            .await
+    }
+
+    pub async fn close_clients_fn<F: FnOnce(CloseClientsResponse) + 'static>(
+        &mut self,
+        target: CloseClientsTarget,
+        f: F,
+    ) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.set_buffer_size_handlers.push_back(Box::new(f));
+        handlers
+            .close_clients_handlers
+            .push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        SetBufferSizeRequest(buffer_size).write(&mut self.writer).await
+        self.send_request(SandstormCommandType::CloseClients, request_id, CloseClientsRequest(target))
+            .await
+    }
+
+    /// Sets a function to be called whenever the server denies a request due to the session's
+    /// permissions. The handler that would have received the response for that request is
+    /// discarded without being called.
+    pub fn set_permission_denied_handler<F: FnMut(SandstormCommandType) + 'static>(&mut self, f: F) {
+        self.handlers.deref().borrow_mut().permission_denied_handler = Some(Box::new(f));
+    }
+
+    pub fn set_unsupported_command_handler<F: FnMut(SandstormCommandType) + 'static>(&mut self, f: F) {
+        self.handlers.deref().borrow_mut().unsupported_command_handler = Some(Box::new(f));
+    }
+
+    pub fn set_timeout_handler<F: FnMut(SandstormCommandType) + 'static>(&mut self, f: F) {
+        self.handlers.deref().borrow_mut().timeout_handler = Some(Box::new(f));
     }
 
     pub async fn meow_fn<F: FnOnce(MeowResponse) + 'static>(&mut self, f: F) -> Result<(), Error> {
+        let request_id = self.next_request_id();
         let mut handlers = self.handlers.deref().borrow_mut();
-        handlers.meow_handlers.push_back(Box::new(f));
+        handlers.meow_handlers.push_back(PendingHandler::new(request_id, Box::new(f)));
         handlers.remaining += 1;
         drop(handlers);
-        MeowRequest.write(&mut self.writer).await
+        self.send_request(SandstormCommandType::Meow, request_id, MeowRequest).await
     }
 
     pub fn into_mutexed(self) -> MutexedSandstormRequestManager<W> {
@@ -649,9 +1425,9 @@ impl<W: AsyncWrite + Unpin> MutexedSandstormRequestManager<W> {
         self.inner.into_inner()
     }
 
-    pub async fn shutdown_fn<F: FnOnce(ShutdownResponse) + 'static>(&self, f: F) -> Result<(), Error> {
+    pub async fn shutdown_fn<F: FnOnce(ShutdownResponse) + 'static>(&self, drain_timeout: Option<u32>, f: F) -> Result<(), Error> {
         let mut guard = self.inner.lock().await;
-        guard.shutdown_fn(f).await?;
+        guard.shutdown_fn(drain_timeout, f).await?;
         guard.flush_writer().await
     }
 
@@ -661,9 +1437,14 @@ impl<W: AsyncWrite + Unpin> MutexedSandstormRequestManager<W> {
         guard.flush_writer().await
     }
 
-    pub async fn add_socks5_socket_fn<F: FnOnce(AddSocks5SocketResponse) + 'static>(&self, address: SocketAddr, f: F) -> Result<(), Error> {
+    pub async fn add_socks5_socket_fn<F: FnOnce(AddSocks5SocketResponse) + 'static>(
+        &self,
+        address: SocketAddr,
+        profile: ListenerProfile,
+        f: F,
+    ) -> Result<(), Error> {
         let mut guard = self.inner.lock().await;
-        guard.add_socks5_socket_fn(address, f).await?;
+        guard.add_socks5_socket_fn(address, profile, f).await?;
         guard.flush_writer().await
     }
 
@@ -6,7 +6,7 @@ use std::{
 use crossterm::event;
 use dust_devil_core::{
     logging,
-    sandstorm::{EventStreamConfigResponse, EventStreamResponse, Metrics},
+    sandstorm::{EventReplay, EventStreamConfigResponse, EventStreamResponse, Metrics, SandstormCapability},
 };
 use tokio::{
     io::AsyncWrite,
@@ -21,6 +21,10 @@ use crate::sandstorm::{EventStreamReceiver, SandstormRequestManager};
 const EVENT_CHANNEL_SIZE: usize = 32;
 const SERVER_EVENTS_CHANNEL_SIZE: usize = 128;
 
+/// How many of the server's recent events to ask to have replayed when connecting. Many of these
+/// are likely bytes sent/received events, which aren't shown in the logs, so this is generous.
+const REPLAYED_EVENTS: u16 = 0x1000;
+
 pub struct TerminalEventReceiver {
     receiver: Receiver<Result<event::Event, Error>>,
 }
@@ -57,6 +61,7 @@ impl From<TerminalEventReceiveError> for Error {
 
 pub struct StreamEventReceiver {
     receiver: Receiver<EventStreamResponse>,
+    replayed_remaining: u16,
 }
 
 impl StreamEventReceiver {
@@ -64,43 +69,75 @@ impl StreamEventReceiver {
     where
         W: AsyncWrite + Unpin,
     {
-        let (result_tx, result_rx) = oneshot::channel::<Result<(Metrics, mpsc::Receiver<EventStreamResponse>), Error>>();
+        let (result_tx, result_rx) = oneshot::channel::<Result<(Metrics, u16, mpsc::Receiver<EventStreamResponse>), Error>>();
+
+        let replay = match manager.capabilities().has(SandstormCapability::EventReplay) {
+            true => Some(EventReplay::Last(REPLAYED_EVENTS)),
+            false => None,
+        };
 
         manager
-            .event_stream_config_fn(true, move |event_stream_config_result| match event_stream_config_result {
-                EventStreamConfigResponse::Enabled(metrics) => {
-                    let (event_sender, event_receiver) = mpsc::channel(SERVER_EVENTS_CHANNEL_SIZE);
-                    result_tx.send(Ok((metrics, event_receiver))).unwrap();
-                    Some(EventStreamReceiver::Channel(event_sender))
-                }
-                EventStreamConfigResponse::Disabled => {
-                    result_tx
-                        .send(Err(Error::new(ErrorKind::Other, "Couldn't enable event stream: Server refused")))
-                        .unwrap();
-                    None
-                }
-                EventStreamConfigResponse::WasAlreadyEnabled => {
-                    result_tx
-                        .send(Err(Error::new(
-                            ErrorKind::Other,
-                            "Couldn't enable event stream: Server responded with \"already enabled\"",
-                        )))
-                        .unwrap();
-                    None
-                }
-            })
+            .event_stream_config_fn(
+                true,
+                None,
+                replay,
+                move |event_stream_config_result| match event_stream_config_result {
+                    EventStreamConfigResponse::Enabled(metrics) => {
+                        let (event_sender, event_receiver) = mpsc::channel(SERVER_EVENTS_CHANNEL_SIZE);
+                        result_tx.send(Ok((metrics, 0, event_receiver))).unwrap();
+                        Some(EventStreamReceiver::Channel(event_sender))
+                    }
+                    EventStreamConfigResponse::EnabledWithHistory(metrics, replayed_count) => {
+                        let (event_sender, event_receiver) = mpsc::channel(SERVER_EVENTS_CHANNEL_SIZE);
+                        result_tx.send(Ok((metrics, replayed_count, event_receiver))).unwrap();
+                        Some(EventStreamReceiver::Channel(event_sender))
+                    }
+                    EventStreamConfigResponse::Disabled => {
+                        result_tx
+                            .send(Err(Error::new(ErrorKind::Other, "Couldn't enable event stream: Server refused")))
+                            .unwrap();
+                        None
+                    }
+                    EventStreamConfigResponse::WasAlreadyEnabled => {
+                        result_tx
+                            .send(Err(Error::new(
+                                ErrorKind::Other,
+                                "Couldn't enable event stream: Server responded with \"already enabled\"",
+                            )))
+                            .unwrap();
+                        None
+                    }
+                },
+            )
             .await?;
         manager.flush_writer().await?;
 
-        let (metrics, receiver) = result_rx
+        let (metrics, replayed_remaining, receiver) = result_rx
             .await
             .map_err(|_| Error::new(ErrorKind::Other, "Unknown error while enabling event streaming"))??;
 
-        Ok((Self { receiver }, metrics))
+        Ok((
+            Self {
+                receiver,
+                replayed_remaining,
+            },
+            metrics,
+        ))
     }
 
-    pub async fn receive(&mut self) -> Result<logging::Event, StreamEventReceiveError> {
-        self.receiver.recv().await.map(|response| response.0).ok_or(StreamEventReceiveError)
+    /// Receives the next event from the stream, alongside whether it's a replayed past event
+    /// (already accounted for in the metrics received when the stream was enabled).
+    pub async fn receive(&mut self) -> Result<(logging::Event, bool), StreamEventReceiveError> {
+        let event = self
+            .receiver
+            .recv()
+            .await
+            .map(|response| response.0)
+            .ok_or(StreamEventReceiveError)?;
+
+        let is_replayed = self.replayed_remaining != 0;
+        self.replayed_remaining = self.replayed_remaining.saturating_sub(1);
+        Ok((event, is_replayed))
     }
 }
 
@@ -1,4 +1,4 @@
-use dust_devil_core::{logging, socks5::SocksRequestAddress};
+use dust_devil_core::{logging, sandstorm::LockoutTarget, socks5::SocksRequestAddress};
 use ratatui::style::{Color, Modifier, Style};
 use time::{OffsetDateTime, UtcOffset};
 
@@ -408,6 +408,12 @@ pub fn log_event_to_single_line(vec: &mut Vec<(StaticString, Style)>, utc_offset
             vec.push((format!("{manager_id}").into(), MANAGER_ID_STYLE));
             vec.push((" requested the server shuts down".into(), SHUTDOWN_REQUEST_STYLE));
         }
+        logging::EventData::SandstormPermissionDenied(manager_id, command) => {
+            vec.push((" Manager ".into(), MANAGER_ID_STYLE));
+            vec.push((format!("{manager_id}").into(), MANAGER_ID_STYLE));
+            vec.push((" was denied permission to ".into(), WARNING_STYLE));
+            vec.push((command.to_str().into(), WARNING_STYLE));
+        }
         logging::EventData::SandstormConnectionFinished(manager_id, Ok(())) => {
             vec.push((" Manager ".into(), MANAGER_ID_STYLE));
             vec.push((format!("{manager_id}").into(), MANAGER_ID_STYLE));
@@ -422,5 +428,315 @@ pub fn log_event_to_single_line(vec: &mut Vec<(StaticString, Style)>, utc_offset
         logging::EventData::ShutdownSignalReceived => {
             vec.push((" Shutdown signal received".into(), SHUTDOWN_SIGNAL_STYLE));
         }
+        logging::EventData::LoginLockoutStarted(target, failures, seconds) => {
+            vec.push((" Locked out ".into(), WARNING_STYLE));
+            push_lockout_target(vec, target);
+            vec.push((
+                format!(" for {seconds}s after {failures} failed login attempts").into(),
+                WARNING_STYLE,
+            ));
+        }
+        logging::EventData::LoginLockoutEnded(target) => {
+            vec.push((" Login lockout on ".into(), DEFAULT_STYLE));
+            push_lockout_target(vec, target);
+            vec.push((" has expired".into(), DEFAULT_STYLE));
+        }
+        logging::EventData::LockoutsClearedByManager(manager_id, Some(target), _) => {
+            vec.push((" Manager ".into(), MANAGER_ID_STYLE));
+            vec.push((format!("{manager_id}").into(), MANAGER_ID_STYLE));
+            vec.push((" cleared the login lockout on ".into(), DEFAULT_STYLE));
+            push_lockout_target(vec, target);
+        }
+        logging::EventData::LockoutsClearedByManager(manager_id, None, count) => {
+            vec.push((" Manager ".into(), MANAGER_ID_STYLE));
+            vec.push((format!("{manager_id}").into(), MANAGER_ID_STYLE));
+            vec.push((format!(" cleared all {count} login lockouts").into(), DEFAULT_STYLE));
+        }
+        logging::EventData::ConnectionRejectedByIpFilter(listener_address, address) => {
+            vec.push((" Rejected incoming connection from ".into(), WARNING_STYLE));
+            vec.push((format!("{address}").into(), CLIENT_ADDRESS_STYLE));
+            vec.push((" at ".into(), WARNING_STYLE));
+            vec.push((format!("{listener_address}").into(), SOCKET_ADDRESS_STYLE));
+            vec.push((" due to IP filters".into(), WARNING_STYLE));
+        }
+        logging::EventData::IpFilterAddedByManager(manager_id, listener_address, kind, network) => {
+            vec.push((" Manager ".into(), MANAGER_ID_STYLE));
+            vec.push((format!("{manager_id}").into(), MANAGER_ID_STYLE));
+            vec.push((format!(" added IP filter {kind} ").into(), DEFAULT_STYLE));
+            vec.push((format!("{network}").into(), CLIENT_ADDRESS_STYLE));
+            vec.push((" at ".into(), DEFAULT_STYLE));
+            vec.push((format!("{listener_address}").into(), SOCKET_ADDRESS_STYLE));
+        }
+        logging::EventData::IpFilterRemovedByManager(manager_id, listener_address, kind, network) => {
+            vec.push((" Manager ".into(), MANAGER_ID_STYLE));
+            vec.push((format!("{manager_id}").into(), MANAGER_ID_STYLE));
+            vec.push((format!(" removed IP filter {kind} ").into(), DEFAULT_STYLE));
+            vec.push((format!("{network}").into(), CLIENT_ADDRESS_STYLE));
+            vec.push((" at ".into(), DEFAULT_STYLE));
+            vec.push((format!("{listener_address}").into(), SOCKET_ADDRESS_STYLE));
+        }
+        logging::EventData::ClientClosedByManager(client_id, manager_id) => {
+            vec.push((" Manager ".into(), MANAGER_ID_STYLE));
+            vec.push((format!("{manager_id}").into(), MANAGER_ID_STYLE));
+            vec.push((" closed ".into(), DEFAULT_STYLE));
+            vec.push(("client ".into(), CLIENT_ID_STYLE));
+            vec.push((format!("{client_id}").into(), CLIENT_ID_STYLE));
+        }
+        logging::EventData::EventStreamLagged(count, metrics) => {
+            vec.push((" Event stream fell behind, ".into(), WARNING_STYLE));
+            vec.push((format!("{count}").into(), WARNING_STYLE));
+            vec.push((" events were dropped".into(), WARNING_STYLE));
+            if metrics.is_some() {
+                vec.push((" and metrics were resynchronized".into(), DEFAULT_STYLE));
+            }
+        }
+        logging::EventData::RestoringStateFromFile(filename) => {
+            vec.push((" Restoring runtime state from file ".into(), DEFAULT_STYLE));
+            vec.push((filename.clone().into(), FILENAME_STYLE));
+        }
+        logging::EventData::Socks5SocketRestoredFromState(socket_address) => {
+            vec.push((" Restored socks5 socket at ".into(), DEFAULT_STYLE));
+            vec.push((format!("{socket_address}").into(), SOCKET_ADDRESS_STYLE));
+            vec.push((" from state".into(), DEFAULT_STYLE));
+        }
+        logging::EventData::SandstormSocketRestoredFromState(socket_address) => {
+            vec.push((" Restored Sandstorm socket at ".into(), DEFAULT_STYLE));
+            vec.push((format!("{socket_address}").into(), SOCKET_ADDRESS_STYLE));
+            vec.push((" from state".into(), DEFAULT_STYLE));
+        }
+        logging::EventData::AuthMethodRestoredFromState(auth_method, enabled) => {
+            vec.push((" Restored authentication method ".into(), DEFAULT_STYLE));
+            vec.push((auth_method.to_str().into(), AUTH_METHOD_STYLE));
+            vec.push((
+                (if *enabled {
+                    " as enabled from state"
+                } else {
+                    " as disabled from state"
+                })
+                .into(),
+                DEFAULT_STYLE,
+            ));
+        }
+        logging::EventData::BufferSizeRestoredFromState(buffer_size) => {
+            vec.push((" Restored client buffer size ".into(), DEFAULT_STYLE));
+            vec.push((format!("{buffer_size}").into(), BUFFER_SIZE_STYLE));
+            vec.push((" from state".into(), DEFAULT_STYLE));
+        }
+        logging::EventData::IpFilterRestoredFromState(listener_address, kind, network) => {
+            vec.push((format!(" Restored IP filter {kind} ").into(), DEFAULT_STYLE));
+            vec.push((format!("{network}").into(), CLIENT_ADDRESS_STYLE));
+            vec.push((" at ".into(), DEFAULT_STYLE));
+            vec.push((format!("{listener_address}").into(), SOCKET_ADDRESS_STYLE));
+            vec.push((" from state".into(), DEFAULT_STYLE));
+        }
+        logging::EventData::SavingStateToFile(filename) => {
+            vec.push((" Saving runtime state to file ".into(), DEFAULT_STYLE));
+            vec.push((filename.clone().into(), FILENAME_STYLE));
+        }
+        logging::EventData::StateSavedToFile(filename, Ok(())) => {
+            vec.push((" Successfully saved runtime state to file ".into(), DEFAULT_STYLE));
+            vec.push((filename.clone().into(), FILENAME_STYLE));
+        }
+        logging::EventData::StateSavedToFile(filename, Err(io_error)) => {
+            vec.push((" Failed to save runtime state to file ".into(), DEFAULT_STYLE));
+            vec.push((filename.clone().into(), FILENAME_STYLE));
+            vec.push((": ".into(), DEFAULT_STYLE));
+            vec.push((format!("{io_error}").into(), ERROR_STYLE));
+        }
+        logging::EventData::MetricsRestoredFromFile(filename, Ok(counter_epoch)) => {
+            vec.push((" Restored metrics from file ".into(), DEFAULT_STYLE));
+            vec.push((filename.clone().into(), FILENAME_STYLE));
+            vec.push((format!(", counted since timestamp {counter_epoch}").into(), DEFAULT_STYLE));
+        }
+        logging::EventData::MetricsRestoredFromFile(filename, Err(io_error)) => {
+            vec.push((" Failed to restore metrics from file ".into(), DEFAULT_STYLE));
+            vec.push((filename.clone().into(), FILENAME_STYLE));
+            vec.push((", they won't be saved: ".into(), DEFAULT_STYLE));
+            vec.push((format!("{io_error}").into(), ERROR_STYLE));
+        }
+        logging::EventData::MetricsSavedToFile(filename, Ok(())) => {
+            vec.push((" Successfully saved metrics to file ".into(), DEFAULT_STYLE));
+            vec.push((filename.clone().into(), FILENAME_STYLE));
+        }
+        logging::EventData::MetricsSavedToFile(filename, Err(io_error)) => {
+            vec.push((" Failed to save metrics to file ".into(), DEFAULT_STYLE));
+            vec.push((filename.clone().into(), FILENAME_STYLE));
+            vec.push((": ".into(), DEFAULT_STYLE));
+            vec.push((format!("{io_error}").into(), ERROR_STYLE));
+        }
+        logging::EventData::DrainStarted(client_count, seconds) => {
+            vec.push((" No longer accepting socks5 clients, waiting up to ".into(), DEFAULT_STYLE));
+            vec.push((format!("{seconds}s").into(), DEFAULT_STYLE));
+            vec.push((" for ".into(), DEFAULT_STYLE));
+            vec.push((format!("{client_count}").into(), CLIENT_ID_STYLE));
+            vec.push((" clients to finish".into(), DEFAULT_STYLE));
+        }
+        logging::EventData::DrainProgress(client_count) => {
+            vec.push((" Waiting for ".into(), DEFAULT_STYLE));
+            vec.push((format!("{client_count}").into(), CLIENT_ID_STYLE));
+            vec.push((" clients to finish".into(), DEFAULT_STYLE));
+        }
+        logging::EventData::DrainCompleted => {
+            vec.push((" All clients finished".into(), DEFAULT_STYLE));
+        }
+        logging::EventData::DrainTimedOut(client_count) => {
+            vec.push((" Drain timed out, closing ".into(), WARNING_STYLE));
+            vec.push((format!("{client_count}").into(), CLIENT_ID_STYLE));
+            vec.push((" remaining clients".into(), WARNING_STYLE));
+        }
+        logging::EventData::DrainAborted(client_count) => {
+            vec.push((" Drain aborted, closing ".into(), WARNING_STYLE));
+            vec.push((format!("{client_count}").into(), CLIENT_ID_STYLE));
+            vec.push((" remaining clients".into(), WARNING_STYLE));
+        }
+        logging::EventData::ListenersInheritedFromHandoff(path) => {
+            vec.push((
+                " Inherited listening sockets from the server at handoff socket ".into(),
+                DEFAULT_STYLE,
+            ));
+            vec.push((path.clone().into(), FILENAME_STYLE));
+        }
+        logging::EventData::Socks5SocketInherited(socket_address) => {
+            vec.push((" Inherited socks5 listening socket at ".into(), DEFAULT_STYLE));
+            vec.push((format!("{socket_address}").into(), SOCKET_ADDRESS_STYLE));
+        }
+        logging::EventData::SandstormSocketInherited(socket_address) => {
+            vec.push((" Inherited Sandstorm listening socket at ".into(), DEFAULT_STYLE));
+            vec.push((format!("{socket_address}").into(), SOCKET_ADDRESS_STYLE));
+        }
+        logging::EventData::HandoffReceiveFailed(path, io_error) => {
+            vec.push((" Failed to inherit listening sockets from handoff socket ".into(), DEFAULT_STYLE));
+            vec.push((path.clone().into(), FILENAME_STYLE));
+            vec.push((", binding new ones instead: ".into(), DEFAULT_STYLE));
+            vec.push((format!("{io_error}").into(), ERROR_STYLE));
+        }
+        logging::EventData::ListeningForHandoff(path) => {
+            vec.push((" Listening for handoff requests at ".into(), DEFAULT_STYLE));
+            vec.push((path.clone().into(), FILENAME_STYLE));
+        }
+        logging::EventData::FailedBindHandoffSocket(path, io_error) => {
+            vec.push((" Failed to bind handoff socket at ".into(), DEFAULT_STYLE));
+            vec.push((path.clone().into(), FILENAME_STYLE));
+            vec.push((": ".into(), DEFAULT_STYLE));
+            vec.push((format!("{io_error}").into(), ERROR_STYLE));
+        }
+        logging::EventData::ListenersHandedOff(socket_count) => {
+            vec.push((" Handed off ".into(), DEFAULT_STYLE));
+            vec.push((format!("{socket_count}").into(), SOCKET_ADDRESS_STYLE));
+            vec.push((" listening sockets to a new process".into(), DEFAULT_STYLE));
+        }
+        logging::EventData::HandoffSendFailed(io_error) => {
+            vec.push((" Failed to hand off listening sockets: ".into(), DEFAULT_STYLE));
+            vec.push((format!("{io_error}").into(), ERROR_STYLE));
+        }
+        logging::EventData::ListenersInheritedFromSystemd(socket_count) => {
+            vec.push((" Received ".into(), DEFAULT_STYLE));
+            vec.push((format!("{socket_count}").into(), SOCKET_ADDRESS_STYLE));
+            vec.push((" listening sockets from systemd".into(), DEFAULT_STYLE));
+        }
+        logging::EventData::SystemdActivationFailed(io_error) => {
+            vec.push((
+                " Failed to take listening sockets from systemd, binding new ones instead: ".into(),
+                DEFAULT_STYLE,
+            ));
+            vec.push((format!("{io_error}").into(), ERROR_STYLE));
+        }
+        logging::EventData::SystemdNotifyFailed(io_error) => {
+            vec.push((" Failed to notify systemd: ".into(), DEFAULT_STYLE));
+            vec.push((format!("{io_error}").into(), ERROR_STYLE));
+        }
+        logging::EventData::NewSandstormUnixSocket(path) => {
+            vec.push((" Listening for Sandstorm connections at Unix socket ".into(), DEFAULT_STYLE));
+            vec.push((path.clone().into(), FILENAME_STYLE));
+        }
+        logging::EventData::FailedBindSandstormUnixSocket(path, io_error) => {
+            vec.push((" Failed to set up Sandstorm Unix socket at ".into(), DEFAULT_STYLE));
+            vec.push((path.clone().into(), FILENAME_STYLE));
+            vec.push((": ".into(), DEFAULT_STYLE));
+            vec.push((format!("{io_error}").into(), ERROR_STYLE));
+        }
+        logging::EventData::NewSandstormUnixConnectionAccepted(manager_id, path) => {
+            vec.push((" New management connection through Unix socket ".into(), DEFAULT_STYLE));
+            vec.push((path.clone().into(), FILENAME_STYLE));
+            vec.push((" assigned ".into(), DEFAULT_STYLE));
+            vec.push((format!("ID {manager_id}").into(), MANAGER_ID_STYLE));
+        }
+        logging::EventData::SandstormUnixSocketRestoredFromState(path) => {
+            vec.push((" Restored Sandstorm Unix socket at ".into(), DEFAULT_STYLE));
+            vec.push((path.clone().into(), FILENAME_STYLE));
+            vec.push((" from state".into(), DEFAULT_STYLE));
+        }
+        logging::EventData::SandstormUnixSocketInherited(path) => {
+            vec.push((" Inherited Sandstorm listening Unix socket at ".into(), DEFAULT_STYLE));
+            vec.push((path.clone().into(), FILENAME_STYLE));
+        }
+        logging::EventData::LogFilterChangedByManager(manager_id, sink, filter) => {
+            vec.push((" Manager ".into(), MANAGER_ID_STYLE));
+            vec.push((format!("{manager_id}").into(), MANAGER_ID_STYLE));
+            vec.push((format!(" set the {sink} log filter to ").into(), DEFAULT_STYLE));
+            vec.push((format!("{filter}").into(), FILENAME_STYLE));
+        }
+        logging::EventData::ClientConnectionSummary(client_id, summary) => {
+            vec.push((" Client ".into(), CLIENT_ID_STYLE));
+            vec.push((format!("{client_id}").into(), CLIENT_ID_STYLE));
+            vec.push((" summary: ".into(), DEFAULT_STYLE));
+            match &summary.username {
+                Some(username) => {
+                    vec.push(("user ".into(), DEFAULT_STYLE));
+                    vec.push((username.clone().into(), USERNAME_STYLE));
+                }
+                None => vec.push(("no user".into(), DEFAULT_STYLE)),
+            }
+            match &summary.destination {
+                Some(destination) => {
+                    vec.push((", destination ".into(), DEFAULT_STYLE));
+                    vec.push((destination.clone().into(), DESTINATION_ADDRESS_STYLE));
+                }
+                None => vec.push((", no destination".into(), DEFAULT_STYLE)),
+            }
+            if let Some(latency) = summary.connect_latency_micros {
+                vec.push((format!(", connected in {}", logging::FormatMicros(latency)).into(), DEFAULT_STYLE));
+            }
+            vec.push((
+                format!(", lasted {}", logging::FormatMicros(summary.duration_micros)).into(),
+                DEFAULT_STYLE,
+            ));
+            if summary.failed {
+                vec.push((", failed".into(), ERROR_STYLE));
+            }
+        }
+        logging::EventData::NewMetricsSocket(socket_address) => {
+            vec.push((" Serving metrics over HTTP at ".into(), DEFAULT_STYLE));
+            vec.push((format!("{socket_address}").into(), SOCKET_ADDRESS_STYLE));
+        }
+        logging::EventData::FailedBindMetricsSocket(socket_address, io_error) => {
+            vec.push((" Failed to set up metrics socket at ".into(), DEFAULT_STYLE));
+            vec.push((format!("{socket_address}").into(), SOCKET_ADDRESS_STYLE));
+            vec.push((": ".into(), DEFAULT_STYLE));
+            vec.push((format!("{io_error}").into(), ERROR_STYLE));
+        }
+    }
+}
+
+fn push_lockout_target(vec: &mut Vec<(StaticString, Style)>, target: &LockoutTarget) {
+    match target {
+        LockoutTarget::Address(address) => {
+            vec.push(("address ".into(), DEFAULT_STYLE));
+            vec.push((format!("{address}").into(), CLIENT_ADDRESS_STYLE));
+        }
+        LockoutTarget::Username(username) => {
+            vec.push(("user ".into(), DEFAULT_STYLE));
+            vec.push((username.clone().into(), USERNAME_STYLE));
+        }
+        LockoutTarget::UnixUser(uid) => {
+            vec.push(("Unix socket uid ".into(), DEFAULT_STYLE));
+            vec.push((format!("{uid}").into(), USERNAME_STYLE));
+        }
+        LockoutTarget::UserFrom(source, username) => {
+            vec.push(("user ".into(), DEFAULT_STYLE));
+            vec.push((username.clone().into(), USERNAME_STYLE));
+            vec.push((" from ".into(), DEFAULT_STYLE));
+            push_lockout_target(vec, source);
+        }
     }
 }
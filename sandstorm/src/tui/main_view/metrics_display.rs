@@ -45,6 +45,19 @@ impl MetricsDisplay {
         }
     }
 
+    /// Replaces the displayed metrics, for when events were missed and the server resynchronized
+    /// them.
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = metrics;
+        self.current_client_connections_str.clear();
+        self.historic_client_connections_str.clear();
+        self.client_bytes_sent_str.clear();
+        self.client_bytes_received_str.clear();
+        self.current_sandstorm_connections_str.clear();
+        self.historic_sandstorm_connections_str.clear();
+        self.redraw_notify.notify_one();
+    }
+
     pub fn on_new_client_connection_accepted(&mut self) {
         self.metrics.current_client_connections += 1;
         self.metrics.historic_client_connections += 1;
@@ -54,7 +67,8 @@ impl MetricsDisplay {
     }
 
     pub fn on_client_connection_finished(&mut self) {
-        self.metrics.current_client_connections -= 1;
+        // If events were dropped, the start of this connection may have been missed.
+        self.metrics.current_client_connections = self.metrics.current_client_connections.saturating_sub(1);
         self.current_client_connections_str.clear();
         self.redraw_notify.notify_one();
     }
@@ -80,7 +94,7 @@ impl MetricsDisplay {
     }
 
     pub fn on_sandstorm_collection_finished(&mut self) {
-        self.metrics.current_sandstorm_connections -= 1;
+        self.metrics.current_sandstorm_connections = self.metrics.current_sandstorm_connections.saturating_sub(1);
         self.current_sandstorm_connections_str.clear();
         self.redraw_notify.notify_one();
     }
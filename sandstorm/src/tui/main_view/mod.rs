@@ -391,18 +391,25 @@ impl MainView {
             logging::EventData::ClientConnectionFinished(_, _, _, _) => {
                 self.metrics_display.on_client_connection_finished();
             }
-            logging::EventData::NewSandstormConnectionAccepted(_, _) => {
+            logging::EventData::NewSandstormConnectionAccepted(_, _) | logging::EventData::NewSandstormUnixConnectionAccepted(_, _) => {
                 self.metrics_display.on_new_sandstorm_collection_accepted();
             }
             logging::EventData::SandstormConnectionFinished(_, _) => {
                 self.metrics_display.on_sandstorm_collection_finished();
             }
+            logging::EventData::EventStreamLagged(_, Some(metrics)) => {
+                self.metrics_display.set_metrics(*metrics);
+            }
             _ => {}
         }
 
         self.log_block.new_stream_event(event);
     }
 
+    pub fn replayed_stream_event(&mut self, event: logging::Event) {
+        self.log_block.new_stream_event(event);
+    }
+
     /// Lays out the elements and sets their areas to their respective `_area` variables. Empty
     /// rectangles indicate the element is not to be rendered.
     fn distribute_areas(&mut self, area: Rect) {
@@ -146,8 +146,12 @@ where
                 }
             }
             stream_event_result = stream_event_receiver.receive() => {
-                let stream_event = stream_event_result?;
-                ui_manager.handle_stream_event(stream_event);
+                let (stream_event, is_replayed) = stream_event_result?;
+                if is_replayed {
+                    ui_manager.handle_replayed_stream_event(stream_event);
+                } else {
+                    ui_manager.handle_stream_event(stream_event);
+                }
             }
             _ = redraw_notify.notified() => {
                 terminal.draw(|frame| ui_manager.draw(frame))?;
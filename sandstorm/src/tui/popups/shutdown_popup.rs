@@ -54,7 +54,7 @@ impl<W: AsyncWrite + Unpin + 'static> DualButtonsHandler for ButtonHandler<W> {
     fn on_left(&mut self) {
         if let Some(rc) = self.manager.upgrade() {
             tokio::task::spawn_local(async move {
-                let _ = rc.shutdown_fn(|_| ()).await;
+                let _ = rc.shutdown_fn(None, |_| ()).await;
             });
 
             self.controller.set_showing_buttons(false);
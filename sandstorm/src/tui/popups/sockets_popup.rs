@@ -8,7 +8,7 @@ use std::{
 };
 
 use crossterm::event;
-use dust_devil_core::sandstorm::RemoveSocketResponse;
+use dust_devil_core::{listener_profile::ListenerProfile, sandstorm::RemoveSocketResponse};
 use ratatui::{
     layout::{Alignment, Rect},
     style::{Color, Style},
@@ -94,7 +94,7 @@ impl SocketPopupType {
             SocketPopupType::Socks5 => {
                 manager
                     .list_socks5_sockets_fn(|result| {
-                        let _ = response_sender.send(result.0);
+                        let _ = response_sender.send(result.0.into_iter().map(|(address, _)| address).collect());
                     })
                     .await
             }
@@ -121,7 +121,7 @@ impl SocketPopupType {
         let send_status = match self {
             SocketPopupType::Socks5 => {
                 manager
-                    .add_socks5_socket_fn(socket_address, |result| {
+                    .add_socks5_socket_fn(socket_address, ListenerProfile::default(), |result| {
                         let _ = response_sender.send(result.0);
                     })
                     .await
@@ -192,6 +192,12 @@ impl<W: AsyncWrite + Unpin + 'static> UIManager<W> {
         self.root.lower.new_stream_event(event);
     }
 
+    /// Handles a past event replayed by the server. Unlike with [`UIManager::handle_stream_event`],
+    /// the state this event describes may be outdated, so it's only shown in the logs.
+    pub fn handle_replayed_stream_event(&mut self, event: logging::Event) {
+        self.root.lower.replayed_stream_event(event);
+    }
+
     pub fn draw(&mut self, frame: &mut Frame) {
         let previous_area = self.current_area;
         self.current_area = frame.size();